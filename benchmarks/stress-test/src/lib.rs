@@ -7,15 +7,17 @@ pub mod systems;
 
 use hotham::{
     asset_importer::{self, add_model_to_world},
-    components::{GlobalTransform, LocalTransform, Mesh, Visible},
+    components::{
+        mesh_lods::DEFAULT_LOD_SCREEN_SIZE, GlobalTransform, LocalTransform, Mesh, MeshLods,
+        Occluder, Visible,
+    },
     contexts::RenderContext,
-    glam::{EulerRot, Quat, Vec3},
+    glam::{Affine3A, EulerRot, Quat, Vec2, Vec3},
     hecs::{With, World},
     rendering::{
         light::Light,
-        material::Material,
-        mesh_data::MeshData,
-        primitive::{calculate_bounding_sphere, Primitive},
+        material::{Material, MaterialDesc},
+        mesh_builder::{Geometry, MeshBuilder},
     },
     systems::{
         animation_system, debug::debug_system, grabbing_system, hands_system, physics_system,
@@ -26,6 +28,9 @@ use hotham::{
 };
 use systems::setup_cubes;
 
+/// How long to run with an optimisation on, then off, when comparing the two
+const TOGGLE_SECONDS: u64 = 5;
+
 #[cfg_attr(target_os = "android", ndk_glue::main(backtrace = "on"))]
 pub fn main() {
     println!("[HOTHAM_STRESS_TEST] MAIN!");
@@ -107,6 +112,12 @@ pub enum StressTest {
     IBLTest,
     /// Khronos provided scene to test Normals and Tangents
     NormalTangentTest,
+    /// Hide thousands of cubes behind occluding walls, and switch occlusion culling on and off
+    /// every few seconds
+    OcclusionCullingTest,
+    /// Fill the view with detailed spheres that have levels of detail, and switch the levels of
+    /// detail on and off every few seconds
+    MeshLodsTest,
 }
 
 fn init(engine: &mut Engine, test: &StressTest) -> HashMap<String, World> {
@@ -219,6 +230,24 @@ fn init(engine: &mut Engine, test: &StressTest) -> HashMap<String, World> {
 
             models
         }
+        StressTest::OcclusionCullingTest => {
+            let glb_buffers: Vec<&[u8]> = vec![include_bytes!("../../../test_assets/cube.glb")];
+            let models =
+                asset_importer::load_models_from_glb(&glb_buffers, vulkan_context, render_context)
+                    .unwrap();
+
+            let resolution = 20; // 8,000 cubes
+
+            setup_cubes(world, resolution, &models);
+            create_walls(render_context, world);
+            render_context.occlusion_culling.enabled = true;
+
+            models
+        }
+        StressTest::MeshLodsTest => {
+            create_spheres(render_context, world);
+            Default::default()
+        }
     }
 }
 
@@ -243,6 +272,8 @@ fn tick(tick_props: &mut TickProps, tick_data: TickData) {
             // StressTest::ManyCubes => rotate_models(world, timer.total_time().as_secs_f32()),
             StressTest::ManyHelmets => model_system(engine, models, timer, "Damaged Helmet"),
             StressTest::ManyVertices => subdivide_mesh_system(engine, timer),
+            StressTest::OcclusionCullingTest => toggle_occlusion_culling_system(engine, timer),
+            StressTest::MeshLodsTest => toggle_mesh_lods_system(engine, timer),
             _ => {}
         }
 
@@ -329,6 +360,105 @@ fn rearrange_models(world: &mut World) {
     println!("[HOTHAM_STRESS_TEST] There are now {num_models} models");
 }
 
+/// Should the optimisation being measured be on? It's switched every [`TOGGLE_SECONDS`], so the
+/// frame times with and without it can be compared in a single run.
+fn optimisation_enabled(timer: &Timer) -> bool {
+    (timer.total_time().as_secs() / TOGGLE_SECONDS) % 2 == 0
+}
+
+fn toggle_occlusion_culling_system(engine: &mut Engine, timer: &Timer) {
+    let occlusion_culling = &mut engine.render_context.occlusion_culling;
+    let enabled = optimisation_enabled(timer);
+    if occlusion_culling.enabled != enabled {
+        occlusion_culling.enabled = enabled;
+        println!("[HOTHAM_STRESS_TEST] Occlusion culling enabled: {enabled}");
+    }
+}
+
+fn toggle_mesh_lods_system(engine: &mut Engine, timer: &Timer) {
+    let enabled = optimisation_enabled(timer);
+    let mut changed = false;
+
+    // Levels of detail with a screen size of 0 are never used.
+    for (_, mesh_lods) in engine.world.query_mut::<&mut MeshLods>() {
+        for (level, lod) in mesh_lods.levels.iter_mut().zip(0..) {
+            let screen_size = if enabled {
+                DEFAULT_LOD_SCREEN_SIZE * 0.5_f32.powi(level)
+            } else {
+                0.
+            };
+            changed |= lod.screen_size != screen_size;
+            lod.screen_size = screen_size;
+        }
+    }
+
+    if changed {
+        println!("[HOTHAM_STRESS_TEST] Levels of detail enabled: {enabled}");
+    }
+}
+
+/// Two walls in front of the cubes, with a gap between them that some of the cubes can be seen
+/// through. The walls are occluders, so the cubes behind them can be culled.
+fn create_walls(render_context: &mut RenderContext, world: &mut World) {
+    let material_id = render_context
+        .resources
+        .add_material(&MaterialDesc::from_color([0.5, 0.5, 0.5, 1.].into()))
+        .unwrap();
+
+    for x in [-2.25, 2.25] {
+        let mesh = MeshBuilder::new()
+            .primitive(Geometry::cuboid([1.75, 3.5, 0.1].into()), material_id)
+            .build(render_context)
+            .unwrap();
+        let local_transform = LocalTransform {
+            translation: [x, 3.5, -4.5].into(),
+            ..Default::default()
+        };
+
+        world.spawn((
+            Visible {},
+            Occluder {},
+            mesh,
+            local_transform,
+            GlobalTransform::default(),
+        ));
+    }
+}
+
+/// A grid of spheres stretching into the distance, each with two lower levels of detail.
+fn create_spheres(render_context: &mut RenderContext, world: &mut World) {
+    let material_id = render_context
+        .resources
+        .add_material(&MaterialDesc::from_color(Vec3::ONE.extend(1.)))
+        .unwrap();
+    let meshes = [128, 32, 8]
+        .iter()
+        .map(|&segments| {
+            MeshBuilder::new()
+                .primitive(Geometry::sphere(0.2, segments), material_id)
+                .build(render_context)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    for row in 0..15 {
+        for column in 0..15 {
+            let local_transform = LocalTransform {
+                translation: [column as f32 - 7., 1., -2. - row as f32].into(),
+                ..Default::default()
+            };
+
+            world.spawn((
+                Visible {},
+                meshes[0].clone(),
+                MeshLods::from_meshes(meshes[1..].iter().cloned()),
+                local_transform,
+                GlobalTransform::default(),
+            ));
+        }
+    }
+}
+
 fn create_mesh(render_context: &mut RenderContext, world: &mut World) {
     let material_id = unsafe {
         render_context
@@ -336,14 +466,10 @@ fn create_mesh(render_context: &mut RenderContext, world: &mut World) {
             .materials_buffer
             .push(&Material::unlit_white())
     };
-    let mesh = Mesh::new(
-        MeshData::new(vec![Primitive {
-            material_id,
-            ..Default::default()
-        }]),
-        render_context,
-    );
-    update_mesh(1, &mesh, render_context);
+    let mesh = MeshBuilder::new()
+        .primitive(grid(1), material_id)
+        .build(render_context)
+        .unwrap();
     let local_transform = LocalTransform {
         translation: [0., 1., -1.].into(),
         ..Default::default()
@@ -358,62 +484,33 @@ fn create_mesh(render_context: &mut RenderContext, world: &mut World) {
 }
 
 fn update_mesh(step: usize, mesh: &Mesh, render_context: &mut RenderContext) {
-    let mut vertices = vec![];
-    let mut indices = vec![];
-
-    let vertex_offset = 1.0 / step as f32;
-
-    let mut n = 0;
-    for row in 0..step {
-        for column in 0..step {
-            {
-                let row = row as f32;
-                let col = column as f32;
-                let x = col * vertex_offset;
-                let y = row * vertex_offset;
-                vertices.push(vertex(x, y + vertex_offset));
-                vertices.push(vertex(x, y));
-                vertices.push(vertex(x + vertex_offset, y));
-                vertices.push(vertex(x + vertex_offset, y + vertex_offset));
-            }
-
-            let index_offset = (n * 4) as u32;
-            indices.push(index_offset);
-            indices.push(index_offset + 1);
-            indices.push(index_offset + 2);
-            indices.push(index_offset);
-            indices.push(index_offset + 2);
-            indices.push(index_offset + 3);
-            n += 1;
-        }
-    }
-
-    let mesh = render_context
+    let material_id = render_context
         .resources
         .mesh_data
-        .get_mut(mesh.handle)
-        .unwrap();
-    mesh.primitives[0].indices_count = indices.len() as _;
-    mesh.primitives[0].bounding_sphere = calculate_bounding_sphere(&vertices);
-
-    // This is *really* nasty, but we can get away with it as we won't have any other meshes in the scene.
-    // In the real world, this would potentially obliterate existing meshes as we're overwriting the shared buffers.
-    // DON'T DO THIS in a real application!
-    unsafe {
-        render_context.resources.index_buffer.overwrite(&indices);
-        render_context
-            .resources
-            .position_buffer
-            .overwrite(&vertices);
-    }
+        .get(mesh.handle)
+        .unwrap()
+        .primitives[0]
+        .material_id;
+    let geometry = grid(step);
 
     println!(
         "There are now {} vertices and {} indices",
-        vertices.len(),
-        indices.len()
+        geometry.positions.len(),
+        geometry.indices.len()
     );
+
+    MeshBuilder::new()
+        .primitive(geometry, material_id)
+        .update(render_context, mesh)
+        .unwrap();
 }
 
-fn vertex(x: f32, y: f32) -> Vec3 {
-    [x, y, -1.0].into()
+/// A one metre square facing the player, split into `step` by `step` quads
+fn grid(step: usize) -> Geometry {
+    let mut geometry = Geometry::plane(Vec2::splat(0.5), step as _);
+    geometry.transform(&Affine3A::from_rotation_translation(
+        Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+        [0.5, 0.5, -1.].into(),
+    ));
+    geometry
 }
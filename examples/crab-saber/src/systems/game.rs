@@ -8,7 +8,7 @@ use crate::{
 use hotham::{
     components::{
         hand::Handedness, physics::Teleport, sound_emitter::SoundState, ui_panel::UIPanelButton,
-        Collider, LocalTransform, RigidBody, UIPanel, Visible,
+        Collider, LocalTransform, ParticleEmitter, RigidBody, UIPanel, Visible,
     },
     contexts::{AudioContext, HapticContext},
     glam,
@@ -20,6 +20,7 @@ use rand::prelude::*;
 const CUBE_X_OFFSETS: [f32; 4] = [-0.6, -0.2, 0.2, 0.6];
 const CUBE_Y: f32 = 1.1;
 const CUBE_Z: f32 = -10.;
+const SPARKS_PER_HIT: usize = 40;
 
 pub fn game_system(engine: &mut Engine, game_context: &mut GameContext) {
     game_system_inner(
@@ -297,9 +298,31 @@ fn check_for_hits(
     }
 
     play_sound_effects(pending_sound_effects, world, game_context);
+    spray_sparks(&cubes_to_dispose, world, game_context);
     dispose_of_cubes(cubes_to_dispose, world);
 }
 
+/// Burst sparks from whichever saber sliced each cube
+fn spray_sparks(sliced_cubes: &[Entity], world: &mut World, game_context: &GameContext) {
+    for saber in [game_context.blue_saber, game_context.red_saber] {
+        let hits = world
+            .get::<&Collider>(saber)
+            .map(|c| {
+                c.collisions_this_frame
+                    .iter()
+                    .filter(|e| sliced_cubes.contains(e))
+                    .count()
+            })
+            .unwrap_or(0);
+        if hits == 0 {
+            continue;
+        }
+        if let Ok(mut sparks) = world.get::<&mut ParticleEmitter>(saber) {
+            sparks.burst(hits * SPARKS_PER_HIT);
+        }
+    }
+}
+
 fn is_cube(e: hotham::hecs::EntityRef) -> bool {
     e.has::<Cube>() && e.has::<Visible>() && e.has::<Collider>() && e.has::<RigidBody>()
 }
@@ -2,10 +2,10 @@ use hotham::{
     asset_importer::{add_model_to_world, Models},
     components::{
         physics::{BodyType, SharedShape},
-        stage, Collider, LocalTransform, RigidBody,
+        stage, Collider, Curve, LocalTransform, ParticleEmitter, RigidBody,
     },
     contexts::InputContext,
-    glam::Affine3A,
+    glam::{Affine3A, Vec3, Vec4},
     hecs::{Entity, With, World},
     systems::pointers::{POSITION_OFFSET, ROTATION_OFFSET},
     Engine,
@@ -52,10 +52,29 @@ pub fn add_saber(color: Color, models: &Models, world: &mut World) -> Entity {
     };
     let saber = add_model_to_world(model_name, models, world, None).unwrap();
     add_saber_physics(world, saber);
-    world.insert(saber, (Saber {}, color)).unwrap();
+    let sparks = saber_sparks(color);
+    world.insert(saber, (Saber {}, color, sparks)).unwrap();
     saber
 }
 
+/// Sparks that burst from the saber when it slices a cube
+fn saber_sparks(color: Color) -> ParticleEmitter {
+    let start: Vec4 = match color {
+        Color::Blue => [0.3, 0.6, 1., 1.].into(),
+        Color::Red => [1., 0.3, 0.2, 1.].into(),
+    };
+    ParticleEmitter {
+        emitting: false,
+        lifetime: 0.4,
+        velocity: Vec3::ZERO,
+        velocity_spread: 2.5,
+        color_over_life: Curve::linear(start, start * Vec4::new(1., 1., 1., 0.)),
+        size_over_life: Curve::linear(0.03, 0.),
+        additive: true,
+        ..Default::default()
+    }
+}
+
 fn add_saber_physics(world: &mut World, saber: Entity) {
     // Give it a collider and rigid-body
     let collider = Collider {
@@ -77,7 +77,7 @@ impl CustomRenderContext {
         let quadrics_pipeline = create_quadrics_pipeline(
             vulkan_context,
             quadrics_pipeline_layout,
-            &render_context.render_area(),
+            render_context.settings().samples,
             render_context.render_pass,
             vertex_shader_code.as_slice(),
             fragment_shader_code.as_slice(),
@@ -152,7 +152,7 @@ fn create_quadrics_descriptor_set_layout(device: &ash::Device) -> vk::Descriptor
 pub fn create_quadrics_pipeline(
     vulkan_context: &VulkanContext,
     pipeline_layout: vk::PipelineLayout,
-    render_area: &vk::Rect2D,
+    samples: vk::SampleCountFlags,
     render_pass: vk::RenderPass,
     vertex_shader_code: &[u32],
     fragment_shader_code: &[u32],
@@ -195,23 +195,13 @@ pub fn create_quadrics_pipeline(
     let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
         .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
 
-    // Viewport State
-    let viewport = vk::Viewport {
-        x: 0.0,
-        y: 0.0,
-        width: render_area.extent.width as _,
-        height: render_area.extent.height as _,
-        min_depth: 0.0,
-        max_depth: 1.0,
-    };
-    let viewports = [viewport];
-
-    // Scissors
-    let scissors = [*render_area];
-
+    // Viewport State, set by the engine when the render pass begins
     let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(&viewports)
-        .scissors(&scissors);
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
 
     // Rasterization state
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
@@ -227,8 +217,8 @@ pub fn create_quadrics_pipeline(
         .line_width(1.0);
 
     // Multisample state
-    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
-        .rasterization_samples(vk::SampleCountFlags::TYPE_4);
+    let multisample_state =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
 
     // Depth stencil state
     let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
@@ -266,6 +256,7 @@ pub fn create_quadrics_pipeline(
         .multisample_state(&multisample_state)
         .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
         .layout(pipeline_layout)
         .render_pass(render_pass)
         .subpass(0)
@@ -14,7 +14,7 @@ use hotham::{
     },
     glam::{Affine3A, Mat4},
     hecs::{With, World},
-    rendering::resources::{DrawData, PrimitiveCullData},
+    rendering::resources::PrimitiveCullData,
     systems::rendering::draw_primitive,
     vk, xr, Engine,
 };
@@ -134,6 +134,9 @@ pub unsafe fn begin(
                     gos_from_local,
                     bounding_sphere: primitive.get_bounding_sphere_in_gos(&gos_from_local),
                     skin_id,
+                    layers: Default::default(),
+                    tint: u32::MAX,
+                    packed_emissive: [0, 0],
                 });
         }
     }
@@ -343,12 +346,7 @@ pub unsafe fn draw_world(
                         .unwrap();
                     let instance =
                         &instanced_primitive.instances[cull_result.index_instance as usize];
-                    let draw_data = DrawData {
-                        gos_from_local: instance.gos_from_local.into(),
-                        local_from_gos: instance.gos_from_local.inverse().into(),
-                        skin_id: instance.skin_id,
-                    };
-                    draw_data_buffer.push(&draw_data);
+                    draw_data_buffer.push(&instance.draw_data());
                     instance_count += 1;
                 }
                 ShaderIndex::Quadric => {
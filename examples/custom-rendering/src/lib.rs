@@ -102,7 +102,7 @@ fn hot_reloading_system(engine: &mut Engine, custom_render_context: &mut CustomR
         let quadrics_pipeline = create_quadrics_pipeline(
             vulkan_context,
             custom_render_context.quadrics_pipeline_layout,
-            &render_context.render_area(),
+            render_context.settings().samples,
             render_context.render_pass,
             custom_render_context.vertex_shader_code.as_slice(),
             custom_render_context.fragment_shader_code.as_slice(),
@@ -7,6 +7,7 @@
 
 #include "../../../../hotham/src/shaders/common.glsl"
 #include "../../../../hotham/src/shaders/lights.glsl"
+#include "../../../../hotham/src/shaders/shadows.glsl"
 #include "../../../../hotham/src/shaders/brdf.glsl"
 #include "../../../../hotham/src/shaders/pbr.glsl"
 
@@ -90,6 +91,7 @@ void main() {
 
     vec4 uv4 = d.uvFromGos * hitPoint;
     uv = uv4.xy / uv4.w;
+    uv1 = uv;
 
     // Unpack the material parameters
     materialFlags = material.flagsAndBaseTextureID & 0xFFFF;
@@ -106,7 +108,7 @@ void main() {
     if ((materialFlags & PBR_WORKFLOW_UNLIT) == 0) {
         outColor.rgb = getPBRMetallicRoughnessColor(baseColor);
     } else {
-        outColor.rgb = tonemap(baseColor);
+        outColor.rgb = baseColor;
     }
 
     // Debugging
@@ -0,0 +1,135 @@
+use glam::{Quat, Vec3};
+use openxr_sys::{
+    HandEXT, HandJointLocationEXT, Posef, Quaternionf, SpaceLocationFlags, Vector3f,
+    HAND_JOINT_COUNT_EXT,
+};
+
+use crate::space_state::SpaceState;
+
+/// A canned hand pose, selected with the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandGesture {
+    Open,
+    Pinch,
+    Fist,
+}
+
+impl Default for HandGesture {
+    fn default() -> Self {
+        HandGesture::Open
+    }
+}
+
+/// Position of each joint of an open left hand, relative to the grip pose.
+/// Fingers point down -Z, the back of the hand faces +Y and the thumb is on +X.
+static OPEN_LEFT_HAND: [[f32; 3]; HAND_JOINT_COUNT_EXT] = [
+    [0.0, 0.0, -0.05],      // palm
+    [0.0, 0.0, 0.0],        // wrist
+    [0.02, -0.01, -0.02],   // thumb metacarpal
+    [0.04, -0.01, -0.045],  // thumb proximal
+    [0.055, -0.01, -0.075], // thumb distal
+    [0.065, -0.01, -0.1],   // thumb tip
+    [0.02, 0.0, -0.02],     // index metacarpal
+    [0.025, 0.0, -0.09],    // index proximal
+    [0.027, 0.0, -0.13],    // index intermediate
+    [0.028, 0.0, -0.155],   // index distal
+    [0.029, 0.0, -0.175],   // index tip
+    [0.0, 0.0, -0.02],      // middle metacarpal
+    [0.0, 0.0, -0.095],     // middle proximal
+    [0.0, 0.0, -0.14],      // middle intermediate
+    [0.0, 0.0, -0.168],     // middle distal
+    [0.0, 0.0, -0.19],      // middle tip
+    [-0.018, 0.0, -0.02],   // ring metacarpal
+    [-0.02, 0.0, -0.09],    // ring proximal
+    [-0.022, 0.0, -0.13],   // ring intermediate
+    [-0.023, 0.0, -0.155],  // ring distal
+    [-0.024, 0.0, -0.175],  // ring tip
+    [-0.03, 0.0, -0.02],    // little metacarpal
+    [-0.04, 0.0, -0.08],    // little proximal
+    [-0.045, 0.0, -0.11],   // little intermediate
+    [-0.047, 0.0, -0.13],   // little distal
+    [-0.048, 0.0, -0.148],  // little tip
+];
+
+const PALM: usize = 0;
+const WRIST: usize = 1;
+const THUMB_TIP: usize = 5;
+const INDEX_TIP: usize = 10;
+const INDEX_METACARPAL: usize = 6;
+
+/// Get the location of each joint of a simulated hand, posed relative to its grip space.
+pub fn locate_hand_joints(
+    hand: HandEXT,
+    gesture: HandGesture,
+    grip: &SpaceState,
+) -> [HandJointLocationEXT; HAND_JOINT_COUNT_EXT] {
+    let mut positions = OPEN_LEFT_HAND.map(Vec3::from);
+
+    match gesture {
+        HandGesture::Open => {}
+        HandGesture::Pinch => {
+            // Bring the thumb and index finger together.
+            let midpoint = positions[THUMB_TIP].lerp(positions[INDEX_TIP], 0.5);
+            positions[THUMB_TIP] = midpoint;
+            positions[INDEX_TIP] = midpoint;
+        }
+        HandGesture::Fist => {
+            // Curl the fingers into the palm, leaving the thumb and metacarpals alone.
+            let palm = positions[PALM];
+            for (joint, position) in positions.iter_mut().enumerate() {
+                // Each finger has five joints, starting with its metacarpal.
+                if joint < INDEX_METACARPAL || (joint - INDEX_METACARPAL) % 5 == 0 {
+                    continue;
+                }
+                *position = palm + Vec3::new(position.x * 0.3, -0.025, 0.);
+            }
+        }
+    }
+
+    let grip_position = Vec3::new(grip.position.x, grip.position.y, grip.position.z);
+    let grip_orientation = Quat::from_xyzw(
+        grip.orientation.x,
+        grip.orientation.y,
+        grip.orientation.z,
+        grip.orientation.w,
+    );
+
+    // The right hand is a mirror image of the left.
+    let mirror = if hand == HandEXT::RIGHT {
+        Vec3::new(-1., 1., 1.)
+    } else {
+        Vec3::ONE
+    };
+
+    let mut locations = [HandJointLocationEXT {
+        location_flags: SpaceLocationFlags::EMPTY,
+        pose: Posef::IDENTITY,
+        radius: 0.,
+    }; HAND_JOINT_COUNT_EXT];
+
+    for (joint, (location, position)) in locations.iter_mut().zip(positions).enumerate() {
+        let position = grip_position + grip_orientation * (position * mirror);
+        *location = HandJointLocationEXT {
+            location_flags: SpaceLocationFlags::POSITION_VALID
+                | SpaceLocationFlags::ORIENTATION_VALID
+                | SpaceLocationFlags::POSITION_TRACKED
+                | SpaceLocationFlags::ORIENTATION_TRACKED,
+            pose: Posef {
+                orientation: Quaternionf {
+                    x: grip_orientation.x,
+                    y: grip_orientation.y,
+                    z: grip_orientation.z,
+                    w: grip_orientation.w,
+                },
+                position: Vector3f {
+                    x: position.x,
+                    y: position.y,
+                    z: position.z,
+                },
+            },
+            radius: if joint <= WRIST { 0.02 } else { 0.008 },
+        };
+    }
+
+    locations
+}
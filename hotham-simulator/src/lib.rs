@@ -1,6 +1,8 @@
 // TODO Safety doc would be nice
 #![allow(clippy::missing_safety_doc)]
 
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub mod hand_tracking;
 #[cfg(any(target_os = "windows", target_os = "linux"))]
 pub mod openxr_loader;
 #[cfg(any(target_os = "windows", target_os = "linux"))]
@@ -155,6 +157,18 @@ pub unsafe extern "C" fn get_instance_proc_addr(
         *function = transmute::<pfn::ApplyHapticFeedback, _>(apply_haptic_feedback);
     } else if name == b"xrEndSession" {
         *function = transmute::<pfn::EndSession, _>(end_session);
+    } else if name == b"xrCreateHandTrackerEXT" {
+        *function = transmute::<pfn::CreateHandTrackerEXT, _>(create_hand_tracker);
+    } else if name == b"xrDestroyHandTrackerEXT" {
+        *function = transmute::<pfn::DestroyHandTrackerEXT, _>(destroy_hand_tracker);
+    } else if name == b"xrLocateHandJointsEXT" {
+        *function = transmute::<pfn::LocateHandJointsEXT, _>(locate_hand_joints);
+    } else if name == b"xrCreateFoveationProfileFB" {
+        *function = transmute::<pfn::CreateFoveationProfileFB, _>(create_foveation_profile);
+    } else if name == b"xrDestroyFoveationProfileFB" {
+        *function = transmute::<pfn::DestroyFoveationProfileFB, _>(destroy_foveation_profile);
+    } else if name == b"xrUpdateSwapchainFB" {
+        *function = transmute::<pfn::UpdateSwapchainFB, _>(update_swapchain);
     } else {
         let _name = String::from_utf8_unchecked(name.to_vec());
         unsafe extern "system" fn bang() -> Result {
@@ -4,9 +4,10 @@
     non_upper_case_globals,
     non_camel_case_types
 )]
+use crate::hand_tracking;
 use crate::openxr_loader::{self, XrExtensionProperties, XrResult};
 use crate::space_state::SpaceState;
-use crate::state::State;
+use crate::state::{LayerSwapchain, State};
 
 use ash::{
     extensions::khr,
@@ -20,17 +21,22 @@ use openxr_sys::{
     platform::{VkDevice, VkInstance, VkPhysicalDevice, VkResult},
     Action, ActionCreateInfo, ActionSet, ActionSetCreateInfo, ActionSpaceCreateInfo,
     ActionStateBoolean, ActionStateFloat, ActionStateGetInfo, ActionStatePose, ActionsSyncInfo,
-    Duration, EnvironmentBlendMode, EventDataBuffer, EventDataSessionStateChanged, Fovf,
+    BaseInStructure, BaseOutStructure, Duration, EnvironmentBlendMode, EventDataBuffer,
+    EventDataSessionStateChanged, FoveationDynamicFB, FoveationLevelFB,
+    FoveationLevelProfileCreateInfoFB, FoveationProfileCreateInfoFB, FoveationProfileFB, Fovf,
     FrameBeginInfo, FrameEndInfo, FrameState, FrameWaitInfo, GraphicsRequirementsVulkanKHR,
-    HapticActionInfo, HapticBaseHeader, Instance, InstanceCreateInfo, InstanceProperties,
-    InteractionProfileSuggestedBinding, Path, Posef, Quaternionf, ReferenceSpaceCreateInfo,
-    ReferenceSpaceType, Result, Session, SessionActionSetsAttachInfo, SessionBeginInfo,
-    SessionCreateInfo, SessionState, Space, SpaceLocation, SpaceLocationFlags, StructureType,
-    Swapchain, SwapchainCreateInfo, SwapchainImageAcquireInfo, SwapchainImageBaseHeader,
-    SwapchainImageReleaseInfo, SwapchainImageVulkanKHR, SwapchainImageWaitInfo, SystemGetInfo,
-    SystemId, SystemProperties, Time, Vector3f, Version, View, ViewConfigurationType,
-    ViewConfigurationView, ViewLocateInfo, ViewState, ViewStateFlags, VulkanDeviceCreateInfoKHR,
-    VulkanGraphicsDeviceGetInfoKHR, VulkanInstanceCreateInfoKHR, FALSE, TRUE,
+    HandEXT, HandJointLocationsEXT, HandJointsLocateInfoEXT, HandTrackerCreateInfoEXT,
+    HandTrackerEXT, HapticActionInfo, HapticBaseHeader, Instance, InstanceCreateInfo,
+    InstanceProperties, InteractionProfileSuggestedBinding, Path, Posef, Quaternionf,
+    ReferenceSpaceCreateInfo, ReferenceSpaceType, Result, Session, SessionActionSetsAttachInfo,
+    SessionBeginInfo, SessionCreateInfo, SessionState, Space, SpaceLocation, SpaceLocationFlags,
+    StructureType, Swapchain, SwapchainCreateInfo, SwapchainImageAcquireInfo,
+    SwapchainImageBaseHeader, SwapchainImageReleaseInfo, SwapchainImageVulkanKHR,
+    SwapchainImageWaitInfo, SwapchainStateBaseHeaderFB, SwapchainStateFoveationFB, SystemGetInfo,
+    SystemHandTrackingPropertiesEXT, SystemId, SystemProperties, Time, Vector3f, Version, View,
+    ViewConfigurationType, ViewConfigurationView, ViewLocateInfo, ViewState, ViewStateFlags,
+    VulkanDeviceCreateInfoKHR, VulkanGraphicsDeviceGetInfoKHR, VulkanInstanceCreateInfoKHR, FALSE,
+    TRUE,
 };
 use rand::random;
 use std::{
@@ -77,6 +83,18 @@ struct HothamSession {
     test: usize,
 }
 
+/// The extensions the simulator supports, and their versions.
+static EXTENSIONS: [(&str, u32); 8] = [
+    ("XR_KHR_vulkan_enable2", 2),
+    ("XR_KHR_vulkan_enable", 1),
+    ("XR_EXT_hand_tracking", 4),
+    ("XR_KHR_composition_layer_cylinder", 4),
+    ("XR_KHR_composition_layer_equirect2", 1),
+    ("XR_FB_foveation", 1),
+    ("XR_FB_foveation_configuration", 1),
+    ("XR_FB_swapchain_update_state", 3),
+];
+
 #[no_mangle]
 pub unsafe extern "C" fn enumerate_instance_extension_properties(
     _layerName: *const ::std::os::raw::c_char,
@@ -85,27 +103,19 @@ pub unsafe extern "C" fn enumerate_instance_extension_properties(
     properties: *mut XrExtensionProperties,
 ) -> XrResult {
     if propertyCapacityInput == 0 {
-        *propertyCountOutput = 2;
+        *propertyCountOutput = EXTENSIONS.len() as _;
         return Result::SUCCESS.into_raw();
     }
 
-    let extension = "XR_KHR_vulkan_enable2";
-    let name = str_to_fixed_bytes(extension);
-    let extensions = std::ptr::slice_from_raw_parts_mut(properties, 2);
-    (*extensions)[0] = openxr_loader::XrExtensionProperties {
-        type_: StructureType::EXTENSION_PROPERTIES.into_raw(),
-        next: ptr::null_mut(),
-        extensionName: name,
-        extensionVersion: 2,
-    };
-    let extension = "XR_KHR_vulkan_enable";
-    let name = str_to_fixed_bytes(extension);
-    (*extensions)[1] = openxr_loader::XrExtensionProperties {
-        type_: StructureType::EXTENSION_PROPERTIES.into_raw(),
-        next: ptr::null_mut(),
-        extensionName: name,
-        extensionVersion: 1,
-    };
+    let extensions = slice::from_raw_parts_mut(properties, EXTENSIONS.len());
+    for (properties, &(extension, version)) in extensions.iter_mut().zip(EXTENSIONS.iter()) {
+        *properties = openxr_loader::XrExtensionProperties {
+            type_: StructureType::EXTENSION_PROPERTIES.into_raw(),
+            next: ptr::null_mut(),
+            extensionName: str_to_fixed_bytes(extension),
+            extensionVersion: version,
+        };
+    }
     Result::SUCCESS.into_raw()
 }
 
@@ -957,6 +967,25 @@ pub unsafe extern "system" fn create_xr_swapchain(
 ) -> Result {
     println!("[HOTHAM_SIMULATOR] Creating XR Swapchain..");
     let mut state = STATE.lock().unwrap();
+
+    // Only the first swapchain is shown in the window. Any others are for composition layers,
+    // which are accepted but not drawn.
+    if state.internal_swapchain != vk::SwapchainKHR::null() {
+        let (images, memory) = create_multiview_images(&state, &(*create_info));
+        let raw = random();
+        state.layer_swapchains.insert(
+            raw,
+            LayerSwapchain {
+                images,
+                memory,
+                next_image: 0,
+            },
+        );
+        println!("[HOTHAM_SIMULATOR] ..created composition layer swapchain {raw}");
+        *swapchain = Swapchain::from_raw(raw);
+        return Result::SUCCESS;
+    }
+
     let format = vk::Format::from_raw((*create_info).format as _);
     let (multiview_images, multiview_images_memory) =
         create_multiview_images(&state, &(*create_info));
@@ -1306,7 +1335,7 @@ fn create_framebuffers(state: &mut MutexGuard<State>) -> Vec<vk::Framebuffer> {
 }
 
 pub unsafe extern "system" fn enumerate_swapchain_images(
-    _swapchain: Swapchain,
+    swapchain: Swapchain,
     image_capacity_input: u32,
     image_count_output: *mut u32,
     images: *mut SwapchainImageBaseHeader,
@@ -1316,7 +1345,11 @@ pub unsafe extern "system" fn enumerate_swapchain_images(
         return Result::SUCCESS;
     }
     println!("[HOTHAM_SIMULATOR] Creating swapchain images..");
-    let multiview_images = &STATE.lock().unwrap().multiview_images;
+    let state = STATE.lock().unwrap();
+    let multiview_images = match state.layer_swapchains.get(&swapchain.into_raw()) {
+        Some(layer_swapchain) => &layer_swapchain.images,
+        None => &state.multiview_images,
+    };
 
     let images = slice::from_raw_parts_mut(images as _, 3);
     for i in 0..3 {
@@ -1348,14 +1381,16 @@ fn create_multiview_images(
     };
     let format = vk::Format::from_raw(create_info.format as _);
     let tiling = vk::ImageTiling::OPTIMAL;
-    let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+    let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | vk::ImageUsageFlags::SAMPLED
+        | vk::ImageUsageFlags::TRANSFER_DST;
     let properties = vk::MemoryPropertyFlags::DEVICE_LOCAL;
 
     let create_info = vk::ImageCreateInfo::builder()
         .image_type(vk::ImageType::TYPE_2D)
         .extent(extent)
         .mip_levels(1)
-        .array_layers(NUM_VIEWS as _)
+        .array_layers(create_info.array_size)
         .format(format)
         .tiling(tiling)
         .initial_layout(vk::ImageLayout::UNDEFINED)
@@ -1417,6 +1452,12 @@ pub unsafe extern "system" fn acquire_swapchain_image(
     index: *mut u32,
 ) -> Result {
     let mut state = STATE.lock().unwrap();
+    if let Some(layer_swapchain) = state.layer_swapchains.get_mut(&swapchain.into_raw()) {
+        *index = layer_swapchain.next_image;
+        layer_swapchain.next_image = (layer_swapchain.next_image + 1) % 3;
+        return Result::SUCCESS;
+    }
+
     let swapchain = vk::SwapchainKHR::from_raw(swapchain.into_raw());
     let device = state.device.as_ref().unwrap();
     let ext = khr::Swapchain::new(state.vulkan_instance.as_ref().unwrap(), device);
@@ -1550,7 +1591,8 @@ pub unsafe extern "system" fn end_frame(
     _session: Session,
     frame_end_info: *const FrameEndInfo,
 ) -> Result {
-    // If there are no layers to present, we're done.
+    // If there are no layers to present, we're done. Only the projection layer is drawn, any
+    // composition layers are ignored.
     if (*frame_end_info).layer_count == 0 {
         return Result::SUCCESS;
     }
@@ -1677,8 +1719,17 @@ pub unsafe extern "system" fn enumerate_reference_spaces(
 pub unsafe extern "system" fn get_system_properties(
     _instance: Instance,
     _system_id: SystemId,
-    _properties: *mut SystemProperties,
+    properties: *mut SystemProperties,
 ) -> Result {
+    // Walk the chain looking for anything we know how to fill in.
+    let mut next = (*properties).next as *mut BaseOutStructure;
+    while !next.is_null() {
+        if (*next).ty == StructureType::SYSTEM_HAND_TRACKING_PROPERTIES_EXT {
+            let hand_tracking_properties = next as *mut SystemHandTrackingPropertiesEXT;
+            (*hand_tracking_properties).supports_hand_tracking = TRUE;
+        }
+        next = (*next).next;
+    }
     Result::SUCCESS
 }
 
@@ -2077,3 +2128,119 @@ pub fn find_memory_type(
 
     panic!("Unable to find suitable memory type")
 }
+
+pub unsafe extern "system" fn create_hand_tracker(
+    _session: Session,
+    create_info: *const HandTrackerCreateInfoEXT,
+    hand_tracker: *mut HandTrackerEXT,
+) -> Result {
+    let mut state = STATE.lock().unwrap();
+    let raw = random();
+    state.hand_trackers.insert(raw, (*create_info).hand);
+    println!("[HOTHAM_SIMULATOR] Created hand tracker for {:?}", (*create_info).hand);
+
+    *hand_tracker = HandTrackerEXT::from_raw(raw);
+    Result::SUCCESS
+}
+
+pub unsafe extern "system" fn destroy_hand_tracker(hand_tracker: HandTrackerEXT) -> Result {
+    STATE
+        .lock()
+        .unwrap()
+        .hand_trackers
+        .remove(&hand_tracker.into_raw());
+    Result::SUCCESS
+}
+
+pub unsafe extern "system" fn create_foveation_profile(
+    _session: Session,
+    create_info: *const FoveationProfileCreateInfoFB,
+    profile: *mut FoveationProfileFB,
+) -> Result {
+    let mut level = (FoveationLevelFB::NONE, FoveationDynamicFB::DISABLED);
+    let mut next = (*create_info).next as *const BaseInStructure;
+    while !next.is_null() {
+        if (*next).ty == StructureType::FOVEATION_LEVEL_PROFILE_CREATE_INFO_FB {
+            let level_profile = &*(next as *const FoveationLevelProfileCreateInfoFB);
+            level = (level_profile.level, level_profile.dynamic);
+        }
+        next = (*next).next;
+    }
+
+    let raw = random();
+    STATE.lock().unwrap().foveation_profiles.insert(raw, level);
+    *profile = FoveationProfileFB::from_raw(raw);
+    Result::SUCCESS
+}
+
+pub unsafe extern "system" fn destroy_foveation_profile(profile: FoveationProfileFB) -> Result {
+    STATE
+        .lock()
+        .unwrap()
+        .foveation_profiles
+        .remove(&profile.into_raw());
+    Result::SUCCESS
+}
+
+pub unsafe extern "system" fn update_swapchain(
+    _swapchain: Swapchain,
+    swapchain_state: *const SwapchainStateBaseHeaderFB,
+) -> Result {
+    if (*swapchain_state).ty != StructureType::SWAPCHAIN_STATE_FOVEATION_FB {
+        return Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    // The simulator renders every pixel anyway, so just check the profile is valid.
+    let swapchain_state = &*(swapchain_state as *const SwapchainStateFoveationFB);
+    let state = STATE.lock().unwrap();
+    match state
+        .foveation_profiles
+        .get(&swapchain_state.profile.into_raw())
+    {
+        Some((level, dynamic)) => {
+            println!("[HOTHAM_SIMULATOR] Foveation is now {level:?}, {dynamic:?}");
+            Result::SUCCESS
+        }
+        None => Result::ERROR_HANDLE_INVALID,
+    }
+}
+
+pub unsafe extern "system" fn locate_hand_joints(
+    hand_tracker: HandTrackerEXT,
+    _locate_info: *const HandJointsLocateInfoEXT,
+    locations: *mut HandJointLocationsEXT,
+) -> Result {
+    let state = STATE.lock().unwrap();
+    let hand = match state.hand_trackers.get(&hand_tracker.into_raw()) {
+        Some(hand) => *hand,
+        None => return Result::ERROR_HANDLE_INVALID,
+    };
+
+    // The simulated hands follow the simulated controllers.
+    let (space, gesture) = if hand == HandEXT::LEFT {
+        (state.left_hand_space, state.left_hand_gesture)
+    } else {
+        (state.right_hand_space, state.right_hand_gesture)
+    };
+
+    let locations = &mut *locations;
+    let grip = match state.spaces.get(&space) {
+        Some(grip) => grip,
+        None => {
+            locations.is_active = FALSE;
+            return Result::SUCCESS;
+        }
+    };
+
+    let joint_locations =
+        slice::from_raw_parts_mut(locations.joint_locations, locations.joint_count as _);
+    for (joint_location, simulated) in joint_locations
+        .iter_mut()
+        .zip(hand_tracking::locate_hand_joints(hand, gesture, grip))
+    {
+        *joint_location = simulated;
+    }
+    locations.is_active = TRUE;
+
+    Result::SUCCESS
+}
@@ -5,7 +5,10 @@ use ash::{
 };
 
 use glam::{Quat, Vec3};
-use openxr_sys::{Action, Bool32, Path, Posef, SessionState, Space, Vector3f};
+use openxr_sys::{
+    Action, Bool32, FoveationDynamicFB, FoveationLevelFB, HandEXT, Path, Posef, SessionState,
+    Space, Vector3f,
+};
 use winit::event::KeyboardInput;
 
 use std::{
@@ -21,7 +24,8 @@ use std::{
 };
 
 use crate::{
-    action_state::ActionState, inputs::Inputs, simulator::NUM_VIEWS, space_state::SpaceState,
+    action_state::ActionState, hand_tracking::HandGesture, inputs::Inputs, simulator::NUM_VIEWS,
+    space_state::SpaceState,
 };
 
 static A_INPUT: &str = "/user/hand/right/input/a/click";
@@ -74,6 +78,18 @@ pub struct State {
     pub last_frame_time: Instant,
     pub camera: Camera,
     pub action_state: ActionState,
+    pub hand_trackers: HashMap<u64, HandEXT>,
+    pub foveation_profiles: HashMap<u64, (FoveationLevelFB, FoveationDynamicFB)>,
+    pub layer_swapchains: HashMap<u64, LayerSwapchain>,
+    pub left_hand_gesture: HandGesture,
+    pub right_hand_gesture: HandGesture,
+}
+
+/// A swapchain for a composition layer. These aren't presented, so they're plain images.
+pub struct LayerSwapchain {
+    pub images: Vec<vk::Image>,
+    pub memory: Vec<vk::DeviceMemory>,
+    pub next_image: u32,
 }
 
 #[derive(Default)]
@@ -128,6 +144,11 @@ impl Default for State {
             input_state: Inputs::default(),
             last_frame_time: Instant::now(),
             action_state: Default::default(),
+            hand_trackers: Default::default(),
+            foveation_profiles: Default::default(),
+            layer_swapchains: Default::default(),
+            left_hand_gesture: Default::default(),
+            right_hand_gesture: Default::default(),
             view_poses: (0..NUM_VIEWS)
                 .map(|_| {
                     let mut pose = Posef::IDENTITY;
@@ -322,6 +343,8 @@ impl State {
     pub fn update_action_state(&mut self) {
         // Reset the state of all the inputs
         self.action_state.clear();
+        self.left_hand_gesture = HandGesture::Open;
+        self.right_hand_gesture = HandGesture::Open;
 
         // Clone to get around mutate after borrow
         for pressed in &self.input_state.clone().pressed {
@@ -338,6 +361,18 @@ impl State {
                 winit::event::VirtualKeyCode::Key4 => {
                     self.press(A_INPUT);
                 }
+                winit::event::VirtualKeyCode::Key5 => {
+                    self.left_hand_gesture = HandGesture::Pinch;
+                }
+                winit::event::VirtualKeyCode::Key6 => {
+                    self.left_hand_gesture = HandGesture::Fist;
+                }
+                winit::event::VirtualKeyCode::Key7 => {
+                    self.right_hand_gesture = HandGesture::Pinch;
+                }
+                winit::event::VirtualKeyCode::Key8 => {
+                    self.right_hand_gesture = HandGesture::Fist;
+                }
                 _ => {}
             }
         }
@@ -3,8 +3,8 @@ pub mod scene;
 
 use crate::{
     components::{
-        animation_controller::AnimationController, Collider, GlobalTransform, Info, LocalTransform,
-        Mesh, Parent, Root, Skin, Visible,
+        self, animation_controller::AnimationController, Collider, GlobalTransform, Info,
+        LocalTransform, Mesh, MeshLods, Occluder, Parent, Root, Skin, Visible,
     },
     contexts::{
         physics_context::{self},
@@ -26,6 +26,8 @@ use self::scene::Scene;
 static COLLIDER_TAG: &str = ".HOTHAM_COLLIDER";
 static WALL_COLLIDER_TAG: &str = ".HOTHAM_COLLIDER_WALL";
 static SENSOR_COLLIDER_TAG: &str = ".HOTHAM_COLLIDER_SENSOR";
+static LOD_TAG: &str = ".LOD";
+static OCCLUDER_TAG: &str = ".HOTHAM_OCCLUDER";
 
 /// Convenience type for models
 pub type Models = HashMap<String, World>;
@@ -157,8 +159,8 @@ fn load_models_from_gltf_data(import_context: &mut ImportContext) -> Result<()>
 
     // Iterate through each of the root nodes in the scene and load it in.
     for node in scene.nodes() {
-        // Don't add wall collider geometry as nodes.
-        if node.name().unwrap_or_default().ends_with(WALL_COLLIDER_TAG) {
+        // Don't add wall collider geometry or lower levels of detail as nodes.
+        if node.name().unwrap_or_default().ends_with(WALL_COLLIDER_TAG) || is_lod_node(&node) {
             continue;
         }
 
@@ -277,6 +279,18 @@ fn load_node(
         world
             .insert(this_entity, (mesh.clone(), Visible {}))
             .unwrap();
+
+        // Big, solid meshes can hide other meshes if occlusion culling is enabled.
+        if node.name().unwrap_or_default().contains(OCCLUDER_TAG) {
+            world.insert_one(this_entity, Occluder {}).unwrap();
+        }
+    }
+
+    // If the node has a light, add it as a component so it follows the node around.
+    if let Some(light) = node.light() {
+        world
+            .insert_one(this_entity, components::Light::from_gltf(&light))
+            .unwrap();
     }
 
     // If this node is at the root, mark it with a `Root` component.
@@ -289,8 +303,14 @@ fn load_node(
         world.insert_one(this_entity, collider).unwrap();
     }
 
-    // Now walk through each of this node's children and load them in.
-    for child in node.children() {
+    // If this node has lower levels of detail, add them in.
+    if let Some(mesh_lods) = get_lods_for_node(node, import_context) {
+        world.insert_one(this_entity, mesh_lods).unwrap();
+    }
+
+    // Now walk through each of this node's children and load them in. Lower levels of detail
+    // belong to the node they're named after, so don't get their own entities.
+    for child in node.children().filter(|c| !is_lod_node(c)) {
         load_node(&child, import_context, world, false);
     }
 
@@ -364,6 +384,48 @@ fn find_wall_collider_for_node<'a>(
     })
 }
 
+/// Levels of detail are stored in separate nodes with the same root name as some entity, eg. `rock`,
+/// `rock.LOD1` and `rock.LOD2`. Returns the root name and the level.
+fn parse_lod_name(name: &str) -> Option<(&str, usize)> {
+    let (root_name, level) = name.rsplit_once(LOD_TAG)?;
+    Some((root_name, level.parse().ok()?))
+}
+
+fn is_lod_node(node: &gltf::Node) -> bool {
+    node.name().and_then(parse_lod_name).is_some()
+}
+
+/// Searches through the glTF document for nodes holding lower levels of detail for this node.
+fn get_lods_for_node(node: &gltf::Node, import_context: &ImportContext) -> Option<MeshLods> {
+    node.mesh()?;
+    let node_name = node.name()?;
+
+    let mut lods = import_context
+        .document
+        .nodes()
+        .filter_map(|n| {
+            let (root_name, level) = parse_lod_name(n.name()?)?;
+            if root_name != node_name {
+                return None;
+            }
+            let mesh = import_context.mesh_map.get(&n.mesh()?.index())?;
+            Some((level, mesh.clone()))
+        })
+        .collect::<Vec<_>>();
+    if lods.is_empty() {
+        return None;
+    }
+    lods.sort_by_key(|(level, _)| *level);
+
+    println!(
+        "[HOTHAM_ASSET_IMPORTER] Found {} levels of detail for {node_name}",
+        lods.len()
+    );
+    Some(MeshLods::from_meshes(
+        lods.into_iter().map(|(_, mesh)| mesh),
+    ))
+}
+
 /// Use Rapier's convex_decomposition to create a shape from the mesh geometry.
 fn get_shape_from_mesh(
     mesh: gltf::Mesh,
@@ -647,6 +709,14 @@ mod tests {
         let _models = load_models_from_glb(&data, &vulkan_context, &mut render_context).unwrap();
     }
 
+    #[test]
+    fn test_parse_lod_name() {
+        assert_eq!(parse_lod_name("rock.LOD1"), Some(("rock", 1)));
+        assert_eq!(parse_lod_name("big.rock.LOD12"), Some(("big.rock", 12)));
+        assert_eq!(parse_lod_name("rock"), None);
+        assert_eq!(parse_lod_name("rock.LODGE"), None);
+    }
+
     #[test]
     pub fn test_hand() {
         let (mut render_context, vulkan_context) = RenderContext::testing();
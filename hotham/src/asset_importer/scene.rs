@@ -6,6 +6,7 @@ use super::Models;
 pub struct Scene {
     /// The models in the scene
     pub models: Models,
-    /// The lights in the scene
+    /// The lights at the top level of the scene. Lights are also added to the models as
+    /// [`crate::components::Light`] components, which is usually more convenient.
     pub lights: Vec<Light>,
 }
@@ -0,0 +1,51 @@
+use glam::{Vec3, Vec4};
+
+use crate::{components::GlobalTransform, rendering::shadows::BlobShadowData};
+
+/// A cheap fallback for real-time shadows: a soft, round shadow drawn on upward facing surfaces
+/// directly below the entity's [`GlobalTransform`].
+///
+/// Useful for hands, grabbable props and characters on devices where shadow maps are too
+/// expensive. Up to [`crate::rendering::shadows::MAX_BLOB_SHADOWS`] are drawn each frame.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::BlobShadow;
+/// world.insert_one(entity, BlobShadow::new(0.1));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlobShadow {
+    /// The radius of the shadow, in metres
+    pub radius: f32,
+    /// How dark the middle of the shadow is, from 0 to 1
+    pub opacity: f32,
+    /// The shadow fades out as the entity gets this far above a surface, in metres
+    pub max_distance: f32,
+}
+
+impl Default for BlobShadow {
+    fn default() -> Self {
+        Self {
+            radius: 0.15,
+            opacity: 0.6,
+            max_distance: 1.,
+        }
+    }
+}
+
+impl BlobShadow {
+    /// Create a blob shadow with the given radius
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn to_data(self, global_transform: &GlobalTransform) -> BlobShadowData {
+        BlobShadowData {
+            position_and_radius: Vec3::from(global_transform.0.translation).extend(self.radius),
+            params: Vec4::new(self.opacity, self.max_distance.max(f32::EPSILON), 0., 0.),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use ash::vk;
+use hecs::{Entity, World};
+
+use crate::{
+    components::{Mesh, Panel, Visible},
+    contexts::{RenderContext, XrContext},
+    rendering::material::MaterialFlags,
+};
+
+/// The shape of a [`CompositionLayer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerShape {
+    /// A flat rectangle the size of the panel, centred on the entity and facing along its +Z axis
+    Quad,
+    /// Part of the inside of an upright cylinder, `radius` meters along the entity's +Z axis. The
+    /// width of the panel is wrapped around the cylinder, with its middle at the entity.
+    ///
+    /// Requires `XR_KHR_composition_layer_cylinder`, and falls back to a quad without it.
+    Cylinder {
+        /// The radius of the cylinder, in meters
+        radius: f32,
+    },
+    /// Part of the inside of a sphere centred on the entity, with the panel's texture mapped onto
+    /// it equirectangularly. Useful for 360 degree images and video.
+    ///
+    /// Requires `XR_KHR_composition_layer_equirect2`, and falls back to a quad without it.
+    Equirect {
+        /// The radius of the sphere, in meters. Zero places it infinitely far away.
+        radius: f32,
+        /// How far around the sphere the texture wraps, in radians
+        central_horizontal_angle: f32,
+        /// How far above the horizon the top of the texture is, in radians
+        upper_vertical_angle: f32,
+        /// How far below the horizon the bottom of the texture is, in radians. Usually negative.
+        lower_vertical_angle: f32,
+    },
+}
+
+/// Where a [`CompositionLayer`] is drawn relative to the scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayerPlacement {
+    /// In front of everything in the scene, whatever its depth
+    #[default]
+    Overlay,
+    /// Behind the scene. The panel's mesh punches a hole in the scene wherever it would have been
+    /// visible, so the layer is hidden by anything in front of the panel.
+    Underlay,
+}
+
+/// Draws an entity's [`Panel`] with the OpenXR compositor, rather than into the scene. The
+/// compositor samples the panel's texture once, straight into the final image, so text and UI
+/// stay much sharper than when they're drawn into the scene and resampled.
+///
+/// Each frame [`crate::systems::composition_layers_system`] copies the panel's texture into the
+/// layer's own swapchain and submits it at the entity's [`crate::components::GlobalTransform`].
+/// Scale is ignored, the size comes from the panel.
+///
+/// Underlays are hidden wherever the panel's mesh would have been hidden, which is only exact for
+/// quads. Transparent objects drawn in front of an underlay are lost.
+///
+/// Basic usage:
+/// ```ignore
+/// let panel = add_ui_panel_to_world(..);
+/// add_composition_layer(
+///     &mut engine.world,
+///     panel,
+///     &mut engine.xr_context,
+///     &mut engine.render_context,
+///     LayerShape::Cylinder { radius: 1.5 },
+///     LayerPlacement::Underlay,
+/// )?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompositionLayer {
+    /// The shape of the layer
+    pub shape: LayerShape,
+    /// Layers with the same placement are drawn from lowest to highest `sort_order`, so higher
+    /// numbers are drawn in front
+    pub sort_order: i32,
+    placement: LayerPlacement,
+    pub(crate) swapchain_index: usize,
+    pub(crate) swapchain_images: Vec<vk::Image>,
+}
+
+impl CompositionLayer {
+    /// Where the layer is drawn relative to the scene. This is fixed when the layer is added, as
+    /// the panel's mesh depends on it.
+    pub fn placement(&self) -> LayerPlacement {
+        self.placement
+    }
+}
+
+/// Convenience function to draw a [`Panel`] entity as a composition layer.
+///
+/// Creates a swapchain for the layer and changes how the panel's mesh is drawn: overlays hide it,
+/// underlays use it to punch a hole in the scene.
+pub fn add_composition_layer(
+    world: &mut World,
+    entity: Entity,
+    xr_context: &mut XrContext,
+    render_context: &mut RenderContext,
+    mut shape: LayerShape,
+    placement: LayerPlacement,
+) -> Result<()> {
+    if !xr_context.supports_layer_shape(&shape) {
+        println!("[HOTHAM_XR] {shape:?} composition layers are not supported, using a quad");
+        shape = LayerShape::Quad;
+    }
+
+    let resolution = world
+        .get::<&Panel>(entity)
+        .map_err(|_| anyhow!("Composition layers can only be added to entities with a Panel"))?
+        .resolution;
+    let (swapchain_index, swapchain_images) = xr_context.create_layer_swapchain(resolution)?;
+
+    match placement {
+        LayerPlacement::Overlay => {
+            let _ = world.remove_one::<Visible>(entity);
+        }
+        LayerPlacement::Underlay => {
+            let mesh = world.get::<&Mesh>(entity)?;
+            punch_holes(&mesh, render_context);
+        }
+    }
+
+    world.insert_one(
+        entity,
+        CompositionLayer {
+            shape,
+            sort_order: 0,
+            placement,
+            swapchain_index,
+            swapchain_images,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Make a mesh's materials cut holes in the scene for underlays to show through.
+fn punch_holes(mesh: &Mesh, render_context: &mut RenderContext) {
+    let mesh_data = render_context.resources.mesh_data.get(mesh.handle).unwrap();
+    let materials = unsafe { render_context.resources.materials_buffer.as_slice_mut() };
+    for primitive in &mesh_data.primitives {
+        materials[primitive.material_id as usize].packed_flags_and_base_texture_id |=
+            MaterialFlags::HOLE_PUNCH.bits();
+    }
+}
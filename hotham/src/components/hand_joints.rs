@@ -0,0 +1,164 @@
+use hecs::{Entity, World};
+
+use crate::{
+    components::{Info, Parent},
+    contexts::input_context::HAND_JOINT_COUNT,
+};
+
+/// The (finger, bone) names of each joint, in `XR_EXT_hand_tracking` order.
+static JOINT_NAMES: [(&str, &str); HAND_JOINT_COUNT] = [
+    ("", "palm"),
+    ("", "wrist"),
+    ("thumb", "metacarpal"),
+    ("thumb", "proximal"),
+    ("thumb", "distal"),
+    ("thumb", "tip"),
+    ("index", "metacarpal"),
+    ("index", "proximal"),
+    ("index", "intermediate"),
+    ("index", "distal"),
+    ("index", "tip"),
+    ("middle", "metacarpal"),
+    ("middle", "proximal"),
+    ("middle", "intermediate"),
+    ("middle", "distal"),
+    ("middle", "tip"),
+    ("ring", "metacarpal"),
+    ("ring", "proximal"),
+    ("ring", "intermediate"),
+    ("ring", "distal"),
+    ("ring", "tip"),
+    ("little", "metacarpal"),
+    ("little", "proximal"),
+    ("little", "intermediate"),
+    ("little", "distal"),
+    ("little", "tip"),
+];
+
+/// A component added to a `Hand` to map each joint reported by `XR_EXT_hand_tracking` to an
+/// entity in the hand's skeleton. When the hand is tracked, `hands_system` will pose these
+/// entities directly from the tracked joints instead of animating the hand with the grip value.
+///
+/// Joints in the hand model are matched by name, using either the OpenXR joint names
+/// (eg. `index_proximal`) or the WebXR joint names (eg. `index-finger-phalanx-proximal`).
+/// Any prefix (eg. `b_l_`) is ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HandJoints {
+    /// The entity for each joint, indexed by `xr::HandJoint`
+    pub joints: [Option<Entity>; HAND_JOINT_COUNT],
+    /// Was this hand being driven by hand tracking this frame?
+    pub is_tracked: bool,
+}
+
+impl HandJoints {
+    /// Find the joints of the model rooted at `root` by name. Returns `None` if there are none.
+    pub fn from_model(world: &World, root: Entity) -> Option<HandJoints> {
+        let mut hand_joints = HandJoints::default();
+
+        for (entity, info) in world.query::<&Info>().iter() {
+            if !is_descendant_of(world, entity, root) {
+                continue;
+            }
+
+            if let Some(joint) = JOINT_NAMES
+                .iter()
+                .position(|(finger, bone)| joint_name_matches(&info.name, finger, bone))
+            {
+                hand_joints.joints[joint] = Some(entity);
+            }
+        }
+
+        if hand_joints.joints.iter().all(Option::is_none) {
+            return None;
+        }
+
+        Some(hand_joints)
+    }
+}
+
+fn joint_name_matches(name: &str, finger: &str, bone: &str) -> bool {
+    let name = name.to_lowercase().replace(['-', ' ', '.', ':'], "_");
+    let finger_aliases: &[&str] = match finger {
+        "" => &[""],
+        "thumb" => &["thumb_"],
+        "little" => &["little_", "little_finger_", "pinky_", "pinky_finger_"],
+        "index" => &["index_", "index_finger_"],
+        "middle" => &["middle_", "middle_finger_"],
+        _ => &["ring_", "ring_finger_"],
+    };
+    let bone_aliases: &[&str] = match bone {
+        "proximal" | "intermediate" | "distal" => &["", "phalanx_"],
+        _ => &[""],
+    };
+
+    finger_aliases.iter().any(|finger_alias| {
+        bone_aliases.iter().any(|bone_alias| {
+            let suffix = format!("{finger_alias}{bone_alias}{bone}");
+            name == suffix || name.ends_with(&format!("_{suffix}"))
+        })
+    })
+}
+
+fn is_descendant_of(world: &World, entity: Entity, ancestor: Entity) -> bool {
+    let mut current = entity;
+    while let Ok(parent) = world.get::<&Parent>(current) {
+        if parent.0 == ancestor {
+            return true;
+        }
+        current = parent.0;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_joint_name_matches() {
+        assert!(joint_name_matches("b_l_wrist", "", "wrist"));
+        assert!(joint_name_matches("index_proximal", "index", "proximal"));
+        assert!(joint_name_matches(
+            "index-finger-phalanx-proximal",
+            "index",
+            "proximal"
+        ));
+        assert!(joint_name_matches("Hand.Pinky_Tip", "little", "tip"));
+        assert!(!joint_name_matches("thumb_tip", "index", "tip"));
+        assert!(!joint_name_matches("index_distal", "index", "proximal"));
+    }
+
+    #[test]
+    pub fn test_from_model() {
+        let mut world = World::new();
+        let root = world.spawn((Info {
+            name: "Left Hand".into(),
+            node_id: 0,
+        },));
+        let wrist = world.spawn((
+            Info {
+                name: "wrist".into(),
+                node_id: 1,
+            },
+            Parent(root),
+        ));
+        let index_tip = world.spawn((
+            Info {
+                name: "index-finger-tip".into(),
+                node_id: 2,
+            },
+            Parent(wrist),
+        ));
+
+        // Not part of this hand
+        world.spawn((Info {
+            name: "thumb_tip".into(),
+            node_id: 3,
+        },));
+
+        let hand_joints = HandJoints::from_model(&world, root).unwrap();
+        assert_eq!(hand_joints.joints[1], Some(wrist));
+        assert_eq!(hand_joints.joints[10], Some(index_tip));
+        assert_eq!(hand_joints.joints.iter().flatten().count(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+use glam::Vec3;
+
+use crate::{components::GlobalTransform, rendering::shadows::ShadowSettings};
+
+/// The kind of light, based on the KHR_lights_punctual extension:
+/// https://github.com/KhronosGroup/glTF/tree/master/extensions/2.0/Khronos/KHR_lights_punctual
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    /// Shines in the direction the entity is facing (-Z) from infinitely far away, like the sun
+    Directional,
+    /// Shines in all directions from the entity's position
+    Point,
+    /// Shines in a cone from the entity's position, in the direction the entity is facing (-Z)
+    Spot {
+        /// The angle, in radians, at which the light starts to fall off
+        inner_cone_angle: f32,
+        /// The angle, in radians, at which the light has completely fallen off
+        outer_cone_angle: f32,
+    },
+}
+
+/// A light that follows its entity's [`GlobalTransform`]. Each frame the rendering system picks the
+/// [`crate::rendering::light::MAX_LIGHTS`] most relevant lights for the player's view: directional
+/// lights first, then point and spot lights that can be seen, brightest and closest first.
+///
+/// Parent the entity to something to make the light move with it, eg. a torch held in a hand.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::{Light, LocalTransform, GlobalTransform};
+/// let torch = Light::point([1., 0.8, 0.6].into(), 10., Some(5.));
+/// world.spawn((torch, LocalTransform::default(), GlobalTransform::default()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    /// The kind of light
+    pub kind: LightKind,
+    /// RGB value for the color of the light in linear space
+    pub color: Vec3,
+    /// Brightness of light in the type specific units.
+    /// Point and spot lights use luminous intensity in candela (lm/sr), while directional lights use
+    /// illuminance in lux (lm/m2)
+    pub intensity: f32,
+    /// How far the light reaches, in metres. `None` is infinite. Ignored for directional lights.
+    pub range: Option<f32>,
+    /// Shadow settings for this light. Only directional and spot lights cast shadows.
+    pub shadows: ShadowSettings,
+}
+
+impl Light {
+    /// Create a directional light
+    pub fn directional(color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            range: None,
+            shadows: Default::default(),
+        }
+    }
+
+    /// Create a point light
+    pub fn point(color: Vec3, intensity: f32, range: Option<f32>) -> Self {
+        Self {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            range,
+            shadows: Default::default(),
+        }
+    }
+
+    /// Create a spot light
+    pub fn spot(
+        color: Vec3,
+        intensity: f32,
+        range: Option<f32>,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            color,
+            intensity,
+            range,
+            shadows: Default::default(),
+        }
+    }
+
+    /// Cast shadows from this light with the given settings
+    pub fn with_shadows(self, shadows: ShadowSettings) -> Self {
+        Self { shadows, ..self }
+    }
+
+    pub(crate) fn from_gltf(light: &gltf::khr_lights_punctual::Light) -> Self {
+        let color = light.color().into();
+        let intensity = light.intensity();
+        let range = light.range();
+
+        match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => Light::directional(color, intensity),
+            gltf::khr_lights_punctual::Kind::Point => Light::point(color, intensity, range),
+            gltf::khr_lights_punctual::Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => Light::spot(color, intensity, range, inner_cone_angle, outer_cone_angle),
+        }
+    }
+
+    /// Convert this light into the representation used by the fragment shader, in global space.
+    pub(crate) fn to_scene_light(
+        self,
+        global_transform: &GlobalTransform,
+    ) -> crate::rendering::light::Light {
+        let (_, rotation, translation) = global_transform.to_scale_rotation_translation();
+        let direction = rotation * Vec3::NEG_Z;
+        // A falloff of zero means infinite range.
+        let range = self.range.unwrap_or(0.);
+
+        match self.kind {
+            LightKind::Directional => crate::rendering::light::Light::new_directional(
+                direction,
+                self.intensity,
+                self.color,
+            ),
+            LightKind::Point => crate::rendering::light::Light::new_point(
+                translation,
+                range,
+                self.intensity,
+                self.color,
+            ),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => crate::rendering::light::Light::new_spotlight(
+                direction,
+                range,
+                self.intensity,
+                self.color,
+                translation,
+                inner_cone_angle,
+                outer_cone_angle,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::light::{LIGHT_TYPE_POINT, LIGHT_TYPE_SPOT};
+    use approx::assert_relative_eq;
+    use glam::{Affine3A, Quat};
+
+    #[test]
+    fn test_to_scene_light_follows_transform() {
+        let global_transform = GlobalTransform(Affine3A::from_rotation_translation(
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            [1., 2., 3.].into(),
+        ));
+
+        let spot = Light::spot(Vec3::ONE, 5., Some(2.), 0., 0.5).to_scene_light(&global_transform);
+        assert_eq!(spot.light_type, LIGHT_TYPE_SPOT);
+        assert_relative_eq!(spot.position, Vec3::new(1., 2., 3.));
+        assert_relative_eq!(spot.direction, Vec3::NEG_X, epsilon = 1e-6);
+        assert_relative_eq!(spot.falloff, 0.25);
+
+        let point = Light::point(Vec3::ONE, 5., None).to_scene_light(&global_transform);
+        assert_eq!(point.light_type, LIGHT_TYPE_POINT);
+        assert_eq!(point.falloff, 0.);
+    }
+}
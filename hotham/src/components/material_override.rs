@@ -0,0 +1,103 @@
+use glam::{Vec3, Vec4};
+
+use crate::rendering::material::{pack_half2x16, pack_unorm4x8};
+
+/// Changes how an entity's [`crate::components::Mesh`] is drawn, without changing the mesh, which
+/// may be shared with other entities.
+///
+/// The material replaces the glTF material of every primitive in the mesh. Create one with
+/// [`crate::rendering::resources::Resources::add_material`]. The tint and emission are applied on
+/// top of whichever material is used, so many entities can share a material but still be drawn in
+/// different colors.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::MaterialOverride;
+/// // Flash the cube red when it's hit..
+/// world.insert_one(cube, MaterialOverride::tint([1., 0.2, 0.2, 1.].into()))?;
+///
+/// // ..or give it a different material altogether.
+/// let gold = render_context.resources.add_material(&MaterialDesc {
+///     base_color_factor: [1., 0.8, 0.3, 1.].into(),
+///     metallic_factor: 1.,
+///     roughness_factor: 0.3,
+///     ..Default::default()
+/// })?;
+/// world.insert_one(cube, MaterialOverride::material(gold))?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialOverride {
+    /// The ID of the material drawn instead of the mesh's own materials, if any
+    pub material_id: Option<u32>,
+    /// Linear RGBA color multiplied with the material's base color, from 0 to 1
+    pub tint: Vec4,
+    /// Linear RGB color added to the material's emission, which can be brighter than 1
+    pub emissive: Vec3,
+}
+
+impl Default for MaterialOverride {
+    fn default() -> Self {
+        Self {
+            material_id: None,
+            tint: Vec4::ONE,
+            emissive: Vec3::ZERO,
+        }
+    }
+}
+
+impl MaterialOverride {
+    /// Draw the mesh with a different material
+    pub fn material(material_id: u32) -> Self {
+        Self {
+            material_id: Some(material_id),
+            ..Default::default()
+        }
+    }
+
+    /// Multiply the mesh's base color by `tint`
+    pub fn tint(tint: Vec4) -> Self {
+        Self {
+            tint,
+            ..Default::default()
+        }
+    }
+
+    /// Make the mesh glow with `emissive`
+    pub fn emissive(emissive: Vec3) -> Self {
+        Self {
+            emissive,
+            ..Default::default()
+        }
+    }
+
+    /// The tint, packed as expected by the shader
+    pub(crate) fn packed_tint(&self) -> u32 {
+        pack_unorm4x8(&self.tint.into())
+    }
+
+    /// The emission, packed as expected by the shader
+    pub(crate) fn packed_emissive(&self) -> [u32; 2] {
+        let emissive = self.emissive;
+        [
+            pack_half2x16(emissive.x, emissive.y),
+            pack_half2x16(emissive.z, 0.),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::resources::DrawData;
+
+    #[test]
+    fn test_default_material_override_changes_nothing() {
+        let material_override = MaterialOverride::default();
+        let draw_data = DrawData::default();
+        assert_eq!(material_override.packed_tint(), draw_data.tint);
+        assert_eq!(
+            material_override.packed_emissive(),
+            draw_data.packed_emissive
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use glam::{Vec3, Vec4};
+
+use crate::components::Mesh;
+
+/// The screen size below which the first lower level of detail is used, if none is given. Each
+/// level after that kicks in at half the size of the one before.
+pub const DEFAULT_LOD_SCREEN_SIZE: f32 = 0.25;
+
+/// A lower level of detail for a [`Mesh`].
+#[derive(Debug, Clone)]
+pub struct MeshLod {
+    /// The mesh to draw at this level of detail
+    pub mesh: Mesh,
+    /// Use this level when the entity's bounding sphere covers less than this fraction of the
+    /// height of the player's view
+    pub screen_size: f32,
+}
+
+/// Lower levels of detail for an entity's [`Mesh`]. Each frame the rendering system projects the
+/// mesh's bounding sphere into the player's view and draws the least detailed mesh whose
+/// `screen_size` is still larger than it. The entity's own `Mesh` is used when it's close.
+///
+/// The asset importer adds this component to any node that has nodes named `<name>.LOD1`,
+/// `<name>.LOD2` etc. in the same file, using [`DEFAULT_LOD_SCREEN_SIZE`].
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::{MeshLods, mesh_lods::MeshLod};
+/// let lods = MeshLods {
+///     levels: vec![MeshLod { mesh: low_detail_mesh, screen_size: 0.1 }],
+///     cull_screen_size: 0.01,
+/// };
+/// world.insert_one(entity, lods).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MeshLods {
+    /// The lower levels of detail, from most to least detailed
+    pub levels: Vec<MeshLod>,
+    /// Don't draw the entity at all when it covers less than this fraction of the player's view
+    pub cull_screen_size: f32,
+}
+
+impl MeshLods {
+    /// Create levels of detail from meshes ordered from most to least detailed, using the default
+    /// screen sizes.
+    pub fn from_meshes(meshes: impl IntoIterator<Item = Mesh>) -> Self {
+        let levels = meshes
+            .into_iter()
+            .zip(0..)
+            .map(|(mesh, i)| MeshLod {
+                mesh,
+                screen_size: DEFAULT_LOD_SCREEN_SIZE * 0.5_f32.powi(i),
+            })
+            .collect();
+        Self {
+            levels,
+            cull_screen_size: 0.,
+        }
+    }
+
+    /// Pick the mesh to draw for a given screen size. `None` means the entity shouldn't be drawn.
+    pub(crate) fn select<'a>(&'a self, mesh: &'a Mesh, screen_size: f32) -> Option<&'a Mesh> {
+        let screen_sizes = self.levels.iter().map(|l| l.screen_size);
+        match lod_level(screen_sizes, self.cull_screen_size, screen_size)? {
+            0 => Some(mesh),
+            level => Some(&self.levels[level - 1].mesh),
+        }
+    }
+}
+
+/// Level 0 is the most detailed level, `None` means the object has been culled.
+fn lod_level(
+    screen_sizes: impl Iterator<Item = f32>,
+    cull_screen_size: f32,
+    screen_size: f32,
+) -> Option<usize> {
+    if screen_size < cull_screen_size {
+        return None;
+    }
+    Some(screen_sizes.take_while(|&s| screen_size < s).count())
+}
+
+/// How much of the view's height a bounding sphere in globally oriented stage space covers.
+/// `projection_scale` is `1 / tan(vertical_fov / 2)`.
+pub(crate) fn screen_size(bounding_sphere: Vec4, eye_position: Vec3, projection_scale: f32) -> f32 {
+    let radius = bounding_sphere.w;
+    let distance = bounding_sphere.truncate().distance(eye_position);
+    if distance <= radius {
+        return f32::INFINITY;
+    }
+    radius * projection_scale / distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lod_level() {
+        let screen_sizes = [0.25, 0.125];
+        let level = |screen_size| lod_level(screen_sizes.into_iter(), 0.01, screen_size);
+        assert_eq!(level(0.5), Some(0));
+        assert_eq!(level(0.2), Some(1));
+        assert_eq!(level(0.1), Some(2));
+        assert_eq!(level(0.001), None);
+    }
+
+    #[test]
+    fn test_screen_size() {
+        // A sphere with a radius of 1m, 10m away, with a 90 degree vertical field of view.
+        let sphere = Vec4::new(0., 0., -10., 1.);
+        assert_eq!(screen_size(sphere, Vec3::ZERO, 1.), 0.1);
+        assert_eq!(
+            screen_size(sphere, Vec3::new(0., 0., -10.), 1.),
+            f32::INFINITY
+        );
+    }
+}
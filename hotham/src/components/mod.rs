@@ -1,43 +1,65 @@
 #![allow(missing_docs)]
 pub mod animation_controller;
 pub mod animation_target;
+pub mod blob_shadow;
+pub mod composition_layer;
 pub mod global_transform;
 pub mod grabbable;
 pub mod hand;
+pub mod hand_joints;
 pub mod hmd;
 pub mod info;
 pub mod joint;
+pub mod light;
 pub mod local_transform;
+pub mod material_override;
 pub mod mesh;
+pub mod mesh_lods;
+pub mod occluder;
 pub mod panel;
 pub mod parent;
+pub mod particle_emitter;
 pub mod physics;
 pub mod pointer;
+pub mod render_layers;
+pub mod render_target_camera;
 pub mod root;
 pub mod skin;
 pub mod sound_emitter;
+pub mod spectator;
 pub mod stage;
 pub mod ui_panel;
 pub mod visible;
 
 pub use animation_controller::AnimationController;
 pub use animation_target::AnimationTarget;
+pub use blob_shadow::BlobShadow;
+pub use composition_layer::CompositionLayer;
 pub use global_transform::GlobalTransform;
 pub use grabbable::*;
 pub use hand::Hand;
+pub use hand_joints::HandJoints;
 pub use hmd::HMD;
 pub use info::Info;
 pub use joint::Joint;
+pub use light::{Light, LightKind};
 pub use local_transform::LocalTransform;
+pub use material_override::MaterialOverride;
 pub use mesh::Mesh;
+pub use mesh_lods::MeshLods;
+pub use occluder::Occluder;
 pub use panel::Panel;
 pub use parent::Parent;
+pub use particle_emitter::{Curve, ParticleEmitter};
 pub use physics::collider::Collider;
 pub use physics::RigidBody;
 pub use pointer::Pointer;
+pub use render_layers::RenderLayers;
+pub use render_target_camera::RenderTargetCamera;
 pub use root::Root;
 pub use skin::Skin;
 pub use sound_emitter::SoundEmitter;
+pub use spectator::Spectator;
 pub use stage::Stage;
 pub use ui_panel::UIPanel;
 pub use visible::Visible;
@@ -0,0 +1,13 @@
+/// Marks an entity's [`crate::components::Mesh`] as an occluder: something big and solid, like a
+/// wall, that hides whatever is behind it. Occluders are only used when
+/// [`crate::rendering::occlusion::OcclusionCulling`] is enabled.
+///
+/// The asset importer adds this component to nodes with `.HOTHAM_OCCLUDER` in their name.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::Occluder;
+/// world.insert_one(wall, Occluder {});
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Occluder {}
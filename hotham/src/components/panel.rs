@@ -46,7 +46,7 @@ impl Panel {
     }
 }
 
-fn create_panel_mesh(
+pub(crate) fn create_panel_mesh(
     output_texture: &Texture,
     render_context: &mut RenderContext,
     world_size: Vec2,
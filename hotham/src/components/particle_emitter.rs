@@ -0,0 +1,234 @@
+use std::ops::{Add, Mul};
+
+use glam::{Affine3A, Vec3, Vec4};
+
+/// A value that changes over a particle's life, from 0 when it's born to 1 when it dies. Values
+/// between keys are linearly interpolated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve<T> {
+    keys: Vec<(f32, T)>,
+}
+
+impl<T> Curve<T>
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    /// A curve through each `(time, value)` key. Panics if there are no keys.
+    pub fn new(mut keys: Vec<(f32, T)>) -> Self {
+        assert!(!keys.is_empty(), "A curve needs at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    /// The same value for the whole of a particle's life
+    pub fn constant(value: T) -> Self {
+        Self::new(vec![(0., value)])
+    }
+
+    /// From `start` when a particle is born to `end` when it dies
+    pub fn linear(start: T, end: T) -> Self {
+        Self::new(vec![(0., start), (1., end)])
+    }
+
+    /// The value at `time`, between 0 and 1
+    pub fn sample(&self, time: f32) -> T {
+        let next = self.keys.partition_point(|(key_time, _)| *key_time <= time);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+
+        let (start_time, start) = self.keys[next - 1];
+        let (end_time, end) = self.keys[next];
+        let t = (time - start_time) / (end_time - start_time);
+        start * (1. - t) + end * t
+    }
+}
+
+/// A single particle, in global space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    /// Position
+    pub position: Vec3,
+    /// Velocity, in metres per second
+    pub velocity: Vec3,
+    /// How long ago the particle was born, in seconds
+    pub age: f32,
+}
+
+/// Emits particles from its entity's [`crate::components::GlobalTransform`], for hits, sparks,
+/// smoke and magic. Particles are simulated on the CPU by [`crate::systems::particles_system`],
+/// and drawn as billboards facing each eye, after everything else that's transparent.
+///
+/// Like meshes, emitters are only drawn when they're [`crate::components::Visible`].
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::{particle_emitter::Curve, ParticleEmitter};
+/// let sparks = ParticleEmitter {
+///     emitting: false,
+///     velocity_spread: 2.,
+///     color_over_life: Curve::linear([1., 0.6, 0.1, 1.].into(), [1., 0.1, 0., 0.].into()),
+///     additive: true,
+///     ..Default::default()
+/// };
+/// world.insert(cube, (sparks, Visible {}))?;
+///
+/// // When the cube is sliced..
+/// world.get::<&mut ParticleEmitter>(cube)?.burst(50);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    /// Whether particles are continuously emitted at `rate`. Bursts are emitted either way.
+    pub emitting: bool,
+    /// How many particles are emitted each second
+    pub rate: f32,
+    /// How long each particle lives, in seconds
+    pub lifetime: f32,
+    /// The velocity of each new particle, in the emitter's space, in metres per second
+    pub velocity: Vec3,
+    /// The most random velocity added to each new particle, in any direction
+    pub velocity_spread: f32,
+    /// Acceleration applied to every particle, in global space
+    pub gravity: Vec3,
+    /// Linear RGBA color over each particle's life
+    pub color_over_life: Curve<Vec4>,
+    /// Width and height over each particle's life, in metres
+    pub size_over_life: Curve<f32>,
+    /// The index of the texture drawn on each particle, eg. `texture.index`. Without one,
+    /// particles are soft, round dots.
+    pub texture_id: Option<u32>,
+    /// Add the particles' color to whatever is behind them instead of blending over it, for
+    /// sparks, fire and magic.
+    pub additive: bool,
+    /// The most particles alive at once. Any more aren't emitted.
+    pub max_particles: usize,
+    particles: Vec<Particle>,
+    spawn_accumulator: f32,
+    pending_burst: usize,
+    random_state: u32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            emitting: true,
+            rate: 20.,
+            lifetime: 1.,
+            velocity: Vec3::Y,
+            velocity_spread: 0.5,
+            gravity: [0., -9.81, 0.].into(),
+            color_over_life: Curve::constant(Vec4::ONE),
+            size_over_life: Curve::linear(0.05, 0.),
+            texture_id: None,
+            additive: false,
+            max_particles: 1000,
+            particles: Vec::new(),
+            spawn_accumulator: 0.,
+            pending_burst: 0,
+            random_state: 0x9E37_79B9,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    /// Emit `count` particles at once, the next time the emitter is updated.
+    pub fn burst(&mut self, count: usize) {
+        self.pending_burst += count;
+    }
+
+    /// The particles that are alive
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Age, move and emit particles. Particles that have outlived `lifetime` are removed.
+    pub(crate) fn update(&mut self, global_from_emitter: &Affine3A, delta_time: f32) {
+        let lifetime = self.lifetime;
+        self.particles.retain_mut(|particle| {
+            particle.age += delta_time;
+            particle.velocity += self.gravity * delta_time;
+            particle.position += particle.velocity * delta_time;
+            particle.age < lifetime
+        });
+
+        let mut count = std::mem::take(&mut self.pending_burst);
+        if self.emitting {
+            self.spawn_accumulator += self.rate * delta_time;
+            let whole = self.spawn_accumulator.floor();
+            self.spawn_accumulator -= whole;
+            count += whole as usize;
+        }
+        count = count.min(self.max_particles.saturating_sub(self.particles.len()));
+
+        let position = global_from_emitter.transform_point3(Vec3::ZERO);
+        let velocity = global_from_emitter.transform_vector3(self.velocity);
+        for _ in 0..count {
+            let spread = self.random_in_unit_sphere() * self.velocity_spread;
+            self.particles.push(Particle {
+                position,
+                velocity: velocity + spread,
+                age: 0.,
+            });
+        }
+    }
+
+    /// The color and size of a particle, for its age
+    pub(crate) fn appearance(&self, particle: &Particle) -> (Vec4, f32) {
+        let time = particle.age / self.lifetime.max(f32::EPSILON);
+        (
+            self.color_over_life.sample(time),
+            self.size_over_life.sample(time),
+        )
+    }
+
+    /// A random point inside a sphere with a radius of 1
+    fn random_in_unit_sphere(&mut self) -> Vec3 {
+        loop {
+            let point = Vec3::new(self.random(), self.random(), self.random()) * 2. - Vec3::ONE;
+            if point.length_squared() <= 1. {
+                return point;
+            }
+        }
+    }
+
+    /// A random number between 0 and 1, from a xorshift generator
+    fn random(&mut self) -> f32 {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random_state = x;
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_curve() {
+        let curve = Curve::new(vec![(1., 0.), (0., 1.), (0.5, 3.)]);
+        assert_relative_eq!(curve.sample(0.), 1.);
+        assert_relative_eq!(curve.sample(0.25), 2.);
+        assert_relative_eq!(curve.sample(0.75), 1.5);
+
+        // Before the first key and after the last, the curve is flat.
+        assert_relative_eq!(curve.sample(-1.), 1.);
+        assert_relative_eq!(curve.sample(2.), 0.);
+        assert_relative_eq!(Curve::constant(4.).sample(0.5), 4.);
+    }
+
+    #[test]
+    fn test_random_in_unit_sphere() {
+        let mut emitter = ParticleEmitter::default();
+        for _ in 0..1000 {
+            let point = emitter.random_in_unit_sphere();
+            assert!(point.length() <= 1.);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+/// The render layers an entity's [`crate::components::Mesh`] is on, as a bitmask of up to 32
+/// layers. Each camera - the player's eyes, [`crate::components::RenderTargetCamera`]s and the
+/// [`crate::components::Spectator`] view - only draws meshes on at least one of its layers.
+///
+/// Entities without this component are on [`RenderLayers::DEFAULT`], and every camera draws every
+/// layer unless told otherwise. Layers don't affect shadows: a mesh casts shadows wherever it is
+/// [`crate::components::Visible`].
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::RenderLayers;
+/// const FIRST_PERSON: u8 = 1;
+/// const LEFT_EYE_ONLY: u8 = 2;
+///
+/// // Only the player sees their own hands..
+/// world.insert_one(hand, RenderLayers::layer(FIRST_PERSON));
+/// // ..and the spectator doesn't.
+/// spectator_camera.layers = RenderLayers::ALL.without(FIRST_PERSON);
+///
+/// // A test pattern drawn into the left eye only, by hiding it from the right.
+/// world.insert_one(test_pattern, RenderLayers::layer(LEFT_EYE_ONLY));
+/// render_context.view_layers[1] = RenderLayers::ALL.without(LEFT_EYE_ONLY);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderLayers(pub u32);
+
+impl RenderLayers {
+    /// Only the first layer, where meshes are by default
+    pub const DEFAULT: Self = Self(1);
+    /// Every layer
+    pub const ALL: Self = Self(u32::MAX);
+    /// No layers at all
+    pub const NONE: Self = Self(0);
+
+    /// Only the given layer, from 0 to 31
+    pub const fn layer(layer: u8) -> Self {
+        Self(1 << layer)
+    }
+
+    /// These layers, and the given one
+    pub const fn with(self, layer: u8) -> Self {
+        Self(self.0 | 1 << layer)
+    }
+
+    /// These layers, without the given one
+    pub const fn without(self, layer: u8) -> Self {
+        Self(self.0 & !(1 << layer))
+    }
+
+    /// Is the given layer one of these?
+    pub const fn contains(self, layer: u8) -> bool {
+        self.0 & 1 << layer != 0
+    }
+
+    /// Do these layers have any in common with `other`? A camera draws a mesh when they do.
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for RenderLayers {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl std::ops::BitOr for RenderLayers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_layers() {
+        let hands = RenderLayers::layer(1);
+        let spectator = RenderLayers::ALL.without(1);
+
+        assert!(hands.contains(1));
+        assert!(!hands.contains(0));
+        assert!(!spectator.intersects(hands));
+        assert!(spectator.intersects(RenderLayers::DEFAULT));
+        assert!(RenderLayers::ALL.intersects(hands));
+        assert!(!RenderLayers::NONE.intersects(RenderLayers::ALL));
+        assert_eq!(RenderLayers::DEFAULT.with(1), RenderLayers::DEFAULT | hands);
+        assert_eq!(RenderLayers::default(), RenderLayers::layer(0));
+    }
+}
@@ -0,0 +1,165 @@
+use ash::vk;
+use glam::{Affine3A, Mat4, Vec2};
+
+use crate::{
+    components::{panel::create_panel_mesh, Mesh, RenderLayers},
+    contexts::{render_context::NEAR_PLANE, RenderContext, VulkanContext},
+    hotham_error::HothamError,
+    rendering::{camera::Frustum, image::Image, texture::Texture},
+};
+
+/// A camera that draws the world into a [`Texture`] instead of the player's eyes, for mirrors,
+/// portals, scopes and in-world screens. It looks down the -Z axis of its entity's
+/// [`crate::components::GlobalTransform`].
+///
+/// Up to [`crate::rendering::render_target::MAX_RENDER_TARGET_CAMERAS`] of these are drawn each
+/// frame, before the player's view, without MSAA or post-processing. They're still tone mapped
+/// like the player's view.
+///
+/// The camera's images aren't freed when it's dropped, as that needs the GPU to have finished
+/// with them. Call [`RenderTargetCamera::destroy`] once it has, eg. after `device_wait_idle`.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::{GlobalTransform, LocalTransform, RenderTargetCamera};
+/// let resolution = vk::Extent2D { width: 512, height: 512 };
+/// let camera =
+///     RenderTargetCamera::new(vulkan_context, render_context, resolution, 60_f32.to_radians())?;
+/// let screen = camera.create_screen_mesh(render_context, [0.5, 0.5].into());
+/// world.spawn((camera, LocalTransform::default(), GlobalTransform::default()));
+/// ```
+pub struct RenderTargetCamera {
+    /// The camera's field of view
+    pub frustum: Frustum,
+    /// Distance to the near clipping plane, in metres
+    pub near: f32,
+    /// The render layers this camera draws. Defaults to all of them.
+    pub layers: RenderLayers,
+    /// The texture the camera draws into. Use its index in a material to show it on a mesh.
+    pub texture: Texture,
+    /// The depth buffer the camera draws with
+    pub depth_image: Image,
+    framebuffer: vk::Framebuffer,
+}
+
+impl RenderTargetCamera {
+    /// Create a camera that draws into a new texture of the given resolution, with a vertical
+    /// field of view in radians. The horizontal field of view matches the texture's aspect ratio.
+    pub fn new(
+        vulkan_context: &VulkanContext,
+        render_context: &mut RenderContext,
+        resolution: vk::Extent2D,
+        vertical_fov: f32,
+    ) -> Result<Self, HothamError> {
+        let texture = Texture::empty(vulkan_context, render_context, resolution);
+        let render_targets = &render_context.render_targets;
+        let depth_image = vulkan_context.create_image(
+            render_targets.depth_format,
+            &resolution,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            1,
+            1,
+        )?;
+
+        let attachments = [texture.image.view, depth_image.view];
+        let framebuffer = unsafe {
+            vulkan_context.device.create_framebuffer(
+                &vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_targets.render_pass)
+                    .attachments(&attachments)
+                    .width(resolution.width)
+                    .height(resolution.height)
+                    .layers(1),
+                None,
+            )
+        }?;
+
+        // Clear the texture once so it can be sampled before the camera has drawn anything.
+        let command_buffer = vulkan_context.begin_single_time_commands();
+        unsafe {
+            render_targets.begin_render_pass(
+                &vulkan_context.device,
+                command_buffer,
+                framebuffer,
+                resolution,
+            );
+            vulkan_context.device.cmd_end_render_pass(command_buffer);
+        }
+        vulkan_context.end_single_time_commands(command_buffer);
+
+        let aspect_ratio = resolution.width as f32 / resolution.height as f32;
+        Ok(Self {
+            frustum: symmetric_frustum(vertical_fov, aspect_ratio),
+            near: NEAR_PLANE,
+            layers: RenderLayers::ALL,
+            texture,
+            depth_image,
+            framebuffer,
+        })
+    }
+
+    /// The resolution of the texture the camera draws into
+    pub fn resolution(&self) -> vk::Extent2D {
+        self.texture.image.extent
+    }
+
+    /// Create a flat, unlit mesh of the given size that shows what the camera sees, like a
+    /// security monitor.
+    pub fn create_screen_mesh(&self, render_context: &mut RenderContext, world_size: Vec2) -> Mesh {
+        create_panel_mesh(&self.texture, render_context, world_size)
+    }
+
+    /// The camera's view-projection matrix, given where it is in globally oriented stage space
+    pub(crate) fn view_projection(&self, gos_from_camera: &Affine3A) -> Mat4 {
+        let view_from_gos: Mat4 = gos_from_camera.inverse().into();
+        self.frustum.projection(self.near) * view_from_gos
+    }
+
+    pub(crate) fn framebuffer(&self) -> vk::Framebuffer {
+        self.framebuffer
+    }
+
+    /// Free the camera's texture, depth buffer and framebuffer. Texture indices are never reused,
+    /// so the texture's index is left pointing at nothing.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must have finished every frame that drew with the camera or showed its texture,
+    /// and nothing may use the texture afterwards, eg. despawn its screen meshes first.
+    pub unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_framebuffer(self.framebuffer, None);
+        self.depth_image.destroy(device);
+        self.texture.image.destroy(device);
+    }
+}
+
+/// A frustum with the given vertical field of view, centered on the camera's -Z axis
+fn symmetric_frustum(vertical_fov: f32, aspect_ratio: f32) -> Frustum {
+    let horizontal_angle = ((vertical_fov * 0.5).tan() * aspect_ratio).atan();
+    Frustum {
+        left: -horizontal_angle,
+        right: horizontal_angle,
+        up: vertical_fov * 0.5,
+        down: -vertical_fov * 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_symmetric_frustum() {
+        // A square texture has the same field of view in both directions.
+        let frustum = symmetric_frustum(90_f32.to_radians(), 1.);
+        assert_relative_eq!(frustum.right, 45_f32.to_radians());
+        assert_relative_eq!(frustum.left, -frustum.right);
+        assert_relative_eq!(frustum.up, 45_f32.to_radians());
+
+        // A wide one is wider, in tangent space.
+        let frustum = symmetric_frustum(90_f32.to_radians(), 2.);
+        assert_relative_eq!(frustum.right.tan(), 2.);
+        assert_relative_eq!(frustum.down, -45_f32.to_radians());
+    }
+}
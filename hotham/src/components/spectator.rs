@@ -0,0 +1,77 @@
+use glam::Vec3;
+
+/// Marks a [`crate::components::RenderTargetCamera`] as the spectator view: a smooth, wide-angle,
+/// non-VR view of the game for people watching on a desktop, streams and trailers.
+///
+/// The camera's texture can be written to disk as an image sequence with
+/// [`crate::EngineBuilder::capture_spectator`], or shown on a screen in the world like any other
+/// [`crate::components::RenderTargetCamera`]. Moved by [`crate::systems::spectator_system`].
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::{GlobalTransform, LocalTransform, RenderTargetCamera, Spectator};
+/// let resolution = vk::Extent2D { width: 1920, height: 1080 };
+/// let camera =
+///     RenderTargetCamera::new(vulkan_context, render_context, resolution, 70_f32.to_radians())?;
+/// world.spawn((
+///     camera,
+///     Spectator::third_person(),
+///     LocalTransform::default(),
+///     GlobalTransform::default(),
+/// ));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spectator {
+    /// Where the camera is
+    pub mode: SpectatorMode,
+    /// Roughly how long the camera takes to catch up with the player's head, in seconds. Zero
+    /// follows it exactly.
+    pub smoothing: f32,
+}
+
+/// Where a [`Spectator`] camera is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpectatorMode {
+    /// Stay wherever the entity's [`crate::components::LocalTransform`] puts it
+    Fixed,
+    /// Follow the player's head, kept level so it doesn't roll with it
+    FollowHead {
+        /// Where the camera is relative to the player's head. Zero for a first person view, or
+        /// behind and above for a third person view.
+        offset: Vec3,
+    },
+}
+
+impl Default for Spectator {
+    fn default() -> Self {
+        Self::first_person()
+    }
+}
+
+impl Spectator {
+    /// A stabilized view from the player's eyes
+    pub fn first_person() -> Self {
+        Self {
+            mode: SpectatorMode::FollowHead { offset: Vec3::ZERO },
+            smoothing: 0.2,
+        }
+    }
+
+    /// A view from behind and above the player's head
+    pub fn third_person() -> Self {
+        Self {
+            mode: SpectatorMode::FollowHead {
+                offset: [0., 0.5, 1.5].into(),
+            },
+            smoothing: 0.3,
+        }
+    }
+
+    /// A camera that stays where it's put
+    pub fn fixed() -> Self {
+        Self {
+            mode: SpectatorMode::Fixed,
+            smoothing: 0.,
+        }
+    }
+}
@@ -1,5 +1,266 @@
+use serde::{Deserialize, Serialize};
+
 use crate::components::hand::Handedness;
 
+/// The frequency used when a haptic request doesn't specify one, in Hz.
+pub const DEFAULT_HAPTIC_FREQUENCY: f32 = 400.;
+/// The duration of a vibration requested with `request_haptic_feedback`, in seconds.
+pub const DEFAULT_HAPTIC_DURATION: f32 = 0.1;
+
+/// A single part of a [`HapticEffect`]. All durations are in seconds and all frequencies in Hz.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HapticSegment {
+    /// Vibrate at a constant amplitude
+    Constant {
+        amplitude: f32,
+        frequency: f32,
+        duration: f32,
+    },
+    /// Vibrate with an amplitude that changes linearly from `from` to `to`
+    Ramp {
+        from: f32,
+        to: f32,
+        frequency: f32,
+        duration: f32,
+    },
+    /// Don't vibrate
+    Pause { duration: f32 },
+    /// Play raw PCM samples from -1 to 1. Uses `XR_FB_haptic_pcm` when the runtime supports it,
+    /// otherwise the absolute value of the samples is used as an amplitude envelope.
+    Pcm { samples: Vec<f32>, sample_rate: f32 },
+}
+
+impl HapticSegment {
+    /// How long this segment lasts, in seconds.
+    pub fn duration(&self) -> f32 {
+        match self {
+            HapticSegment::Constant { duration, .. }
+            | HapticSegment::Ramp { duration, .. }
+            | HapticSegment::Pause { duration } => *duration,
+            // Without a valid sample rate the samples can't be played, so the segment is skipped.
+            HapticSegment::Pcm {
+                samples,
+                sample_rate,
+            } if sample_rate.is_finite() && *sample_rate > 0. => samples.len() as f32 / sample_rate,
+            HapticSegment::Pcm { .. } => 0.,
+        }
+    }
+
+    /// The amplitude and frequency of this segment `t` seconds after it started.
+    fn sample(&self, t: f32) -> (f32, f32) {
+        match self {
+            HapticSegment::Constant {
+                amplitude,
+                frequency,
+                ..
+            } => (*amplitude, *frequency),
+            HapticSegment::Ramp {
+                from,
+                to,
+                frequency,
+                duration,
+            } => {
+                let progress = if *duration > 0. {
+                    (t / duration).clamp(0., 1.)
+                } else {
+                    1.
+                };
+                (from + (to - from) * progress, *frequency)
+            }
+            HapticSegment::Pause { .. } => (0., DEFAULT_HAPTIC_FREQUENCY),
+            HapticSegment::Pcm {
+                samples,
+                sample_rate,
+            } => {
+                let index = ((t * sample_rate) as usize).min(samples.len().saturating_sub(1));
+                let amplitude = samples.get(index).map(|s| s.abs()).unwrap_or_default();
+                (amplitude, DEFAULT_HAPTIC_FREQUENCY)
+            }
+        }
+    }
+}
+
+/// A haptic effect made of a sequence of segments, eg. a ramp followed by a series of pulses.
+///
+/// Effects can be built in code, or authored as assets in any format supported by `serde`:
+///
+/// ```ignore
+/// let hit = HapticEffect::pulse(1.0, 320., 0.05)
+///     .pause(0.03)
+///     .then(HapticEffect::ramp(0.8, 0., 160., 0.2));
+/// let miss = HapticEffect::pulse(0.3, 80., 0.02).repeat(2);
+/// haptic_context.play(hit, Handedness::Right);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct HapticEffect {
+    /// The segments of this effect, played in order
+    pub segments: Vec<HapticSegment>,
+    /// How many times to repeat the segments after they're played the first time
+    #[serde(default)]
+    pub repeat: u32,
+}
+
+impl HapticEffect {
+    /// A vibration at a constant amplitude.
+    pub fn pulse(amplitude: f32, frequency: f32, duration: f32) -> Self {
+        HapticEffect::from_segment(HapticSegment::Constant {
+            amplitude,
+            frequency,
+            duration,
+        })
+    }
+
+    /// A vibration that changes amplitude linearly from `from` to `to`.
+    pub fn ramp(from: f32, to: f32, frequency: f32, duration: f32) -> Self {
+        HapticEffect::from_segment(HapticSegment::Ramp {
+            from,
+            to,
+            frequency,
+            duration,
+        })
+    }
+
+    /// Raw PCM samples, played at `sample_rate` samples per second. If the sample rate isn't
+    /// positive, the samples are skipped.
+    pub fn pcm(samples: Vec<f32>, sample_rate: f32) -> Self {
+        HapticEffect::from_segment(HapticSegment::Pcm {
+            samples,
+            sample_rate,
+        })
+    }
+
+    fn from_segment(segment: HapticSegment) -> Self {
+        HapticEffect {
+            segments: vec![segment],
+            repeat: 0,
+        }
+    }
+
+    /// Play `next` after this effect has finished.
+    pub fn then(self, next: HapticEffect) -> Self {
+        let mut segments = self.into_segments();
+        segments.extend(next.into_segments());
+        HapticEffect {
+            segments,
+            repeat: 0,
+        }
+    }
+
+    /// Wait for `duration` seconds after this effect has finished.
+    pub fn pause(self, duration: f32) -> Self {
+        self.then(HapticEffect::from_segment(HapticSegment::Pause {
+            duration,
+        }))
+    }
+
+    /// Repeat this effect `count` more times.
+    pub fn repeat(mut self, count: u32) -> Self {
+        self.repeat = count;
+        self
+    }
+
+    /// How long this effect lasts, including repeats, in seconds.
+    pub fn duration(&self) -> f32 {
+        let once: f32 = self.segments.iter().map(HapticSegment::duration).sum();
+        once * (self.repeat + 1) as f32
+    }
+
+    fn into_segments(self) -> Vec<HapticSegment> {
+        let count = self.segments.len() * (self.repeat as usize + 1);
+        self.segments.into_iter().cycle().take(count).collect()
+    }
+
+    /// Find the repeat and segment that is playing `t` seconds into the effect, and how far into
+    /// the segment we are.
+    fn segment_at(&self, t: f32) -> Option<(u32, usize, f32)> {
+        let once = self.duration() / (self.repeat + 1) as f32;
+        if t >= self.duration() || once <= 0. {
+            return None;
+        }
+
+        let iteration = (t / once) as u32;
+        let mut t = t % once;
+        for (index, segment) in self.segments.iter().enumerate() {
+            let duration = segment.duration();
+            if t < duration {
+                return Some((iteration, index, t));
+            }
+            t -= duration;
+        }
+        None
+    }
+}
+
+/// A handle to an effect started with [`HapticContext::play`], used to stop it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HapticHandle(u64);
+
+/// What `haptics_system` should send to a controller this frame.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HapticCommand {
+    /// Apply a vibration, replacing any vibration currently playing
+    Vibrate {
+        amplitude: f32,
+        frequency: f32,
+        duration: f32,
+    },
+    /// Send PCM samples. `haptics_system` reports how many were consumed with
+    /// `HapticContext::pcm_samples_consumed`.
+    Pcm {
+        handle: HapticHandle,
+        samples: Vec<f32>,
+        sample_rate: f32,
+        append: bool,
+    },
+    /// Stop any vibration currently playing
+    Stop,
+}
+
+#[derive(Clone, Debug)]
+struct PlayingEffect {
+    handle: HapticHandle,
+    handedness: Handedness,
+    effect: HapticEffect,
+    elapsed: f32,
+    /// The segment of any PCM data sent to the runtime, and how many samples it has consumed
+    pcm_sent: Option<((u32, usize), usize)>,
+}
+
+impl PlayingEffect {
+    fn push_pcm_commands(&mut self, segment: (u32, usize), commands: &mut Vec<HapticCommand>) {
+        let (samples, sample_rate) = match &self.effect.segments[segment.1] {
+            HapticSegment::Pcm {
+                samples,
+                sample_rate,
+            } => (samples, *sample_rate),
+            _ => return,
+        };
+
+        match self.pcm_sent {
+            Some((sent, consumed)) if sent == segment => {
+                // The runtime didn't have room for all the samples, so send the rest.
+                if consumed < samples.len() {
+                    commands.push(HapticCommand::Pcm {
+                        handle: self.handle,
+                        samples: samples[consumed..].to_vec(),
+                        sample_rate,
+                        append: true,
+                    });
+                }
+            }
+            _ => {
+                commands.push(HapticCommand::Pcm {
+                    handle: self.handle,
+                    samples: samples.clone(),
+                    sample_rate,
+                    append: false,
+                });
+                self.pcm_sent = Some((segment, 0));
+            }
+        }
+    }
+}
+
 /// Wrapper around XR Haptics
 #[derive(Clone, Debug, Default)]
 pub struct HapticContext {
@@ -7,6 +268,9 @@ pub struct HapticContext {
     pub left_hand_amplitude_this_frame: f32,
     /// Haptics that should be applied to the right hand
     pub right_hand_amplitude_this_frame: f32,
+    playing: Vec<PlayingEffect>,
+    stop_requested: [bool; 2],
+    next_handle: u64,
 }
 
 impl HapticContext {
@@ -25,4 +289,256 @@ impl HapticContext {
             }
         }
     }
+
+    /// Request a single vibration with a given amplitude, frequency (Hz) and duration (seconds).
+    pub fn request_vibration(
+        &mut self,
+        amplitude: f32,
+        frequency: f32,
+        duration: f32,
+        handedness: Handedness,
+    ) -> HapticHandle {
+        self.play(
+            HapticEffect::pulse(amplitude, frequency, duration),
+            handedness,
+        )
+    }
+
+    /// Start playing an effect. If several effects are playing on the same hand, the strongest
+    /// one wins.
+    pub fn play(&mut self, effect: HapticEffect, handedness: Handedness) -> HapticHandle {
+        let handle = HapticHandle(self.next_handle);
+        self.next_handle += 1;
+        self.playing.push(PlayingEffect {
+            handle,
+            handedness,
+            effect,
+            elapsed: 0.,
+            pcm_sent: None,
+        });
+        handle
+    }
+
+    /// Is this effect still playing?
+    pub fn is_playing(&self, handle: HapticHandle) -> bool {
+        self.playing.iter().any(|p| p.handle == handle)
+    }
+
+    /// Stop an effect. Any vibration on that hand is cancelled immediately.
+    pub fn stop(&mut self, handle: HapticHandle) {
+        if let Some(index) = self.playing.iter().position(|p| p.handle == handle) {
+            let playing = self.playing.remove(index);
+            self.stop_requested[playing.handedness as usize] = true;
+        }
+    }
+
+    /// Stop all effects and vibrations on a hand.
+    pub fn stop_all(&mut self, handedness: Handedness) {
+        self.playing.retain(|p| p.handedness != handedness);
+        self.stop_requested[handedness as usize] = true;
+        match handedness {
+            Handedness::Left => self.left_hand_amplitude_this_frame = 0.,
+            Handedness::Right => self.right_hand_amplitude_this_frame = 0.,
+        }
+    }
+
+    /// Work out what should be sent to each controller this frame, then advance every effect by
+    /// `delta_time` seconds. Returns the commands for the [left, right] hands.
+    pub(crate) fn tick(&mut self, delta_time: f32, pcm_supported: bool) -> [Vec<HapticCommand>; 2] {
+        let mut commands: [Vec<HapticCommand>; 2] = Default::default();
+
+        for handedness in [Handedness::Left, Handedness::Right] {
+            let commands = &mut commands[handedness as usize];
+            if std::mem::take(&mut self.stop_requested[handedness as usize]) {
+                commands.push(HapticCommand::Stop);
+            }
+
+            let amplitude_this_frame = match handedness {
+                Handedness::Left => std::mem::take(&mut self.left_hand_amplitude_this_frame),
+                Handedness::Right => std::mem::take(&mut self.right_hand_amplitude_this_frame),
+            };
+            let mut strongest = (amplitude_this_frame > 0.).then(|| HapticCommand::Vibrate {
+                amplitude: amplitude_this_frame,
+                frequency: DEFAULT_HAPTIC_FREQUENCY,
+                duration: DEFAULT_HAPTIC_DURATION,
+            });
+            let mut playing_pcm = false;
+
+            for playing in self.playing.iter_mut() {
+                if playing.handedness != handedness {
+                    continue;
+                }
+                let (iteration, index, t) = match playing.effect.segment_at(playing.elapsed) {
+                    Some(segment) => segment,
+                    None => continue,
+                };
+                let segment = &playing.effect.segments[index];
+
+                if pcm_supported && matches!(segment, HapticSegment::Pcm { .. }) {
+                    playing_pcm = true;
+                    playing.push_pcm_commands((iteration, index), commands);
+                    continue;
+                }
+
+                let (amplitude, frequency) = segment.sample(t);
+                if amplitude <= 0. {
+                    continue;
+                }
+                let is_stronger = match &strongest {
+                    Some(HapticCommand::Vibrate { amplitude: a, .. }) => amplitude > *a,
+                    _ => true,
+                };
+                if is_stronger {
+                    // Constant segments play for exactly as long as they were asked to. Anything
+                    // else is kept going until the next frame, when it will be resampled, but
+                    // never past the end of the segment.
+                    let remaining = segment.duration() - t;
+                    let duration = match segment {
+                        HapticSegment::Constant { .. } => remaining,
+                        _ => (delta_time * 2.).min(remaining),
+                    };
+                    strongest = Some(HapticCommand::Vibrate {
+                        amplitude,
+                        frequency,
+                        duration,
+                    });
+                }
+            }
+
+            // Vibrating would interrupt any PCM playback on this hand.
+            if let (Some(vibration), false) = (strongest, playing_pcm) {
+                commands.push(vibration);
+            }
+        }
+
+        for playing in self.playing.iter_mut() {
+            playing.elapsed += delta_time;
+        }
+        self.playing.retain(|p| p.elapsed < p.effect.duration());
+
+        commands
+    }
+
+    /// Record how many PCM samples the runtime accepted from a `HapticCommand::Pcm`. The rest
+    /// will be sent on the next frame.
+    pub(crate) fn pcm_samples_consumed(&mut self, handle: HapticHandle, consumed: usize) {
+        if let Some(playing) = self.playing.iter_mut().find(|p| p.handle == handle) {
+            if let Some((segment, offset)) = playing.pcm_sent {
+                playing.pcm_sent = Some((segment, offset + consumed));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    pub fn test_effect_sampling() {
+        let effect = HapticEffect::pulse(1., 320., 0.1)
+            .pause(0.1)
+            .then(HapticEffect::ramp(1., 0., 160., 0.2))
+            .repeat(1);
+        assert_relative_eq!(effect.duration(), 0.8);
+
+        let sample = |t| {
+            let (_, index, t) = effect.segment_at(t)?;
+            Some(effect.segments[index].sample(t))
+        };
+        assert_eq!(sample(0.05), Some((1., 320.)));
+        assert_eq!(sample(0.15).unwrap().0, 0.);
+        assert_relative_eq!(sample(0.3).unwrap().0, 0.5);
+        assert_eq!(sample(0.45), Some((1., 320.)));
+        assert_eq!(sample(0.85), None);
+    }
+
+    #[test]
+    pub fn test_tick() {
+        let mut haptic_context = HapticContext::default();
+
+        // Legacy requests still produce a default vibration.
+        haptic_context.request_haptic_feedback(0.5, Handedness::Left);
+        let handle = haptic_context.request_vibration(0.8, 200., 0.05, Handedness::Right);
+        let [left, right] = haptic_context.tick(0.02, false);
+        assert_eq!(
+            left,
+            vec![HapticCommand::Vibrate {
+                amplitude: 0.5,
+                frequency: DEFAULT_HAPTIC_FREQUENCY,
+                duration: DEFAULT_HAPTIC_DURATION,
+            }]
+        );
+        assert_eq!(
+            right,
+            vec![HapticCommand::Vibrate {
+                amplitude: 0.8,
+                frequency: 200.,
+                duration: 0.05,
+            }]
+        );
+        assert_eq!(haptic_context.left_hand_amplitude_this_frame, 0.);
+
+        // The effect is still playing, until it's cancelled.
+        assert!(haptic_context.is_playing(handle));
+        haptic_context.stop(handle);
+        assert!(!haptic_context.is_playing(handle));
+        let [left, right] = haptic_context.tick(0.02, false);
+        assert!(left.is_empty());
+        assert_eq!(right, vec![HapticCommand::Stop]);
+
+        // Effects finish on their own.
+        let handle = haptic_context.request_vibration(1., 200., 0.03, Handedness::Left);
+        haptic_context.tick(0.02, false);
+        haptic_context.tick(0.02, false);
+        assert!(!haptic_context.is_playing(handle));
+
+        // Short effects aren't stretched out to the length of a frame.
+        let duration = |commands: &[HapticCommand]| match commands {
+            [HapticCommand::Vibrate { duration, .. }] => *duration,
+            _ => panic!("Expected a single vibration, got {commands:?}"),
+        };
+        haptic_context.request_vibration(1., 200., 0.005, Handedness::Left);
+        let [left, _] = haptic_context.tick(0.014, false);
+        assert_relative_eq!(duration(&left), 0.005);
+        haptic_context.play(HapticEffect::ramp(1., 0.5, 200., 0.01), Handedness::Left);
+        let [left, _] = haptic_context.tick(0.014, false);
+        assert_relative_eq!(duration(&left), 0.01);
+    }
+
+    #[test]
+    pub fn test_pcm() {
+        let mut haptic_context = HapticContext::default();
+        let samples = vec![0.5; 100];
+        let handle = haptic_context.play(HapticEffect::pcm(samples, 1000.), Handedness::Left);
+
+        // Without PCM support, the samples are used as an envelope.
+        let [left, _] = haptic_context.clone().tick(0.01, false);
+        assert!(matches!(left[0], HapticCommand::Vibrate { amplitude, .. } if amplitude == 0.5));
+
+        // With PCM support, they're sent to the runtime until it has consumed them all.
+        let pcm = |command: &HapticCommand| match command {
+            HapticCommand::Pcm {
+                samples, append, ..
+            } => Some((samples.len(), *append)),
+            _ => None,
+        };
+        let [left, _] = haptic_context.tick(0.01, true);
+        assert_eq!(pcm(&left[0]), Some((100, false)));
+        haptic_context.pcm_samples_consumed(handle, 60);
+
+        let [left, _] = haptic_context.tick(0.01, true);
+        assert_eq!(pcm(&left[0]), Some((40, true)));
+        haptic_context.pcm_samples_consumed(handle, 40);
+
+        let [left, _] = haptic_context.tick(0.01, true);
+        assert!(left.is_empty());
+
+        // Samples without a valid sample rate are skipped, rather than playing forever.
+        let effect = HapticEffect::pcm(vec![0.5; 100], 0.).then(HapticEffect::pulse(1., 320., 0.1));
+        assert_relative_eq!(effect.duration(), 0.1);
+        assert_eq!(effect.segment_at(0.).map(|(_, index, _)| index), Some(1));
+        assert_eq!(HapticEffect::pcm(vec![0.5; 100], f32::NAN).duration(), 0.);
+    }
 }
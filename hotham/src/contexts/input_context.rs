@@ -1,5 +1,12 @@
+use std::collections::HashMap;
+
 use crate::{
-    contexts::XrContext,
+    components::hand::Handedness::{self, Left, Right},
+    contexts::{
+        input_recording::{ButtonFlags, ControllerSnapshot, HandSnapshot, InputSnapshot},
+        xr_context::NamedAction,
+        XrContext,
+    },
     util::{affine_from_posef, is_space_valid, lerp_slerp},
     xr,
 };
@@ -44,6 +51,8 @@ pub struct LeftInputContext {
     // pose input
     stage_from_grip: Affine3A,
     stage_from_aim: Affine3A,
+    // hand tracking input
+    hand_tracking: HandTrackingInputContext,
 }
 
 impl LeftInputContext {
@@ -167,6 +176,77 @@ impl LeftInputContext {
     pub fn stage_from_aim(&self) -> Affine3A {
         self.stage_from_aim
     }
+    pub fn hand_tracking(&self) -> &HandTrackingInputContext {
+        &self.hand_tracking
+    }
+    pub(crate) fn hand_tracking_mut(&mut self) -> &mut HandTrackingInputContext {
+        &mut self.hand_tracking
+    }
+
+    fn store_previous(&mut self) {
+        self.x_button_prev = self.x_button;
+        self.y_button_prev = self.y_button;
+        self.menu_button_prev = self.menu_button;
+        self.grip_button_prev = self.grip_button;
+        self.trigger_button_prev = self.trigger_button;
+        self.thumbstick_click_prev = self.thumbstick_click;
+        self.x_touch_prev = self.x_touch;
+        self.y_touch_prev = self.y_touch;
+        self.trigger_touch_prev = self.trigger_touch;
+        self.thumbstick_touch_prev = self.thumbstick_touch;
+        self.thumbrest_touch_prev = self.thumbrest_touch;
+        self.grip_analog_prev = self.grip_analog;
+        self.trigger_analog_prev = self.trigger_analog;
+    }
+
+    fn snapshot(&self) -> ControllerSnapshot {
+        let mut buttons = ButtonFlags::empty();
+        buttons.set(ButtonFlags::PRIMARY, self.x_button);
+        buttons.set(ButtonFlags::SECONDARY, self.y_button);
+        buttons.set(ButtonFlags::MENU, self.menu_button);
+        buttons.set(ButtonFlags::THUMBSTICK_CLICK, self.thumbstick_click);
+        buttons.set(ButtonFlags::PRIMARY_TOUCH, self.x_touch);
+        buttons.set(ButtonFlags::SECONDARY_TOUCH, self.y_touch);
+        buttons.set(ButtonFlags::TRIGGER_TOUCH, self.trigger_touch);
+        buttons.set(ButtonFlags::THUMBSTICK_TOUCH, self.thumbstick_touch);
+        buttons.set(ButtonFlags::THUMBREST_TOUCH, self.thumbrest_touch);
+
+        ControllerSnapshot {
+            buttons,
+            grip_analog: self.grip_analog,
+            trigger_analog: self.trigger_analog,
+            thumbstick_xy: self.thumbstick_xy,
+            linear_velocity: self.linear_velocity,
+            angular_velocity: self.angular_velocity,
+            stage_from_grip: self.stage_from_grip,
+            stage_from_aim: self.stage_from_aim,
+            hand_tracking: self.hand_tracking.snapshot(),
+        }
+    }
+
+    fn apply_snapshot(&mut self, snapshot: &ControllerSnapshot) {
+        self.store_previous();
+        let buttons = snapshot.buttons;
+        self.x_button = buttons.contains(ButtonFlags::PRIMARY);
+        self.y_button = buttons.contains(ButtonFlags::SECONDARY);
+        self.menu_button = buttons.contains(ButtonFlags::MENU);
+        self.thumbstick_click = buttons.contains(ButtonFlags::THUMBSTICK_CLICK);
+        self.x_touch = buttons.contains(ButtonFlags::PRIMARY_TOUCH);
+        self.y_touch = buttons.contains(ButtonFlags::SECONDARY_TOUCH);
+        self.trigger_touch = buttons.contains(ButtonFlags::TRIGGER_TOUCH);
+        self.thumbstick_touch = buttons.contains(ButtonFlags::THUMBSTICK_TOUCH);
+        self.thumbrest_touch = buttons.contains(ButtonFlags::THUMBREST_TOUCH);
+        self.grip_analog = snapshot.grip_analog;
+        self.grip_button = self.grip_analog > 0.1;
+        self.trigger_analog = snapshot.trigger_analog;
+        self.trigger_button = self.trigger_analog > 0.1;
+        self.thumbstick_xy = snapshot.thumbstick_xy;
+        self.linear_velocity = snapshot.linear_velocity;
+        self.angular_velocity = snapshot.angular_velocity;
+        self.stage_from_grip = snapshot.stage_from_grip;
+        self.stage_from_aim = snapshot.stage_from_aim;
+        self.hand_tracking.apply_snapshot(&snapshot.hand_tracking);
+    }
 }
 
 #[derive(Debug, Default)]
@@ -206,6 +286,8 @@ pub struct RightInputContext {
     // pose input
     stage_from_grip: Affine3A,
     stage_from_aim: Affine3A,
+    // hand tracking input
+    hand_tracking: HandTrackingInputContext,
 }
 
 impl RightInputContext {
@@ -320,6 +402,222 @@ impl RightInputContext {
     pub fn stage_from_aim(&self) -> Affine3A {
         self.stage_from_aim
     }
+    pub fn hand_tracking(&self) -> &HandTrackingInputContext {
+        &self.hand_tracking
+    }
+    pub(crate) fn hand_tracking_mut(&mut self) -> &mut HandTrackingInputContext {
+        &mut self.hand_tracking
+    }
+
+    fn store_previous(&mut self) {
+        self.a_button_prev = self.a_button;
+        self.b_button_prev = self.b_button;
+        self.grip_button_prev = self.grip_button;
+        self.trigger_button_prev = self.trigger_button;
+        self.thumbstick_click_prev = self.thumbstick_click;
+        self.a_touch_prev = self.a_touch;
+        self.b_touch_prev = self.b_touch;
+        self.trigger_touch_prev = self.trigger_touch;
+        self.thumbstick_touch_prev = self.thumbstick_touch;
+        self.thumbrest_touch_prev = self.thumbrest_touch;
+        self.grip_analog_prev = self.grip_analog;
+        self.trigger_analog_prev = self.trigger_analog;
+    }
+
+    fn snapshot(&self) -> ControllerSnapshot {
+        let mut buttons = ButtonFlags::empty();
+        buttons.set(ButtonFlags::PRIMARY, self.a_button);
+        buttons.set(ButtonFlags::SECONDARY, self.b_button);
+        buttons.set(ButtonFlags::THUMBSTICK_CLICK, self.thumbstick_click);
+        buttons.set(ButtonFlags::PRIMARY_TOUCH, self.a_touch);
+        buttons.set(ButtonFlags::SECONDARY_TOUCH, self.b_touch);
+        buttons.set(ButtonFlags::TRIGGER_TOUCH, self.trigger_touch);
+        buttons.set(ButtonFlags::THUMBSTICK_TOUCH, self.thumbstick_touch);
+        buttons.set(ButtonFlags::THUMBREST_TOUCH, self.thumbrest_touch);
+
+        ControllerSnapshot {
+            buttons,
+            grip_analog: self.grip_analog,
+            trigger_analog: self.trigger_analog,
+            thumbstick_xy: self.thumbstick_xy,
+            linear_velocity: self.linear_velocity,
+            angular_velocity: self.angular_velocity,
+            stage_from_grip: self.stage_from_grip,
+            stage_from_aim: self.stage_from_aim,
+            hand_tracking: self.hand_tracking.snapshot(),
+        }
+    }
+
+    fn apply_snapshot(&mut self, snapshot: &ControllerSnapshot) {
+        self.store_previous();
+        let buttons = snapshot.buttons;
+        self.a_button = buttons.contains(ButtonFlags::PRIMARY);
+        self.b_button = buttons.contains(ButtonFlags::SECONDARY);
+        self.thumbstick_click = buttons.contains(ButtonFlags::THUMBSTICK_CLICK);
+        self.a_touch = buttons.contains(ButtonFlags::PRIMARY_TOUCH);
+        self.b_touch = buttons.contains(ButtonFlags::SECONDARY_TOUCH);
+        self.trigger_touch = buttons.contains(ButtonFlags::TRIGGER_TOUCH);
+        self.thumbstick_touch = buttons.contains(ButtonFlags::THUMBSTICK_TOUCH);
+        self.thumbrest_touch = buttons.contains(ButtonFlags::THUMBREST_TOUCH);
+        self.grip_analog = snapshot.grip_analog;
+        self.grip_button = self.grip_analog > 0.1;
+        self.trigger_analog = snapshot.trigger_analog;
+        self.trigger_button = self.trigger_analog > 0.1;
+        self.thumbstick_xy = snapshot.thumbstick_xy;
+        self.linear_velocity = snapshot.linear_velocity;
+        self.angular_velocity = snapshot.angular_velocity;
+        self.stage_from_grip = snapshot.stage_from_grip;
+        self.stage_from_aim = snapshot.stage_from_aim;
+        self.hand_tracking.apply_snapshot(&snapshot.hand_tracking);
+    }
+}
+
+/// The number of joints reported for each hand by `XR_EXT_hand_tracking`.
+pub const HAND_JOINT_COUNT: usize = xr::HAND_JOINT_COUNT;
+
+/// Below this distance (in metres) between thumb tip and index tip, the hand is fully pinching.
+const PINCH_DISTANCE_MIN: f32 = 0.015;
+/// Above this distance (in metres) between thumb tip and index tip, the hand is not pinching.
+const PINCH_DISTANCE_MAX: f32 = 0.06;
+/// Below this distance (in metres) between a finger tip and the palm, the finger is fully curled.
+const CURL_DISTANCE_MIN: f32 = 0.035;
+/// Above this distance (in metres) between a finger tip and the palm, the finger is fully extended.
+const CURL_DISTANCE_MAX: f32 = 0.09;
+
+#[derive(Debug, Clone, Default)]
+/// Input from a tracked hand, provided by `XR_EXT_hand_tracking`.
+///
+/// Joints are indexed by [`xr::HandJoint`], eg. `xr::HandJoint::INDEX_TIP.into_raw() as usize`.
+pub struct HandTrackingInputContext {
+    is_tracked: bool,
+    stage_from_joints: [Affine3A; HAND_JOINT_COUNT],
+    joint_radii: [f32; HAND_JOINT_COUNT],
+    pinch_strength: f32,
+    pinch_strength_prev: f32,
+    grab_strength: f32,
+}
+
+impl HandTrackingInputContext {
+    /// Is this hand currently being tracked? If not, the rest of the values are stale.
+    pub fn is_tracked(&self) -> bool {
+        self.is_tracked
+    }
+    /// The pose of each of the hand's joints in stage space.
+    pub fn stage_from_joints(&self) -> &[Affine3A; HAND_JOINT_COUNT] {
+        &self.stage_from_joints
+    }
+    /// The pose of a single joint in stage space.
+    pub fn stage_from_joint(&self, joint: xr::HandJoint) -> Affine3A {
+        self.stage_from_joints[joint.into_raw() as usize]
+    }
+    /// The radius of each joint, in metres.
+    pub fn joint_radii(&self) -> &[f32; HAND_JOINT_COUNT] {
+        &self.joint_radii
+    }
+    /// How strongly the thumb and index finger are pinched together, from 0 to 1.
+    pub fn pinch_strength(&self) -> f32 {
+        self.pinch_strength
+    }
+    pub fn pinch_just_pressed(&self) -> bool {
+        self.pinch_strength > 0.9 && self.pinch_strength_prev <= 0.9
+    }
+    pub fn pinch_just_released(&self) -> bool {
+        self.pinch_strength <= 0.9 && self.pinch_strength_prev > 0.9
+    }
+    /// How strongly the middle, ring and little fingers are curled into a fist, from 0 to 1.
+    pub fn grab_strength(&self) -> f32 {
+        self.grab_strength
+    }
+
+    fn update(&mut self, joint_locations: Option<xr::HandJointLocations>) {
+        self.pinch_strength_prev = self.pinch_strength;
+
+        let joint_locations = match joint_locations {
+            Some(joint_locations) if is_hand_valid(&joint_locations) => joint_locations,
+            _ => {
+                self.lose_tracking();
+                return;
+            }
+        };
+
+        for (n, joint_location) in joint_locations.iter().enumerate() {
+            self.stage_from_joints[n] = affine_from_posef(joint_location.pose);
+            self.joint_radii[n] = joint_location.radius;
+        }
+        self.is_tracked = true;
+        self.update_gestures();
+    }
+
+    /// Set the pose of each joint directly and derive gestures from them. Useful for testing.
+    pub(crate) fn set_stage_from_joints(
+        &mut self,
+        stage_from_joints: [Affine3A; HAND_JOINT_COUNT],
+    ) {
+        self.pinch_strength_prev = self.pinch_strength;
+        self.stage_from_joints = stage_from_joints;
+        self.is_tracked = true;
+        self.update_gestures();
+    }
+
+    /// The hand isn't tracked, so it isn't making any gestures.
+    fn lose_tracking(&mut self) {
+        self.is_tracked = false;
+        self.pinch_strength = 0.;
+        self.grab_strength = 0.;
+    }
+
+    fn snapshot(&self) -> HandSnapshot {
+        HandSnapshot {
+            is_tracked: self.is_tracked,
+            stage_from_joints: self.stage_from_joints,
+            joint_radii: self.joint_radii,
+        }
+    }
+
+    fn apply_snapshot(&mut self, snapshot: &HandSnapshot) {
+        if snapshot.is_tracked {
+            self.joint_radii = snapshot.joint_radii;
+            self.set_stage_from_joints(snapshot.stage_from_joints);
+        } else {
+            self.pinch_strength_prev = self.pinch_strength;
+            self.lose_tracking();
+        }
+    }
+
+    /// Derive the pinch and grab gestures from the current joint poses.
+    fn update_gestures(&mut self) {
+        let joints = &self.stage_from_joints;
+        let position = |joint: xr::HandJoint| joints[joint.into_raw() as usize].translation;
+
+        let pinch_distance =
+            position(xr::HandJoint::THUMB_TIP).distance(position(xr::HandJoint::INDEX_TIP));
+        self.pinch_strength = 1.
+            - ((pinch_distance - PINCH_DISTANCE_MIN) / (PINCH_DISTANCE_MAX - PINCH_DISTANCE_MIN))
+                .clamp(0., 1.);
+
+        let palm = position(xr::HandJoint::PALM);
+        let curl_tips = [
+            xr::HandJoint::MIDDLE_TIP,
+            xr::HandJoint::RING_TIP,
+            xr::HandJoint::LITTLE_TIP,
+        ];
+        let curl: f32 = curl_tips
+            .iter()
+            .map(|tip| {
+                let distance = position(*tip).distance(palm);
+                1. - ((distance - CURL_DISTANCE_MIN) / (CURL_DISTANCE_MAX - CURL_DISTANCE_MIN))
+                    .clamp(0., 1.)
+            })
+            .sum();
+        self.grab_strength = curl / curl_tips.len() as f32;
+    }
+}
+
+fn is_hand_valid(joint_locations: &xr::HandJointLocations) -> bool {
+    let wrist = &joint_locations[xr::HandJoint::WRIST.into_raw() as usize];
+    wrist.location_flags.contains(
+        xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID,
+    )
 }
 
 #[derive(Debug, Default)]
@@ -344,16 +642,161 @@ impl HmdInputContext {
     }
 }
 
+/// The value of an action for a single hand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionValue {
+    Boolean(bool),
+    Float(f32),
+    Vector2(Vec2),
+    Pose(Option<Affine3A>),
+}
+
+impl ActionValue {
+    fn default_value(&self) -> ActionValue {
+        match self {
+            ActionValue::Boolean(_) => ActionValue::Boolean(false),
+            ActionValue::Float(_) => ActionValue::Float(0.),
+            ActionValue::Vector2(_) => ActionValue::Vector2(Vec2::ZERO),
+            ActionValue::Pose(_) => ActionValue::Pose(None),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// The state of every action in the application's `ActionMap`, for each hand, this frame and the
+/// last. Actions are queried by name - querying an action that doesn't exist or has a different
+/// type returns a default value.
+pub struct ActionsInputContext {
+    // [left, right]
+    current: HashMap<String, [ActionValue; 2]>,
+    previous: HashMap<String, [ActionValue; 2]>,
+}
+
+impl ActionsInputContext {
+    /// The state of a boolean action.
+    pub fn boolean(&self, name: &str, handedness: Handedness) -> bool {
+        get_boolean(&self.current, name, handedness)
+    }
+
+    /// Was this boolean action pressed this frame?
+    pub fn boolean_just_pressed(&self, name: &str, handedness: Handedness) -> bool {
+        self.boolean(name, handedness) && !get_boolean(&self.previous, name, handedness)
+    }
+
+    /// Was this boolean action released this frame?
+    pub fn boolean_just_released(&self, name: &str, handedness: Handedness) -> bool {
+        !self.boolean(name, handedness) && get_boolean(&self.previous, name, handedness)
+    }
+
+    /// The state of a float action.
+    pub fn float(&self, name: &str, handedness: Handedness) -> f32 {
+        match get_value(&self.current, name, handedness) {
+            Some(ActionValue::Float(value)) => value,
+            _ => 0.,
+        }
+    }
+
+    /// The state of a vector2 action.
+    pub fn vector2(&self, name: &str, handedness: Handedness) -> Vec2 {
+        match get_value(&self.current, name, handedness) {
+            Some(ActionValue::Vector2(value)) => value,
+            _ => Vec2::ZERO,
+        }
+    }
+
+    /// The pose of a pose action in stage space, if it is currently valid.
+    pub fn stage_from_pose(&self, name: &str, handedness: Handedness) -> Option<Affine3A> {
+        match get_value(&self.current, name, handedness) {
+            Some(ActionValue::Pose(pose)) => pose,
+            _ => None,
+        }
+    }
+
+    /// Set the value of an action. Useful for testing.
+    pub fn set(&mut self, name: &str, handedness: Handedness, value: ActionValue) {
+        let values = self
+            .current
+            .entry(name.to_string())
+            .or_insert([value.default_value(); 2]);
+        values[handedness as usize] = value;
+    }
+
+    fn snapshot(&self) -> HashMap<String, [ActionValue; 2]> {
+        self.current.clone()
+    }
+
+    fn apply_snapshot(&mut self, actions: &HashMap<String, [ActionValue; 2]>) {
+        self.previous = std::mem::replace(&mut self.current, actions.clone());
+    }
+
+    fn update(&mut self, xr_context: &XrContext) {
+        let input = &xr_context.input;
+        let session = &xr_context.session;
+        let time = xr_context.frame_state.predicted_display_time;
+
+        self.previous = std::mem::take(&mut self.current);
+        for (name, action) in &input.actions {
+            if let NamedAction::Haptic(_) = action {
+                continue;
+            }
+
+            let value = |handedness| match action {
+                NamedAction::Boolean(_) => {
+                    ActionValue::Boolean(input.get_boolean(session, name, handedness))
+                }
+                NamedAction::Float(_) => {
+                    ActionValue::Float(input.get_float(session, name, handedness))
+                }
+                NamedAction::Vector2(_) => {
+                    let value = input.get_vector2(session, name, handedness);
+                    ActionValue::Vector2(Vec2::new(value.x, value.y))
+                }
+                NamedAction::Pose { .. } => {
+                    let space = input.pose_space(name, handedness).unwrap();
+                    let location = space.locate(&xr_context.stage_space, time).unwrap();
+                    ActionValue::Pose(
+                        is_space_valid(&location).then(|| affine_from_posef(location.pose)),
+                    )
+                }
+                NamedAction::Haptic(_) => unreachable!(),
+            };
+            self.current
+                .insert(name.clone(), [value(Left), value(Right)]);
+        }
+    }
+}
+
+fn get_value(
+    values: &HashMap<String, [ActionValue; 2]>,
+    name: &str,
+    handedness: Handedness,
+) -> Option<ActionValue> {
+    values.get(name).map(|v| v[handedness as usize])
+}
+
+fn get_boolean(
+    values: &HashMap<String, [ActionValue; 2]>,
+    name: &str,
+    handedness: Handedness,
+) -> bool {
+    matches!(
+        get_value(values, name, handedness),
+        Some(ActionValue::Boolean(true))
+    )
+}
+
 #[derive(Debug, Default)]
 /// Context that holds input state. Allows users to query for input events without having to
 /// worry about OpenXR internals.
 ///
-/// Currently only supports buttons on the Oculus Touch controllers, but will be expanded to
-/// support triggers and more.
+/// `left` and `right` expose the actions in the default `ActionMap`, named after the buttons on
+/// the Oculus Touch controller. Any action in the application's `ActionMap` can be queried by
+/// name with `actions`.
 pub struct InputContext {
     pub left: LeftInputContext,
     pub right: RightInputContext,
     pub hmd: HmdInputContext,
+    pub actions: ActionsInputContext,
 }
 
 impl InputContext {
@@ -362,181 +805,89 @@ impl InputContext {
     pub(crate) fn update(&mut self, xr_context: &XrContext) {
         let input = &xr_context.input;
         let session = &xr_context.session;
-        let left_subaction_path = input.left_hand_subaction_path;
-        let right_subaction_path = input.right_hand_subaction_path;
         let time = xr_context.frame_state.predicted_display_time;
 
-        self.left.x_button_prev = self.left.x_button;
-        self.left.y_button_prev = self.left.y_button;
-        self.left.menu_button_prev = self.left.menu_button;
-        self.left.grip_button_prev = self.left.grip_button;
-        self.left.trigger_button_prev = self.left.trigger_button;
-        self.left.thumbstick_click_prev = self.left.thumbstick_click;
-        self.left.x_touch_prev = self.left.x_touch;
-        self.left.y_touch_prev = self.left.y_touch;
-        self.left.trigger_touch_prev = self.left.trigger_touch;
-        self.left.thumbstick_touch_prev = self.left.thumbstick_touch;
-        self.left.thumbrest_touch_prev = self.left.thumbrest_touch;
-        self.left.grip_analog_prev = self.left.grip_analog;
-        self.left.trigger_analog_prev = self.left.trigger_analog;
-
-        self.right.a_button_prev = self.right.a_button;
-        self.right.b_button_prev = self.right.b_button;
-        self.right.grip_button_prev = self.right.grip_button;
-        self.right.trigger_button_prev = self.right.trigger_button;
-        self.right.thumbstick_click_prev = self.right.thumbstick_click;
-        self.right.a_touch_prev = self.right.a_touch;
-        self.right.b_touch_prev = self.right.b_touch;
-        self.right.trigger_touch_prev = self.right.trigger_touch;
-        self.right.thumbstick_touch_prev = self.right.thumbstick_touch;
-        self.right.thumbrest_touch_prev = self.right.thumbrest_touch;
-        self.right.grip_analog_prev = self.right.grip_analog;
-        self.right.trigger_analog_prev = self.right.trigger_analog;
-
-        self.left.x_button =
-            xr::ActionInput::get(&input.x_button_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.y_button =
-            xr::ActionInput::get(&input.y_button_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.menu_button =
-            xr::ActionInput::get(&input.menu_button_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.thumbstick_click =
-            xr::ActionInput::get(&input.thumbstick_click_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.x_touch =
-            xr::ActionInput::get(&input.x_touch_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.y_touch =
-            xr::ActionInput::get(&input.y_touch_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.trigger_touch =
-            xr::ActionInput::get(&input.trigger_touch_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.thumbstick_touch =
-            xr::ActionInput::get(&input.thumbstick_touch_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.thumbrest_touch =
-            xr::ActionInput::get(&input.thumbrest_touch_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.grip_analog =
-            xr::ActionInput::get(&input.squeeze_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
+        self.left.store_previous();
+        self.right.store_previous();
+
+        let boolean = |name, handedness| input.get_boolean(session, name, handedness);
+        let float = |name, handedness| input.get_float(session, name, handedness);
+
+        self.left.x_button = boolean("x_button", Left);
+        self.left.y_button = boolean("y_button", Left);
+        self.left.menu_button = boolean("menu_button", Left);
+        self.left.thumbstick_click = boolean("thumbstick_click", Left);
+        self.left.x_touch = boolean("x_touch", Left);
+        self.left.y_touch = boolean("y_touch", Left);
+        self.left.trigger_touch = boolean("trigger_touch", Left);
+        self.left.thumbstick_touch = boolean("thumbstick_touch", Left);
+        self.left.thumbrest_touch = boolean("thumbrest_touch", Left);
+        self.left.grip_analog = float("squeeze", Left);
         self.left.grip_button = self.left.grip_analog > 0.1;
-        self.left.trigger_analog =
-            xr::ActionInput::get(&input.trigger_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
+        self.left.trigger_analog = float("trigger", Left);
         self.left.trigger_button = self.left.trigger_analog > 0.1;
-        self.left.thumbstick_xy.x =
-            xr::ActionInput::get(&input.thumbstick_x_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-        self.left.thumbstick_xy.y =
-            xr::ActionInput::get(&input.thumbstick_y_action, session, left_subaction_path)
-                .unwrap()
-                .current_state;
-
-        let (location, velocity) = &input
-            .left_hand_grip_space
-            .relate(&xr_context.stage_space, time)
-            .unwrap();
-        if is_space_valid(location) {
-            self.left.stage_from_grip = affine_from_posef(location.pose);
-            self.left.linear_velocity = mint::Vector3::from(velocity.linear_velocity).into();
-            self.left.angular_velocity = mint::Vector3::from(velocity.angular_velocity).into();
+        self.left.thumbstick_xy.x = float("thumbstick_x", Left);
+        self.left.thumbstick_xy.y = float("thumbstick_y", Left);
+
+        if let Some(grip_space) = input.pose_space("grip_pose", Left) {
+            let (location, velocity) = &grip_space.relate(&xr_context.stage_space, time).unwrap();
+            if is_space_valid(location) {
+                self.left.stage_from_grip = affine_from_posef(location.pose);
+                self.left.linear_velocity = mint::Vector3::from(velocity.linear_velocity).into();
+                self.left.angular_velocity = mint::Vector3::from(velocity.angular_velocity).into();
+            }
         }
-        let location = &input
-            .left_hand_aim_space
-            .locate(&xr_context.stage_space, time)
-            .unwrap();
-        if is_space_valid(location) {
-            self.left.stage_from_aim = affine_from_posef(location.pose);
+        if let Some(aim_space) = input.pose_space("aim_pose", Left) {
+            let location = &aim_space.locate(&xr_context.stage_space, time).unwrap();
+            if is_space_valid(location) {
+                self.left.stage_from_aim = affine_from_posef(location.pose);
+            }
         }
 
-        self.right.a_button =
-            xr::ActionInput::get(&input.a_button_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-        self.right.b_button =
-            xr::ActionInput::get(&input.b_button_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-        self.right.thumbstick_click = xr::ActionInput::get(
-            &input.thumbstick_click_action,
-            session,
-            right_subaction_path,
-        )
-        .unwrap()
-        .current_state;
-        self.right.a_touch =
-            xr::ActionInput::get(&input.a_touch_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-        self.right.b_touch =
-            xr::ActionInput::get(&input.b_touch_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-        self.right.trigger_touch =
-            xr::ActionInput::get(&input.trigger_touch_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-        self.right.thumbstick_touch = xr::ActionInput::get(
-            &input.thumbstick_touch_action,
-            session,
-            right_subaction_path,
-        )
-        .unwrap()
-        .current_state;
-        self.right.thumbrest_touch =
-            xr::ActionInput::get(&input.thumbrest_touch_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-        self.right.grip_analog =
-            xr::ActionInput::get(&input.squeeze_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
+        self.right.a_button = boolean("a_button", Right);
+        self.right.b_button = boolean("b_button", Right);
+        self.right.thumbstick_click = boolean("thumbstick_click", Right);
+        self.right.a_touch = boolean("a_touch", Right);
+        self.right.b_touch = boolean("b_touch", Right);
+        self.right.trigger_touch = boolean("trigger_touch", Right);
+        self.right.thumbstick_touch = boolean("thumbstick_touch", Right);
+        self.right.thumbrest_touch = boolean("thumbrest_touch", Right);
+        self.right.grip_analog = float("squeeze", Right);
         self.right.grip_button = self.right.grip_analog > 0.1;
-        self.right.trigger_analog =
-            xr::ActionInput::get(&input.trigger_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
+        self.right.trigger_analog = float("trigger", Right);
         self.right.trigger_button = self.right.trigger_analog > 0.1;
-        self.right.thumbstick_xy.x =
-            xr::ActionInput::get(&input.thumbstick_x_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-        self.right.thumbstick_xy.y =
-            xr::ActionInput::get(&input.thumbstick_y_action, session, right_subaction_path)
-                .unwrap()
-                .current_state;
-
-        let (location, velocity) = &input
-            .right_hand_grip_space
-            .relate(&xr_context.stage_space, time)
-            .unwrap();
-        if is_space_valid(location) {
-            self.right.stage_from_grip = affine_from_posef(location.pose);
-            self.right.linear_velocity = mint::Vector3::from(velocity.linear_velocity).into();
-            self.right.angular_velocity = mint::Vector3::from(velocity.angular_velocity).into();
+        self.right.thumbstick_xy.x = float("thumbstick_x", Right);
+        self.right.thumbstick_xy.y = float("thumbstick_y", Right);
+
+        if let Some(grip_space) = input.pose_space("grip_pose", Right) {
+            let (location, velocity) = &grip_space.relate(&xr_context.stage_space, time).unwrap();
+            if is_space_valid(location) {
+                self.right.stage_from_grip = affine_from_posef(location.pose);
+                self.right.linear_velocity = mint::Vector3::from(velocity.linear_velocity).into();
+                self.right.angular_velocity = mint::Vector3::from(velocity.angular_velocity).into();
+            }
+        }
+        if let Some(aim_space) = input.pose_space("aim_pose", Right) {
+            let location = &aim_space.locate(&xr_context.stage_space, time).unwrap();
+            if is_space_valid(location) {
+                self.right.stage_from_aim = affine_from_posef(location.pose);
+            }
         }
-        let location = &input
-            .right_hand_aim_space
-            .locate(&xr_context.stage_space, time)
-            .unwrap();
-        if is_space_valid(location) {
-            self.right.stage_from_aim = affine_from_posef(location.pose);
+
+        self.actions.update(xr_context);
+
+        if let Some(hand_trackers) = &xr_context.hand_trackers {
+            let stage_space = &xr_context.stage_space;
+            self.left.hand_tracking.update(
+                stage_space
+                    .locate_hand_joints(&hand_trackers.left, time)
+                    .unwrap(),
+            );
+            self.right.hand_tracking.update(
+                stage_space
+                    .locate_hand_joints(&hand_trackers.right, time)
+                    .unwrap(),
+            );
         }
 
         self.hmd.update(xr_context);
@@ -544,6 +895,27 @@ impl InputContext {
 }
 
 impl InputContext {
+    /// Capture the current input state, eg. to record it with an `InputRecorder`.
+    pub fn snapshot(&self) -> InputSnapshot {
+        InputSnapshot {
+            left: self.left.snapshot(),
+            right: self.right.snapshot(),
+            left_eye_in_stage: self.hmd.left_eye_in_stage,
+            right_eye_in_stage: self.hmd.right_eye_in_stage,
+            actions: self.actions.snapshot(),
+        }
+    }
+
+    /// Replace the current input state with a snapshot, as if it had come from OpenXR. The
+    /// previous state is kept, so `*_just_pressed` and friends work as expected.
+    pub fn apply_snapshot(&mut self, snapshot: &InputSnapshot) {
+        self.left.apply_snapshot(&snapshot.left);
+        self.right.apply_snapshot(&snapshot.right);
+        self.hmd.left_eye_in_stage = snapshot.left_eye_in_stage;
+        self.hmd.right_eye_in_stage = snapshot.right_eye_in_stage;
+        self.actions.apply_snapshot(&snapshot.actions);
+    }
+
     /// Get an `InputContext` used for testing. Uses the same values as defined in the simulator.
     pub fn testing() -> Self {
         let mut input_context = Self::default();
@@ -559,7 +931,14 @@ impl InputContext {
 
 #[cfg(test)]
 pub mod tests {
-    use super::HmdInputContext;
+    use super::{
+        ActionValue, ActionsInputContext, HandTrackingInputContext, HmdInputContext,
+        HAND_JOINT_COUNT,
+    };
+    use crate::components::hand::Handedness::{Left, Right};
+    use crate::xr;
+    use approx::assert_relative_eq;
+    use glam::Affine3A;
 
     #[test]
     pub fn test_hmd_context() {
@@ -574,4 +953,53 @@ pub mod tests {
         let (_, _, translation) = hmd_context.hmd_in_stage().to_scale_rotation_translation();
         assert_eq!(translation, expected_translation);
     }
+
+    #[test]
+    pub fn test_actions_context() {
+        let mut actions = ActionsInputContext::default();
+        actions.set("jump", Right, ActionValue::Boolean(true));
+        actions.set("throttle", Left, ActionValue::Float(0.5));
+
+        assert!(actions.boolean("jump", Right));
+        assert!(actions.boolean_just_pressed("jump", Right));
+        assert!(!actions.boolean("jump", Left));
+        assert_eq!(actions.float("throttle", Left), 0.5);
+        assert_eq!(actions.float("throttle", Right), 0.);
+
+        // Missing actions, or actions of the wrong type, return default values.
+        assert!(!actions.boolean("missing", Left));
+        assert_eq!(actions.float("jump", Right), 0.);
+        assert_eq!(actions.stage_from_pose("jump", Right), None);
+
+        actions.previous = std::mem::take(&mut actions.current);
+        assert!(actions.boolean_just_released("jump", Right));
+    }
+
+    #[test]
+    pub fn test_hand_tracking_gestures() {
+        let mut hand_tracking = HandTrackingInputContext::default();
+        assert!(!hand_tracking.is_tracked());
+
+        // All joints in the same place: pinching, and all fingers curled into the palm.
+        hand_tracking.set_stage_from_joints([Affine3A::IDENTITY; HAND_JOINT_COUNT]);
+        assert!(hand_tracking.is_tracked());
+        assert_relative_eq!(hand_tracking.pinch_strength(), 1.);
+        assert_relative_eq!(hand_tracking.grab_strength(), 1.);
+        assert!(hand_tracking.pinch_just_pressed());
+
+        // Now open the hand.
+        let mut joints = [Affine3A::IDENTITY; HAND_JOINT_COUNT];
+        for tip in [
+            xr::HandJoint::INDEX_TIP,
+            xr::HandJoint::MIDDLE_TIP,
+            xr::HandJoint::RING_TIP,
+            xr::HandJoint::LITTLE_TIP,
+        ] {
+            joints[tip.into_raw() as usize] = Affine3A::from_translation([0., 0., -0.15].into());
+        }
+        hand_tracking.set_stage_from_joints(joints);
+        assert_relative_eq!(hand_tracking.pinch_strength(), 0.);
+        assert_relative_eq!(hand_tracking.grab_strength(), 0.);
+        assert!(hand_tracking.pinch_just_released());
+    }
 }
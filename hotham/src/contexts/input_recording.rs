@@ -0,0 +1,638 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use bitflags::bitflags;
+use glam::{Affine3A, Vec2, Vec3};
+
+use crate::{
+    contexts::input_context::{ActionValue, HAND_JOINT_COUNT},
+    xr, HothamResult, TickData,
+};
+
+/// Identifies an input log. The version is bumped whenever the frame layout changes.
+const MAGIC: &[u8; 4] = b"HTIR";
+const VERSION: u32 = 2;
+
+bitflags! {
+    /// The state of each button on a controller. Buttons are named after their position, so
+    /// `PRIMARY` is X on the left controller and A on the right.
+    #[derive(Default)]
+    pub struct ButtonFlags: u32 {
+        /// X or A
+        const PRIMARY = 1 << 0;
+        /// Y or B
+        const SECONDARY = 1 << 1;
+        /// Menu (left controller only)
+        const MENU = 1 << 2;
+        /// Thumbstick click
+        const THUMBSTICK_CLICK = 1 << 3;
+        /// X or A touch
+        const PRIMARY_TOUCH = 1 << 4;
+        /// Y or B touch
+        const SECONDARY_TOUCH = 1 << 5;
+        /// Trigger touch
+        const TRIGGER_TOUCH = 1 << 6;
+        /// Thumbstick touch
+        const THUMBSTICK_TOUCH = 1 << 7;
+        /// Thumbrest touch
+        const THUMBREST_TOUCH = 1 << 8;
+    }
+}
+
+/// The state of a tracked hand for one frame. Gestures like pinching are derived from the joints
+/// when the snapshot is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HandSnapshot {
+    pub is_tracked: bool,
+    pub stage_from_joints: [Affine3A; HAND_JOINT_COUNT],
+    pub joint_radii: [f32; HAND_JOINT_COUNT],
+}
+
+/// The state of a single controller, and the hand holding it, for one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ControllerSnapshot {
+    pub buttons: ButtonFlags,
+    pub grip_analog: f32,
+    pub trigger_analog: f32,
+    pub thumbstick_xy: Vec2,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+    pub stage_from_grip: Affine3A,
+    pub stage_from_aim: Affine3A,
+    pub hand_tracking: HandSnapshot,
+}
+
+/// The state of an [`crate::contexts::InputContext`] for one frame. Created with
+/// `InputContext::snapshot` and restored with `InputContext::apply_snapshot`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InputSnapshot {
+    pub left: ControllerSnapshot,
+    pub right: ControllerSnapshot,
+    pub left_eye_in_stage: Affine3A,
+    pub right_eye_in_stage: Affine3A,
+    /// The value of every named action in the application's `ActionMap`, for each hand
+    pub actions: HashMap<String, [ActionValue; 2]>,
+}
+
+/// A single frame in an input log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputFrame {
+    /// The predicted display time when the input was sampled
+    pub predicted_display_time: xr::Time,
+    /// What `Engine::update` returned for this frame
+    pub tick_data: TickData,
+    /// The input state for this frame
+    pub input: InputSnapshot,
+}
+
+impl InputFrame {
+    /// Write this frame in the input log format.
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.predicted_display_time.as_nanos().to_le_bytes())?;
+        writer.write_all(&self.tick_data.previous_state.into_raw().to_le_bytes())?;
+        writer.write_all(&self.tick_data.current_state.into_raw().to_le_bytes())?;
+        writer.write_all(&(self.tick_data.swapchain_image_index as u32).to_le_bytes())?;
+        write_affine(writer, &self.input.left_eye_in_stage)?;
+        write_affine(writer, &self.input.right_eye_in_stage)?;
+        write_controller(writer, &self.input.left)?;
+        write_controller(writer, &self.input.right)?;
+        write_actions(writer, &self.input.actions)
+    }
+
+    /// Read a frame in the input log format. Returns `None` at the end of the log, and an
+    /// [`ErrorKind::UnexpectedEof`] error if the log ends part way through the frame.
+    pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Option<InputFrame>> {
+        let mut time = [0; 8];
+        match reader.read_exact(&mut time) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+
+        let predicted_display_time = xr::Time::from_nanos(i64::from_le_bytes(time));
+        let tick_data = TickData {
+            previous_state: xr::SessionState::from_raw(read_i32(reader)?),
+            current_state: xr::SessionState::from_raw(read_i32(reader)?),
+            swapchain_image_index: read_u32(reader)? as usize,
+        };
+        let left_eye_in_stage = read_affine(reader)?;
+        let right_eye_in_stage = read_affine(reader)?;
+        let left = read_controller(reader)?;
+        let right = read_controller(reader)?;
+        let actions = read_actions(reader)?;
+
+        Ok(Some(InputFrame {
+            predicted_display_time,
+            tick_data,
+            input: InputSnapshot {
+                left,
+                right,
+                left_eye_in_stage,
+                right_eye_in_stage,
+                actions,
+            },
+        }))
+    }
+}
+
+/// Records each frame of input to a compact binary log, which can be replayed with
+/// [`InputReplay`].
+pub struct InputRecorder {
+    writer: Box<dyn Write>,
+}
+
+impl InputRecorder {
+    /// Create a new log at `path`, overwriting any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> HothamResult<Self> {
+        Self::from_writer(BufWriter::new(File::create(path)?))
+    }
+
+    /// Record input to any writer.
+    pub fn from_writer<W: Write + 'static>(writer: W) -> HothamResult<Self> {
+        let mut writer: Box<dyn Write> = Box::new(writer);
+        write_header(&mut writer)?;
+        Ok(InputRecorder { writer })
+    }
+
+    /// Append a frame to the log.
+    pub fn record(&mut self, frame: &InputFrame) -> HothamResult<()> {
+        frame.write(&mut self.writer)?;
+        Ok(())
+    }
+
+    /// Flush any buffered frames to the underlying writer.
+    pub fn flush(&mut self) -> HothamResult<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// A log recorded with [`InputRecorder`], replayed one frame at a time.
+///
+/// To reproduce a bug in a test, apply each frame to an `InputContext` and run the systems under
+/// test:
+///
+/// ```ignore
+/// let mut replay = InputReplay::open("bug.input")?;
+/// let mut input_context = InputContext::testing();
+/// while let Some(frame) = replay.next_frame() {
+///     input_context.apply_snapshot(&frame.input);
+///     hands_system_inner(&mut world, &input_context);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InputReplay {
+    frames: Vec<InputFrame>,
+    next: usize,
+}
+
+impl InputReplay {
+    /// Load a log from `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> HothamResult<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a log from any reader. A log that ends part way through a frame, eg. because the app
+    /// was killed while recording, is replayed up to the last complete frame.
+    pub fn from_reader<R: Read>(mut reader: R) -> HothamResult<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        let version = read_u32(&mut reader)?;
+        if &magic != MAGIC || version != VERSION {
+            return Err(anyhow::anyhow!("Unsupported input log (version {version})").into());
+        }
+
+        let mut frames = Vec::new();
+        loop {
+            match InputFrame::read(&mut reader) {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    println!(
+                        "[HOTHAM_INPUT] Input log ends part way through frame {}, dropping it",
+                        frames.len()
+                    );
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(InputReplay { frames, next: 0 })
+    }
+
+    /// Get the next frame, or `None` if the replay has finished.
+    pub fn next_frame(&mut self) -> Option<&InputFrame> {
+        let frame = self.frames.get(self.next)?;
+        self.next += 1;
+        Some(frame)
+    }
+
+    /// Get the next frame that was recorded while the session was focused, skipping any others,
+    /// or `None` if the replay has finished. `Engine` only updates input while the session is
+    /// focused, so this keeps the replay in step with it.
+    pub fn next_focused_frame(&mut self) -> Option<&InputFrame> {
+        while let Some(frame) = self.frames.get(self.next) {
+            self.next += 1;
+            if frame.tick_data.current_state == xr::SessionState::FOCUSED {
+                return Some(frame);
+            }
+        }
+        None
+    }
+
+    /// Has every frame been replayed?
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    /// Start the replay again from the first frame.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+    }
+
+    /// All the frames in this log.
+    pub fn frames(&self) -> &[InputFrame] {
+        &self.frames
+    }
+}
+
+/// Every log starts with the magic number and version.
+fn write_header<W: Write>(writer: &mut W) -> std::io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())
+}
+
+fn write_controller<W: Write>(
+    writer: &mut W,
+    controller: &ControllerSnapshot,
+) -> std::io::Result<()> {
+    writer.write_all(&controller.buttons.bits().to_le_bytes())?;
+    write_f32s(
+        writer,
+        &[
+            controller.grip_analog,
+            controller.trigger_analog,
+            controller.thumbstick_xy.x,
+            controller.thumbstick_xy.y,
+        ],
+    )?;
+    write_f32s(writer, &controller.linear_velocity.to_array())?;
+    write_f32s(writer, &controller.angular_velocity.to_array())?;
+    write_affine(writer, &controller.stage_from_grip)?;
+    write_affine(writer, &controller.stage_from_aim)?;
+    write_hand(writer, &controller.hand_tracking)
+}
+
+fn read_controller<R: Read>(reader: &mut R) -> std::io::Result<ControllerSnapshot> {
+    let buttons = ButtonFlags::from_bits_truncate(read_u32(reader)?);
+    let [grip_analog, trigger_analog, x, y] = read_f32s(reader)?;
+    Ok(ControllerSnapshot {
+        buttons,
+        grip_analog,
+        trigger_analog,
+        thumbstick_xy: Vec2::new(x, y),
+        linear_velocity: Vec3::from_array(read_f32s(reader)?),
+        angular_velocity: Vec3::from_array(read_f32s(reader)?),
+        stage_from_grip: read_affine(reader)?,
+        stage_from_aim: read_affine(reader)?,
+        hand_tracking: read_hand(reader)?,
+    })
+}
+
+/// Hands that aren't tracked are written without their joints.
+fn write_hand<W: Write>(writer: &mut W, hand: &HandSnapshot) -> std::io::Result<()> {
+    writer.write_all(&(hand.is_tracked as u32).to_le_bytes())?;
+    if !hand.is_tracked {
+        return Ok(());
+    }
+    for stage_from_joint in &hand.stage_from_joints {
+        write_affine(writer, stage_from_joint)?;
+    }
+    write_f32s(writer, &hand.joint_radii)
+}
+
+fn read_hand<R: Read>(reader: &mut R) -> std::io::Result<HandSnapshot> {
+    let mut hand = HandSnapshot {
+        is_tracked: read_u32(reader)? != 0,
+        ..Default::default()
+    };
+    if !hand.is_tracked {
+        return Ok(hand);
+    }
+    for stage_from_joint in &mut hand.stage_from_joints {
+        *stage_from_joint = read_affine(reader)?;
+    }
+    hand.joint_radii = read_f32s(reader)?;
+    Ok(hand)
+}
+
+/// Actions are written in order of their names, so the same input is always written the same way.
+fn write_actions<W: Write>(
+    writer: &mut W,
+    actions: &HashMap<String, [ActionValue; 2]>,
+) -> std::io::Result<()> {
+    let mut names = actions.keys().collect::<Vec<_>>();
+    names.sort();
+    writer.write_all(&(names.len() as u32).to_le_bytes())?;
+    for name in names {
+        writer.write_all(&(name.len() as u32).to_le_bytes())?;
+        writer.write_all(name.as_bytes())?;
+        for value in &actions[name] {
+            write_action_value(writer, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_actions<R: Read>(reader: &mut R) -> std::io::Result<HashMap<String, [ActionValue; 2]>> {
+    let count = read_u32(reader)?;
+    let mut actions = HashMap::new();
+    for _ in 0..count {
+        let mut name = vec![0; read_u32(reader)? as usize];
+        reader.read_exact(&mut name)?;
+        let name =
+            String::from_utf8(name).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let values = [read_action_value(reader)?, read_action_value(reader)?];
+        actions.insert(name, values);
+    }
+    Ok(actions)
+}
+
+fn write_action_value<W: Write>(writer: &mut W, value: &ActionValue) -> std::io::Result<()> {
+    match value {
+        ActionValue::Boolean(value) => {
+            writer.write_all(&0u32.to_le_bytes())?;
+            writer.write_all(&(*value as u32).to_le_bytes())
+        }
+        ActionValue::Float(value) => {
+            writer.write_all(&1u32.to_le_bytes())?;
+            write_f32s(writer, &[*value])
+        }
+        ActionValue::Vector2(value) => {
+            writer.write_all(&2u32.to_le_bytes())?;
+            write_f32s(writer, &value.to_array())
+        }
+        ActionValue::Pose(None) => writer.write_all(&3u32.to_le_bytes()),
+        ActionValue::Pose(Some(pose)) => {
+            writer.write_all(&4u32.to_le_bytes())?;
+            write_affine(writer, pose)
+        }
+    }
+}
+
+fn read_action_value<R: Read>(reader: &mut R) -> std::io::Result<ActionValue> {
+    Ok(match read_u32(reader)? {
+        0 => ActionValue::Boolean(read_u32(reader)? != 0),
+        1 => ActionValue::Float(read_f32s::<_, 1>(reader)?[0]),
+        2 => ActionValue::Vector2(Vec2::from_array(read_f32s(reader)?)),
+        3 => ActionValue::Pose(None),
+        4 => ActionValue::Pose(Some(read_affine(reader)?)),
+        kind => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown action value type {kind}"),
+            ))
+        }
+    })
+}
+
+fn write_affine<W: Write>(writer: &mut W, affine: &Affine3A) -> std::io::Result<()> {
+    write_f32s(writer, &affine.to_cols_array())
+}
+
+fn read_affine<R: Read>(reader: &mut R) -> std::io::Result<Affine3A> {
+    Ok(Affine3A::from_cols_array(&read_f32s(reader)?))
+}
+
+fn write_f32s<W: Write>(writer: &mut W, values: &[f32]) -> std::io::Result<()> {
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_f32s<R: Read, const N: usize>(reader: &mut R) -> std::io::Result<[f32; N]> {
+    let mut values = [0.; N];
+    for value in &mut values {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        *value = f32::from_le_bytes(bytes);
+    }
+    Ok(values)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> std::io::Result<i32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contexts::InputContext;
+    use glam::Quat;
+
+    /// An empty log, ready for frames to be written to it.
+    fn new_log() -> Vec<u8> {
+        let mut log = Vec::new();
+        write_header(&mut log).unwrap();
+        log
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        let mut input_context = InputContext::testing();
+        let mut frames = Vec::new();
+
+        // Press and release the A button over three frames.
+        for (i, pressed) in [false, true, false].into_iter().enumerate() {
+            let mut input = input_context.snapshot();
+            input.right.buttons.set(ButtonFlags::PRIMARY, pressed);
+            input.right.trigger_analog = i as f32 * 0.25;
+            input.right.thumbstick_xy = Vec2::new(0.5, -0.5);
+            input.left_eye_in_stage = Affine3A::from_rotation_translation(
+                Quat::from_rotation_y(0.1 * i as f32),
+                [0., 1.6, 0.].into(),
+            );
+            frames.push(InputFrame {
+                predicted_display_time: xr::Time::from_nanos(i as i64 * 13_888_889),
+                tick_data: TickData {
+                    previous_state: xr::SessionState::FOCUSED,
+                    current_state: xr::SessionState::FOCUSED,
+                    swapchain_image_index: i % 3,
+                },
+                input,
+            });
+        }
+
+        let mut log = new_log();
+        for frame in &frames {
+            frame.write(&mut log).unwrap();
+        }
+
+        let mut replay = InputReplay::from_reader(log.as_slice()).unwrap();
+        assert_eq!(replay.frames(), frames.as_slice());
+
+        let frame = replay.next_frame().unwrap();
+        input_context.apply_snapshot(&frame.input);
+        assert!(!input_context.right.a_button());
+
+        let frame = replay.next_frame().unwrap();
+        input_context.apply_snapshot(&frame.input);
+        assert!(input_context.right.a_button_just_pressed());
+        assert!(input_context.right.trigger_button());
+        assert_eq!(input_context.right.thumbstick_xy(), Vec2::new(0.5, -0.5));
+        assert_eq!(
+            input_context.snapshot().left_eye_in_stage,
+            frames[1].input.left_eye_in_stage
+        );
+
+        let frame = replay.next_frame().unwrap();
+        input_context.apply_snapshot(&frame.input);
+        assert!(input_context.right.a_button_just_released());
+        assert!(replay.is_finished());
+        assert!(replay.next_frame().is_none());
+    }
+
+    #[test]
+    pub fn test_round_trip_actions_and_hands() {
+        use crate::components::hand::Handedness::{Left, Right};
+
+        let mut input_context = InputContext::testing();
+        let actions = &mut input_context.actions;
+        actions.set("jump", Right, ActionValue::Boolean(true));
+        actions.set("throttle", Left, ActionValue::Float(0.75));
+        actions.set("move", Left, ActionValue::Vector2(Vec2::new(0.5, -1.)));
+        actions.set(
+            "wand",
+            Right,
+            ActionValue::Pose(Some(Affine3A::from_translation([0., 1., -0.5].into()))),
+        );
+
+        // Thumb and index tips touching, so the hand is pinching.
+        let joints = [Affine3A::from_translation([0.1, 1.2, -0.3].into()); HAND_JOINT_COUNT];
+        input_context
+            .right
+            .hand_tracking_mut()
+            .set_stage_from_joints(joints);
+
+        let frame = InputFrame {
+            predicted_display_time: xr::Time::from_nanos(0),
+            tick_data: TickData {
+                previous_state: xr::SessionState::FOCUSED,
+                current_state: xr::SessionState::FOCUSED,
+                swapchain_image_index: 0,
+            },
+            input: input_context.snapshot(),
+        };
+        let mut log = new_log();
+        frame.write(&mut log).unwrap();
+
+        let mut replay = InputReplay::from_reader(log.as_slice()).unwrap();
+        assert_eq!(replay.frames(), &[frame]);
+
+        // Replaying into a fresh context reproduces the actions and the pinch.
+        let mut replayed = InputContext::testing();
+        replayed.apply_snapshot(&replay.next_frame().unwrap().input);
+        let actions = &replayed.actions;
+        assert!(actions.boolean_just_pressed("jump", Right));
+        assert!(!actions.boolean("jump", Left));
+        assert_eq!(actions.float("throttle", Left), 0.75);
+        assert_eq!(actions.vector2("move", Left), Vec2::new(0.5, -1.));
+        assert!(actions.stage_from_pose("wand", Right).is_some());
+        assert!(actions.stage_from_pose("wand", Left).is_none());
+
+        let hand_tracking = replayed.right.hand_tracking();
+        assert!(hand_tracking.is_tracked());
+        assert!(hand_tracking.pinch_just_pressed());
+        assert_eq!(hand_tracking.stage_from_joints(), &joints);
+        assert!(!replayed.left.hand_tracking().is_tracked());
+    }
+
+    #[test]
+    pub fn test_replay_skips_unfocused_frames() {
+        use xr::SessionState;
+
+        // Startup frames, then the A button pressed on the first and third focused frames.
+        let states = [
+            (SessionState::IDLE, SessionState::READY, false),
+            (SessionState::READY, SessionState::SYNCHRONIZED, false),
+            (SessionState::SYNCHRONIZED, SessionState::VISIBLE, false),
+            (SessionState::VISIBLE, SessionState::FOCUSED, true),
+            (SessionState::FOCUSED, SessionState::FOCUSED, false),
+            (SessionState::FOCUSED, SessionState::VISIBLE, false),
+            (SessionState::VISIBLE, SessionState::FOCUSED, true),
+        ];
+        let mut log = new_log();
+        for (i, (previous_state, current_state, pressed)) in states.into_iter().enumerate() {
+            let mut input = InputSnapshot::default();
+            input.right.buttons.set(ButtonFlags::PRIMARY, pressed);
+            let frame = InputFrame {
+                predicted_display_time: xr::Time::from_nanos(i as i64),
+                tick_data: TickData {
+                    previous_state,
+                    current_state,
+                    swapchain_image_index: 0,
+                },
+                input,
+            };
+            frame.write(&mut log).unwrap();
+        }
+
+        let mut replay = InputReplay::from_reader(log.as_slice()).unwrap();
+        let mut pressed = Vec::new();
+        while let Some(frame) = replay.next_focused_frame() {
+            assert_eq!(frame.tick_data.current_state, SessionState::FOCUSED);
+            pressed.push(frame.input.right.buttons.contains(ButtonFlags::PRIMARY));
+        }
+        assert_eq!(pressed, [true, false, true]);
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    pub fn test_truncated_log() {
+        let mut log = new_log();
+        let frames: Vec<_> = (0..3)
+            .map(|i| InputFrame {
+                predicted_display_time: xr::Time::from_nanos(i),
+                tick_data: TickData {
+                    previous_state: xr::SessionState::FOCUSED,
+                    current_state: xr::SessionState::FOCUSED,
+                    swapchain_image_index: 0,
+                },
+                input: InputSnapshot::default(),
+            })
+            .collect();
+        for frame in &frames {
+            frame.write(&mut log).unwrap();
+        }
+
+        // The app was killed part way through writing the last frame.
+        log.truncate(log.len() - 5);
+        let replay = InputReplay::from_reader(log.as_slice()).unwrap();
+        assert_eq!(replay.frames(), &frames[..2]);
+    }
+
+    #[test]
+    pub fn test_invalid_log() {
+        assert!(InputReplay::from_reader(&b"NOPE\x01\x00\x00\x00"[..]).is_err());
+        assert!(InputReplay::from_reader(&b""[..]).is_err());
+    }
+}
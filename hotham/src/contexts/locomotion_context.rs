@@ -0,0 +1,175 @@
+use glam::Vec3;
+
+use crate::{
+    components::hand::Handedness,
+    contexts::physics_context::{DEFAULT_COLLISION_GROUP, WALL_COLLISION_GROUP},
+};
+
+/// Which way is "forward" when moving with the thumbstick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementDirection {
+    /// Move in the direction the player is looking
+    Head,
+    /// Move in the direction the movement hand is pointing
+    Hand,
+}
+
+/// How the player turns with the thumbstick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TurnMode {
+    /// Don't turn
+    Off,
+    /// Turn instantly by `angle` radians each time the thumbstick is pushed to the side
+    Snap { angle: f32 },
+    /// Turn continuously at up to `speed` radians per second
+    Smooth { speed: f32 },
+}
+
+/// Settings for [`crate::systems::locomotion_system`]. The defaults are a reasonable starting
+/// point for comfort: smooth movement on the left thumbstick, snap turning and teleporting on the
+/// right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocomotionSettings {
+    /// Allow smooth movement with the thumbstick
+    pub smooth_movement: bool,
+    /// The hand whose thumbstick moves the player
+    pub movement_hand: Handedness,
+    /// Which way is forward when moving
+    pub movement_direction: MovementDirection,
+    /// Movement speed in metres per second
+    pub movement_speed: f32,
+    /// How the player turns
+    pub turn_mode: TurnMode,
+    /// The hand whose thumbstick turns and teleports the player
+    pub turn_hand: Handedness,
+    /// Allow teleporting by pushing the thumbstick forward, aiming and letting go
+    pub teleport: bool,
+    /// The speed the teleport arc is launched at, in metres per second. Controls its range.
+    pub teleport_arc_speed: f32,
+    /// The steepest surface, in radians, that can be teleported to
+    pub teleport_max_slope: f32,
+    /// Colliders in these collision groups can be teleported to, and block the teleport arc
+    pub teleport_collision_groups: u32,
+    /// How long to fade out, and then in again, when teleporting, in seconds
+    pub teleport_fade_duration: f32,
+    /// How far the player's head stays from walls when moving, in metres
+    pub player_radius: f32,
+    /// Thumbstick values smaller than this are ignored
+    pub thumbstick_deadzone: f32,
+    /// How far the thumbstick must be pushed to snap turn or start aiming a teleport
+    pub thumbstick_threshold: f32,
+}
+
+impl Default for LocomotionSettings {
+    fn default() -> Self {
+        Self {
+            smooth_movement: true,
+            movement_hand: Handedness::Left,
+            movement_direction: MovementDirection::Head,
+            movement_speed: 2.,
+            turn_mode: TurnMode::Snap {
+                angle: 30_f32.to_radians(),
+            },
+            turn_hand: Handedness::Right,
+            teleport: true,
+            teleport_arc_speed: 7.,
+            teleport_max_slope: 30_f32.to_radians(),
+            teleport_collision_groups: DEFAULT_COLLISION_GROUP | WALL_COLLISION_GROUP,
+            teleport_fade_duration: 0.15,
+            player_radius: 0.2,
+            thumbstick_deadzone: 0.2,
+            thumbstick_threshold: 0.7,
+        }
+    }
+}
+
+/// The state of a teleport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TeleportState {
+    Idle,
+    /// The player is aiming. The target is `None` if the arc didn't land somewhere valid.
+    Aiming {
+        target: Option<Vec3>,
+    },
+    /// Fading out. The player will be moved to `target` when the fade is complete.
+    FadingOut {
+        target: Vec3,
+        elapsed: f32,
+    },
+    FadingIn {
+        elapsed: f32,
+    },
+}
+
+/// Context for artificial locomotion. Configure it with `settings`, and query it to draw the
+/// teleport arc. The rendering system draws the teleport fade.
+#[derive(Debug, Clone)]
+pub struct LocomotionContext {
+    /// Locomotion settings
+    pub settings: LocomotionSettings,
+    pub(crate) teleport_state: TeleportState,
+    pub(crate) teleport_arc: Vec<Vec3>,
+    pub(crate) snap_turn_ready: bool,
+}
+
+impl Default for LocomotionContext {
+    fn default() -> Self {
+        LocomotionContext::new(Default::default())
+    }
+}
+
+impl LocomotionContext {
+    /// Create a new context with the given settings.
+    pub fn new(settings: LocomotionSettings) -> Self {
+        Self {
+            settings,
+            teleport_state: TeleportState::Idle,
+            teleport_arc: Vec::new(),
+            snap_turn_ready: true,
+        }
+    }
+
+    /// Is the player currently aiming a teleport?
+    pub fn is_aiming_teleport(&self) -> bool {
+        matches!(self.teleport_state, TeleportState::Aiming { .. })
+    }
+
+    /// The points along the teleport arc in global space, if the player is aiming.
+    pub fn teleport_arc(&self) -> &[Vec3] {
+        &self.teleport_arc
+    }
+
+    /// Where the player will land if they let go of the thumbstick, in global space. `None` if
+    /// they're not aiming, or the arc doesn't land somewhere they can stand.
+    pub fn teleport_target(&self) -> Option<Vec3> {
+        match self.teleport_state {
+            TeleportState::Aiming { target } => target,
+            _ => None,
+        }
+    }
+
+    /// How much the view should be faded out during a teleport, from 0 (not at all) to 1.
+    ///
+    /// You don't need to draw this yourself: [`crate::systems::rendering::rendering_system`]
+    /// fades the view to black by this amount each frame, unless the
+    /// [`crate::rendering::fade::ScreenFade`] covers more of the view.
+    pub fn fade(&self) -> f32 {
+        let duration = self.settings.teleport_fade_duration;
+        if duration <= 0. {
+            return 0.;
+        }
+        match self.teleport_state {
+            TeleportState::FadingOut { elapsed, .. } => (elapsed / duration).min(1.),
+            TeleportState::FadingIn { elapsed } => 1. - (elapsed / duration).min(1.),
+            _ => 0.,
+        }
+    }
+
+    /// Is a teleport in progress?
+    pub fn is_teleporting(&self) -> bool {
+        matches!(
+            self.teleport_state,
+            TeleportState::FadingOut { .. } | TeleportState::FadingIn { .. }
+        )
+    }
+}
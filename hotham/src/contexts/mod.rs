@@ -3,6 +3,8 @@ pub mod audio_context;
 pub mod gui_context;
 pub mod haptic_context;
 pub mod input_context;
+pub mod input_recording;
+pub mod locomotion_context;
 pub mod physics_context;
 pub mod render_context;
 pub mod vulkan_context;
@@ -12,6 +14,7 @@ pub use audio_context::AudioContext;
 pub use gui_context::GuiContext;
 pub use haptic_context::HapticContext;
 pub use input_context::InputContext;
+pub use locomotion_context::LocomotionContext;
 pub use physics_context::PhysicsContext;
 pub use render_context::RenderContext;
 pub use vulkan_context::VulkanContext;
@@ -17,30 +17,43 @@ pub static CLEAR_VALUES: [vk::ClearValue; 2] = [
 const CULLING_TIMEOUT: u64 = u64::MAX;
 
 #[cfg(target_os = "android")]
-const RESOLVE_ATTACHMENT: u32 = 3;
+const OUTPUT_ATTACHMENT: u32 = 3;
 
 #[cfg(not(target_os = "android"))]
-const RESOLVE_ATTACHMENT: u32 = 2;
+const OUTPUT_ATTACHMENT: u32 = 2;
 
 use crate::{
+    components::RenderLayers,
     contexts::{VulkanContext, XrContext},
     rendering::{
         camera::{extract_planes_from_frustum, Camera, Frustum},
+        debug_draw::create_debug_draw_pipeline,
         descriptors::Descriptors,
+        dynamic_resolution::GpuTimer,
+        environment::{create_skybox_pipeline, EnvironmentMap, Skybox},
+        fade::ScreenFade,
         frame::Frame,
         image::Image,
         material::Material,
+        occlusion::OcclusionCulling,
+        particles::create_particle_pipeline,
+        post_processing::{
+            create_post_processing_pipeline, PostProcessing, POST_PROCESSING_SUBPASS,
+        },
         primitive::Primitive,
-        resources::Resources,
+        render_settings::{is_depth_format, scale_extent, supported_samples, RenderSettings},
+        render_target::RenderTargets,
+        resources::{DrawData, Resources},
         scene_data::SceneData,
+        shadows::ShadowMaps,
         swapchain::{Swapchain, SwapchainInfo},
         vertex::Vertex,
     },
-    COLOR_FORMAT, DEPTH_FORMAT, VIEW_COUNT,
+    DEPTH_FORMAT, SCENE_COLOR_FORMAT, VIEW_COUNT,
 };
 use anyhow::Result;
 use ash::vk::{self, Handle};
-use glam::{Affine3A, Mat4, Vec3, Vec4};
+use glam::{Affine3A, Mat4, UVec4, Vec3, Vec4};
 use openxr as xr;
 use vk_shader_macros::include_glsl;
 
@@ -50,11 +63,20 @@ static COMPUTE: &[u32] = include_glsl!("src/shaders/culling.comp", target: vulka
 
 // TODO: Is this a good idea?
 pub const PIPELINE_DEPTH: usize = 2;
+/// The default number of MSAA samples. See [`RenderSettings::samples`].
 pub const SAMPLES: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_4;
+/// The render area can shrink with dynamic resolution, so pipelines drawing into the main render
+/// pass set their viewport and scissor when it begins.
+pub(crate) const DYNAMIC_VIEWPORT_STATES: [vk::DynamicState; 2] =
+    [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+/// Distance to the near clipping plane, in metres
+pub const NEAR_PLANE: f32 = 0.05;
 
 pub struct RenderContext {
     pub frame_index: usize,
     pub pipeline: vk::Pipeline,
+    /// Used to draw `ALPHA_BLEND` materials, after all opaque geometry
+    pub transparent_pipeline: vk::Pipeline,
     pub compute_pipeline: vk::Pipeline,
     pub pipeline_layout: vk::PipelineLayout,
     pub compute_pipeline_layout: vk::PipelineLayout,
@@ -67,6 +89,34 @@ pub struct RenderContext {
     pub swapchain: Swapchain,
     pub descriptors: Descriptors,
     pub shaders: Shaders,
+    /// Shadow maps, and the settings for each light's shadows
+    pub shadow_maps: ShadowMaps,
+    /// Draws the skybox
+    pub skybox_pipeline: vk::Pipeline,
+    /// The skybox drawn behind the scene, if any
+    pub skybox: Option<Skybox>,
+    /// Draws [`crate::rendering::debug_draw::DebugDraw`] lines on top of the scene
+    pub debug_draw_pipeline: vk::Pipeline,
+    /// Draws [`crate::components::ParticleEmitter`] particles after the transparent meshes
+    pub particle_pipeline: vk::Pipeline,
+    /// Optional occlusion culling against big occluders, eg. walls
+    pub occlusion_culling: OcclusionCulling,
+    pub post_processing_pipeline: vk::Pipeline,
+    /// Tone mapping, color grading, vignette and fade settings
+    pub post_processing: PostProcessing,
+    /// Fades the view to a color, eg. while loading a new scene
+    pub screen_fade: ScreenFade,
+    /// Draws [`crate::components::RenderTargetCamera`]s
+    pub render_targets: RenderTargets,
+    /// The render layers drawn into the left and right eye. Defaults to all of them.
+    pub view_layers: [RenderLayers; 2],
+    /// Did `scene_data.lights` come from `Light` components last frame? If so, they're cleared
+    /// when the last `Light` is despawned.
+    pub(crate) lights_from_world: bool,
+    settings: RenderSettings,
+    resolution_scale: f32,
+    gpu_timer: Option<GpuTimer>,
+    last_gpu_time: Option<f32>,
     // Populated only between rendering::begin and rendering::end
     pub primitive_map: HashMap<u32, InstancedPrimitive>,
 }
@@ -103,13 +153,29 @@ impl Shaders {
 
 impl RenderContext {
     pub fn new(vulkan_context: &VulkanContext, xr_context: &XrContext) -> Result<Self> {
+        Self::new_with_settings(vulkan_context, xr_context, Default::default())
+    }
+
+    /// Create a renderer with the given settings. Settings the GPU doesn't support fall back to
+    /// the defaults.
+    pub fn new_with_settings(
+        vulkan_context: &VulkanContext,
+        xr_context: &XrContext,
+        settings: RenderSettings,
+    ) -> Result<Self> {
         println!("[HOTHAM_RENDERER] Creating renderer..");
         let xr_swapchain = &xr_context.swapchain;
         let swapchain_resolution = xr_context.swapchain_resolution;
 
+        // The swapchain format has already been checked against what the runtime supports.
+        let settings = RenderSettings {
+            color_format: xr_context.color_format,
+            ..settings
+        };
+
         // Build swapchain
         let swapchain = SwapchainInfo::from_openxr_swapchain(xr_swapchain, swapchain_resolution)?;
-        Self::new_from_swapchain_info(vulkan_context, &swapchain)
+        Self::new_from_swapchain_info(vulkan_context, &swapchain, settings)
     }
 
     /// Command buffer of the current frame
@@ -121,16 +187,28 @@ impl RenderContext {
         self.swapchain.render_area
     }
 
+    /// The settings the renderer was created with, after falling back from anything unsupported
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// The current resolution scale. With dynamic resolution this changes from frame to frame.
+    pub fn resolution_scale(&self) -> f32 {
+        self.resolution_scale
+    }
+
     pub(crate) fn new_from_swapchain_info(
         vulkan_context: &VulkanContext,
         swapchain_info: &SwapchainInfo,
+        settings: RenderSettings,
     ) -> Result<Self> {
+        let settings = validate_settings(vulkan_context, settings);
         let descriptors = unsafe { Descriptors::new(vulkan_context) };
         let resources = unsafe { Resources::new(vulkan_context, &descriptors) };
 
         // Pipeline, render pass
-        let render_pass = create_render_pass(vulkan_context)?;
-        let swapchain = Swapchain::new(swapchain_info, vulkan_context, render_pass);
+        let render_pass = create_render_pass(vulkan_context, &settings)?;
+        let mut swapchain = Swapchain::new(swapchain_info, vulkan_context, render_pass, &settings);
         let pipeline_layout =
             create_pipeline_layout(vulkan_context, slice_from_ref(&descriptors.graphics_layout))?;
 
@@ -139,11 +217,63 @@ impl RenderContext {
         let pipeline = create_pipeline(
             vulkan_context,
             pipeline_layout,
-            &swapchain.render_area,
+            settings.samples,
+            render_pass,
+            &shaders,
+            BlendMode::Opaque,
+        )?;
+        let transparent_pipeline = create_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            settings.samples,
             render_pass,
             &shaders,
+            BlendMode::AlphaBlend,
+        )?;
+
+        let render_targets = RenderTargets::new(
+            vulkan_context,
+            pipeline_layout,
+            &shaders,
+            settings.depth_format,
+        )?;
+
+        let shadow_maps = ShadowMaps::new(vulkan_context, &descriptors, pipeline_layout)?;
+        let skybox_pipeline = create_skybox_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            settings.samples,
+            render_pass,
+        )?;
+
+        let debug_draw_pipeline = create_debug_draw_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            settings.samples,
+            render_pass,
+        )?;
+
+        let particle_pipeline = create_particle_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            settings.samples,
+            render_pass,
         )?;
 
+        let occlusion_culling =
+            OcclusionCulling::new(vulkan_context, &descriptors, pipeline_layout)?;
+
+        let post_processing_pipeline = create_post_processing_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            settings.samples,
+            render_pass,
+        )?;
+        unsafe {
+            descriptors
+                .write_scene_color_descriptor(vulkan_context, swapchain.scene_color_image.view);
+        }
+
         let (compute_pipeline, compute_pipeline_layout) = create_compute_pipeline(
             &vulkan_context.device,
             slice_from_ref(&descriptors.compute_layout),
@@ -161,11 +291,22 @@ impl RenderContext {
 
         let scene_data = Default::default();
 
+        // The swapchain is big enough for the largest scale, so start with part of it.
+        let resolution_scale = settings.initial_resolution_scale();
+        swapchain.render_area.extent = scale_extent(
+            swapchain.resolution,
+            resolution_scale / settings.max_resolution_scale(),
+        );
+        let gpu_timer = settings
+            .dynamic_resolution
+            .and_then(|_| GpuTimer::new(vulkan_context));
+
         Ok(Self {
             frames,
             frame_index: 0,
             swapchain,
             pipeline,
+            transparent_pipeline,
             compute_pipeline,
             pipeline_layout,
             compute_pipeline_layout,
@@ -176,6 +317,22 @@ impl RenderContext {
             descriptors,
             resources,
             shaders,
+            shadow_maps,
+            skybox_pipeline,
+            skybox: None,
+            debug_draw_pipeline,
+            particle_pipeline,
+            occlusion_culling,
+            post_processing_pipeline,
+            post_processing: Default::default(),
+            screen_fade: Default::default(),
+            render_targets,
+            view_layers: [RenderLayers::ALL; 2],
+            lights_from_world: false,
+            settings,
+            resolution_scale,
+            gpu_timer,
+            last_gpu_time: None,
             primitive_map: HashMap::default(),
         })
     }
@@ -191,7 +348,7 @@ impl RenderContext {
         // Create an image with vulkan_context
         let image = vulkan_context
             .create_image(
-                COLOR_FORMAT,
+                crate::COLOR_FORMAT,
                 &resolution,
                 vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
                 2,
@@ -208,7 +365,8 @@ impl RenderContext {
         };
 
         (
-            RenderContext::new_from_swapchain_info(&vulkan_context, &swapchain).unwrap(),
+            RenderContext::new_from_swapchain_info(&vulkan_context, &swapchain, Default::default())
+                .unwrap(),
             vulkan_context,
         )
     }
@@ -224,7 +382,7 @@ impl RenderContext {
         // Create an image with vulkan_context
         let image = vulkan_context
             .create_image(
-                COLOR_FORMAT,
+                crate::COLOR_FORMAT,
                 &resolution,
                 vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
                 2,
@@ -241,7 +399,8 @@ impl RenderContext {
         };
 
         (
-            RenderContext::new_from_swapchain_info(&vulkan_context, &swapchain).unwrap(),
+            RenderContext::new_from_swapchain_info(&vulkan_context, &swapchain, Default::default())
+                .unwrap(),
             vulkan_context,
             image,
         )
@@ -264,14 +423,12 @@ impl RenderContext {
             .collect::<Vec<_>>();
 
         // Projection
-        let near = 0.05;
-
         let fov_left = views[0].fov;
         let fov_right = views[1].fov;
 
         self.scene_data.view_projection = [
-            Frustum::from(fov_left).projection(near) * view_matrices[0],
-            Frustum::from(fov_right).projection(near) * view_matrices[1],
+            Frustum::from(fov_left).projection(NEAR_PLANE) * view_matrices[0],
+            Frustum::from(fov_right).projection(NEAR_PLANE) * view_matrices[1],
         ];
 
         self.scene_data.camera_position = [
@@ -285,16 +442,52 @@ impl RenderContext {
             scene_data.camera_position = self.scene_data.camera_position;
             scene_data.view_projection = self.scene_data.view_projection;
             scene_data.params = self.scene_data.params;
+            scene_data.view_layers = UVec4::new(self.view_layers[0].0, self.view_layers[1].0, 0, 0);
             scene_data.lights = self.scene_data.lights.clone();
             for light in &mut scene_data.lights {
                 light.position = gos_from_global.transform_point3(light.position);
                 light.direction = gos_from_global.transform_vector3(light.direction);
             }
+
+            // Shadows are fitted around the point between the player's eyes.
+            let eyes_in_gos =
+                ((scene_data.camera_position[0] + scene_data.camera_position[1]) * 0.5).truncate();
+            (scene_data.shadow_from_gos, scene_data.shadow_params) = self
+                .shadow_maps
+                .shadow_data(&scene_data.lights, eyes_in_gos);
+
+            scene_data.blob_shadows = self.scene_data.blob_shadows;
+            for blob_shadow in &mut scene_data.blob_shadows {
+                let position = blob_shadow.position_and_radius.truncate();
+                blob_shadow.position_and_radius = gos_from_global
+                    .transform_point3(position)
+                    .extend(blob_shadow.position_and_radius.w);
+            }
         }
     }
 
+    /// Replace the cube maps used for Image Based Lighting, eg. when loading a new level.
+    ///
+    /// Waits for the GPU to finish any work in flight, as it may still be using the old maps. The
+    /// old maps aren't destroyed, so they can be swapped back in later.
+    pub fn set_environment_map(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        environment_map: EnvironmentMap,
+    ) {
+        unsafe {
+            vulkan_context.device.device_wait_idle().unwrap();
+            environment_map.write_descriptors(
+                vulkan_context,
+                &self.descriptors,
+                self.resources.cube_sampler,
+            );
+        }
+        self.resources.environment_map = environment_map;
+    }
+
     /// Start rendering a frame
-    pub fn begin_frame(&self, vulkan_context: &VulkanContext) {
+    pub fn begin_frame(&mut self, vulkan_context: &VulkanContext) {
         // Get the values we need to start the frame..
         let device = &vulkan_context.device;
         let frame = &self.frames[self.frame_index];
@@ -302,6 +495,9 @@ impl RenderContext {
         // Wait for the GPU to be ready.
         self.wait(device, frame);
 
+        // Anything freed before this frame was last submitted is no longer being drawn.
+        self.resources.frame_finished(self.frame_index);
+
         let command_buffer = frame.command_buffer;
         unsafe {
             device
@@ -312,6 +508,29 @@ impl RenderContext {
                 )
                 .unwrap();
         }
+
+        // The last frame to use these resources has finished, so its timings are ready.
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            self.last_gpu_time = gpu_timer.read(device, self.frame_index);
+            unsafe { gpu_timer.begin(device, command_buffer, self.frame_index) };
+        }
+    }
+
+    /// Pick the resolution scale for the next frame from how long the GPU took to render the last
+    /// one. Does nothing unless dynamic resolution is enabled.
+    pub(crate) fn update_resolution_scale(&mut self, frame_period: f32) {
+        let (dynamic_resolution, gpu_time) =
+            match (self.settings.dynamic_resolution, self.last_gpu_time.take()) {
+                (Some(dynamic_resolution), Some(gpu_time)) => (dynamic_resolution, gpu_time),
+                _ => return,
+            };
+
+        self.resolution_scale =
+            dynamic_resolution.next_scale(self.resolution_scale, gpu_time, frame_period);
+        self.swapchain.render_area.extent = scale_extent(
+            self.swapchain.resolution,
+            self.resolution_scale / self.settings.max_resolution_scale(),
+        );
     }
 
     pub fn cull_objects(&mut self, vulkan_context: &VulkanContext) {
@@ -323,9 +542,11 @@ impl RenderContext {
         let fence = frame.compute_fence;
 
         // Create the cull parameters to pass to the compute shader
+        let occlusion_culling = self.occlusion_culling.is_active();
         let cull_params = CullParams::new(
             &self.scene_data.view_projection,
             primitive_cull_buffer.len(),
+            occlusion_culling,
         );
 
         unsafe {
@@ -342,6 +563,22 @@ impl RenderContext {
                         .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
                 )
                 .unwrap();
+
+            // Draw the occluders and build the Hi-Z pyramid before culling against it.
+            if occlusion_culling {
+                self.occlusion_culling.record(
+                    device,
+                    command_buffer,
+                    self.pipeline_layout,
+                    self.descriptors.sets[frame_index],
+                    &[
+                        self.resources.position_buffer.buffer,
+                        self.resources.vertex_buffer.buffer,
+                    ],
+                    self.resources.index_buffer.buffer,
+                );
+            }
+
             device.cmd_bind_pipeline(
                 command_buffer,
                 vk::PipelineBindPoint::COMPUTE,
@@ -387,11 +624,20 @@ impl RenderContext {
         let framebuffer = self.swapchain.framebuffers[swapchain_image_index];
 
         // Begin the renderpass.
+        let render_area = self.swapchain.render_area;
         let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
             .render_pass(self.render_pass)
             .framebuffer(framebuffer)
-            .render_area(self.swapchain.render_area)
+            .render_area(render_area)
             .clear_values(&CLEAR_VALUES);
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: render_area.extent.width as _,
+            height: render_area.extent.height as _,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
 
         unsafe {
             device.cmd_begin_render_pass(
@@ -399,6 +645,8 @@ impl RenderContext {
                 &render_pass_begin_info,
                 vk::SubpassContents::INLINE,
             );
+            device.cmd_set_viewport(command_buffer, 0, slice_from_ref(&viewport));
+            device.cmd_set_scissor(command_buffer, 0, slice_from_ref(&render_area));
             device.cmd_bind_pipeline(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
@@ -430,11 +678,36 @@ impl RenderContext {
         }
     }
 
+    /// Run the post-processing subpass, which writes the final image to the swapchain, and end the
+    /// PBR renderpass.
     pub fn end_pbr_render_pass(&mut self, vulkan_context: &VulkanContext) {
         let device = &vulkan_context.device;
         let frame = &self.frames[self.frame_index];
         let command_buffer = frame.command_buffer;
+        let push_constants = self.post_processing.push_constants();
         unsafe {
+            device.cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.post_processing_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                slice_from_ref(&self.descriptors.sets[self.frame_index]),
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                create_push_constant(&push_constants),
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
             device.cmd_end_render_pass(command_buffer);
         }
     }
@@ -449,6 +722,9 @@ impl RenderContext {
 
         // End the render pass and submit.
         unsafe {
+            if let Some(gpu_timer) = &mut self.gpu_timer {
+                gpu_timer.end(device, command_buffer, self.frame_index);
+            }
             device.end_command_buffer(command_buffer).unwrap();
             let fence = frame.fence;
             let submit_info =
@@ -458,6 +734,9 @@ impl RenderContext {
                 .expect("[HOTHAM_RENDER] @@ GPU CRASH DETECTED @@ - You are probably doing too much work in a compute shader!");
         }
 
+        // Space freed while recording this frame can be reused once it's finished.
+        self.resources.frame_submitted(self.frame_index);
+
         // And we're done! Bump the frame index.
         self.frame_index = (self.frame_index + 1) % PIPELINE_DEPTH;
     }
@@ -526,6 +805,25 @@ pub struct Instance {
     pub gos_from_local: Affine3A,
     pub bounding_sphere: Vec4,
     pub skin_id: u32,
+    pub layers: RenderLayers,
+    /// The tint from the instance's `MaterialOverride`, packed as four unorm8
+    pub tint: u32,
+    /// The emission from the instance's `MaterialOverride`, packed as three half floats
+    pub packed_emissive: [u32; 2],
+}
+
+impl Instance {
+    /// The draw data the shaders need to draw this instance
+    pub fn draw_data(&self) -> DrawData {
+        DrawData {
+            gos_from_local: self.gos_from_local.into(),
+            local_from_gos: self.gos_from_local.inverse().into(),
+            skin_id: self.skin_id,
+            layers: self.layers.0,
+            tint: self.tint,
+            packed_emissive: self.packed_emissive,
+        }
+    }
 }
 
 // TODO: use bytemuck instead
@@ -533,26 +831,65 @@ pub fn create_push_constant<T: 'static>(p: &T) -> &[u8] {
     unsafe { std::slice::from_raw_parts(p as *const T as *const u8, std::mem::size_of::<T>()) }
 }
 
+/// Fall back to the defaults for any settings the GPU doesn't support.
+fn validate_settings(vulkan_context: &VulkanContext, settings: RenderSettings) -> RenderSettings {
+    let mut settings = settings;
+    let limits = &vulkan_context.physical_device_properties.limits;
+    let samples = supported_samples(settings.samples, limits);
+    if samples != settings.samples {
+        println!(
+            "[HOTHAM_RENDERER] {:?} MSAA is not supported, using {:?}",
+            settings.samples, samples
+        );
+        settings.samples = samples;
+    }
+
+    let depth_format_properties = unsafe {
+        vulkan_context
+            .instance
+            .get_physical_device_format_properties(
+                vulkan_context.physical_device,
+                settings.depth_format,
+            )
+    };
+    let depth_attachment_supported = depth_format_properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT);
+    if !is_depth_format(settings.depth_format) || !depth_attachment_supported {
+        println!(
+            "[HOTHAM_RENDERER] Depth format {:?} is not supported, using {:?}",
+            settings.depth_format, DEPTH_FORMAT
+        );
+        settings.depth_format = DEPTH_FORMAT;
+    }
+
+    settings
+}
+
 // TODO: Handle Android/Desktop code split more elegantly
-fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass> {
-    // Attachment used for MSAA
+fn create_render_pass(
+    vulkan_context: &VulkanContext,
+    settings: &RenderSettings,
+) -> Result<vk::RenderPass> {
+    // Attachment used for MSAA, in HDR. It never leaves tile memory: the post-processing subpass
+    // reads it and writes to the swapchain.
     let color_store_op = vk::AttachmentStoreOp::DONT_CARE;
     let color_attachment = vk::AttachmentDescription::builder()
-        .format(COLOR_FORMAT)
-        .samples(SAMPLES)
+        .format(SCENE_COLOR_FORMAT)
+        .samples(settings.samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(color_store_op)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
 
-    // Final attachment to be presented
-    let color_attachment_resolve = vk::AttachmentDescription::builder()
-        .format(COLOR_FORMAT)
+    // Final attachment to be presented, written by the post-processing subpass
+    let color_attachment_output = vk::AttachmentDescription::builder()
+        .format(settings.color_format)
         .samples(vk::SampleCountFlags::TYPE_1)
         .load_op(vk::AttachmentLoadOp::DONT_CARE)
-        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
@@ -560,8 +897,8 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
 
     // Depth buffer
     let depth_attachment = vk::AttachmentDescription::builder()
-        .format(DEPTH_FORMAT)
-        .samples(SAMPLES)
+        .format(settings.depth_format)
+        .samples(settings.samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(vk::AttachmentStoreOp::DONT_CARE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
@@ -599,16 +936,30 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
         .attachment(2)
         .layout(vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT);
 
-    let color_attachment_resolve_reference = vk::AttachmentReference::builder()
-        .attachment(RESOLVE_ATTACHMENT)
+    let scene_color_input_reference = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .build();
+
+    let color_attachment_output_reference = vk::AttachmentReference::builder()
+        .attachment(OUTPUT_ATTACHMENT)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
         .build();
 
-    let subpass = vk::SubpassDescription::builder()
-        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .color_attachments(&color_attachment_reference)
-        .resolve_attachments(std::slice::from_ref(&color_attachment_resolve_reference))
-        .depth_stencil_attachment(&depth_stencil_reference);
+    let subpasses = [
+        // The scene
+        vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_reference)
+            .depth_stencil_attachment(&depth_stencil_reference)
+            .build(),
+        // Post-processing
+        vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .input_attachments(slice_from_ref(&scene_color_input_reference))
+            .color_attachments(slice_from_ref(&color_attachment_output_reference))
+            .build(),
+    ];
 
     let dependency = vk::SubpassDependency::builder()
         .src_subpass(vk::SUBPASS_EXTERNAL)
@@ -625,12 +976,27 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
         .dst_access_mask(
             vk::AccessFlags::COLOR_ATTACHMENT_WRITE
                 | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-        );
+        )
+        .build();
+
+    // Post-processing only reads the pixel it's writing, so it can stay on the same tile.
+    let post_processing_dependency = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(POST_PROCESSING_SUBPASS)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
+        .dependency_flags(vk::DependencyFlags::BY_REGION | vk::DependencyFlags::VIEW_LOCAL)
+        .build();
 
-    let view_masks = [!(!0 << VIEW_COUNT)];
+    let dependencies = [dependency, post_processing_dependency];
+
+    let view_mask = !(!0 << VIEW_COUNT);
+    let view_masks = [view_mask; 2];
     let mut multiview = vk::RenderPassMultiviewCreateInfo::builder()
         .view_masks(&view_masks)
-        .correlation_masks(&view_masks);
+        .correlation_masks(slice_from_ref(&view_mask));
 
     #[cfg(target_os = "android")]
     let mut ffr_info = vk::RenderPassFragmentDensityMapCreateInfoEXT::builder()
@@ -641,21 +1007,21 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
         *color_attachment,
         *depth_attachment,
         *ffr_attachment,
-        *color_attachment_resolve,
+        *color_attachment_output,
     ];
 
     #[cfg(not(target_os = "android"))]
     let attachments = [
         *color_attachment,
         *depth_attachment,
-        *color_attachment_resolve,
+        *color_attachment_output,
     ];
 
     #[allow(unused_mut)]
     let mut create_info = vk::RenderPassCreateInfo::builder()
         .attachments(&attachments)
-        .subpasses(std::slice::from_ref(&subpass))
-        .dependencies(std::slice::from_ref(&dependency))
+        .subpasses(&subpasses)
+        .dependencies(&dependencies)
         .push_next(&mut multiview);
 
     #[cfg(target_os = "android")]
@@ -666,13 +1032,25 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
     Ok(render_pass)
 }
 
+/// How a pipeline writes to the color and depth attachments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrite the color attachment and write depth
+    Opaque,
+    /// Blend with the color attachment using the fragment's alpha, and don't write depth
+    AlphaBlend,
+}
+
 pub(crate) fn create_pipeline(
     vulkan_context: &VulkanContext,
     pipeline_layout: vk::PipelineLayout,
-    render_area: &vk::Rect2D,
+    samples: vk::SampleCountFlags,
     render_pass: vk::RenderPass,
     shaders: &Shaders,
+    blend_mode: BlendMode,
 ) -> Result<vk::Pipeline> {
+    let alpha_blend = blend_mode == BlendMode::AlphaBlend;
+
     // Build up the state of the pipeline
 
     // Vertex shader stage
@@ -713,23 +1091,12 @@ pub(crate) fn create_pipeline(
     let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
         .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
 
-    // Viewport State
-    let viewport = vk::Viewport {
-        x: 0.0,
-        y: 0.0,
-        width: render_area.extent.width as _,
-        height: render_area.extent.height as _,
-        min_depth: 0.0,
-        max_depth: 1.0,
-    };
-    let viewports = [viewport];
-
-    // Scissors
-    let scissors = [*render_area];
-
+    // Viewport State, set when the render pass begins
     let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(&viewports)
-        .scissors(&scissors);
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_VIEWPORT_STATES);
 
     // Rasterization state
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
@@ -746,12 +1113,12 @@ pub(crate) fn create_pipeline(
 
     // Multisample state
     let multisample_state =
-        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(SAMPLES);
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
 
     // Depth stencil state
     let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
         .depth_test_enable(true)
-        .depth_write_enable(true)
+        .depth_write_enable(!alpha_blend)
         .depth_compare_op(vk::CompareOp::GREATER)
         .depth_bounds_test_enable(false)
         .min_depth_bounds(0.0)
@@ -766,7 +1133,13 @@ pub(crate) fn create_pipeline(
                 | vk::ColorComponentFlags::B
                 | vk::ColorComponentFlags::A,
         )
-        .blend_enable(false)
+        .blend_enable(alpha_blend)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
         .build();
 
     let color_blend_attachments = [color_blend_attachment];
@@ -784,6 +1157,7 @@ pub(crate) fn create_pipeline(
         .multisample_state(&multisample_state)
         .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
         .layout(pipeline_layout)
         .render_pass(render_pass)
         .subpass(0)
@@ -862,15 +1236,21 @@ pub struct CullParams {
     /// Four clip planes per camera, one plane per row.
     pub left_clip_planes: Mat4,
     pub right_clip_planes: Mat4,
+    /// Used to project bounding spheres for occlusion culling
+    pub view_projection: [Mat4; 2],
     pub draw_calls: u32,
+    /// Non-zero if primitives should be tested against the Hi-Z pyramid
+    pub occlusion_culling: u32,
 }
 
 impl CullParams {
-    fn new(view_projections: &[Mat4; 2], draw_calls: usize) -> Self {
+    fn new(view_projections: &[Mat4; 2], draw_calls: usize, occlusion_culling: bool) -> Self {
         Self {
             left_clip_planes: extract_planes_from_frustum(&view_projections[0]),
             right_clip_planes: extract_planes_from_frustum(&view_projections[1]),
+            view_projection: *view_projections,
             draw_calls: draw_calls as u32,
+            occlusion_culling: occlusion_culling as u32,
         }
     }
 }
@@ -2,8 +2,11 @@
 
 use crate::{
     hotham_error::HothamError,
-    rendering::{image::Image, texture::DEFAULT_COMPONENT_MAPPING},
-    DEPTH_FORMAT,
+    rendering::{
+        image::Image,
+        render_settings::{has_stencil, is_depth_format},
+        texture::DEFAULT_COMPONENT_MAPPING,
+    },
 };
 use anyhow::{anyhow, Result};
 use ash::{
@@ -336,6 +339,36 @@ impl VulkanContext {
         array_layers: u32,
         mip_levels: u32,
         component_mapping: vk::ComponentMapping,
+    ) -> Result<Image> {
+        // TODO: This indicates that it's MSAA.. but do we need MSAA for depth?
+        let samples = if usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+            SAMPLES
+        } else {
+            vk::SampleCountFlags::TYPE_1
+        };
+
+        self.create_image_with_samples(
+            format,
+            extent,
+            usage,
+            array_layers,
+            mip_levels,
+            component_mapping,
+            samples,
+        )
+    }
+
+    /// Create an image with an explicit sample count, eg. for MSAA attachments.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_image_with_samples(
+        &self,
+        format: vk::Format,
+        extent: &vk::Extent2D,
+        usage: vk::ImageUsageFlags,
+        array_layers: u32,
+        mip_levels: u32,
+        component_mapping: vk::ComponentMapping,
+        samples: vk::SampleCountFlags,
     ) -> Result<Image> {
         let tiling = vk::ImageTiling::OPTIMAL;
         let (flags, image_view_type) = if array_layers == 1 {
@@ -357,13 +390,6 @@ impl VulkanContext {
             )
         };
 
-        // TODO: This indicates that it's MSAA.. but do we need MSAA for depth?
-        let samples = if usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
-            SAMPLES
-        } else {
-            vk::SampleCountFlags::TYPE_1
-        };
-
         let create_info = vk::ImageCreateInfo::builder()
             .format(format)
             .image_type(vk::ImageType::TYPE_2D)
@@ -914,7 +940,9 @@ fn create_descriptor_pool(device: &Device) -> Result<vk::DescriptorPool, anyhow:
 }
 
 fn get_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
-    if format == DEPTH_FORMAT {
+    if has_stencil(format) {
+        vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+    } else if is_depth_format(format) {
         vk::ImageAspectFlags::DEPTH
     } else {
         vk::ImageAspectFlags::COLOR
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Interaction profile for the Oculus / Meta Touch controllers
+pub const OCULUS_TOUCH_PROFILE: &str = "/interaction_profiles/oculus/touch_controller";
+/// Interaction profile for the Valve Index controllers
+pub const VALVE_INDEX_PROFILE: &str = "/interaction_profiles/valve/index_controller";
+/// Interaction profile for the HTC Vive controllers
+pub const HTC_VIVE_PROFILE: &str = "/interaction_profiles/htc/vive_controller";
+/// Interaction profile for the Pico 4 controllers. Requires `XR_BD_controller_interaction`,
+/// which can be enabled with `EngineBuilder::openxr_extensions`.
+pub const PICO_4_PROFILE: &str = "/interaction_profiles/bytedance/pico4_controller";
+/// Interaction profile for Windows Mixed Reality motion controllers
+pub const WMR_PROFILE: &str = "/interaction_profiles/microsoft/motion_controller";
+/// The Khronos simple controller profile, supported by every runtime
+pub const KHR_SIMPLE_PROFILE: &str = "/interaction_profiles/khr/simple_controller";
+
+/// The type of value an action produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionType {
+    /// A button, eg. `/input/a/click`
+    Boolean,
+    /// An analog value from 0 to 1, eg. `/input/trigger/value`
+    Float,
+    /// A 2D axis, eg. `/input/thumbstick`
+    Vector2,
+    /// A pose, eg. `/input/grip/pose`
+    Pose,
+    /// A haptic output, eg. `/output/haptic`
+    Haptic,
+}
+
+impl ActionType {
+    fn parse(s: &str) -> Option<ActionType> {
+        match s {
+            "boolean" => Some(ActionType::Boolean),
+            "float" => Some(ActionType::Float),
+            "vector2" => Some(ActionType::Vector2),
+            "pose" => Some(ActionType::Pose),
+            "haptic" => Some(ActionType::Haptic),
+            _ => None,
+        }
+    }
+}
+
+/// A named action, along with the bindings suggested for each interaction profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionDescription {
+    /// The name of the action. Must be lowercase, eg. `jump`
+    pub name: String,
+    /// The name of the action displayed to the user, eg. `Jump`
+    pub localized_name: String,
+    /// The type of value this action produces
+    pub action_type: ActionType,
+    /// Suggested binding paths, keyed by interaction profile
+    pub bindings: HashMap<String, Vec<String>>,
+}
+
+/// A description of all the actions an application is interested in, and how they should be bound
+/// to each controller.
+///
+/// Every action is created for both `/user/hand/left` and `/user/hand/right`, so its value can be
+/// queried for each hand with [`crate::contexts::input_context::ActionsInputContext`].
+///
+/// [`ActionMap::default`] contains the actions used by [`crate::contexts::InputContext`], named after
+/// the buttons on the Oculus Touch controller, with bindings for Touch, Valve Index, HTC Vive,
+/// Pico 4, Windows Mixed Reality and the Khronos simple controller. Applications should usually
+/// start with the default map and add their own actions to it.
+///
+/// ## Overrides
+/// Bindings can be overridden with [`ActionMap::apply_overrides`], using a simple text format:
+///
+/// ```text
+/// # Declare a new action: action <name> <boolean|float|vector2|pose|haptic> [localized name]
+/// action jump boolean Jump
+///
+/// # Replace the suggested bindings for an action: bind <interaction profile> <action> [paths..]
+/// bind /interaction_profiles/valve/index_controller jump /user/hand/right/input/a/click
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionMap {
+    actions: Vec<ActionDescription>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut action_map = ActionMap::empty();
+        for (name, localized_name, action_type) in DEFAULT_ACTIONS {
+            action_map.add_action(name, localized_name, *action_type);
+        }
+
+        for (profile, name, hands, input) in DEFAULT_BINDINGS {
+            let paths = hands
+                .iter()
+                .map(|hand| format!("/user/hand/{hand}/{input}"))
+                .collect::<Vec<_>>();
+            let paths = paths.iter().map(String::as_str).collect::<Vec<_>>();
+            action_map.suggest_bindings(name, profile, &paths);
+        }
+
+        action_map
+    }
+}
+
+impl ActionMap {
+    /// Create an action map with no actions. Note that [`crate::contexts::InputContext`] depends
+    /// on the actions in [`ActionMap::default`].
+    pub fn empty() -> Self {
+        ActionMap {
+            actions: Vec::new(),
+        }
+    }
+
+    /// Add an action. If an action with this name already exists, it will be replaced.
+    pub fn add_action(
+        &mut self,
+        name: &str,
+        localized_name: &str,
+        action_type: ActionType,
+    ) -> &mut Self {
+        self.actions.retain(|a| a.name != name);
+        self.actions.push(ActionDescription {
+            name: name.to_string(),
+            localized_name: localized_name.to_string(),
+            action_type,
+            bindings: Default::default(),
+        });
+        self
+    }
+
+    /// Suggest that `action` be bound to `paths` when using the controller described by `profile`.
+    pub fn suggest_bindings(&mut self, action: &str, profile: &str, paths: &[&str]) -> &mut Self {
+        match self.actions.iter_mut().find(|a| a.name == action) {
+            Some(action) => action
+                .bindings
+                .entry(profile.to_string())
+                .or_default()
+                .extend(paths.iter().map(|p| p.to_string())),
+            None => println!("[HOTHAM_INPUT] Unable to add binding for unknown action {action}"),
+        }
+        self
+    }
+
+    /// Remove all suggested bindings for `action` with the controller described by `profile`.
+    pub fn clear_bindings(&mut self, action: &str, profile: &str) -> &mut Self {
+        if let Some(action) = self.actions.iter_mut().find(|a| a.name == action) {
+            action.bindings.remove(profile);
+        }
+        self
+    }
+
+    /// All the actions in this map.
+    pub fn actions(&self) -> &[ActionDescription] {
+        &self.actions
+    }
+
+    /// Get an action by name.
+    pub fn get(&self, name: &str) -> Option<&ActionDescription> {
+        self.actions.iter().find(|a| a.name == name)
+    }
+
+    /// Every interaction profile that has at least one binding.
+    pub fn interaction_profiles(&self) -> Vec<&str> {
+        let mut profiles = self
+            .actions
+            .iter()
+            .flat_map(|a| a.bindings.keys())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        profiles.sort_unstable();
+        profiles.dedup();
+        profiles
+    }
+
+    /// Apply overrides from a file in the format described in [`ActionMap`].
+    pub fn apply_overrides_from_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<()> {
+        let overrides = std::fs::read_to_string(path)?;
+        self.apply_overrides(&overrides)
+    }
+
+    /// Apply overrides in the format described in [`ActionMap`].
+    ///
+    /// The first `bind` line for a given action and profile replaces any existing bindings for
+    /// that pair - subsequent lines add to them.
+    pub fn apply_overrides(&mut self, overrides: &str) -> Result<()> {
+        let mut replaced = Vec::new();
+
+        for (line_number, line) in overrides.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let error =
+                |reason: &str| anyhow!("Invalid action map line {}: {reason}", line_number + 1);
+
+            match words.next() {
+                Some("action") => {
+                    let name = words.next().ok_or_else(|| error("missing action name"))?;
+                    let action_type = words
+                        .next()
+                        .and_then(ActionType::parse)
+                        .ok_or_else(|| error("missing or invalid action type"))?;
+                    let localized_name = words.collect::<Vec<_>>().join(" ");
+                    let localized_name = if localized_name.is_empty() {
+                        name.to_string()
+                    } else {
+                        localized_name
+                    };
+                    self.add_action(name, &localized_name, action_type);
+                }
+                Some("bind") => {
+                    let profile = words.next().ok_or_else(|| error("missing profile"))?;
+                    let name = words.next().ok_or_else(|| error("missing action name"))?;
+                    if self.get(name).is_none() {
+                        return Err(error("unknown action"));
+                    }
+
+                    let key = (profile.to_string(), name.to_string());
+                    if !replaced.contains(&key) {
+                        self.clear_bindings(name, profile);
+                        replaced.push(key);
+                    }
+
+                    let paths = words.collect::<Vec<_>>();
+                    self.suggest_bindings(name, profile, &paths);
+                }
+                _ => return Err(error("expected `action` or `bind`")),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+static DEFAULT_ACTIONS: [(&str, &str, ActionType); 20] = [
+    ("grip_pose", "Grip Pose", ActionType::Pose),
+    ("aim_pose", "Aim Pose", ActionType::Pose),
+    ("squeeze", "Grip Pull", ActionType::Float),
+    ("trigger", "Trigger Pull", ActionType::Float),
+    ("trigger_touch", "Trigger Touch", ActionType::Boolean),
+    ("haptic_feedback", "Haptic Feedback", ActionType::Haptic),
+    ("x_button", "X Button", ActionType::Boolean),
+    ("x_touch", "X Button Touch", ActionType::Boolean),
+    ("y_button", "Y Button", ActionType::Boolean),
+    ("y_touch", "Y Button Touch", ActionType::Boolean),
+    ("menu_button", "Menu Button", ActionType::Boolean),
+    ("a_button", "A Button", ActionType::Boolean),
+    ("a_touch", "A Button Touch", ActionType::Boolean),
+    ("b_button", "B Button", ActionType::Boolean),
+    ("b_touch", "B Button Touch", ActionType::Boolean),
+    ("thumbstick_x", "Thumbstick X", ActionType::Float),
+    ("thumbstick_y", "Thumbstick Y", ActionType::Float),
+    ("thumbstick_click", "Thumbstick Click", ActionType::Boolean),
+    ("thumbstick_touch", "Thumbstick Touch", ActionType::Boolean),
+    ("thumbrest_touch", "Thumbrest Touch", ActionType::Boolean),
+];
+
+const BOTH: &[&str] = &["left", "right"];
+const LEFT: &[&str] = &["left"];
+const RIGHT: &[&str] = &["right"];
+
+/// (profile, action, hands, input)
+#[rustfmt::skip]
+static DEFAULT_BINDINGS: &[(&str, &str, &[&str], &str)] = &[
+    // Oculus Touch
+    (OCULUS_TOUCH_PROFILE, "grip_pose", BOTH, "input/grip/pose"),
+    (OCULUS_TOUCH_PROFILE, "aim_pose", BOTH, "input/aim/pose"),
+    (OCULUS_TOUCH_PROFILE, "squeeze", BOTH, "input/squeeze/value"),
+    (OCULUS_TOUCH_PROFILE, "trigger", BOTH, "input/trigger/value"),
+    (OCULUS_TOUCH_PROFILE, "trigger_touch", BOTH, "input/trigger/touch"),
+    (OCULUS_TOUCH_PROFILE, "haptic_feedback", BOTH, "output/haptic"),
+    (OCULUS_TOUCH_PROFILE, "x_button", LEFT, "input/x/click"),
+    (OCULUS_TOUCH_PROFILE, "x_touch", LEFT, "input/x/touch"),
+    (OCULUS_TOUCH_PROFILE, "y_button", LEFT, "input/y/click"),
+    (OCULUS_TOUCH_PROFILE, "y_touch", LEFT, "input/y/touch"),
+    (OCULUS_TOUCH_PROFILE, "menu_button", LEFT, "input/menu/click"),
+    (OCULUS_TOUCH_PROFILE, "a_button", RIGHT, "input/a/click"),
+    (OCULUS_TOUCH_PROFILE, "a_touch", RIGHT, "input/a/touch"),
+    (OCULUS_TOUCH_PROFILE, "b_button", RIGHT, "input/b/click"),
+    (OCULUS_TOUCH_PROFILE, "b_touch", RIGHT, "input/b/touch"),
+    (OCULUS_TOUCH_PROFILE, "thumbstick_x", BOTH, "input/thumbstick/x"),
+    (OCULUS_TOUCH_PROFILE, "thumbstick_y", BOTH, "input/thumbstick/y"),
+    (OCULUS_TOUCH_PROFILE, "thumbstick_click", BOTH, "input/thumbstick/click"),
+    (OCULUS_TOUCH_PROFILE, "thumbstick_touch", BOTH, "input/thumbstick/touch"),
+    (OCULUS_TOUCH_PROFILE, "thumbrest_touch", BOTH, "input/thumbrest/touch"),
+    // Valve Index - both controllers have A and B buttons, and the system button is reserved.
+    (VALVE_INDEX_PROFILE, "grip_pose", BOTH, "input/grip/pose"),
+    (VALVE_INDEX_PROFILE, "aim_pose", BOTH, "input/aim/pose"),
+    (VALVE_INDEX_PROFILE, "squeeze", BOTH, "input/squeeze/value"),
+    (VALVE_INDEX_PROFILE, "trigger", BOTH, "input/trigger/value"),
+    (VALVE_INDEX_PROFILE, "trigger_touch", BOTH, "input/trigger/touch"),
+    (VALVE_INDEX_PROFILE, "haptic_feedback", BOTH, "output/haptic"),
+    (VALVE_INDEX_PROFILE, "x_button", LEFT, "input/a/click"),
+    (VALVE_INDEX_PROFILE, "x_touch", LEFT, "input/a/touch"),
+    (VALVE_INDEX_PROFILE, "y_button", LEFT, "input/b/click"),
+    (VALVE_INDEX_PROFILE, "y_touch", LEFT, "input/b/touch"),
+    (VALVE_INDEX_PROFILE, "a_button", RIGHT, "input/a/click"),
+    (VALVE_INDEX_PROFILE, "a_touch", RIGHT, "input/a/touch"),
+    (VALVE_INDEX_PROFILE, "b_button", RIGHT, "input/b/click"),
+    (VALVE_INDEX_PROFILE, "b_touch", RIGHT, "input/b/touch"),
+    (VALVE_INDEX_PROFILE, "thumbstick_x", BOTH, "input/thumbstick/x"),
+    (VALVE_INDEX_PROFILE, "thumbstick_y", BOTH, "input/thumbstick/y"),
+    (VALVE_INDEX_PROFILE, "thumbstick_click", BOTH, "input/thumbstick/click"),
+    (VALVE_INDEX_PROFILE, "thumbstick_touch", BOTH, "input/thumbstick/touch"),
+    // HTC Vive - the trackpad stands in for the thumbstick.
+    (HTC_VIVE_PROFILE, "grip_pose", BOTH, "input/grip/pose"),
+    (HTC_VIVE_PROFILE, "aim_pose", BOTH, "input/aim/pose"),
+    (HTC_VIVE_PROFILE, "squeeze", BOTH, "input/squeeze/click"),
+    (HTC_VIVE_PROFILE, "trigger", BOTH, "input/trigger/value"),
+    (HTC_VIVE_PROFILE, "haptic_feedback", BOTH, "output/haptic"),
+    (HTC_VIVE_PROFILE, "menu_button", BOTH, "input/menu/click"),
+    (HTC_VIVE_PROFILE, "thumbstick_x", BOTH, "input/trackpad/x"),
+    (HTC_VIVE_PROFILE, "thumbstick_y", BOTH, "input/trackpad/y"),
+    (HTC_VIVE_PROFILE, "thumbstick_click", BOTH, "input/trackpad/click"),
+    (HTC_VIVE_PROFILE, "thumbstick_touch", BOTH, "input/trackpad/touch"),
+    // Pico 4
+    (PICO_4_PROFILE, "grip_pose", BOTH, "input/grip/pose"),
+    (PICO_4_PROFILE, "aim_pose", BOTH, "input/aim/pose"),
+    (PICO_4_PROFILE, "squeeze", BOTH, "input/squeeze/value"),
+    (PICO_4_PROFILE, "trigger", BOTH, "input/trigger/value"),
+    (PICO_4_PROFILE, "trigger_touch", BOTH, "input/trigger/touch"),
+    (PICO_4_PROFILE, "haptic_feedback", BOTH, "output/haptic"),
+    (PICO_4_PROFILE, "x_button", LEFT, "input/x/click"),
+    (PICO_4_PROFILE, "x_touch", LEFT, "input/x/touch"),
+    (PICO_4_PROFILE, "y_button", LEFT, "input/y/click"),
+    (PICO_4_PROFILE, "y_touch", LEFT, "input/y/touch"),
+    (PICO_4_PROFILE, "menu_button", LEFT, "input/menu/click"),
+    (PICO_4_PROFILE, "a_button", RIGHT, "input/a/click"),
+    (PICO_4_PROFILE, "a_touch", RIGHT, "input/a/touch"),
+    (PICO_4_PROFILE, "b_button", RIGHT, "input/b/click"),
+    (PICO_4_PROFILE, "b_touch", RIGHT, "input/b/touch"),
+    (PICO_4_PROFILE, "thumbstick_x", BOTH, "input/thumbstick/x"),
+    (PICO_4_PROFILE, "thumbstick_y", BOTH, "input/thumbstick/y"),
+    (PICO_4_PROFILE, "thumbstick_click", BOTH, "input/thumbstick/click"),
+    (PICO_4_PROFILE, "thumbstick_touch", BOTH, "input/thumbstick/touch"),
+    (PICO_4_PROFILE, "thumbrest_touch", BOTH, "input/thumbrest/touch"),
+    // Windows Mixed Reality
+    (WMR_PROFILE, "grip_pose", BOTH, "input/grip/pose"),
+    (WMR_PROFILE, "aim_pose", BOTH, "input/aim/pose"),
+    (WMR_PROFILE, "squeeze", BOTH, "input/squeeze/click"),
+    (WMR_PROFILE, "trigger", BOTH, "input/trigger/value"),
+    (WMR_PROFILE, "haptic_feedback", BOTH, "output/haptic"),
+    (WMR_PROFILE, "menu_button", BOTH, "input/menu/click"),
+    (WMR_PROFILE, "thumbstick_x", BOTH, "input/thumbstick/x"),
+    (WMR_PROFILE, "thumbstick_y", BOTH, "input/thumbstick/y"),
+    (WMR_PROFILE, "thumbstick_click", BOTH, "input/thumbstick/click"),
+    // Khronos simple controller
+    (KHR_SIMPLE_PROFILE, "grip_pose", BOTH, "input/grip/pose"),
+    (KHR_SIMPLE_PROFILE, "aim_pose", BOTH, "input/aim/pose"),
+    (KHR_SIMPLE_PROFILE, "trigger", BOTH, "input/select/click"),
+    (KHR_SIMPLE_PROFILE, "haptic_feedback", BOTH, "output/haptic"),
+    (KHR_SIMPLE_PROFILE, "menu_button", BOTH, "input/menu/click"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_default_action_map() {
+        let action_map = ActionMap::default();
+        assert_eq!(action_map.actions().len(), DEFAULT_ACTIONS.len());
+        assert_eq!(action_map.interaction_profiles().len(), 6);
+
+        let x_button = action_map.get("x_button").unwrap();
+        assert_eq!(x_button.action_type, ActionType::Boolean);
+        assert_eq!(
+            x_button.bindings[VALVE_INDEX_PROFILE],
+            vec!["/user/hand/left/input/a/click".to_string()]
+        );
+        assert!(!x_button.bindings.contains_key(KHR_SIMPLE_PROFILE));
+
+        let trigger = action_map.get("trigger").unwrap();
+        assert_eq!(
+            trigger.bindings[OCULUS_TOUCH_PROFILE],
+            vec![
+                "/user/hand/left/input/trigger/value".to_string(),
+                "/user/hand/right/input/trigger/value".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_apply_overrides() {
+        let mut action_map = ActionMap::default();
+        action_map
+            .apply_overrides(
+                "
+                # A custom action
+                action jump boolean Jump Button
+                bind /interaction_profiles/valve/index_controller jump /user/hand/right/input/a/click
+                bind /interaction_profiles/valve/index_controller jump /user/hand/left/input/a/click
+
+                # Remap an existing action
+                bind /interaction_profiles/oculus/touch_controller x_button /user/hand/left/input/y/click
+                ",
+            )
+            .unwrap();
+
+        let jump = action_map.get("jump").unwrap();
+        assert_eq!(jump.localized_name, "Jump Button");
+        assert_eq!(jump.bindings[VALVE_INDEX_PROFILE].len(), 2);
+
+        let x_button = action_map.get("x_button").unwrap();
+        assert_eq!(
+            x_button.bindings[OCULUS_TOUCH_PROFILE],
+            vec!["/user/hand/left/input/y/click".to_string()]
+        );
+
+        assert!(action_map
+            .apply_overrides("bind /a/b missing_action")
+            .is_err());
+        assert!(action_map
+            .apply_overrides("action broken not_a_type")
+            .is_err());
+        assert!(action_map.apply_overrides("something else").is_err());
+    }
+}
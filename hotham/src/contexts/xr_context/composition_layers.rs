@@ -0,0 +1,189 @@
+use anyhow::Result;
+use ash::vk::{self, Handle};
+use glam::Vec2;
+use openxr::{self as xr, Space, Swapchain, Vulkan};
+use xr::{CompositionLayerFlags, SwapchainCreateFlags, SwapchainUsageFlags};
+
+use crate::{
+    components::composition_layer::{LayerPlacement, LayerShape},
+    COLOR_FORMAT,
+};
+
+use super::XrContext;
+
+/// A composition layer to hand to the compositor at the end of this frame.
+#[derive(Debug, Clone)]
+pub(crate) struct LayerSubmission {
+    /// Index into [`XrContext::layer_swapchains`]. The image has already been acquired.
+    pub swapchain_index: usize,
+    /// The pose of the layer in stage space
+    pub pose: xr::Posef,
+    pub shape: LayerShape,
+    pub placement: LayerPlacement,
+    pub sort_order: i32,
+    /// The size of the panel in the world
+    pub world_size: Vec2,
+    pub resolution: vk::Extent2D,
+}
+
+/// A built OpenXR composition layer, so layers of different shapes can be ordered together.
+pub(crate) enum Layer<'a> {
+    Quad(xr::CompositionLayerQuad<'a, Vulkan>),
+    Cylinder(xr::CompositionLayerCylinderKHR<'a, Vulkan>),
+    Equirect(xr::CompositionLayerEquirect2KHR<'a, Vulkan>),
+}
+
+impl<'a> Layer<'a> {
+    fn base(&self) -> &xr::CompositionLayerBase<'a, Vulkan> {
+        match self {
+            Layer::Quad(layer) => layer,
+            Layer::Cylinder(layer) => layer,
+            Layer::Equirect(layer) => layer,
+        }
+    }
+}
+
+impl XrContext {
+    /// Can the runtime draw composition layers with this shape? Quads are always supported.
+    pub fn supports_layer_shape(&self, shape: &LayerShape) -> bool {
+        let extensions = self.instance.exts();
+        match shape {
+            LayerShape::Quad => true,
+            LayerShape::Cylinder { .. } => extensions.khr_composition_layer_cylinder.is_some(),
+            LayerShape::Equirect { .. } => extensions.khr_composition_layer_equirect2.is_some(),
+        }
+    }
+
+    /// Create a swapchain for a composition layer, returning its index and images. The images are
+    /// written to with transfer commands, and are left in `COLOR_ATTACHMENT_OPTIMAL`.
+    pub(crate) fn create_layer_swapchain(
+        &mut self,
+        resolution: vk::Extent2D,
+    ) -> Result<(usize, Vec<vk::Image>)> {
+        let swapchain = self.session.create_swapchain(&xr::SwapchainCreateInfo {
+            create_flags: SwapchainCreateFlags::EMPTY,
+            usage_flags: SwapchainUsageFlags::COLOR_ATTACHMENT | SwapchainUsageFlags::TRANSFER_DST,
+            format: COLOR_FORMAT.as_raw() as u32,
+            sample_count: 1,
+            width: resolution.width,
+            height: resolution.height,
+            face_count: 1,
+            array_size: 1,
+            mip_count: 1,
+        })?;
+        let images = swapchain
+            .enumerate_images()?
+            .into_iter()
+            .map(vk::Image::from_raw)
+            .collect();
+
+        self.layer_swapchains.push(swapchain);
+        Ok((self.layer_swapchains.len() - 1, images))
+    }
+
+    /// Acquire and wait for the next image of a layer swapchain, returning its index.
+    pub(crate) fn acquire_layer_image(&mut self, swapchain_index: usize) -> Result<usize> {
+        let swapchain = &mut self.layer_swapchains[swapchain_index];
+        let image_index = swapchain.acquire_image()?;
+        swapchain.wait_image(xr::Duration::INFINITE)?;
+        Ok(image_index as _)
+    }
+}
+
+/// Build the composition layers for this frame from their submissions. Underlays come first, as
+/// the compositor draws layers back to front.
+pub(crate) fn build_layers<'a>(
+    submissions: &mut [LayerSubmission],
+    swapchains: &'a [Swapchain<Vulkan>],
+    stage_space: &'a Space,
+) -> (Vec<Layer<'a>>, Vec<Layer<'a>>) {
+    submissions.sort_by_key(|s| s.sort_order);
+    let build = |placement| -> Vec<Layer<'a>> {
+        submissions
+            .iter()
+            .filter(|s| s.placement == placement)
+            .map(|s| build_layer(s, &swapchains[s.swapchain_index], stage_space))
+            .collect()
+    };
+    (
+        build(LayerPlacement::Underlay),
+        build(LayerPlacement::Overlay),
+    )
+}
+
+/// Collect the layers to submit: underlays, then the projection layer, then overlays.
+pub(crate) fn order_layers<'a, 'b>(
+    underlays: &'b [Layer<'a>],
+    projection: &'b xr::CompositionLayerBase<'a, Vulkan>,
+    overlays: &'b [Layer<'a>],
+) -> Vec<&'b xr::CompositionLayerBase<'a, Vulkan>> {
+    underlays
+        .iter()
+        .map(Layer::base)
+        .chain(std::iter::once(projection))
+        .chain(overlays.iter().map(Layer::base))
+        .collect()
+}
+
+fn build_layer<'a>(
+    submission: &LayerSubmission,
+    swapchain: &'a Swapchain<Vulkan>,
+    stage_space: &'a Space,
+) -> Layer<'a> {
+    let sub_image = xr::SwapchainSubImage::new()
+        .swapchain(swapchain)
+        .image_array_index(0)
+        .image_rect(xr::Rect2Di {
+            offset: xr::Offset2Di { x: 0, y: 0 },
+            extent: xr::Extent2Di {
+                width: submission.resolution.width as _,
+                height: submission.resolution.height as _,
+            },
+        });
+    // Panels are drawn with premultiplied alpha.
+    let flags = CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA;
+    let world_size = submission.world_size;
+
+    match submission.shape {
+        LayerShape::Quad => Layer::Quad(
+            xr::CompositionLayerQuad::new()
+                .layer_flags(flags)
+                .space(stage_space)
+                .eye_visibility(xr::EyeVisibility::BOTH)
+                .sub_image(sub_image)
+                .pose(submission.pose)
+                .size(xr::Extent2Df {
+                    width: world_size.x,
+                    height: world_size.y,
+                }),
+        ),
+        LayerShape::Cylinder { radius } => Layer::Cylinder(
+            xr::CompositionLayerCylinderKHR::new()
+                .layer_flags(flags)
+                .space(stage_space)
+                .eye_visibility(xr::EyeVisibility::BOTH)
+                .sub_image(sub_image)
+                .pose(submission.pose)
+                .radius(radius)
+                .central_angle(world_size.x / radius)
+                .aspect_ratio(world_size.x / world_size.y),
+        ),
+        LayerShape::Equirect {
+            radius,
+            central_horizontal_angle,
+            upper_vertical_angle,
+            lower_vertical_angle,
+        } => Layer::Equirect(
+            xr::CompositionLayerEquirect2KHR::new()
+                .layer_flags(flags)
+                .space(stage_space)
+                .eye_visibility(xr::EyeVisibility::BOTH)
+                .sub_image(sub_image)
+                .pose(submission.pose)
+                .radius(radius)
+                .central_horizontal_angle(central_horizontal_angle)
+                .upper_vertical_angle(upper_vertical_angle)
+                .lower_vertical_angle(lower_vertical_angle),
+        ),
+    }
+}
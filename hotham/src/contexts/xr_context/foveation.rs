@@ -0,0 +1,129 @@
+use anyhow::Result;
+use openxr::{self as xr, Session, Swapchain, Vulkan};
+use xr::{FoveationDynamicFB, FoveationLevelFB};
+
+use super::XrContext;
+
+/// How much detail to drop towards the edges of each eye's view, where the lenses blur the image
+/// anyway. Higher levels save more GPU time.
+///
+/// Foveation uses `XR_FB_foveation` and `XR_FB_swapchain_update_state`. On runtimes without them
+/// it is [`Foveation::Off`], and changing it does nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Foveation {
+    /// Full detail everywhere
+    Off,
+    /// Drop a little detail at the very edges
+    Low,
+    /// Balance detail and performance
+    Medium,
+    /// Drop as much detail as possible
+    #[default]
+    High,
+    /// Let the runtime pick a level, up to high, depending on how busy the GPU is
+    Dynamic,
+}
+
+impl Foveation {
+    fn level_profile(self) -> xr::FoveationLevelProfile {
+        let (level, dynamic) = match self {
+            Foveation::Off => (FoveationLevelFB::NONE, FoveationDynamicFB::DISABLED),
+            Foveation::Low => (FoveationLevelFB::LOW, FoveationDynamicFB::DISABLED),
+            Foveation::Medium => (FoveationLevelFB::MEDIUM, FoveationDynamicFB::DISABLED),
+            Foveation::High => (FoveationLevelFB::HIGH, FoveationDynamicFB::DISABLED),
+            Foveation::Dynamic => (FoveationLevelFB::HIGH, FoveationDynamicFB::LEVEL_ENABLED),
+        };
+        xr::FoveationLevelProfile {
+            level,
+            vertical_offset: 0.,
+            dynamic,
+        }
+    }
+}
+
+impl XrContext {
+    /// Does the runtime support foveation?
+    pub fn supports_foveation(&self) -> bool {
+        supports_foveation(&self.instance)
+    }
+
+    /// The current foveation level
+    pub fn foveation(&self) -> Foveation {
+        self.foveation
+    }
+
+    /// Change the foveation level, eg. to drop detail in a demanding scene. Does nothing if the
+    /// runtime doesn't support foveation.
+    pub fn set_foveation(&mut self, foveation: Foveation) -> Result<()> {
+        if !self.supports_foveation() {
+            println!("[HOTHAM_XR] Foveation is not supported, ignoring {foveation:?}");
+            return Ok(());
+        }
+
+        apply_foveation(&self.session, &self.swapchain, foveation)?;
+        self.foveation = foveation;
+        Ok(())
+    }
+}
+
+pub(crate) fn supports_foveation(instance: &xr::Instance) -> bool {
+    let extensions = instance.exts();
+    extensions.fb_foveation.is_some()
+        && extensions.fb_foveation_configuration.is_some()
+        && extensions.fb_swapchain_update_state.is_some()
+}
+
+/// Apply a foveation level to a swapchain created with `XrSwapchainCreateInfoFoveationFB`.
+pub(crate) fn apply_foveation(
+    session: &Session<Vulkan>,
+    swapchain: &Swapchain<Vulkan>,
+    foveation: Foveation,
+) -> Result<()> {
+    let swapchain_update_state = session
+        .instance()
+        .exts()
+        .fb_swapchain_update_state
+        .ok_or_else(|| anyhow::anyhow!("XR_FB_swapchain_update_state is not enabled"))?;
+
+    // The profile is only needed while it's applied, so it can be dropped afterwards.
+    let profile = session.create_foveation_profile(Some(foveation.level_profile()))?;
+    let swapchain_state = xr::sys::SwapchainStateFoveationFB {
+        ty: xr::sys::SwapchainStateFoveationFB::TYPE,
+        next: std::ptr::null_mut(),
+        flags: xr::SwapchainStateFoveationFlagsFB::EMPTY,
+        profile: profile.as_raw(),
+    };
+
+    let result = unsafe {
+        (swapchain_update_state.update_swapchain)(
+            swapchain.as_raw(),
+            &swapchain_state as *const _ as *const xr::sys::SwapchainStateBaseHeaderFB,
+        )
+    };
+    if result.into_raw() < 0 {
+        return Err(anyhow::Error::new(result));
+    }
+
+    println!("[HOTHAM_XR] Foveation is now {foveation:?}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_foveation_level_profiles() {
+        let profile = Foveation::Off.level_profile();
+        assert_eq!(profile.level, FoveationLevelFB::NONE);
+
+        let profile = Foveation::Medium.level_profile();
+        assert_eq!(profile.level, FoveationLevelFB::MEDIUM);
+        assert_eq!(profile.dynamic, FoveationDynamicFB::DISABLED);
+
+        // Dynamic foveation can go as high as the runtime likes.
+        let profile = Foveation::Dynamic.level_profile();
+        assert_eq!(profile.level, FoveationLevelFB::HIGH);
+        assert_eq!(profile.dynamic, FoveationDynamicFB::LEVEL_ENABLED);
+    }
+}
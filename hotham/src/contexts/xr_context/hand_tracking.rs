@@ -0,0 +1,35 @@
+use anyhow::Result;
+use openxr::{self as xr, HandTracker, Session, Vulkan};
+
+/// Hand trackers for both hands, created when `XR_EXT_hand_tracking` is enabled and supported
+/// by the runtime.
+pub struct HandTrackers {
+    pub left: HandTracker,
+    pub right: HandTracker,
+}
+
+impl HandTrackers {
+    /// Create the hand trackers if the extension was enabled and the system supports hand tracking.
+    pub(crate) fn new(
+        instance: &xr::Instance,
+        system: xr::SystemId,
+        session: &Session<Vulkan>,
+    ) -> Result<Option<HandTrackers>> {
+        if instance.exts().ext_hand_tracking.is_none() {
+            return Ok(None);
+        }
+
+        if !instance.supports_hand_tracking(system)? {
+            println!(
+                "[HOTHAM_XR] Hand tracking was requested, but is not supported by this system"
+            );
+            return Ok(None);
+        }
+
+        let left = session.create_hand_tracker(xr::Hand::LEFT)?;
+        let right = session.create_hand_tracker(xr::Hand::RIGHT)?;
+        println!("[HOTHAM_XR] Hand tracking enabled");
+
+        Ok(Some(HandTrackers { left, right }))
+    }
+}
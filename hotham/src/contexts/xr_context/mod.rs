@@ -10,12 +10,22 @@ use xr::{
 };
 
 use crate::{
-    contexts::VulkanContext, util::is_view_valid, HothamError, HothamResult, BLEND_MODE,
-    COLOR_FORMAT, VIEW_COUNT, VIEW_TYPE,
+    contexts::VulkanContext,
+    rendering::render_settings::{scale_extent, RenderSettings},
+    util::is_view_valid,
+    HothamError, HothamResult, COLOR_FORMAT, VIEW_COUNT, VIEW_TYPE,
 };
 
+pub mod action_map;
+mod composition_layers;
+mod foveation;
+mod hand_tracking;
 mod input;
-use input::Input;
+pub use action_map::{ActionDescription, ActionMap, ActionType};
+pub(crate) use composition_layers::LayerSubmission;
+pub use foveation::Foveation;
+pub use hand_tracking::HandTrackers;
+pub use input::{Input, NamedAction};
 
 #[derive(Default)]
 pub struct XrContextBuilder<'a> {
@@ -23,6 +33,9 @@ pub struct XrContextBuilder<'a> {
     application_name: Option<&'a str>,
     application_version: Option<u32>,
     required_extensions: Option<xr::ExtensionSet>,
+    enable_hand_tracking: bool,
+    action_map: Option<ActionMap>,
+    render_settings: RenderSettings,
 }
 
 impl<'a> XrContextBuilder<'a> {
@@ -50,16 +63,45 @@ impl<'a> XrContextBuilder<'a> {
         self
     }
 
+    /// Request `XR_EXT_hand_tracking`. Hand tracking is disabled if the runtime lacks support.
+    pub fn enable_hand_tracking(&mut self, enabled: bool) -> &mut Self {
+        self.enable_hand_tracking = enabled;
+        self
+    }
+
+    /// The actions to create and bind. Defaults to [`ActionMap::default`].
+    pub fn action_map(&mut self, action_map: Option<ActionMap>) -> &mut Self {
+        self.action_map = action_map;
+        self
+    }
+
+    /// The swapchain format, blend mode and resolution scale to use. Defaults to
+    /// [`RenderSettings::default`].
+    pub fn render_settings(&mut self, render_settings: RenderSettings) -> &mut Self {
+        self.render_settings = render_settings;
+        self
+    }
+
     pub fn build(&mut self) -> Result<(XrContext, VulkanContext)> {
         let application_name = self.application_name.unwrap_or("Hotham Application");
         let application_version = self.application_version.unwrap_or(1);
-        let (instance, system) = create_xr_instance(
+        let (instance, system, haptic_pcm_supported) = create_xr_instance(
             self.path,
             application_name,
             application_version,
             self.required_extensions.as_ref(),
+            self.enable_hand_tracking,
         )?;
-        XrContext::_new(instance, system, application_name, application_version)
+        let action_map = self.action_map.take().unwrap_or_default();
+        XrContext::_new(
+            instance,
+            system,
+            application_name,
+            application_version,
+            &action_map,
+            haptic_pcm_supported,
+            &self.render_settings,
+        )
     }
 }
 
@@ -71,12 +113,27 @@ pub struct XrContext {
     pub stage_space: Space,
     pub view_space: Space,
     pub input: Input,
+    pub hand_trackers: Option<HandTrackers>,
+    /// Was `XR_FB_haptic_pcm` available and enabled?
+    pub haptic_pcm_supported: bool,
     pub swapchain_resolution: vk::Extent2D,
+    /// The part of the swapchain images that was rendered to this frame, which is smaller than
+    /// `swapchain_resolution` when the resolution scale drops
+    pub render_resolution: vk::Extent2D,
+    /// The format of the swapchain
+    pub color_format: vk::Format,
+    /// How the rendered image is combined with the real world
+    pub blend_mode: xr::EnvironmentBlendMode,
+    foveation: Foveation,
     pub frame_waiter: FrameWaiter,
     pub frame_stream: FrameStream<Vulkan>,
     pub frame_state: FrameState,
     pub views: Vec<View>,
     pub view_state_flags: ViewStateFlags,
+    /// Swapchains for [`crate::components::CompositionLayer`]s
+    pub layer_swapchains: Vec<Swapchain<Vulkan>>,
+    /// Composition layers to submit at the end of this frame, whose images have been acquired
+    pub(crate) layer_submissions: Vec<LayerSubmission>,
 }
 
 impl XrContext {
@@ -98,6 +155,9 @@ impl XrContext {
         system: xr::SystemId,
         application_name: &str,
         application_version: u32,
+        action_map: &ActionMap,
+        haptic_pcm_supported: bool,
+        render_settings: &RenderSettings,
     ) -> Result<(XrContext, VulkanContext)> {
         let vulkan_context =
             create_vulkan_context(&instance, system, application_name, application_version)?;
@@ -108,10 +168,34 @@ impl XrContext {
             session.create_reference_space(ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)?;
         let view_space =
             session.create_reference_space(ReferenceSpaceType::VIEW, xr::Posef::IDENTITY)?;
-        let swapchain_resolution = get_swapchain_resolution(&instance, system)?;
-        let swapchain = create_xr_swapchain(&session, &swapchain_resolution, VIEW_COUNT)?;
+        let blend_mode = choose_blend_mode(&instance, system, render_settings.blend_mode)?;
+        let color_format = choose_color_format(&session, render_settings.color_format)?;
+        let swapchain_resolution =
+            get_swapchain_resolution(&instance, system, render_settings.max_resolution_scale())?;
+        let render_resolution = scale_extent(
+            swapchain_resolution,
+            render_settings.initial_resolution_scale() / render_settings.max_resolution_scale(),
+        );
+        let foveation_supported = foveation::supports_foveation(&instance);
+        let swapchain = create_xr_swapchain(
+            &session,
+            &swapchain_resolution,
+            VIEW_COUNT,
+            color_format,
+            foveation_supported,
+        )?;
+        let foveation = if foveation_supported {
+            foveation::apply_foveation(&session, &swapchain, render_settings.foveation)?;
+            render_settings.foveation
+        } else {
+            if render_settings.foveation != Foveation::Off {
+                println!("[HOTHAM_XR] Foveation is not supported by this runtime, disabling it");
+            }
+            Foveation::Off
+        };
 
-        let input = Input::oculus_touch_controller(&instance, &session)?;
+        let input = Input::from_action_map(&instance, &session, action_map)?;
+        let hand_trackers = HandTrackers::new(&instance, system, &session)?;
 
         let frame_state = FrameState {
             predicted_display_time: Time::from_nanos(0),
@@ -130,12 +214,20 @@ impl XrContext {
             stage_space,
             view_space,
             input,
+            hand_trackers,
+            haptic_pcm_supported,
             swapchain_resolution,
+            render_resolution,
+            color_format,
+            blend_mode,
+            foveation,
             frame_waiter,
             frame_stream,
             frame_state,
             views: vec![Default::default(); VIEW_COUNT as usize],
             view_state_flags: ViewStateFlags::EMPTY,
+            layer_swapchains: Vec::new(),
+            layer_submissions: Vec::new(),
         };
 
         Ok((xr_context, vulkan_context))
@@ -199,20 +291,31 @@ impl XrContext {
     pub fn end_frame(&mut self) -> std::result::Result<(), openxr::sys::Result> {
         // If we aren't in the rendering state, just submit empty views.
         if !self.frame_state.should_render {
+            self.layer_submissions.clear();
             self.frame_stream
-                .end(self.frame_state.predicted_display_time, BLEND_MODE, &[])
+                .end(
+                    self.frame_state.predicted_display_time,
+                    self.blend_mode,
+                    &[],
+                )
                 .unwrap();
             return Ok(());
         }
 
-        // Release the swapchain image.
+        // Release the swapchain image, and the images of any composition layers.
         self.swapchain.release_image().unwrap();
+        let mut layer_submissions = std::mem::take(&mut self.layer_submissions);
+        for submission in &layer_submissions {
+            self.layer_swapchains[submission.swapchain_index]
+                .release_image()
+                .unwrap();
+        }
 
         let rect = xr::Rect2Di {
             offset: xr::Offset2Di { x: 0, y: 0 },
             extent: xr::Extent2Di {
-                width: self.swapchain_resolution.width as _,
-                height: self.swapchain_resolution.height as _,
+                width: self.render_resolution.width as _,
+                height: self.render_resolution.height as _,
             },
         };
 
@@ -239,12 +342,26 @@ impl XrContext {
                 ),
         ];
 
+        let (underlays, overlays) = composition_layers::build_layers(
+            &mut layer_submissions,
+            &self.layer_swapchains,
+            &self.stage_space,
+        );
+
+        // Underlays can only be seen through the holes they punch in the scene's alpha.
+        let projection_flags = if underlays.is_empty() {
+            xr::CompositionLayerFlags::EMPTY
+        } else {
+            xr::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA
+        };
         let layer_projection = xr::CompositionLayerProjection::new()
+            .layer_flags(projection_flags)
             .space(&self.stage_space)
             .views(&views);
 
-        let layers = [&*layer_projection];
-        self.frame_stream.end(display_time, BLEND_MODE, &layers)
+        let layers = composition_layers::order_layers(&underlays, &layer_projection, &overlays);
+        self.frame_stream
+            .end(display_time, self.blend_mode, &layers)
     }
 
     pub(crate) fn end_session(&mut self) -> anyhow::Result<()> {
@@ -290,69 +407,97 @@ fn create_vulkan_context(
     Ok(vulkan_context)
 }
 
+/// The runtime's recommended resolution multiplied by `scale`, up to the largest it supports.
 pub(crate) fn get_swapchain_resolution(
     xr_instance: &xr::Instance,
     system: xr::SystemId,
+    scale: f32,
 ) -> Result<vk::Extent2D> {
     let views = xr_instance.enumerate_view_configuration_views(system, VIEW_TYPE)?;
     println!("[HOTHAM_VULKAN] Views: {views:?}");
-    let resolution = vk::Extent2D {
+    let recommended = vk::Extent2D {
         width: views[0].recommended_image_rect_width,
         height: views[0].recommended_image_rect_height,
     };
+    let scaled = scale_extent(recommended, scale);
+    let resolution = vk::Extent2D {
+        width: scaled.width.min(views[0].max_image_rect_width),
+        height: scaled.height.min(views[0].max_image_rect_height),
+    };
 
     Ok(resolution)
 }
 
-#[cfg(not(target_os = "android"))]
-pub(crate) fn create_xr_swapchain(
-    xr_session: &Session<Vulkan>,
-    resolution: &vk::Extent2D,
-    array_size: u32,
-) -> Result<Swapchain<Vulkan>> {
-    xr_session
-        .create_swapchain(&xr::SwapchainCreateInfo {
-            create_flags: SwapchainCreateFlags::EMPTY,
-            usage_flags: SwapchainUsageFlags::COLOR_ATTACHMENT,
-            format: COLOR_FORMAT.as_raw() as u32,
-            sample_count: 1,
-            width: resolution.width,
-            height: resolution.height,
-            face_count: 1,
-            array_size,
-            mip_count: 1,
-        })
-        .map_err(Into::into)
+/// Use `requested` if the runtime supports it, otherwise the runtime's preferred blend mode.
+fn choose_blend_mode(
+    xr_instance: &xr::Instance,
+    system: xr::SystemId,
+    requested: xr::EnvironmentBlendMode,
+) -> Result<xr::EnvironmentBlendMode> {
+    let blend_modes = xr_instance.enumerate_environment_blend_modes(system, VIEW_TYPE)?;
+    if blend_modes.contains(&requested) || blend_modes.is_empty() {
+        return Ok(requested);
+    }
+
+    let blend_mode = blend_modes[0];
+    println!("[HOTHAM_XR] Blend mode {requested:?} is not supported, using {blend_mode:?}");
+    Ok(blend_mode)
 }
 
-/// Creates the OpenXR swapchain with Fixed Foveated Rendering support on Quest 2
-///
-/// This requires a fair bit of setup as there isn't yet a wrapper for this functionality in OpenXR.
-#[cfg(target_os = "android")]
+/// Use `requested` for the swapchain if the runtime supports it, otherwise [`COLOR_FORMAT`].
+fn choose_color_format(xr_session: &Session<Vulkan>, requested: vk::Format) -> Result<vk::Format> {
+    if requested == COLOR_FORMAT {
+        return Ok(requested);
+    }
+
+    let formats = xr_session.enumerate_swapchain_formats()?;
+    if formats.contains(&(requested.as_raw() as u32)) {
+        return Ok(requested);
+    }
+
+    println!("[HOTHAM_XR] Swapchain format {requested:?} is not supported, using {COLOR_FORMAT:?}");
+    Ok(COLOR_FORMAT)
+}
+
+/// Creates the OpenXR swapchain. With `foveated`, foveation can be applied to it with
+/// `XR_FB_swapchain_update_state`. On Quest the runtime also provides fragment density maps for
+/// fixed foveated rendering, which the render pass uses.
 pub(crate) fn create_xr_swapchain(
     xr_session: &Session<Vulkan>,
     resolution: &vk::Extent2D,
     array_size: u32,
+    format: vk::Format,
+    foveated: bool,
 ) -> Result<Swapchain<Vulkan>> {
+    #[cfg(target_os = "android")]
+    let foveation_flags = xr::sys::SwapchainCreateFoveationFlagsFB::FRAGMENT_DENSITY_MAP;
+    #[cfg(not(target_os = "android"))]
+    let foveation_flags = xr::sys::SwapchainCreateFoveationFlagsFB::EMPTY;
+
     let mut swapchain_raw = xr::sys::Swapchain::NULL;
     let foveation_info = xr::sys::SwapchainCreateInfoFoveationFB {
         ty: xr::sys::StructureType::SWAPCHAIN_CREATE_INFO_FOVEATION_FB,
         next: std::ptr::null_mut(),
-        flags: xr::sys::SwapchainCreateFoveationFlagsFB::FRAGMENT_DENSITY_MAP,
+        flags: foveation_flags,
+    };
+    let next = if foveated {
+        &foveation_info as *const _ as *const std::ffi::c_void
+    } else {
+        std::ptr::null()
     };
 
     let create_info = xr::sys::SwapchainCreateInfo {
         ty: xr::sys::SwapchainCreateInfo::TYPE,
         create_flags: SwapchainCreateFlags::EMPTY,
         usage_flags: SwapchainUsageFlags::COLOR_ATTACHMENT,
-        format: COLOR_FORMAT.as_raw() as _,
+        format: format.as_raw() as _,
         sample_count: 1,
         width: resolution.width,
         height: resolution.height,
         face_count: 1,
         mip_count: 1,
         array_size,
-        next: &foveation_info as *const _ as *const std::ffi::c_void,
+        next,
     };
 
     unsafe {
@@ -360,42 +505,11 @@ pub(crate) fn create_xr_swapchain(
         let xr_result =
             (fp.create_swapchain)(xr_session.as_raw(), &create_info, &mut swapchain_raw);
 
-        let swapchain = if xr_result.into_raw() >= 0 {
-            Swapchain::from_raw(xr_session.clone(), swapchain_raw)
-        } else {
+        if xr_result.into_raw() < 0 {
             return Err(anyhow::Error::new(xr_result));
-        };
-
-        let fp = xr_session
-            .instance()
-            .exts()
-            .fb_swapchain_update_state
-            .unwrap();
-
-        let foveation_profile = xr::FoveationLevelProfile {
-            level: xr::FoveationLevelFB::HIGH,
-            vertical_offset: 0.,
-            dynamic: xr::FoveationDynamicFB::DISABLED,
-        };
-
-        let foveation_profile_handle =
-            xr_session.create_foveation_profile(Some(foveation_profile))?;
-
-        let swapchain_update_state = xr::sys::SwapchainStateFoveationFB {
-            ty: xr::sys::SwapchainStateFoveationFB::TYPE,
-            next: std::ptr::null_mut(),
-            flags: xr::SwapchainStateFoveationFlagsFB::EMPTY,
-            profile: foveation_profile_handle.as_raw(),
-        };
-
-        let result =
-            (fp.update_swapchain)(swapchain_raw, std::mem::transmute(&swapchain_update_state));
-
-        if result.into_raw() < 0 {
-            return Err(anyhow::Error::new(result));
         }
 
-        Ok(swapchain)
+        Ok(Swapchain::from_raw(xr_session.clone(), swapchain_raw))
     }
 }
 
@@ -420,12 +534,15 @@ pub(crate) fn create_xr_session(
     .unwrap())
 }
 
+pub(crate) const HAPTIC_PCM_EXTENSION_NAME: &str = "XR_FB_haptic_pcm";
+
 pub(crate) fn create_xr_instance(
     path: Option<&std::path::Path>,
     application_name: &str,
     application_version: u32,
     required_extensions: Option<&xr::ExtensionSet>,
-) -> anyhow::Result<(xr::Instance, xr::SystemId)> {
+    enable_hand_tracking: bool,
+) -> anyhow::Result<(xr::Instance, xr::SystemId, bool)> {
     let xr_entry = if let Some(path) = path {
         unsafe { xr::Entry::load_from(path)? }
     } else {
@@ -446,9 +563,49 @@ pub(crate) fn create_xr_instance(
         xr_entry.initialize_android_loader()?;
     }
 
+    let available_extensions = xr_entry.enumerate_extensions()?;
+
+    // Hand tracking is optional - only enable it if the runtime actually has it.
+    if enable_hand_tracking {
+        if available_extensions.ext_hand_tracking {
+            required_extensions.ext_hand_tracking = true;
+        } else {
+            println!(
+                "[HOTHAM_XR] Hand tracking was requested, but XR_EXT_hand_tracking is not available"
+            );
+        }
+    }
+
+    // PCM haptics are used when available, otherwise haptics fall back to simple vibrations. The
+    // application may have asked for the extension already, and it mustn't be enabled twice.
+    let has_haptic_pcm =
+        |extensions: &Vec<String>| extensions.iter().any(|e| e == HAPTIC_PCM_EXTENSION_NAME);
+    let haptic_pcm_supported = has_haptic_pcm(&available_extensions.other);
+    if haptic_pcm_supported && !has_haptic_pcm(&required_extensions.other) {
+        required_extensions
+            .other
+            .push(HAPTIC_PCM_EXTENSION_NAME.to_string());
+    }
+
+    // Foveation is optional, except on Android where the render pass depends on it.
+    if available_extensions.fb_foveation
+        && available_extensions.fb_foveation_configuration
+        && available_extensions.fb_swapchain_update_state
+    {
+        required_extensions.fb_foveation = true;
+        required_extensions.fb_foveation_configuration = true;
+        required_extensions.fb_swapchain_update_state = true;
+    }
+
+    // Composition layers fall back to quads if these shapes aren't available.
+    required_extensions.khr_composition_layer_cylinder =
+        available_extensions.khr_composition_layer_cylinder;
+    required_extensions.khr_composition_layer_equirect2 =
+        available_extensions.khr_composition_layer_equirect2;
+
     let instance = xr_entry.create_instance(&xr_app_info, &required_extensions, &[])?;
     let system = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)?;
-    Ok((instance, system))
+    Ok((instance, system, haptic_pcm_supported))
 }
 
 #[cfg(target_os = "android")]
@@ -1,20 +1,32 @@
 use crate::{
     asset_importer::{self, add_model_to_world},
-    components::{GlobalTransform, Info, LocalTransform, Parent, Stage, HMD},
+    components::{
+        GlobalTransform, Info, LocalTransform, Parent, RenderTargetCamera, Spectator, Stage, HMD,
+    },
     contexts::{
-        render_context::create_pipeline, AudioContext, GuiContext, HapticContext, InputContext,
-        PhysicsContext, RenderContext, VulkanContext, XrContext, XrContextBuilder,
+        input_recording::{InputFrame, InputRecorder, InputReplay},
+        render_context::{create_pipeline, BlendMode},
+        xr_context::{ActionMap, Foveation},
+        AudioContext, GuiContext, HapticContext, InputContext, LocomotionContext, PhysicsContext,
+        RenderContext, VulkanContext, XrContext, XrContextBuilder,
+    },
+    rendering::{
+        debug_draw::DebugDraw, dynamic_resolution::DynamicResolution, environment::load_cube_map,
+        render_settings::RenderSettings, spectator_capture::SpectatorCapture,
     },
+    systems::rendering_system,
     util::{u8_to_u32, PerformanceTimer},
     workers::Workers,
     HothamError, HothamResult, VIEW_TYPE,
 };
+use ash::vk;
 use hotham_asset_client::AssetUpdatedMessage;
 use openxr as xr;
 
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
+        mpsc::{self, TryRecvError},
         Arc,
     },
     thread::sleep,
@@ -36,6 +48,12 @@ pub struct EngineBuilder<'a> {
     application_name: Option<&'a str>,
     application_version: Option<u32>,
     openxr_extensions: Option<xr::ExtensionSet>,
+    enable_hand_tracking: bool,
+    action_map: Option<ActionMap>,
+    record_input_path: Option<&'a std::path::Path>,
+    replay_input_path: Option<&'a std::path::Path>,
+    capture_spectator_path: Option<&'a std::path::Path>,
+    render_settings: RenderSettings,
 }
 
 impl<'a> EngineBuilder<'a> {
@@ -62,6 +80,93 @@ impl<'a> EngineBuilder<'a> {
         self
     }
 
+    /// Enable hand tracking with `XR_EXT_hand_tracking`, if the runtime supports it.
+    /// Tracked joints are available in [`InputContext`] and are used to pose `Hand`s.
+    pub fn enable_hand_tracking(&mut self, enabled: bool) -> &mut Self {
+        self.enable_hand_tracking = enabled;
+        self
+    }
+
+    /// Set the actions the application wants to use, and how they should be bound to each
+    /// controller. Defaults to [`ActionMap::default`], which [`InputContext`] depends on.
+    pub fn action_map(&mut self, action_map: ActionMap) -> &mut Self {
+        self.action_map = Some(action_map);
+        self
+    }
+
+    /// Record the input for each frame to a log at `path`, which can be replayed later with
+    /// [`EngineBuilder::replay_input`] or [`InputReplay`].
+    pub fn record_input(&mut self, path: Option<&'a std::path::Path>) -> &mut Self {
+        self.record_input_path = path;
+        self
+    }
+
+    /// Replay the input log at `path` in place of live OpenXR input. Once the log is finished,
+    /// the engine goes back to live input.
+    pub fn replay_input(&mut self, path: Option<&'a std::path::Path>) -> &mut Self {
+        self.replay_input_path = path;
+        self
+    }
+
+    /// Write what the [`Spectator`] camera sees to the directory at `path`, as a numbered sequence
+    /// of PNGs. See [`SpectatorCapture`].
+    pub fn capture_spectator(&mut self, path: Option<&'a std::path::Path>) -> &mut Self {
+        self.capture_spectator_path = path;
+        self
+    }
+
+    /// Set the number of samples used for MSAA. Defaults to 4, and falls back to the highest count
+    /// the GPU supports.
+    pub fn msaa_samples(&mut self, samples: vk::SampleCountFlags) -> &mut Self {
+        self.render_settings.samples = samples;
+        self
+    }
+
+    /// Scale the render resolution recommended by the runtime, eg. `0.8` to render fewer pixels or
+    /// `1.2` to supersample. Defaults to 1.
+    pub fn resolution_scale(&mut self, scale: f32) -> &mut Self {
+        self.render_settings.resolution_scale = scale;
+        self
+    }
+
+    /// Set the format of the swapchain. Falls back to the default if the runtime doesn't support
+    /// it.
+    pub fn color_format(&mut self, format: vk::Format) -> &mut Self {
+        self.render_settings.color_format = format;
+        self
+    }
+
+    /// Set the format of the depth buffer. Falls back to the default if the GPU doesn't support
+    /// it.
+    pub fn depth_format(&mut self, format: vk::Format) -> &mut Self {
+        self.render_settings.depth_format = format;
+        self
+    }
+
+    /// Set how the rendered image is combined with the real world, eg. `ALPHA_BLEND` for
+    /// passthrough. Falls back to the runtime's preferred mode if it isn't supported.
+    pub fn blend_mode(&mut self, blend_mode: xr::EnvironmentBlendMode) -> &mut Self {
+        self.render_settings.blend_mode = blend_mode;
+        self
+    }
+
+    /// Scale the render resolution each frame to keep GPU frame time within budget. Disabled by
+    /// default.
+    pub fn dynamic_resolution(
+        &mut self,
+        dynamic_resolution: Option<DynamicResolution>,
+    ) -> &mut Self {
+        self.render_settings.dynamic_resolution = dynamic_resolution;
+        self
+    }
+
+    /// Set how much detail to drop towards the edges of the view. Defaults to
+    /// [`Foveation::High`], and is off on runtimes without `XR_FB_foveation`.
+    pub fn foveation(&mut self, foveation: Foveation) -> &mut Self {
+        self.render_settings.foveation = foveation;
+        self
+    }
+
     /// Build the `Engine`
     pub fn build(self) -> Engine {
         #[allow(unused_mut)] // Only Android mutates this.
@@ -84,16 +189,31 @@ impl<'a> EngineBuilder<'a> {
             .application_name(self.application_name)
             .application_version(self.application_version)
             .required_extensions(self.openxr_extensions)
+            .enable_hand_tracking(self.enable_hand_tracking)
+            .action_map(self.action_map)
+            .render_settings(self.render_settings)
             .build()
             .expect("!!FATAL ERROR - Unable to initialize OpenXR!!");
-        let render_context = RenderContext::new(&vulkan_context, &xr_context)
-            .expect("!!FATAL ERROR - Unable to initialize renderer!");
+        let render_context =
+            RenderContext::new_with_settings(&vulkan_context, &xr_context, self.render_settings)
+                .expect("!!FATAL ERROR - Unable to initialize renderer!");
         let gui_context = GuiContext::new(&vulkan_context);
 
         // Initialize the world with our "tracking" entities, the stage and the HMD.
         let mut world = hecs::World::default();
         let (stage_entity, hmd_entity) = create_tracking_entities(&mut world);
 
+        let input_recorder = self.record_input_path.map(|path| {
+            InputRecorder::create(path).expect("!!FATAL ERROR - Unable to create input log!")
+        });
+        let input_replay = self.replay_input_path.map(|path| {
+            InputReplay::open(path).expect("!!FATAL ERROR - Unable to read input log!")
+        });
+        let spectator_capture = self.capture_spectator_path.map(|path| {
+            SpectatorCapture::create(path)
+                .expect("!!FATAL ERROR - Unable to create spectator capture directory!")
+        });
+
         Engine {
             world,
             should_quit,
@@ -106,6 +226,11 @@ impl<'a> EngineBuilder<'a> {
             gui_context,
             haptic_context: Default::default(),
             input_context: Default::default(),
+            input_recorder,
+            input_replay,
+            spectator_capture,
+            debug_draw: Default::default(),
+            locomotion_context: Default::default(),
             physics_context: Default::default(),
             stage_entity,
             hmd_entity,
@@ -116,6 +241,28 @@ impl<'a> EngineBuilder<'a> {
     }
 }
 
+/// Copy the first spectator camera's texture for the capture. Only the first
+/// [`MAX_RENDER_TARGET_CAMERAS`](crate::rendering::render_target::MAX_RENDER_TARGET_CAMERAS)
+/// cameras are drawn, so the spectator should be one of them.
+fn capture_spectator(
+    world: &hecs::World,
+    vulkan_context: &VulkanContext,
+    render_context: &RenderContext,
+    spectator_capture: &mut SpectatorCapture,
+) {
+    let mut query = world.query::<(&Spectator, &RenderTargetCamera)>();
+    if let Some((_, (_, camera))) = query.iter().next() {
+        unsafe {
+            spectator_capture.record(
+                vulkan_context,
+                render_context.cmd(),
+                render_context.frame_index,
+                &camera.texture,
+            );
+        }
+    }
+}
+
 fn create_tracking_entities(world: &mut hecs::World) -> (hecs::Entity, hecs::Entity) {
     let stage_entity = world.spawn((
         Stage {},
@@ -157,6 +304,16 @@ pub struct Engine {
     pub haptic_context: HapticContext,
     /// Input context
     pub input_context: InputContext,
+    /// Records input each frame, if enabled with `EngineBuilder::record_input`
+    pub input_recorder: Option<InputRecorder>,
+    /// Replaces live input, if enabled with `EngineBuilder::replay_input`
+    pub input_replay: Option<InputReplay>,
+    /// Writes the spectator view to disk, if enabled with `EngineBuilder::capture_spectator`
+    pub spectator_capture: Option<SpectatorCapture>,
+    /// Debug lines, shapes and labels, drawn on top of the world and cleared each frame
+    pub debug_draw: DebugDraw,
+    /// Locomotion context
+    pub locomotion_context: LocomotionContext,
     /// Stage entity
     pub stage_entity: hecs::Entity,
     /// HMD entity
@@ -170,6 +327,7 @@ pub struct Engine {
 }
 
 /// The result of calling `update()` on Engine.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct TickData {
     /// The previous XR state.
     pub previous_state: xr::SessionState,
@@ -209,7 +367,14 @@ impl Engine {
             // If we're in the FOCUSSED state, process input.
             if current_state == SessionState::FOCUSED {
                 self.xr_context.update_views();
-                self.input_context.update(&self.xr_context);
+                match self
+                    .input_replay
+                    .as_mut()
+                    .and_then(InputReplay::next_focused_frame)
+                {
+                    Some(frame) => self.input_context.apply_snapshot(&frame.input),
+                    None => self.input_context.update(&self.xr_context),
+                }
 
                 // Since the HMD is parented to the Stage, its LocalTransform (ie. its transform with respect to the parent)
                 // is equal to its pose in stage space.
@@ -257,18 +422,50 @@ impl Engine {
                 Err(HothamError::NotRendering) => continue,
                 Ok(swapchain_image_index) => {
                     render_context.begin_frame(vulkan_context);
+                    let frame_period = self
+                        .xr_context
+                        .frame_state
+                        .predicted_display_period
+                        .as_secs_f32();
+                    render_context.screen_fade.update(frame_period);
+                    render_context.update_resolution_scale(frame_period);
                     self.performance_timer.start();
-                    return Ok(TickData {
+                    let tick_data = TickData {
                         previous_state,
                         current_state,
                         swapchain_image_index,
-                    });
+                    };
+                    self.record_input(tick_data);
+                    return Ok(tick_data);
                 }
                 err => panic!("Error beginning frame: {err:?}"),
             };
         }
     }
 
+    /// Record this frame's input, if it was updated. Input is only updated while the session is
+    /// focused, so that's the only time it's recorded, keeping replays in step.
+    fn record_input(&mut self, tick_data: TickData) {
+        if tick_data.current_state != SessionState::FOCUSED {
+            return;
+        }
+        let recorder = match self.input_recorder.as_mut() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+
+        let frame = InputFrame {
+            predicted_display_time: self.xr_context.frame_state.predicted_display_time,
+            tick_data,
+            input: self.input_context.snapshot(),
+        };
+
+        if let Err(e) = recorder.record(&frame) {
+            println!("[HOTHAM_ENGINE] Unable to record input, recording stopped: {e:?}");
+            self.input_recorder = None;
+        }
+    }
+
     /// Call this after update
     pub fn finish(&mut self) -> xr::Result<()> {
         self.performance_timer.end();
@@ -276,11 +473,65 @@ impl Engine {
         let render_context = &mut self.render_context;
 
         if self.xr_context.frame_state.should_render {
+            if let Some(spectator_capture) = &mut self.spectator_capture {
+                capture_spectator(
+                    &self.world,
+                    vulkan_context,
+                    render_context,
+                    spectator_capture,
+                );
+            }
             render_context.end_frame(vulkan_context);
         }
+        self.xr_context.render_resolution = render_context.render_area().extent;
         self.xr_context.end_frame()
     }
 
+    /// Run `job` on another thread, eg. to read and decompress the next scene, while continuing to
+    /// draw the world and submit frames to the compositor. Without this the runtime would consider
+    /// the application unresponsive. Do it behind an opaque
+    /// [`ScreenFade`](crate::rendering::fade::ScreenFade) to hide the world standing still.
+    ///
+    /// This runs whole frames, so call it between [`Engine::finish`] and the next
+    /// [`Engine::update`].
+    ///
+    /// Basic usage:
+    /// ```ignore
+    /// engine.render_context.screen_fade.fade_out(Vec3::ZERO, 0.5);
+    /// // ..keep ticking until `screen_fade.is_opaque()`..
+    /// let glb = engine.load_in_background(|| std::fs::read("level_2.glb"))??;
+    /// let models = load_models_from_glb(
+    ///     &[&glb],
+    ///     &engine.vulkan_context,
+    ///     &mut engine.render_context,
+    /// )?;
+    /// engine.render_context.screen_fade.fade_in(0.5);
+    /// ```
+    pub fn load_in_background<T: Send + 'static>(
+        &mut self,
+        job: impl FnOnce() -> T + Send + 'static,
+    ) -> HothamResult<T> {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // Nobody is listening if the engine has shut down in the meantime.
+            let _ = sender.send(job());
+        });
+
+        loop {
+            let tick_data = self.update()?;
+            rendering_system(self, tick_data.swapchain_image_index);
+            self.finish()?;
+
+            match receiver.try_recv() {
+                Ok(result) => return Ok(result),
+                Err(TryRecvError::Empty) => continue,
+                Err(TryRecvError::Disconnected) => {
+                    return Err(anyhow::anyhow!("The loading job panicked").into())
+                }
+            }
+        }
+    }
+
     /// Watch some assets, just for fun.
     pub fn watch_assets(&mut self, asset_list: Vec<String>) {
         self.workers = Workers::new(asset_list);
@@ -309,6 +560,14 @@ impl Engine {
                             world,
                             asset_updated.asset_data.clone(),
                         ),
+                        (asset_id, "ktx2") if asset_id.contains("environment_map") => {
+                            update_environment_map(
+                                vulkan_context,
+                                render_context,
+                                asset_id,
+                                asset_updated.asset_data.clone(),
+                            )
+                        }
                         ("hotham/src/shaders/pbr.frag.spv", _)
                         | ("hotham/src/shaders/pbr.vert.spv", _) => update_shader(
                             vulkan_context,
@@ -362,6 +621,35 @@ fn update_models(
     }
 }
 
+/// Replace one of the current environment maps. The asset's name says which: eg.
+/// `environment_map_diffuse.ktx2` or `forest_environment_map_specular.ktx2`.
+fn update_environment_map(
+    vulkan_context: &VulkanContext,
+    render_context: &mut RenderContext,
+    asset_id: &str,
+    asset_data: Arc<Vec<u8>>,
+) {
+    let mut environment_map = render_context.resources.environment_map.clone();
+    let cube_map = match load_cube_map(vulkan_context, &asset_data) {
+        Ok(cube_map) => cube_map,
+        Err(e) => {
+            println!("[HOTHAM_ASSET_HOT_RELOAD] Unable to load environment map {asset_id}: {e:?}");
+            return;
+        }
+    };
+
+    if asset_id.contains("diffuse") {
+        environment_map.diffuse = cube_map;
+    } else if asset_id.contains("specular") {
+        environment_map.specular = cube_map;
+    } else {
+        println!("[HOTHAM_ASSET_HOT_RELOAD] {asset_id} is neither a diffuse nor a specular map");
+        return;
+    }
+
+    render_context.set_environment_map(vulkan_context, environment_map);
+}
+
 fn update_shader(
     vulkan_context: &VulkanContext,
     render_context: &mut RenderContext,
@@ -387,11 +675,29 @@ fn update_shader(
         render_context.pipeline = create_pipeline(
             vulkan_context,
             render_context.pipeline_layout,
-            &render_context.render_area(),
+            render_context.settings().samples,
+            render_context.render_pass,
+            &render_context.shaders,
+            BlendMode::Opaque,
+        )
+        .unwrap();
+        render_context.transparent_pipeline = create_pipeline(
+            vulkan_context,
+            render_context.pipeline_layout,
+            render_context.settings().samples,
             render_context.render_pass,
             &render_context.shaders,
+            BlendMode::AlphaBlend,
         )
         .unwrap();
+        render_context
+            .render_targets
+            .create_pipelines(
+                vulkan_context,
+                render_context.pipeline_layout,
+                &render_context.shaders,
+            )
+            .unwrap();
     }
 }
 
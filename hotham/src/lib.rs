@@ -47,19 +47,24 @@ mod workers;
 /// Hotham result type
 pub type HothamResult<T> = std::result::Result<T, HothamError>;
 
-/// Format used for color textures
+/// Format used for color textures, and the default swapchain format. See
+/// [`rendering::render_settings::RenderSettings`].
 pub const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
-/// Format used for depth textures
+/// Format the scene is drawn in before post-processing. It can hold values above 1.0, and its alpha
+/// channel is how much of the scene covers each pixel, which is less than 1.0 where holes have been
+/// punched for underlay composition layers.
+pub const SCENE_COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+/// Default format used for the depth buffer
 pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
 
 /// Number of views
 pub const VIEW_COUNT: u32 = 2;
 
-/// Swapchain length
+/// Swapchain length. The OpenXR runtime decides how many images a swapchain really has.
 pub const SWAPCHAIN_LENGTH: usize = 3;
 
 /// OpenXR view type
 pub const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
 
-/// OpenXR blend mode
+/// Default OpenXR blend mode
 pub const BLEND_MODE: xr::EnvironmentBlendMode = xr::EnvironmentBlendMode::OPAQUE;
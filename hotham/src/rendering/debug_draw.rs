@@ -0,0 +1,538 @@
+use std::{f32::consts::TAU, ffi::CStr, mem::size_of};
+
+use anyhow::Result;
+use ash::vk;
+use glam::{Affine3A, Vec3, Vec4};
+use hecs::{With, World};
+use vk_shader_macros::include_glsl;
+
+use crate::{
+    components::{LocalTransform, Pointer, Visible},
+    contexts::{render_context::DYNAMIC_VIEWPORT_STATES, PhysicsContext, VulkanContext},
+    systems::pointers::{cast_pointer_ray, POINTER_MAX_TOI},
+    util::{decompose_isometry, glam_vec_from_na},
+};
+
+static DEBUG_VERT: &[u32] = include_glsl!("src/shaders/debug.vert", target: vulkan1_1);
+static DEBUG_FRAG: &[u32] = include_glsl!("src/shaders/debug.frag", target: vulkan1_1);
+
+/// The most debug vertices drawn each frame - two per line. Any more are dropped.
+pub const MAX_DEBUG_VERTICES: usize = 100_000;
+
+const CIRCLE_SEGMENTS: usize = 24;
+const COLLIDER_COLOR: Vec4 = Vec4::new(0., 1., 1., 1.);
+const SENSOR_COLOR: Vec4 = Vec4::new(1., 0., 1., 1.);
+const HIT_COLOR: Vec4 = Vec4::new(0., 1., 0., 1.);
+const MISS_COLOR: Vec4 = Vec4::new(1., 0., 0., 1.);
+
+/// A vertex of a debug line, in global space until it's uploaded to the GPU.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DebugVertex {
+    /// Position
+    pub position: Vec3,
+    /// Linear RGBA color
+    pub color: Vec4,
+}
+
+impl DebugVertex {
+    pub(crate) fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(memoffset::offset_of!(DebugVertex, position) as _)
+                .build(),
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(memoffset::offset_of!(DebugVertex, color) as _)
+                .build(),
+        ]
+    }
+}
+
+/// Immediate mode debug drawing. Lines, shapes and labels added during a frame are drawn once, in
+/// a single unlit batch on top of everything else, and then cleared. Positions are in global
+/// space, and colors are linear RGBA.
+///
+/// Basic usage:
+/// ```ignore
+/// fn tick(engine: &mut Engine) {
+///     let debug_draw = &mut engine.debug_draw;
+///     debug_draw.show_colliders = true;
+///     debug_draw.axes(&global_transform.0, 0.1);
+///     debug_draw.text(position + Vec3::Y * 0.2, &format!("{speed:.1}"), 0.05, Vec4::ONE);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DebugDraw {
+    /// Draw the shape of every collider in the [`PhysicsContext`]. Sensors are drawn in a
+    /// different color.
+    pub show_colliders: bool,
+    /// Draw the ray cast by each [`Pointer`], up to whatever it hits.
+    pub show_pointers: bool,
+    vertices: Vec<DebugVertex>,
+    labels: Vec<Label>,
+}
+
+#[derive(Debug, Clone)]
+struct Label {
+    position: Vec3,
+    text: String,
+    height: f32,
+    color: Vec4,
+}
+
+impl DebugDraw {
+    /// A line between two points
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Vec4) {
+        self.vertices.push(DebugVertex {
+            position: start,
+            color,
+        });
+        self.vertices.push(DebugVertex {
+            position: end,
+            color,
+        });
+    }
+
+    /// A line with an arrow head at `end`
+    pub fn arrow(&mut self, start: Vec3, end: Vec3, color: Vec4) {
+        self.line(start, end, color);
+        let direction = end - start;
+        let length = direction.length();
+        if length <= f32::EPSILON {
+            return;
+        }
+
+        let (a, b) = (direction / length).any_orthonormal_pair();
+        let head_length = length * 0.2;
+        let base = end - direction * 0.2;
+        for side in [a, -a, b, -b] {
+            self.line(end, base + side * head_length * 0.5, color);
+        }
+    }
+
+    /// A ray cast from `origin` along the normalized `direction`. Rays that hit something at
+    /// `hit_toi` are drawn up to the hit, with a cross where they hit. Rays that miss are drawn all
+    /// the way to `max_toi`.
+    pub fn ray_cast(&mut self, origin: Vec3, direction: Vec3, max_toi: f32, hit_toi: Option<f32>) {
+        match hit_toi {
+            Some(toi) => {
+                let hit_point = origin + direction * toi;
+                self.line(origin, hit_point, HIT_COLOR);
+                self.cross(hit_point, 0.02, HIT_COLOR);
+            }
+            None => self.line(origin, origin + direction * max_toi, MISS_COLOR),
+        }
+    }
+
+    /// Three short lines crossing at `position`
+    pub fn cross(&mut self, position: Vec3, size: f32, color: Vec4) {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.line(position - axis * size, position + axis * size, color);
+        }
+    }
+
+    /// The edges of a box with the given half extents, centered on `transform`
+    pub fn wire_box(&mut self, transform: &Affine3A, half_extents: Vec3, color: Vec4) {
+        let corner =
+            |x: f32, y: f32, z: f32| transform.transform_point3(half_extents * Vec3::new(x, y, z));
+        for (x, y) in [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)] {
+            // Along Z, then around each end.
+            self.line(corner(x, y, -1.), corner(x, y, 1.), color);
+            for z in [-1., 1.] {
+                self.line(corner(x, y, z), corner(-y, x, z), color);
+            }
+        }
+    }
+
+    /// A circle around `normal`
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec4) {
+        let (a, b) = normal.normalize().any_orthonormal_pair();
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            center + (a * angle.cos() + b * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// A sphere, drawn as a circle around each axis
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.circle(center, axis, radius, color);
+        }
+    }
+
+    /// The X, Y and Z axes of `transform`, in red, green and blue
+    pub fn axes(&mut self, transform: &Affine3A, size: f32) {
+        let origin = transform.transform_point3(Vec3::ZERO);
+        for (axis, color) in [
+            (Vec3::X, Vec4::new(1., 0., 0., 1.)),
+            (Vec3::Y, Vec4::new(0., 1., 0., 1.)),
+            (Vec3::Z, Vec4::new(0., 0., 1., 1.)),
+        ] {
+            self.arrow(origin, transform.transform_point3(axis * size), color);
+        }
+    }
+
+    /// A label centered on `position`, facing the player. The built in font is made of lines, and
+    /// only has digits, capital letters and a little punctuation. Lowercase letters are drawn as
+    /// capitals.
+    pub fn text(&mut self, position: Vec3, text: &str, height: f32, color: Vec4) {
+        self.labels.push(Label {
+            position,
+            text: text.to_owned(),
+            height,
+            color,
+        });
+    }
+
+    /// The vertices of every line drawn this frame, two per line. Labels are added when the frame
+    /// is drawn.
+    pub fn vertices(&self) -> &[DebugVertex] {
+        &self.vertices
+    }
+
+    /// Throw away everything drawn this frame
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.labels.clear();
+    }
+
+    /// Draw the shape of each collider. Shapes without their own outline are drawn as a box around
+    /// them.
+    pub(crate) fn colliders(&mut self, physics_context: &PhysicsContext) {
+        use rapier3d::geometry::TypedShape;
+
+        for (_, collider) in physics_context.colliders.iter() {
+            let (rotation, translation) = decompose_isometry(collider.position());
+            let transform = Affine3A::from_rotation_translation(rotation, translation);
+            let color = if collider.is_sensor() {
+                SENSOR_COLOR
+            } else {
+                COLLIDER_COLOR
+            };
+
+            match collider.shape().as_typed_shape() {
+                TypedShape::Ball(ball) => self.sphere(translation, ball.radius, color),
+                TypedShape::Cuboid(cuboid) => {
+                    self.wire_box(&transform, glam_vec_from_na(&cuboid.half_extents), color)
+                }
+                TypedShape::Capsule(capsule) => {
+                    let a = transform.transform_point3(glam_vec_from_na(&capsule.segment.a.coords));
+                    let b = transform.transform_point3(glam_vec_from_na(&capsule.segment.b.coords));
+                    self.capsule(a, b, capsule.radius, color);
+                }
+                _ => {
+                    let aabb = collider.shape().compute_local_aabb();
+                    let mins = glam_vec_from_na(&aabb.mins.coords);
+                    let maxs = glam_vec_from_na(&aabb.maxs.coords);
+                    let center = transform * Affine3A::from_translation((mins + maxs) * 0.5);
+                    self.wire_box(&center, (maxs - mins) * 0.5, color);
+                }
+            }
+        }
+    }
+
+    fn capsule(&mut self, a: Vec3, b: Vec3, radius: f32, color: Vec4) {
+        self.sphere(a, radius, color);
+        self.sphere(b, radius, color);
+        let axis = (b - a).try_normalize().unwrap_or(Vec3::Y);
+        let (x, y) = axis.any_orthonormal_pair();
+        for side in [x, -x, y, -y] {
+            self.line(a + side * radius, b + side * radius, color);
+        }
+    }
+
+    /// Draw the ray each visible pointer casts, as the pointers system casts it.
+    pub(crate) fn pointers(&mut self, world: &World, physics_context: &PhysicsContext) {
+        for (_, local_transform) in world
+            .query::<With<&LocalTransform, (&Pointer, &Visible)>>()
+            .iter()
+        {
+            let (ray, hit) = cast_pointer_ray(physics_context, local_transform);
+            self.ray_cast(
+                glam_vec_from_na(&ray.origin.coords),
+                glam_vec_from_na(&ray.dir),
+                POINTER_MAX_TOI,
+                hit.map(|(_, toi)| toi),
+            );
+        }
+    }
+
+    /// Turn the labels into lines, facing the camera at `global_from_camera`.
+    pub(crate) fn layout_labels(&mut self, global_from_camera: &Affine3A) {
+        let right = global_from_camera.transform_vector3(Vec3::X).normalize();
+        let up = global_from_camera.transform_vector3(Vec3::Y).normalize();
+
+        for label in std::mem::take(&mut self.labels) {
+            let advance = label.height * 0.8;
+            let width = label.height * 0.6;
+            let character_count = label.text.chars().count() as f32;
+            let left = -(character_count * advance - (advance - width)) * 0.5;
+
+            for (n, character) in label.text.chars().enumerate() {
+                let glyph_left = left + n as f32 * advance;
+                let point = |key: u8| {
+                    let (column, row) = keypad_position(key);
+                    let x = glyph_left + column * width * 0.5;
+                    let y = (row - 1.) * label.height * 0.5;
+                    label.position + right * x + up * y
+                };
+                for stroke in glyph(character).split(' ') {
+                    for pair in stroke.as_bytes().windows(2) {
+                        self.line(point(pair[0]), point(pair[1]), label.color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Where a phone keypad's key is on a 3x3 grid, from `1` in the bottom left to `9` in the top
+/// right, as `(column, row)`.
+fn keypad_position(key: u8) -> (f32, f32) {
+    let index = key - b'1';
+    ((index % 3) as f32, (index / 3) as f32)
+}
+
+/// The strokes of a character, separated by spaces. Each stroke joins the points of a 3x3 grid
+/// laid out like a phone keypad:
+///
+/// ```text
+/// 7 8 9
+/// 4 5 6
+/// 1 2 3
+/// ```
+fn glyph(character: char) -> &'static str {
+    match character.to_ascii_uppercase() {
+        '0' => "79317 19",
+        '1' => "482 13",
+        '2' => "796413",
+        '3' => "7931 46",
+        '4' => "746 93",
+        '5' | 'S' => "974631",
+        '6' => "971364",
+        '7' => "793",
+        '8' => "79317 46",
+        '9' => "647931",
+        'A' => "1793 46",
+        'B' => "17864 6321",
+        'C' => "9713",
+        'D' => "178621",
+        'E' => "9713 45",
+        'F' => "971 45",
+        'G' => "971365",
+        'H' => "71 93 46",
+        'I' => "79 82 13",
+        'J' => "9314",
+        'K' => "71 943",
+        'L' => "713",
+        'M' => "17593",
+        'N' => "1739",
+        'O' => "79317",
+        'P' => "17964",
+        'Q' => "79317 53",
+        'R' => "17964 53",
+        'T' => "79 82",
+        'U' => "7139",
+        'V' => "729",
+        'W' => "71539",
+        'X' => "73 19",
+        'Y' => "759 52",
+        'Z' => "7913",
+        '-' => "46",
+        '+' => "46 82",
+        '=' => "46 13",
+        '_' | '.' | ',' => "12",
+        '/' => "19",
+        '(' => "842",
+        ')' => "862",
+        _ => "",
+    }
+}
+
+/// Create the pipeline used to draw debug lines in the main render pass.
+pub(crate) fn create_debug_draw_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    samples: vk::SampleCountFlags,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    let device = &vulkan_context.device;
+    let main = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    let vertex_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(DEBUG_VERT),
+            None,
+        )
+    }?;
+    let fragment_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(DEBUG_FRAG),
+            None,
+        )
+    }?;
+
+    let stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .name(main)
+            .module(vertex_shader)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .name(main)
+            .module(fragment_shader)
+            .build(),
+    ];
+
+    let vertex_binding_descriptions = [vk::VertexInputBindingDescription::builder()
+        .binding(0)
+        .stride(size_of::<DebugVertex>() as _)
+        .input_rate(vk::VertexInputRate::VERTEX)
+        .build()];
+    let vertex_attribute_descriptions = DebugVertex::attribute_descriptions();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions)
+        .vertex_binding_descriptions(&vertex_binding_descriptions);
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::LINE_LIST);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_VIEWPORT_STATES);
+
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .line_width(1.0);
+
+    let multisample_state =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    // Debug lines are drawn on top of everything, so colliders can't hide inside the meshes they
+    // belong to.
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false)
+        .depth_write_enable(false)
+        .max_depth_bounds(1.0);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .build()];
+    let color_blend_state =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0)
+        .build();
+
+    let pipelines = unsafe {
+        device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader, None);
+        device.destroy_shader_module(fragment_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_shapes() {
+        let mut debug_draw = DebugDraw::default();
+        let color = Vec4::ONE;
+
+        // Twelve edges, each one as long as the box.
+        debug_draw.wire_box(&Affine3A::IDENTITY, Vec3::ONE, color);
+        let vertices = debug_draw.vertices();
+        assert_eq!(vertices.len(), 24);
+        for edge in vertices.chunks(2) {
+            assert_relative_eq!(edge[0].position.distance(edge[1].position), 2.);
+        }
+        debug_draw.clear();
+
+        // Every point on the sphere is one radius from its center.
+        let center = Vec3::new(1., 2., 3.);
+        debug_draw.sphere(center, 0.5, color);
+        assert_eq!(debug_draw.vertices().len(), CIRCLE_SEGMENTS * 2 * 3);
+        for vertex in debug_draw.vertices() {
+            assert_relative_eq!(vertex.position.distance(center), 0.5, epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut debug_draw = DebugDraw::default();
+        let position = Vec3::new(0., 1., -1.);
+        debug_draw.text(position, "HI", 0.1, Vec4::ONE);
+        assert!(debug_draw.vertices().is_empty());
+
+        // The label faces the camera, and is centered on its position.
+        debug_draw.layout_labels(&Affine3A::IDENTITY);
+        let vertices = debug_draw.vertices();
+        assert_eq!(vertices.len(), (3 + 3) * 2);
+        let sum = vertices.iter().fold(Vec3::ZERO, |sum, v| sum + v.position);
+        assert_relative_eq!(sum / vertices.len() as f32, position, epsilon = 0.0001);
+        assert!(vertices.iter().all(|v| v.position.z == position.z));
+
+        // Labels are only laid out once.
+        debug_draw.layout_labels(&Affine3A::IDENTITY);
+        assert_eq!(debug_draw.vertices().len(), 12);
+    }
+
+    #[test]
+    fn test_glyphs() {
+        let characters = ('0'..='9').chain('A'..='Z').chain("-+=_.,/()".chars());
+        for character in characters {
+            let glyph = glyph(character);
+            assert!(!glyph.is_empty(), "{character} has no glyph");
+            for stroke in glyph.split(' ') {
+                assert!(stroke.len() >= 2, "{character} has a stroke with one point");
+                assert!(stroke.bytes().all(|key| (b'1'..=b'9').contains(&key)));
+            }
+        }
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph(' '), "");
+    }
+}
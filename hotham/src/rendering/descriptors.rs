@@ -1,6 +1,9 @@
 use std::convert::TryInto;
 
-use crate::contexts::{render_context::PIPELINE_DEPTH, VulkanContext};
+use crate::{
+    contexts::{render_context::PIPELINE_DEPTH, VulkanContext},
+    rendering::render_target::MAX_RENDER_TARGET_CAMERAS,
+};
 use ash::vk;
 
 pub const DRAW_DATA_BINDING: u32 = 0;
@@ -8,9 +11,12 @@ pub const SKINS_BINDING: u32 = 1;
 pub const SCENE_DATA_BINDING: u32 = 2;
 pub const TEXTURE_BINDING: u32 = 3;
 pub const CUBE_TEXTURE_BINDING: u32 = 4;
+pub const SHADOW_MAP_BINDING: u32 = 5;
+pub const SCENE_COLOR_BINDING: u32 = 6;
 
 pub const PRIMITIVE_CULL_DATA_BINDING: u32 = 0;
 pub const CULL_PARAMS_BINDING: u32 = 1;
+pub const HI_Z_BINDING: u32 = 2;
 
 const TEXTURE_BINDING_DESCRIPTOR_COUNT: u32 = 10_000;
 
@@ -21,6 +27,9 @@ pub struct Descriptors {
     pub compute_layout: vk::DescriptorSetLayout,
     // One descriptor set per frame
     pub sets: [vk::DescriptorSet; PIPELINE_DEPTH],
+    // One descriptor set per render target camera per frame. They only differ from `sets` in their
+    // scene data.
+    pub render_target_sets: [[vk::DescriptorSet; PIPELINE_DEPTH]; MAX_RENDER_TARGET_CAMERAS],
     // One descriptor set per frame
     pub compute_sets: [vk::DescriptorSet; PIPELINE_DEPTH],
     #[allow(unused)]
@@ -37,17 +46,28 @@ impl Descriptors {
 
         // Finally, allocate the shared descriptor set.
         let sets = allocate_descriptor_sets(vulkan_context, pool, graphics_layout);
+        let render_target_sets = [(); MAX_RENDER_TARGET_CAMERAS]
+            .map(|_| allocate_descriptor_sets(vulkan_context, pool, graphics_layout));
         let compute_sets = allocate_compute_descriptor_sets(vulkan_context, pool, compute_layout);
 
         Self {
             graphics_layout,
             sets,
+            render_target_sets,
             pool,
             compute_layout,
             compute_sets,
         }
     }
 
+    /// Every graphics descriptor set: the main sets and those of each render target camera.
+    pub fn graphics_sets(&self) -> impl Iterator<Item = vk::DescriptorSet> + '_ {
+        self.sets
+            .iter()
+            .chain(self.render_target_sets.iter().flatten())
+            .copied()
+    }
+
     pub unsafe fn write_texture_descriptor(
         &self,
         vulkan_context: &VulkanContext,
@@ -61,15 +81,18 @@ impl Descriptors {
             image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
         };
 
-        let texture_writes = self.sets.map(|set| {
-            vk::WriteDescriptorSet::builder()
-                .image_info(std::slice::from_ref(&image_info))
-                .dst_binding(TEXTURE_BINDING)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .dst_array_element(array_index)
-                .dst_set(set)
-                .build()
-        });
+        let texture_writes = self
+            .graphics_sets()
+            .map(|set| {
+                vk::WriteDescriptorSet::builder()
+                    .image_info(std::slice::from_ref(&image_info))
+                    .dst_binding(TEXTURE_BINDING)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .dst_array_element(array_index)
+                    .dst_set(set)
+                    .build()
+            })
+            .collect::<Vec<_>>();
 
         vulkan_context
             .device
@@ -89,19 +112,105 @@ impl Descriptors {
             image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
         };
 
-        let texture_writes = self.sets.map(|set| {
+        let texture_writes = self
+            .graphics_sets()
+            .map(|set| {
+                vk::WriteDescriptorSet::builder()
+                    .image_info(std::slice::from_ref(&image_info))
+                    .dst_binding(CUBE_TEXTURE_BINDING)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .dst_array_element(array_index)
+                    .dst_set(set)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        vulkan_context
+            .device
+            .update_descriptor_sets(&texture_writes, &[]);
+    }
+
+    pub unsafe fn write_shadow_map_descriptor(
+        &self,
+        vulkan_context: &VulkanContext,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) {
+        let image_info = vk::DescriptorImageInfo {
+            sampler,
+            image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+
+        let shadow_map_writes = self
+            .graphics_sets()
+            .map(|set| {
+                vk::WriteDescriptorSet::builder()
+                    .image_info(std::slice::from_ref(&image_info))
+                    .dst_binding(SHADOW_MAP_BINDING)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .dst_set(set)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        vulkan_context
+            .device
+            .update_descriptor_sets(&shadow_map_writes, &[]);
+    }
+
+    pub unsafe fn write_scene_color_descriptor(
+        &self,
+        vulkan_context: &VulkanContext,
+        image_view: vk::ImageView,
+    ) {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+
+        let scene_color_writes = self
+            .graphics_sets()
+            .map(|set| {
+                vk::WriteDescriptorSet::builder()
+                    .image_info(std::slice::from_ref(&image_info))
+                    .dst_binding(SCENE_COLOR_BINDING)
+                    .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                    .dst_set(set)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        vulkan_context
+            .device
+            .update_descriptor_sets(&scene_color_writes, &[]);
+    }
+
+    pub unsafe fn write_hi_z_descriptor(
+        &self,
+        vulkan_context: &VulkanContext,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) {
+        let image_info = vk::DescriptorImageInfo {
+            sampler,
+            image_view,
+            image_layout: vk::ImageLayout::GENERAL,
+        };
+
+        let hi_z_writes = self.compute_sets.map(|set| {
             vk::WriteDescriptorSet::builder()
                 .image_info(std::slice::from_ref(&image_info))
-                .dst_binding(CUBE_TEXTURE_BINDING)
+                .dst_binding(HI_Z_BINDING)
                 .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .dst_array_element(array_index)
                 .dst_set(set)
                 .build()
         });
 
         vulkan_context
             .device
-            .update_descriptor_sets(&texture_writes, &[]);
+            .update_descriptor_sets(&hi_z_writes, &[]);
     }
 }
 
@@ -189,6 +298,22 @@ unsafe fn create_descriptor_layouts(
             descriptor_count: 100,
             ..Default::default()
         },
+        // Shadow Maps
+        vk::DescriptorSetLayoutBinding {
+            binding: SHADOW_MAP_BINDING,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            descriptor_count: 1,
+            ..Default::default()
+        },
+        // Scene color, read by the post-processing subpass
+        vk::DescriptorSetLayoutBinding {
+            binding: SCENE_COLOR_BINDING,
+            descriptor_type: vk::DescriptorType::INPUT_ATTACHMENT,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            descriptor_count: 1,
+            ..Default::default()
+        },
     ];
 
     let compute_bindings = [
@@ -208,6 +333,14 @@ unsafe fn create_descriptor_layouts(
             descriptor_count: 1,
             ..Default::default()
         },
+        // Hi-Z pyramid
+        vk::DescriptorSetLayoutBinding {
+            binding: HI_Z_BINDING,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            descriptor_count: 1,
+            ..Default::default()
+        },
     ];
 
     let flags = vk::DescriptorBindingFlags::PARTIALLY_BOUND;
@@ -218,6 +351,8 @@ unsafe fn create_descriptor_layouts(
         vk::DescriptorBindingFlags::empty(),
         flags,
         flags,
+        vk::DescriptorBindingFlags::empty(),
+        vk::DescriptorBindingFlags::empty(),
     ];
     let mut binding_flags = vk::DescriptorSetLayoutBindingFlagsCreateInfoEXT::builder()
         .binding_flags(&descriptor_flags);
@@ -251,9 +386,15 @@ unsafe fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
             ty: vk::DescriptorType::UNIFORM_BUFFER,
             descriptor_count: 100,
         },
+        // Each graphics set has just over 10,000 of these, and there's one set per frame plus one
+        // per render target camera per frame.
         vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: 60_000,
+            descriptor_count: 120_000,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 20,
         },
     ];
     device
@@ -0,0 +1,181 @@
+use ash::vk;
+
+use crate::contexts::{render_context::PIPELINE_DEPTH, VulkanContext};
+
+/// Settings for dynamic resolution, which lowers the render resolution when the GPU can't keep up
+/// with the display and raises it again when there is headroom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DynamicResolution {
+    /// The lowest resolution scale to drop to
+    pub min_scale: f32,
+    /// The highest resolution scale to rise to. The swapchain is created at this scale.
+    pub max_scale: f32,
+    /// How much of each frame the GPU should be busy for, from 0 to 1. Leave some headroom for
+    /// the compositor and for spikes.
+    pub target_utilization: f32,
+}
+
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        Self {
+            min_scale: 0.6,
+            max_scale: 1.,
+            target_utilization: 0.85,
+        }
+    }
+}
+
+/// How much the scale grows each frame while the GPU has headroom. Growing slowly avoids
+/// oscillating around the budget.
+const SCALE_STEP: f32 = 0.01;
+
+impl DynamicResolution {
+    pub(crate) fn clamp(&self, scale: f32) -> f32 {
+        scale.clamp(self.min_scale, self.max_scale)
+    }
+
+    /// Pick the resolution scale for the next frame, given how long the GPU took to render the
+    /// last one and how long a frame lasts on the display, both in seconds.
+    pub(crate) fn next_scale(&self, scale: f32, gpu_time: f32, frame_period: f32) -> f32 {
+        let budget = frame_period * self.target_utilization;
+        if gpu_time <= 0. || budget <= 0. {
+            return self.clamp(scale);
+        }
+
+        let next = if gpu_time > budget {
+            // GPU time is roughly proportional to the number of pixels, so scale both axes by the
+            // square root of how far over budget the frame was.
+            scale * (budget / gpu_time).sqrt()
+        } else if gpu_time < budget * 0.9 {
+            scale + SCALE_STEP
+        } else {
+            scale
+        };
+        self.clamp(next)
+    }
+}
+
+/// Measures how long the GPU spends on each frame's command buffer, using timestamp queries.
+pub(crate) struct GpuTimer {
+    query_pool: vk::QueryPool,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    written: [bool; PIPELINE_DEPTH],
+}
+
+impl GpuTimer {
+    /// Create a timer, if the GPU supports timestamps on the graphics queue.
+    pub(crate) fn new(vulkan_context: &VulkanContext) -> Option<Self> {
+        let limits = &vulkan_context.physical_device_properties.limits;
+        if limits.timestamp_compute_and_graphics == vk::FALSE {
+            println!("[HOTHAM_RENDERER] GPU timestamps unsupported, disabling dynamic resolution");
+            return None;
+        }
+
+        let query_pool = unsafe {
+            vulkan_context.device.create_query_pool(
+                &vk::QueryPoolCreateInfo::builder()
+                    .query_type(vk::QueryType::TIMESTAMP)
+                    .query_count(2 * PIPELINE_DEPTH as u32),
+                None,
+            )
+        }
+        .ok()?;
+
+        Some(Self {
+            query_pool,
+            timestamp_period: limits.timestamp_period,
+            written: [false; PIPELINE_DEPTH],
+        })
+    }
+
+    /// How long the GPU took to render the last frame that used `frame_index`, in seconds. Call
+    /// this after waiting for that frame's fence.
+    pub(crate) fn read(&self, device: &ash::Device, frame_index: usize) -> Option<f32> {
+        if !self.written[frame_index] {
+            return None;
+        }
+
+        let mut timestamps = [0u64; 2];
+        unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                2 * frame_index as u32,
+                2,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        }
+        .ok()?;
+
+        let ticks = timestamps[1].saturating_sub(timestamps[0]);
+        Some(ticks as f32 * self.timestamp_period * 1e-9)
+    }
+
+    /// Record the start of a frame. Call this at the start of the command buffer.
+    pub(crate) unsafe fn begin(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+    ) {
+        let first_query = 2 * frame_index as u32;
+        device.cmd_reset_query_pool(command_buffer, self.query_pool, first_query, 2);
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            self.query_pool,
+            first_query,
+        );
+    }
+
+    /// Record the end of a frame. Call this at the end of the command buffer.
+    pub(crate) unsafe fn end(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+    ) {
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.query_pool,
+            2 * frame_index as u32 + 1,
+        );
+        self.written[frame_index] = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_next_scale() {
+        let dynamic_resolution = DynamicResolution {
+            min_scale: 0.5,
+            max_scale: 1.,
+            target_utilization: 1.,
+        };
+        let frame_period = 1. / 72.;
+
+        // Four times over budget halves the resolution in each direction.
+        let scale = dynamic_resolution.next_scale(1., 4. * frame_period, frame_period);
+        assert_relative_eq!(scale, 0.5);
+
+        // But never drops below the minimum.
+        let scale = dynamic_resolution.next_scale(0.6, 4. * frame_period, frame_period);
+        assert_relative_eq!(scale, 0.5);
+
+        // With plenty of headroom it slowly grows again, up to the maximum.
+        let scale = dynamic_resolution.next_scale(0.5, 0.5 * frame_period, frame_period);
+        assert_relative_eq!(scale, 0.5 + SCALE_STEP);
+        let scale = dynamic_resolution.next_scale(1., 0.5 * frame_period, frame_period);
+        assert_relative_eq!(scale, 1.);
+
+        // Just under budget, it stays put.
+        let scale = dynamic_resolution.next_scale(0.8, 0.95 * frame_period, frame_period);
+        assert_relative_eq!(scale, 0.8);
+    }
+}
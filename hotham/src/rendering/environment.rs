@@ -0,0 +1,268 @@
+use std::ffi::CStr;
+
+use anyhow::Result;
+use ash::vk;
+use vk_shader_macros::include_glsl;
+
+use crate::{
+    contexts::{render_context::DYNAMIC_VIEWPORT_STATES, VulkanContext},
+    rendering::{
+        descriptors::Descriptors,
+        image::Image,
+        texture::{parse_ktx2, Texture, DEFAULT_COMPONENT_MAPPING},
+    },
+};
+
+static SKYBOX_VERT: &[u32] = include_glsl!("src/shaders/skybox.vert", target: vulkan1_1);
+static SKYBOX_FRAG: &[u32] = include_glsl!("src/shaders/skybox.frag", target: vulkan1_1);
+
+/// Index of the diffuse (irradiance) cube map in the shader's cube texture array
+pub const IRRADIANCE_CUBE_TEXTURE_ID: u32 = 0;
+/// Index of the specular (environment) cube map in the shader's cube texture array
+pub const ENVIRONMENT_CUBE_TEXTURE_ID: u32 = 1;
+
+/// The pair of pre-filtered cube maps used for Image Based Lighting (IBL).
+///
+/// Load one per level with [`EnvironmentMap::from_ktx2`] and make it current with
+/// [`crate::contexts::RenderContext::set_environment_map`]. Maps are never destroyed, so swapping
+/// back to a map you've kept hold of is cheap.
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    /// Diffuse irradiance cube map
+    pub diffuse: Image,
+    /// Specular cube map, with one mip level per roughness step
+    pub specular: Image,
+}
+
+impl EnvironmentMap {
+    /// Load an environment map from a pair of KTX2 cube maps, eg. generated with `cmgen`.
+    pub fn from_ktx2(
+        vulkan_context: &VulkanContext,
+        diffuse_ktx2: &[u8],
+        specular_ktx2: &[u8],
+    ) -> Result<Self> {
+        Ok(Self {
+            diffuse: load_cube_map(vulkan_context, diffuse_ktx2)?,
+            specular: load_cube_map(vulkan_context, specular_ktx2)?,
+        })
+    }
+
+    /// The environment map that ships with Hotham
+    pub(crate) fn built_in(vulkan_context: &VulkanContext) -> Result<Self> {
+        #[cfg(target_os = "android")]
+        let (diffuse, specular): (&[u8], &[u8]) = (
+            include_bytes!("../../data/environment_map_diffuse_android.ktx2"),
+            include_bytes!("../../data/environment_map_specular_android.ktx2"),
+        );
+
+        #[cfg(not(target_os = "android"))]
+        let (diffuse, specular): (&[u8], &[u8]) = (
+            include_bytes!("../../data/environment_map_diffuse.ktx2"),
+            include_bytes!("../../data/environment_map_specular.ktx2"),
+        );
+
+        Self::from_ktx2(vulkan_context, diffuse, specular)
+    }
+
+    /// Point the shader's IBL cube textures at these maps.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor sets must not be in use by the GPU.
+    pub(crate) unsafe fn write_descriptors(
+        &self,
+        vulkan_context: &VulkanContext,
+        descriptors: &Descriptors,
+        cube_sampler: vk::Sampler,
+    ) {
+        descriptors.write_cube_texture_descriptor(
+            vulkan_context,
+            self.diffuse.view,
+            cube_sampler,
+            IRRADIANCE_CUBE_TEXTURE_ID,
+        );
+        descriptors.write_cube_texture_descriptor(
+            vulkan_context,
+            self.specular.view,
+            cube_sampler,
+            ENVIRONMENT_CUBE_TEXTURE_ID,
+        );
+    }
+}
+
+/// Upload a KTX2 cube map, with all of its mip levels, to the GPU.
+pub(crate) fn load_cube_map(vulkan_context: &VulkanContext, ktx2_data: &[u8]) -> Result<Image> {
+    let ktx2_image = parse_ktx2(ktx2_data);
+    if ktx2_image.faces != 6 {
+        anyhow::bail!(
+            "Cube maps must have 6 faces, this one has {}",
+            ktx2_image.faces
+        );
+    }
+    let mip_levels = ktx2_image.mip_levels;
+
+    let image = vulkan_context.create_image_with_component_mapping(
+        ktx2_image.format,
+        &ktx2_image.extent,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        6,
+        mip_levels,
+        DEFAULT_COMPONENT_MAPPING,
+    )?;
+
+    vulkan_context.upload_image(
+        &ktx2_image.image_buf,
+        mip_levels,
+        ktx2_image.offsets,
+        &image,
+    );
+
+    Ok(image)
+}
+
+/// A cube map drawn behind everything else in the scene.
+///
+/// Basic usage:
+/// ```ignore
+/// // Show the specular environment map, slightly blurred
+/// render_context.skybox = Some(Skybox { lod: 1., ..Default::default() });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct Skybox {
+    /// Index of the cube map in the shader's cube texture array. Defaults to the specular
+    /// environment map.
+    pub cube_texture_id: u32,
+    /// The mip level to sample. Higher levels of the specular environment map are blurrier.
+    pub lod: f32,
+    /// Multiplier for the skybox's color
+    pub intensity: f32,
+}
+
+impl Default for Skybox {
+    fn default() -> Self {
+        Self {
+            cube_texture_id: ENVIRONMENT_CUBE_TEXTURE_ID,
+            lod: 0.,
+            intensity: 1.,
+        }
+    }
+}
+
+impl Skybox {
+    /// Use a separate cube map, eg. loaded with [`Texture::from_ktx2`], as the skybox.
+    pub fn from_texture(texture: &Texture) -> Self {
+        assert_eq!(
+            texture.image.layer_count, 6,
+            "Skybox textures must be cube maps"
+        );
+        Self {
+            cube_texture_id: texture.index,
+            ..Default::default()
+        }
+    }
+}
+
+/// Create the pipeline used to draw the skybox: a single triangle covering the screen, drawn at
+/// infinity wherever nothing else has been drawn.
+pub(crate) fn create_skybox_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    samples: vk::SampleCountFlags,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    let device = &vulkan_context.device;
+    let main = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    let vertex_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(SKYBOX_VERT),
+            None,
+        )
+    }?;
+    let fragment_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(SKYBOX_FRAG),
+            None,
+        )
+    }?;
+
+    let stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .name(main)
+            .module(vertex_shader)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .name(main)
+            .module(fragment_shader)
+            .build(),
+    ];
+
+    // The vertices are generated in the vertex shader.
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_VIEWPORT_STATES);
+
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisample_state =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    // With reverse Z, infinity is at a depth of 0 - the value the depth buffer is cleared to. Only
+    // pixels nothing has been drawn to will pass.
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
+        .max_depth_bounds(1.0);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .build()];
+    let color_blend_state =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0)
+        .build();
+
+    let pipelines = unsafe {
+        device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader, None);
+        device.destroy_shader_module(fragment_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
@@ -0,0 +1,235 @@
+use glam::Vec3;
+
+/// What a [`ScreenFade`] is currently doing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FadeState {
+    /// Not fading, the view is clear
+    Clear,
+    /// Fading to the fade color at `speed` per second, then holding
+    FadingOut { speed: f32, hold: Hold },
+    /// The view is completely covered by the fade color
+    Holding { hold: Hold },
+    /// Fading back to the scene at `speed` per second
+    FadingIn { speed: f32 },
+}
+
+/// How long to stay faded out for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Hold {
+    /// Until [`ScreenFade::fade_in`] is called
+    Indefinitely,
+    /// For `remaining` seconds, then fade in over `fade_in_duration` seconds
+    For {
+        remaining: f32,
+        fade_in_duration: f32,
+    },
+}
+
+/// A fade to a solid color in front of everything in both eyes, for loading screens and scene
+/// transitions. It's drawn by the post-processing subpass, and advanced once per frame by
+/// [`crate::Engine::update`].
+///
+/// Basic usage:
+/// ```ignore
+/// // Fade to black over half a second and stay there until `fade_in` is called
+/// engine.render_context.screen_fade.fade_out(Vec3::ZERO, 0.5);
+///
+/// // Then, once the view is covered, after `engine.finish()`:
+/// if engine.render_context.screen_fade.is_opaque() {
+///     let glb = engine.load_in_background(|| std::fs::read("level_2.glb").unwrap())?;
+///     // ..import the scene..
+///     engine.render_context.screen_fade.fade_in(0.5);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenFade {
+    color: Vec3,
+    amount: f32,
+    state: FadeState,
+}
+
+impl Default for ScreenFade {
+    fn default() -> Self {
+        Self {
+            color: Vec3::ZERO,
+            amount: 0.,
+            state: FadeState::Clear,
+        }
+    }
+}
+
+impl ScreenFade {
+    /// Fade to `color` over `duration` seconds, and stay faded out until [`ScreenFade::fade_in`]
+    /// is called.
+    pub fn fade_out(&mut self, color: Vec3, duration: f32) {
+        self.start_fade_out(color, duration, Hold::Indefinitely);
+    }
+
+    /// Fade to `color` over `duration` seconds, hold for `hold_duration` seconds, then fade back
+    /// in over `duration` seconds.
+    pub fn fade_out_and_in(&mut self, color: Vec3, duration: f32, hold_duration: f32) {
+        let hold = Hold::For {
+            remaining: hold_duration,
+            fade_in_duration: duration,
+        };
+        self.start_fade_out(color, duration, hold);
+    }
+
+    /// Fade back to the scene over `duration` seconds, from wherever the fade currently is.
+    pub fn fade_in(&mut self, duration: f32) {
+        self.state = FadeState::FadingIn {
+            speed: speed(duration),
+        };
+        // Apply instant fades straight away.
+        self.update(0.);
+    }
+
+    /// How much of the view is covered by the fade color, from 0 (none) to 1 (all of it).
+    pub fn amount(&self) -> f32 {
+        self.amount
+    }
+
+    /// The color being faded to.
+    pub fn color(&self) -> Vec3 {
+        self.color
+    }
+
+    /// Is the view completely covered? Safe to swap scenes behind the fade if so.
+    pub fn is_opaque(&self) -> bool {
+        matches!(self.state, FadeState::Holding { .. })
+    }
+
+    /// Is the fade finished, with the view completely clear?
+    pub fn is_clear(&self) -> bool {
+        self.state == FadeState::Clear
+    }
+
+    /// Advance the fade by `delta_time` seconds.
+    pub(crate) fn update(&mut self, delta_time: f32) {
+        self.state = match self.state {
+            FadeState::Clear => FadeState::Clear,
+            FadeState::FadingOut { speed, hold } => {
+                self.amount = (self.amount + step(speed, delta_time)).min(1.);
+                if self.amount < 1. {
+                    FadeState::FadingOut { speed, hold }
+                } else {
+                    FadeState::Holding { hold }
+                }
+            }
+            FadeState::Holding {
+                hold: Hold::Indefinitely,
+            } => self.state,
+            FadeState::Holding {
+                hold:
+                    Hold::For {
+                        remaining,
+                        fade_in_duration,
+                    },
+            } => {
+                let remaining = remaining - delta_time;
+                if remaining > 0. {
+                    FadeState::Holding {
+                        hold: Hold::For {
+                            remaining,
+                            fade_in_duration,
+                        },
+                    }
+                } else {
+                    FadeState::FadingIn {
+                        speed: speed(fade_in_duration),
+                    }
+                }
+            }
+            FadeState::FadingIn { speed } => {
+                self.amount = (self.amount - step(speed, delta_time)).max(0.);
+                if self.amount > 0. {
+                    FadeState::FadingIn { speed }
+                } else {
+                    FadeState::Clear
+                }
+            }
+        };
+    }
+
+    fn start_fade_out(&mut self, color: Vec3, duration: f32, hold: Hold) {
+        self.color = color;
+        self.state = FadeState::FadingOut {
+            speed: speed(duration),
+            hold,
+        };
+        // Apply instant fades straight away.
+        self.update(0.);
+    }
+}
+
+/// How much the fade changes per second. Fades with no duration happen instantly.
+fn speed(duration: f32) -> f32 {
+    if duration > 0. {
+        1. / duration
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// How much the fade changes over `delta_time` seconds.
+fn step(speed: f32, delta_time: f32) -> f32 {
+    if speed.is_infinite() {
+        1.
+    } else {
+        speed * delta_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_fade_out_hold_and_in() {
+        let mut fade = ScreenFade::default();
+        fade.fade_out_and_in(Vec3::ONE, 1., 0.5);
+
+        fade.update(0.5);
+        assert_relative_eq!(fade.amount(), 0.5);
+        assert!(!fade.is_opaque());
+
+        fade.update(0.5);
+        assert_eq!(fade.amount(), 1.);
+        assert!(fade.is_opaque());
+
+        // Hold, then start fading in.
+        fade.update(0.5);
+        assert!(!fade.is_opaque());
+        fade.update(0.25);
+        assert_relative_eq!(fade.amount(), 0.75);
+
+        fade.update(1.);
+        assert_eq!(fade.amount(), 0.);
+        assert!(fade.is_clear());
+    }
+
+    #[test]
+    fn test_fade_in_from_partial_fade() {
+        let mut fade = ScreenFade::default();
+        fade.fade_out(Vec3::ZERO, 1.);
+        fade.update(0.5);
+        fade.fade_in(1.);
+        fade.update(0.25);
+        assert_relative_eq!(fade.amount(), 0.25);
+    }
+
+    #[test]
+    fn test_instant_fade() {
+        let mut fade = ScreenFade::default();
+        fade.fade_out(Vec3::ZERO, 0.);
+        assert!(fade.is_opaque());
+
+        // An indefinite hold lasts until we fade in.
+        fade.update(100.);
+        assert!(fade.is_opaque());
+
+        fade.fade_in(0.);
+        assert!(fade.is_clear());
+    }
+}
@@ -5,16 +5,23 @@ use anyhow::Result;
 
 use super::{
     buffer::Buffer,
+    debug_draw::{DebugVertex, MAX_DEBUG_VERTICES},
     descriptors::{
         Descriptors, CULL_PARAMS_BINDING, DRAW_DATA_BINDING, PRIMITIVE_CULL_DATA_BINDING,
         SCENE_DATA_BINDING,
     },
+    particles::{ParticleInstance, MAX_PARTICLES},
+    render_target::MAX_RENDER_TARGET_CAMERAS,
     resources::{DrawData, PrimitiveCullData},
     scene_data::SceneData,
 };
 
-// We *can* draw this many objects, but.. seriously?
-static DRAW_DATA_BUFFER_SIZE: usize = 5000;
+/// The most instances each pass sharing the draw data buffer is expected to draw.
+const DRAW_DATA_PER_PASS: usize = 5000;
+
+// The shadow pass, each render target camera and the main pass share the buffer, so there's room
+// for all of them.
+static DRAW_DATA_BUFFER_SIZE: usize = DRAW_DATA_PER_PASS * (2 + MAX_RENDER_TARGET_CAMERAS);
 
 // We *can* draw this many objects, but.. seriously?
 static PRIMITIVE_CULL_DATA_BUFFER_SIZE: usize = 100_000;
@@ -36,8 +43,14 @@ pub struct Frame {
     pub primitive_cull_data_buffer: Buffer<PrimitiveCullData>,
     /// Shared data used in a scene
     pub scene_data_buffer: Buffer<SceneData>,
+    /// The scene as seen by each render target camera
+    pub render_target_scene_data_buffers: Vec<Buffer<SceneData>>,
     /// Shared data used in a scene
     pub cull_params_buffer: Buffer<CullParams>,
+    /// Lines drawn with [`super::debug_draw::DebugDraw`] this frame
+    pub debug_vertex_buffer: Buffer<DebugVertex>,
+    /// Every visible particle this frame, furthest first
+    pub particle_buffer: Buffer<ParticleInstance>,
 }
 
 impl Frame {
@@ -87,6 +100,25 @@ impl Frame {
             unsafe { Buffer::new(vulkan_context, vk::BufferUsageFlags::UNIFORM_BUFFER, 1) };
         let cull_params_buffer =
             unsafe { Buffer::new(vulkan_context, vk::BufferUsageFlags::UNIFORM_BUFFER, 1) };
+        let mut render_target_scene_data_buffers = (0..MAX_RENDER_TARGET_CAMERAS)
+            .map(|_| unsafe {
+                Buffer::new(vulkan_context, vk::BufferUsageFlags::UNIFORM_BUFFER, 1)
+            })
+            .collect::<Vec<_>>();
+        let debug_vertex_buffer = unsafe {
+            Buffer::new(
+                vulkan_context,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                MAX_DEBUG_VERTICES,
+            )
+        };
+        let particle_buffer = unsafe {
+            Buffer::new(
+                vulkan_context,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                MAX_PARTICLES,
+            )
+        };
 
         // Update the descriptor sets for this frame.
         unsafe {
@@ -102,6 +134,25 @@ impl Frame {
                 SCENE_DATA_BINDING,
             );
 
+            // Render targets share the draw data, but see the scene from their own cameras.
+            for (sets, scene_data_buffer) in descriptors
+                .render_target_sets
+                .iter()
+                .zip(&mut render_target_scene_data_buffers)
+            {
+                draw_data_buffer.update_descriptor_set(
+                    &vulkan_context.device,
+                    sets[index],
+                    DRAW_DATA_BINDING,
+                );
+                scene_data_buffer.update_descriptor_set(
+                    &vulkan_context.device,
+                    sets[index],
+                    SCENE_DATA_BINDING,
+                );
+                scene_data_buffer.push(&Default::default());
+            }
+
             // Compute
             primitive_cull_data_buffer.update_descriptor_set(
                 &vulkan_context.device,
@@ -126,7 +177,10 @@ impl Frame {
             draw_data_buffer,
             primitive_cull_data_buffer,
             scene_data_buffer,
+            render_target_scene_data_buffers,
             cull_params_buffer,
+            debug_vertex_buffer,
+            particle_buffer,
         })
     }
 }
@@ -0,0 +1,82 @@
+use std::ops::Range;
+
+/// Keeps track of the unused ranges of a buffer that's shared by many primitives, so space freed
+/// by one primitive can be used again by another.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FreeList {
+    /// Free ranges, sorted by where they start. Neighbouring ranges are always merged.
+    ranges: Vec<Range<u32>>,
+}
+
+impl FreeList {
+    /// Take `len` elements from the first free range big enough to hold them, if there is one.
+    pub fn allocate(&mut self, len: u32) -> Option<u32> {
+        let index = self.ranges.iter().position(|r| r.end - r.start >= len)?;
+        let range = &mut self.ranges[index];
+        let start = range.start;
+        range.start += len;
+        if range.is_empty() {
+            self.ranges.remove(index);
+        }
+        Some(start)
+    }
+
+    /// Make `range` available again.
+    pub fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        let index = self.ranges.partition_point(|r| r.start < range.start);
+        self.ranges.insert(index, range);
+
+        // Merge with the ranges after and before it.
+        if index + 1 < self.ranges.len() && self.ranges[index].end == self.ranges[index + 1].start {
+            self.ranges[index].end = self.ranges.remove(index + 1).end;
+        }
+        if index > 0 && self.ranges[index - 1].end == self.ranges[index].start {
+            self.ranges[index - 1].end = self.ranges.remove(index).end;
+        }
+    }
+
+    /// Give back the free range at the end of a buffer that's `len` long, so it can be used by
+    /// anything that pushes onto the buffer. Returns the new length of the buffer.
+    pub fn trim(&mut self, len: usize) -> usize {
+        match self.ranges.last() {
+            Some(last) if last.end as usize == len => {
+                let start = last.start as usize;
+                self.ranges.pop();
+                start
+            }
+            _ => len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_list() {
+        let mut free_list = FreeList::default();
+        assert_eq!(free_list.allocate(1), None);
+
+        // Neighbouring ranges are merged, whichever order they're freed in.
+        free_list.free(10..20);
+        free_list.free(30..40);
+        free_list.free(20..30);
+        assert_eq!(free_list.ranges, vec![10..40]);
+
+        // Allocations come from the first range that fits.
+        free_list.free(0..5);
+        assert_eq!(free_list.allocate(10), Some(10));
+        assert_eq!(free_list.allocate(5), Some(0));
+        assert_eq!(free_list.allocate(25), None);
+        assert_eq!(free_list.ranges, vec![20..40]);
+
+        // Only space at the end of the buffer is given back to it.
+        assert_eq!(free_list.trim(50), 50);
+        assert_eq!(free_list.trim(40), 20);
+        assert!(free_list.ranges.is_empty());
+    }
+}
@@ -46,4 +46,12 @@ impl Image {
             layer_count,
         }
     }
+
+    /// safety: After calling this function the image will be in an UNUSABLE state, and the GPU
+    /// must have finished with it
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.handle, None);
+        device.free_memory(self.device_memory, None);
+    }
 }
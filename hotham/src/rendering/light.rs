@@ -1,4 +1,4 @@
-use glam::{Quat, Vec3};
+use glam::{Mat4, Quat, Vec3};
 use serde::{Deserialize, Serialize};
 
 /// A directional light.
@@ -147,6 +147,51 @@ fn get_offet_and_scale(inner_cone_angle: f32, outer_cone_angle: f32) -> (f32, f3
     (scale, offset)
 }
 
+/// Pick the [`MAX_LIGHTS`] lights that matter most to the player, returning their indices, most
+/// relevant first. Lights must be in the same space as `clip_planes` and `eyes`.
+///
+/// Directional lights always come first. Point and spot lights whose range doesn't reach into
+/// either eye's view are culled, and the rest are ranked by how bright they are at the player.
+pub(crate) fn select_lights(lights: &[Light], clip_planes: &[Mat4; 2], eyes: Vec3) -> Vec<usize> {
+    let mut candidates = lights
+        .iter()
+        .enumerate()
+        .filter(|(_, light)| light.light_type != LIGHT_TYPE_NONE)
+        .filter(|(_, light)| {
+            light.light_type == LIGHT_TYPE_DIRECTIONAL || is_light_visible(light, clip_planes)
+        })
+        .map(|(index, light)| (index, relevance(light, eyes)))
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    candidates
+        .into_iter()
+        .take(MAX_LIGHTS)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Does the light's range reach into either eye's view? Lights without a range always do.
+fn is_light_visible(light: &Light, clip_planes: &[Mat4; 2]) -> bool {
+    if light.falloff <= 0. {
+        return true;
+    }
+    let range = light.falloff.sqrt().recip();
+    let position = light.position.extend(1.);
+
+    // Each row of the clip planes matrix is a plane whose normal points into the frustum.
+    clip_planes
+        .iter()
+        .any(|planes| (*planes * position).min_element() >= -range)
+}
+
+fn relevance(light: &Light, eyes: Vec3) -> f32 {
+    if light.light_type == LIGHT_TYPE_DIRECTIONAL {
+        return f32::INFINITY;
+    }
+    light.intensity * light.color.max_element() / light.position.distance_squared(eyes).max(0.01)
+}
+
 // TODO: is this correct? Filament's glTF importer just does this
 /// Calculate the falloff for a light from a glTF range value
 pub fn get_falloff(range: f32) -> f32 {
@@ -157,3 +202,42 @@ pub fn get_falloff(range: f32) -> f32 {
         0.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::camera::{extract_planes_from_frustum, Frustum};
+
+    #[test]
+    fn test_select_lights() {
+        // Both eyes at the origin, looking down -Z with a 90 degree field of view.
+        let fov = Frustum {
+            left: -std::f32::consts::FRAC_PI_4,
+            right: std::f32::consts::FRAC_PI_4,
+            up: std::f32::consts::FRAC_PI_4,
+            down: -std::f32::consts::FRAC_PI_4,
+        };
+        let planes = extract_planes_from_frustum(&fov.projection(0.05));
+        let clip_planes = [planes, planes];
+
+        let lights = [
+            // 0: In front, dim
+            Light::new_point([0., 0., -2.].into(), 5., 1., Vec3::ONE),
+            // 1: Behind the player and out of range
+            Light::new_point([0., 0., 5.].into(), 2., 100., Vec3::ONE),
+            // 2: Behind the player, but its range reaches into view
+            Light::new_point([0., 0., 1.].into(), 5., 2., Vec3::ONE),
+            // 3: No light
+            Light::none(),
+            // 4: The sun
+            Light::new_directional(Vec3::NEG_Y, 1., Vec3::ONE),
+            // 5: In front, bright
+            Light::new_point([1., 0., -3.].into(), 10., 50., Vec3::ONE),
+            // 6: Far away in front, dimmest
+            Light::new_point([0., 0., -20.].into(), 50., 1., Vec3::ONE),
+        ];
+
+        let selected = select_lights(&lights, &clip_planes, Vec3::ZERO);
+        assert_eq!(selected, vec![4, 5, 2, 0]);
+    }
+}
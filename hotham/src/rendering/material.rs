@@ -1,4 +1,5 @@
-use gltf::Material as MaterialData;
+use glam::{Affine2, Mat2, Vec2, Vec3, Vec4};
+use gltf::{material::AlphaMode, Material as MaterialData};
 
 use crate::{
     asset_importer::ImportContext,
@@ -22,16 +23,40 @@ bitflags! {
         const HAS_EMISSION_TEXTURE = 1 << 4;
         /// Are we using unlit workflow?
         const UNLIT_WORKFLOW = 1 << 5;
+        /// Discard fragments with an alpha below the alpha cutoff?
+        const ALPHA_MASK = 1 << 6;
+        /// Blend with whatever is behind this material? Drawn after opaque geometry, back to front.
+        const ALPHA_BLEND = 1 << 7;
+        /// Is there a clear coat layer on top of the material?
+        const HAS_CLEARCOAT = 1 << 8;
+        /// Does the AO texture use the second set of texture coordinates?
+        const OCCLUSION_TEXCOORD_1 = 1 << 9;
+        /// Should the texture coordinates be transformed before sampling textures?
+        const HAS_UV_TRANSFORM = 1 << 10;
+        /// Is diffuse lighting baked into a lightmap? If so, dynamic lights only add specular.
+        const HAS_LIGHTMAP = 1 << 11;
+        /// Cut a hole in the scene for underlay composition layers to show through?
+        const HOLE_PUNCH = 1 << 12;
     }
 }
 
 /// Material index into the default material
 pub static NO_MATERIAL: usize = 0;
 
+/// The alpha cutoff used by `ALPHA_MASK` materials if none is specified, as defined by the glTF spec
+pub const DEFAULT_ALPHA_CUTOFF: f32 = 0.5;
+
+/// Texture index used in 16 bit texture ID fields to indicate there is no texture
+pub const NO_TEXTURE_16: u32 = 0xFFFF;
+
+/// Materials with this in their name use their occlusion texture as a lightmap
+static LIGHTMAP_TAG: &str = ".HOTHAM_LIGHTMAP";
+
 /// Mostly maps to the [glTF material spec](https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#materials) and
 /// added by default by the `gltf_loader`
 ///
-/// TOOD: It would be good to be able to set / retrieve values more naturally and only use the packed representation on the GPU
+/// To create or change materials at runtime, use a [`MaterialDesc`] instead, which can be converted
+/// to and from a `Material`.
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Material {
@@ -39,8 +64,18 @@ pub struct Material {
     pub packed_flags_and_base_texture_id: u32,
     /// The base color of the material
     pub packed_base_color_factor: u32,
-    /// The metallic and roughness factors
+    /// The metallic and roughness factors, followed by the alpha cutoff
     pub packed_metallic_roughness_factor: u32,
+    /// The emissive factor, multiplied by the emissive strength, as three half floats
+    pub packed_emissive_factor: [u32; 2],
+    /// The clear coat and clear coat roughness factors
+    pub packed_clearcoat_factor: u32,
+    /// The clear coat texture ID, followed by the clear coat roughness texture ID, as two u16
+    pub packed_clearcoat_texture_ids: u32,
+    /// The transform applied to texture coordinates, as a 2x3 matrix of half floats in column order
+    pub packed_uv_transform: [u32; 3],
+    /// The lightmap texture ID as a u16, followed by the lightmap intensity as a half float
+    pub packed_lightmap: u32,
 }
 
 impl Default for Material {
@@ -78,12 +113,19 @@ impl Material {
             .map(|i| Texture::load(i.texture(), TextureUsage::Normal, import_context))
             .unwrap_or(NO_TEXTURE);
 
+        // Lightmapped materials put their lightmap in the occlusion texture slot, as that's
+        // where exporters like Blender will let you plug it in.
+        let is_lightmapped = material
+            .name()
+            .map(|n| n.contains(LIGHTMAP_TAG))
+            .unwrap_or(false);
+
         // For performance, we don't allow unpacked AO textures.
         //
         // see: https://github.com/leetvr/hotham/issues/395
-        let has_occlusion_texture = if let Some(occlusion_texture_info) =
-            material.occlusion_texture()
-        {
+        let has_occlusion_texture = if is_lightmapped {
+            false
+        } else if let Some(occlusion_texture_info) = material.occlusion_texture() {
             // This is.. quite ugly.
             if Some(occlusion_texture_info.texture().source().index())
                 == material
@@ -106,6 +148,38 @@ impl Material {
         let emissive_texture_set = emissive_texture_info
             .map(|i| Texture::load(i.texture(), TextureUsage::Emission, import_context))
             .unwrap_or(NO_TEXTURE);
+        let emissive_strength = material.emissive_strength().unwrap_or(1.0);
+        let [r, g, b] = material.emissive_factor().map(|f| f * emissive_strength);
+
+        // Clear coat. This must be loaded after the other textures, as the shader expects those
+        // to follow the base color texture.
+        let clearcoat = Clearcoat::load(&material, import_context);
+
+        // We only support a single texture transform per material, shared by every texture
+        // sampled with TEXCOORD_0, which is almost always what exporters produce. Take it from the
+        // first texture that has one, and warn about any texture that won't be drawn as intended.
+        let texture_mappings =
+            TextureMapping::load_all(&material, has_occlusion_texture, clearcoat.is_some());
+        let uv_transform = texture_mappings
+            .iter()
+            .filter(|m| m.sampled_tex_coord() == 0)
+            .find_map(|m| m.transform);
+        check_texture_mappings(
+            material.name().unwrap_or_default(),
+            &texture_mappings,
+            uv_transform.unwrap_or(Affine2::IDENTITY),
+        );
+        let occlusion_tex_coord = texture_mappings
+            .iter()
+            .find(|m| m.texture == OCCLUSION)
+            .map(|m| m.sampled_tex_coord());
+
+        // Lightmap. Like clear coat, it has its own texture ID.
+        let lightmap_texture_id = if is_lightmapped {
+            load_lightmap(&material, import_context)
+        } else {
+            NO_TEXTURE_16
+        };
 
         let mut material_flags = MaterialFlags::empty();
         if base_color_texture_set != NO_TEXTURE {
@@ -132,11 +206,40 @@ impl Material {
             material_flags.insert(MaterialFlags::UNLIT_WORKFLOW);
         }
 
+        if clearcoat.is_some() {
+            material_flags.insert(MaterialFlags::HAS_CLEARCOAT);
+        }
+
+        if occlusion_tex_coord == Some(1) {
+            material_flags.insert(MaterialFlags::OCCLUSION_TEXCOORD_1);
+        }
+
+        if uv_transform.is_some() {
+            material_flags.insert(MaterialFlags::HAS_UV_TRANSFORM);
+        }
+
+        if lightmap_texture_id != NO_TEXTURE_16 {
+            material_flags.insert(MaterialFlags::HAS_LIGHTMAP);
+        }
+
+        match material.alpha_mode() {
+            AlphaMode::Opaque => {}
+            AlphaMode::Mask => material_flags.insert(MaterialFlags::ALPHA_MASK),
+            AlphaMode::Blend => material_flags.insert(MaterialFlags::ALPHA_BLEND),
+        }
+
         // Don't allow non-sensical flags
-        assert_ne!(material_flags, MaterialFlags::HAS_EMISSION_TEXTURE);
-        assert_ne!(material_flags, MaterialFlags::HAS_AO_TEXTURE);
+        let texture_flags = material_flags
+            - MaterialFlags::ALPHA_MASK
+            - MaterialFlags::ALPHA_BLEND
+            - MaterialFlags::HAS_CLEARCOAT
+            - MaterialFlags::OCCLUSION_TEXCOORD_1
+            - MaterialFlags::HAS_UV_TRANSFORM
+            - MaterialFlags::HAS_LIGHTMAP;
+        assert_ne!(texture_flags, MaterialFlags::HAS_EMISSION_TEXTURE);
+        assert_ne!(texture_flags, MaterialFlags::HAS_AO_TEXTURE);
         assert_ne!(
-            material_flags,
+            texture_flags,
             MaterialFlags::HAS_AO_TEXTURE | MaterialFlags::HAS_EMISSION_TEXTURE
         );
 
@@ -147,9 +250,20 @@ impl Material {
             packed_metallic_roughness_factor: pack_unorm4x8(&[
                 pbr_metallic_roughness.metallic_factor(),
                 pbr_metallic_roughness.roughness_factor(),
-                0.0,
+                material.alpha_cutoff().unwrap_or(DEFAULT_ALPHA_CUTOFF),
                 0.0,
             ]),
+            packed_emissive_factor: [pack_half2x16(r, g), pack_half2x16(b, 0.0)],
+            packed_clearcoat_factor: clearcoat
+                .as_ref()
+                .map(|c| pack_unorm4x8(&[c.factor, c.roughness_factor, 0.0, 0.0]))
+                .unwrap_or(0),
+            packed_clearcoat_texture_ids: clearcoat
+                .as_ref()
+                .map(|c| pack2x16(c.texture_id, c.roughness_texture_id))
+                .unwrap_or(u32::MAX),
+            packed_uv_transform: pack_uv_transform(&uv_transform.unwrap_or(Affine2::IDENTITY)),
+            packed_lightmap: pack_lightmap(lightmap_texture_id, 1.0),
         };
 
         // Then push it into the materials buffer
@@ -166,11 +280,21 @@ impl Material {
     pub fn unlit_white() -> Material {
         Material {
             packed_flags_and_base_texture_id: MaterialFlags::UNLIT_WORKFLOW.bits,
-            packed_base_color_factor: u32::MAX,
-            packed_metallic_roughness_factor: pack_unorm4x8(&[1.0, 1.0, 0.0, 0.0]),
+            ..Material::gltf_default()
         }
     }
 
+    /// The flags used by the shader to render this material
+    pub fn flags(&self) -> MaterialFlags {
+        MaterialFlags::from_bits_truncate(self.packed_flags_and_base_texture_id & 0xFFFF)
+    }
+
+    /// Should this material be blended with what's behind it? If so, it will be drawn after all
+    /// opaque geometry, sorted back to front.
+    pub fn is_alpha_blended(&self) -> bool {
+        self.flags().contains(MaterialFlags::ALPHA_BLEND)
+    }
+
     /// The default material, reasonably close to what's defined by the glTF 2.0 spec
     /// https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#reference-material-pbrmetallicroughness
     pub fn gltf_default() -> Self {
@@ -178,10 +302,410 @@ impl Material {
             packed_flags_and_base_texture_id: MaterialFlags::empty().bits,
             packed_base_color_factor: u32::MAX,
             packed_metallic_roughness_factor: pack_unorm4x8(&[1.0, 1.0, 0.0, 0.0]),
+            packed_emissive_factor: [0, 0],
+            packed_clearcoat_factor: 0,
+            packed_clearcoat_texture_ids: u32::MAX,
+            packed_uv_transform: pack_uv_transform(&Affine2::IDENTITY),
+            packed_lightmap: pack_lightmap(NO_TEXTURE_16, 1.0),
+        }
+    }
+}
+
+/// A [`Material`] with its values unpacked, for creating and changing materials at runtime.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::rendering::material::MaterialDesc;
+/// // Clone a mesh's material and make it red..
+/// let resources = &mut render_context.resources;
+/// let mut red = resources.material(primitive.material_id).unwrap();
+/// red.base_color_factor = [1., 0., 0., 1.].into();
+/// let red_id = resources.add_material(&red)?;
+///
+/// // ..then use it instead of the glTF material.
+/// world.insert_one(cube, MaterialOverride::material(red_id))?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialDesc {
+    /// The flags used by the shader to render this material. These aren't updated when textures
+    /// are changed, so it's up to you to keep them in sync.
+    pub flags: MaterialFlags,
+    /// The ID of the base color texture. The metallic roughness, normal and emission textures
+    /// follow it, in that order.
+    pub base_texture_id: u32,
+    /// Linear RGBA base color, multiplied with the base color texture's alpha
+    pub base_color_factor: Vec4,
+    /// How metallic the material is, from 0 to 1
+    pub metallic_factor: f32,
+    /// How rough the material is, from 0 to 1
+    pub roughness_factor: f32,
+    /// Fragments with an alpha below this are discarded, if the material has `ALPHA_MASK` set
+    pub alpha_cutoff: f32,
+    /// Linear RGB color that the material emits, which can be brighter than 1
+    pub emissive_factor: Vec3,
+    /// The strength of the clear coat layer, if the material has `HAS_CLEARCOAT` set
+    pub clearcoat_factor: f32,
+    /// The roughness of the clear coat layer
+    pub clearcoat_roughness_factor: f32,
+    /// The ID of the clear coat texture, or [`NO_TEXTURE_16`]
+    pub clearcoat_texture_id: u32,
+    /// The ID of the clear coat roughness texture, or [`NO_TEXTURE_16`]
+    pub clearcoat_roughness_texture_id: u32,
+    /// The transform applied to texture coordinates, if the material has `HAS_UV_TRANSFORM` set
+    pub uv_transform: Affine2,
+    /// The ID of the lightmap texture, or [`NO_TEXTURE_16`]
+    pub lightmap_texture_id: u32,
+    /// How bright the lightmap is
+    pub lightmap_intensity: f32,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        (&Material::gltf_default()).into()
+    }
+}
+
+impl MaterialDesc {
+    /// A simple, untextured material of a single color
+    pub fn from_color(base_color_factor: Vec4) -> Self {
+        Self {
+            base_color_factor,
+            ..Default::default()
         }
     }
 }
 
+impl From<&Material> for MaterialDesc {
+    fn from(material: &Material) -> Self {
+        let [metallic_factor, roughness_factor, alpha_cutoff, _] =
+            unpack_unorm4x8(material.packed_metallic_roughness_factor);
+        let [r, g] = unpack_half2x16(material.packed_emissive_factor[0]);
+        let [b, _] = unpack_half2x16(material.packed_emissive_factor[1]);
+        let [clearcoat_factor, clearcoat_roughness_factor, _, _] =
+            unpack_unorm4x8(material.packed_clearcoat_factor);
+        let [_, lightmap_intensity] = unpack_half2x16(material.packed_lightmap);
+        Self {
+            flags: material.flags(),
+            base_texture_id: material.packed_flags_and_base_texture_id >> 16,
+            base_color_factor: unpack_unorm4x8(material.packed_base_color_factor).into(),
+            metallic_factor,
+            roughness_factor,
+            alpha_cutoff,
+            emissive_factor: Vec3::new(r, g, b),
+            clearcoat_factor,
+            clearcoat_roughness_factor,
+            clearcoat_texture_id: material.packed_clearcoat_texture_ids & 0xFFFF,
+            clearcoat_roughness_texture_id: material.packed_clearcoat_texture_ids >> 16,
+            uv_transform: unpack_uv_transform(&material.packed_uv_transform),
+            lightmap_texture_id: material.packed_lightmap & 0xFFFF,
+            lightmap_intensity,
+        }
+    }
+}
+
+impl From<&MaterialDesc> for Material {
+    fn from(desc: &MaterialDesc) -> Self {
+        let emissive = desc.emissive_factor;
+        Self {
+            packed_flags_and_base_texture_id: pack2x16(desc.flags.bits, desc.base_texture_id),
+            packed_base_color_factor: pack_unorm4x8(&desc.base_color_factor.into()),
+            packed_metallic_roughness_factor: pack_unorm4x8(&[
+                desc.metallic_factor,
+                desc.roughness_factor,
+                desc.alpha_cutoff,
+                0.0,
+            ]),
+            packed_emissive_factor: [
+                pack_half2x16(emissive.x, emissive.y),
+                pack_half2x16(emissive.z, 0.0),
+            ],
+            packed_clearcoat_factor: pack_unorm4x8(&[
+                desc.clearcoat_factor,
+                desc.clearcoat_roughness_factor,
+                0.0,
+                0.0,
+            ]),
+            packed_clearcoat_texture_ids: pack2x16(
+                desc.clearcoat_texture_id,
+                desc.clearcoat_roughness_texture_id,
+            ),
+            packed_uv_transform: pack_uv_transform(&desc.uv_transform),
+            packed_lightmap: pack_lightmap(desc.lightmap_texture_id, desc.lightmap_intensity),
+        }
+    }
+}
+
+/// Load the lightmap of a material tagged with [`LIGHTMAP_TAG`] from its occlusion texture.
+/// Lightmaps are always sampled with the second set of texture coordinates (TEXCOORD_1).
+fn load_lightmap(material: &MaterialData, import_context: &mut ImportContext) -> u32 {
+    let name = material.name().unwrap_or_default();
+    let lightmap = match material.occlusion_texture() {
+        Some(lightmap) => lightmap,
+        None => {
+            println!("[HOTHAM_TEXTURE] WARNING: Material {name} is tagged as lightmapped, but has no occlusion texture to use as its lightmap!");
+            return NO_TEXTURE_16;
+        }
+    };
+    if lightmap.tex_coord() != 1 {
+        println!("[HOTHAM_TEXTURE] WARNING: The lightmap for material {name} will be sampled with TEXCOORD_1, not TEXCOORD_{}", lightmap.tex_coord());
+    }
+
+    Texture::load(lightmap.texture(), TextureUsage::Lightmap, import_context)
+}
+
+/// A clear coat layer, from the KHR_materials_clearcoat extension:
+/// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_materials_clearcoat
+struct Clearcoat {
+    factor: f32,
+    roughness_factor: f32,
+    texture_id: u32,
+    roughness_texture_id: u32,
+}
+
+impl Clearcoat {
+    /// The gltf crate doesn't parse this extension, so we read its JSON ourselves. Returns `None`
+    /// if the material has no clear coat.
+    fn load(material: &MaterialData, import_context: &mut ImportContext) -> Option<Self> {
+        let extension = material.extension_value("KHR_materials_clearcoat")?;
+        let factor = extension
+            .get("clearcoatFactor")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.) as f32;
+        if factor <= 0. {
+            return None;
+        }
+        let roughness_factor = extension
+            .get("clearcoatRoughnessFactor")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.) as f32;
+
+        // TODO: Support clear coat normal maps. Until then, the clear coat shares the base
+        // material's normal.
+        if extension.get("clearcoatNormalTexture").is_some() {
+            println!("[HOTHAM_TEXTURE] WARNING: Clear coat normal textures are not supported yet, so the one on material {} will be ignored.", material.name().unwrap_or_default());
+        }
+
+        let texture_index = |name: &str| {
+            extension
+                .get(name)
+                .and_then(|t| t.get("index"))
+                .and_then(|i| i.as_u64())
+                .map(|i| i as usize)
+        };
+        let clearcoat_texture = texture_index("clearcoatTexture");
+        let roughness_texture = texture_index("clearcoatRoughnessTexture");
+
+        // Textures borrow the document, so we need our own copy while `import_context` is in use.
+        let document = (clearcoat_texture.is_some() || roughness_texture.is_some())
+            .then(|| import_context.document.clone());
+        let mut load_texture = |index: Option<usize>| {
+            index
+                .and_then(|i| document.as_ref()?.textures().nth(i))
+                .map(|t| Texture::load(t, TextureUsage::Other, import_context))
+                .unwrap_or(NO_TEXTURE_16)
+        };
+        let texture_id = load_texture(clearcoat_texture);
+        // Clear coat and clear coat roughness are often packed into the same image.
+        let roughness_texture_id = if roughness_texture == clearcoat_texture {
+            texture_id
+        } else {
+            load_texture(roughness_texture)
+        };
+
+        Some(Self {
+            factor,
+            roughness_factor,
+            texture_id,
+            roughness_texture_id,
+        })
+    }
+}
+
+/// Name of the occlusion texture in a [`TextureMapping`], the only texture that may be sampled with
+/// TEXCOORD_1
+static OCCLUSION: &str = "occlusion";
+
+/// How a material's texture is meant to be mapped onto a mesh, according to the glTF document
+#[derive(Debug, Clone, PartialEq)]
+struct TextureMapping {
+    /// Which of the material's textures this is, for warnings
+    texture: &'static str,
+    /// The set of texture coordinates to sample the texture with, after any override by the
+    /// KHR_texture_transform extension
+    tex_coord: u32,
+    /// The transform from the KHR_texture_transform extension, if the texture has one
+    transform: Option<Affine2>,
+}
+
+impl TextureMapping {
+    /// Get the mappings of every texture on the material that will be drawn.
+    fn load_all(
+        material: &MaterialData,
+        has_occlusion_texture: bool,
+        has_clearcoat: bool,
+    ) -> Vec<Self> {
+        let pbr_metallic_roughness = material.pbr_metallic_roughness();
+        let mut mappings = Vec::new();
+        if let Some(info) = pbr_metallic_roughness.base_color_texture() {
+            mappings.push(Self::from_info("base color", &info));
+        }
+        if let Some(info) = pbr_metallic_roughness.metallic_roughness_texture() {
+            mappings.push(Self::from_info("metallic roughness", &info));
+        }
+        // The gltf crate only parses the extension for `Info`, so read the rest ourselves.
+        if let Some(normal) = material.normal_texture() {
+            let extension = normal.extension_value("KHR_texture_transform");
+            mappings.push(Self::from_json("normal", normal.tex_coord(), extension));
+        }
+        if let Some(occlusion) = material
+            .occlusion_texture()
+            .filter(|_| has_occlusion_texture)
+        {
+            let extension = occlusion.extension_value("KHR_texture_transform");
+            mappings.push(Self::from_json(OCCLUSION, occlusion.tex_coord(), extension));
+        }
+        if let Some(info) = material.emissive_texture() {
+            mappings.push(Self::from_info("emissive", &info));
+        }
+
+        let clearcoat = material
+            .extension_value("KHR_materials_clearcoat")
+            .filter(|_| has_clearcoat);
+        for (texture, name) in [
+            ("clear coat", "clearcoatTexture"),
+            ("clear coat roughness", "clearcoatRoughnessTexture"),
+        ] {
+            if let Some(info) = clearcoat.and_then(|c| c.get(name)) {
+                let tex_coord = info.get("texCoord").and_then(|t| t.as_u64()).unwrap_or(0);
+                let extension = info
+                    .get("extensions")
+                    .and_then(|e| e.get("KHR_texture_transform"));
+                mappings.push(Self::from_json(texture, tex_coord as u32, extension));
+            }
+        }
+
+        mappings
+    }
+
+    fn from_info(texture: &'static str, info: &gltf::texture::Info) -> Self {
+        let transform = info.texture_transform();
+        Self {
+            texture,
+            tex_coord: transform
+                .as_ref()
+                .and_then(|t| t.tex_coord())
+                .unwrap_or_else(|| info.tex_coord()),
+            transform: transform
+                .map(|t| uv_transform(t.offset().into(), t.rotation(), t.scale().into())),
+        }
+    }
+
+    fn from_json(
+        texture: &'static str,
+        tex_coord: u32,
+        extension: Option<&gltf::json::Value>,
+    ) -> Self {
+        let extension = match extension {
+            Some(extension) => extension,
+            None => {
+                return Self {
+                    texture,
+                    tex_coord,
+                    transform: None,
+                }
+            }
+        };
+
+        let number = |value: &gltf::json::Value| value.as_f64().unwrap_or(0.) as f32;
+        let vec2 = |name: &str, default: Vec2| match extension
+            .get(name)
+            .and_then(|v| v.as_array())
+            .map(|a| a.as_slice())
+        {
+            Some([x, y]) => Vec2::new(number(x), number(y)),
+            _ => default,
+        };
+        let offset = vec2("offset", Vec2::ZERO);
+        let rotation = extension.get("rotation").map(number).unwrap_or(0.);
+        let scale = vec2("scale", Vec2::ONE);
+
+        Self {
+            texture,
+            tex_coord: extension
+                .get("texCoord")
+                .and_then(|t| t.as_u64())
+                .map(|t| t as u32)
+                .unwrap_or(tex_coord),
+            transform: Some(uv_transform(offset, rotation, scale)),
+        }
+    }
+
+    /// The set of texture coordinates the shader will actually sample the texture with. Only
+    /// the occlusion texture can be sampled with TEXCOORD_1, and it's never transformed if it is.
+    fn sampled_tex_coord(&self) -> u32 {
+        if self.texture == OCCLUSION && self.tex_coord == 1 {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// Warn about textures that won't be sampled with the coordinates or transform they ask for.
+fn check_texture_mappings(name: &str, mappings: &[TextureMapping], uv_transform: Affine2) {
+    for mapping in mappings {
+        let sampled_tex_coord = mapping.sampled_tex_coord();
+        if mapping.tex_coord != sampled_tex_coord {
+            println!("[HOTHAM_TEXTURE] WARNING: The {} texture of material {name} will be sampled with TEXCOORD_{sampled_tex_coord}, not TEXCOORD_{}", mapping.texture, mapping.tex_coord);
+        }
+
+        let sampled_transform = if sampled_tex_coord == 0 {
+            uv_transform
+        } else {
+            Affine2::IDENTITY
+        };
+        if mapping.transform.unwrap_or(Affine2::IDENTITY) != sampled_transform {
+            println!("[HOTHAM_TEXTURE] WARNING: Only one texture transform per material is supported, so the {} texture of material {name} will be drawn with the wrong transform.", mapping.texture);
+        }
+    }
+}
+
+/// Build a transform for texture coordinates from the parameters of the KHR_texture_transform
+/// extension: https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_texture_transform
+pub fn uv_transform(offset: Vec2, rotation: f32, scale: Vec2) -> Affine2 {
+    // Note that the extension rotates clockwise, ie. in the opposite direction to glam.
+    Affine2::from_scale_angle_translation(scale, -rotation, offset)
+}
+
+/// Pack a texture coordinate transform into half floats, as expected by the shader.
+pub fn pack_uv_transform(transform: &Affine2) -> [u32; 3] {
+    [
+        pack_half2x16(transform.matrix2.x_axis.x, transform.matrix2.x_axis.y),
+        pack_half2x16(transform.matrix2.y_axis.x, transform.matrix2.y_axis.y),
+        pack_half2x16(transform.translation.x, transform.translation.y),
+    ]
+}
+
+/// Pack a lightmap texture ID and intensity, as expected by the shader.
+pub fn pack_lightmap(texture_id: u32, intensity: f32) -> u32 {
+    let intensity = half::f16::from_f32(intensity).to_bits() as u32;
+    pack2x16(texture_id, intensity)
+}
+
+/// Unpack a texture coordinate transform packed by [`pack_uv_transform`].
+pub fn unpack_uv_transform(packed: &[u32; 3]) -> Affine2 {
+    let [x_axis, y_axis, translation] = packed.map(|p| Vec2::from(unpack_half2x16(p)));
+    Affine2::from_mat2_translation(Mat2::from_cols(x_axis, y_axis), translation)
+}
+
+/// Convert two floating-point values into half floats and pack them into an u32.
+/// First value is stored in least significant bits. This works the same as packHalf2x16 in GLSL.
+pub fn pack_half2x16(lsb: f32, msb: f32) -> u32 {
+    let lsb = half::f16::from_f32(lsb).to_bits() as u32;
+    let msb = half::f16::from_f32(msb).to_bits() as u32;
+    (msb << 16) | lsb
+}
+
 /// Convert normalized floating-point values into 8-bit integer values and pack them into an u32.
 /// First value is stored in least significant bits. This works the same as packUnorm4x8 in GLSL.
 pub fn pack_unorm4x8(array: &[f32; 4]) -> u32 {
@@ -193,6 +717,19 @@ pub fn pack_unorm4x8(array: &[f32; 4]) -> u32 {
     packed
 }
 
+/// Unpack two half floats from an u32. This works the same as unpackHalf2x16 in GLSL.
+pub fn unpack_half2x16(packed: u32) -> [f32; 2] {
+    [
+        half::f16::from_bits(packed as u16).to_f32(),
+        half::f16::from_bits((packed >> 16) as u16).to_f32(),
+    ]
+}
+
+/// Unpack four normalized values from an u32. This works the same as unpackUnorm4x8 in GLSL.
+pub fn unpack_unorm4x8(packed: u32) -> [f32; 4] {
+    [0, 8, 16, 24].map(|shift| ((packed >> shift) & 0xFF) as f32 / 255.0)
+}
+
 /// Pack the least significant 16 bits from two u32 into a single u32.
 pub fn pack2x16(lsb: u32, msb: u32) -> u32 {
     (msb << 16) | (lsb & 0xFFFF)
@@ -209,4 +746,118 @@ mod tests {
         assert_eq!(pack_unorm4x8(&[0.0, 0.0, 1.0, 0.0]), 0x00FF0000);
         assert_eq!(pack_unorm4x8(&[0.0, 0.0, 0.0, 1.0]), 0xFF000000);
     }
+
+    #[test]
+    fn pack_half2x16_test() {
+        assert_eq!(pack_half2x16(1.0, 0.0), 0x00003C00);
+        assert_eq!(pack_half2x16(0.0, -2.0), 0xC0000000);
+    }
+
+    #[test]
+    fn pack_lightmap_test() {
+        assert_eq!(pack_lightmap(5, 1.0), 0x3C000005);
+        assert_eq!(pack_lightmap(NO_TEXTURE_16, 2.0), 0x4000FFFF);
+    }
+
+    #[test]
+    fn uv_transform_test() {
+        use approx::assert_relative_eq;
+
+        // The example from the KHR_texture_transform spec: rotate a quarter turn, then offset.
+        let transform = uv_transform(
+            Vec2::new(0.0, 1.0),
+            std::f32::consts::FRAC_PI_2,
+            Vec2::new(0.5, 0.5),
+        );
+
+        // u' = cos * sx * u + sin * sy * v + offset_x, v' = -sin * sx * u + cos * sy * v + offset_y
+        assert_relative_eq!(
+            transform.transform_point2(Vec2::new(1.0, 0.0)),
+            Vec2::new(0.0, 0.5),
+            epsilon = 1e-6
+        );
+        assert_relative_eq!(
+            transform.transform_point2(Vec2::new(0.0, 1.0)),
+            Vec2::new(0.5, 1.0),
+            epsilon = 1e-6
+        );
+
+        let identity = pack_uv_transform(&Affine2::IDENTITY);
+        assert_eq!(identity, [0x00003C00, 0x3C000000, 0]);
+    }
+
+    #[test]
+    fn texture_mapping_test() {
+        // Without the extension, the texture is sampled as the texture info says.
+        let mapping = TextureMapping::from_json("normal", 1, None);
+        assert_eq!(mapping.tex_coord, 1);
+        assert_eq!(mapping.transform, None);
+        assert_eq!(mapping.sampled_tex_coord(), 0);
+
+        // The extension can override the texture coordinates, and unset values use the defaults.
+        let extension = serde_json::json!({ "offset": [0.25, 0.5], "texCoord": 1 });
+        let mapping = TextureMapping::from_json(OCCLUSION, 0, Some(&extension));
+        assert_eq!(mapping.tex_coord, 1);
+        assert_eq!(mapping.sampled_tex_coord(), 1);
+        assert_eq!(
+            mapping.transform,
+            Some(uv_transform(Vec2::new(0.25, 0.5), 0.0, Vec2::ONE))
+        );
+    }
+
+    #[test]
+    fn alpha_flags_test() {
+        let mut material = Material::gltf_default();
+        assert!(!material.is_alpha_blended());
+
+        material.packed_flags_and_base_texture_id = pack2x16(
+            (MaterialFlags::HAS_BASE_COLOR_TEXTURE | MaterialFlags::ALPHA_BLEND).bits,
+            42,
+        );
+        assert!(material.is_alpha_blended());
+        assert_eq!(
+            material.flags(),
+            MaterialFlags::HAS_BASE_COLOR_TEXTURE | MaterialFlags::ALPHA_BLEND
+        );
+    }
+
+    #[test]
+    fn unpack_test() {
+        assert_eq!(unpack_unorm4x8(0xFF00FF00), [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(unpack_half2x16(pack_half2x16(0.5, -2.0)), [0.5, -2.0]);
+
+        let transform = uv_transform(Vec2::new(0.25, 0.5), 0.0, Vec2::new(2.0, 4.0));
+        assert_eq!(
+            unpack_uv_transform(&pack_uv_transform(&transform)),
+            transform
+        );
+    }
+
+    #[test]
+    fn material_desc_test() {
+        // The default description is the default material.
+        assert_eq!(
+            Material::from(&MaterialDesc::default()),
+            Material::gltf_default()
+        );
+
+        // Values that can be packed exactly survive the round trip.
+        let desc = MaterialDesc {
+            flags: MaterialFlags::HAS_BASE_COLOR_TEXTURE | MaterialFlags::ALPHA_MASK,
+            base_texture_id: 42,
+            base_color_factor: Vec4::new(1.0, 0.2, 0.0, 0.6),
+            metallic_factor: 0.0,
+            roughness_factor: 0.4,
+            alpha_cutoff: 0.2,
+            emissive_factor: Vec3::new(4.0, 0.5, 0.0),
+            lightmap_intensity: 2.0,
+            ..Default::default()
+        };
+        let material = Material::from(&desc);
+        assert_eq!(
+            material.flags(),
+            MaterialFlags::HAS_BASE_COLOR_TEXTURE | MaterialFlags::ALPHA_MASK
+        );
+        assert_eq!(MaterialDesc::from(&material), desc);
+    }
 }
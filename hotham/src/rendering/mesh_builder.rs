@@ -0,0 +1,684 @@
+use std::{
+    borrow::Cow,
+    f32::consts::{FRAC_PI_2, PI, TAU},
+};
+
+use anyhow::{bail, ensure, Result};
+use glam::{Affine3A, Mat3, Vec2, Vec3, Vec4};
+
+use crate::{
+    components::Mesh,
+    contexts::RenderContext,
+    rendering::{
+        material::pack_unorm4x8,
+        mesh_data::MeshData,
+        primitive::{calculate_bounding_sphere, Primitive},
+        resources::Resources,
+        vertex::Vertex,
+    },
+};
+
+/// The vertices and triangles of a single primitive, built in code rather than imported from a
+/// glTF file.
+///
+/// Normals, texture coordinates and colors are optional, but if they're given there must be one
+/// for each position. Without normals, smooth normals are worked out from the triangles. Without
+/// indices, every three positions make a triangle. Triangles are counter-clockwise when seen from
+/// the front.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Geometry {
+    /// Vertex positions
+    pub positions: Vec<Vec3>,
+    /// Vertex normals
+    pub normals: Vec<Vec3>,
+    /// Texture coordinates
+    pub uvs: Vec<Vec2>,
+    /// Linear RGBA vertex colors, multiplied with the material's base color
+    pub colors: Vec<Vec4>,
+    /// Three indices into the vertices for each triangle
+    pub indices: Vec<u32>,
+}
+
+impl Geometry {
+    /// Add a vertex, and get its index
+    pub fn add_vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> u32 {
+        let index = self.positions.len() as u32;
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        index
+    }
+
+    /// Add a triangle between three vertices
+    pub fn add_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    /// Add two triangles between four vertices, going around the quad
+    pub fn add_quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+        self.add_triangle(a, b, c);
+        self.add_triangle(a, c, d);
+    }
+
+    /// Give every vertex the same color
+    pub fn set_color(&mut self, color: Vec4) {
+        self.colors = vec![color; self.positions.len()];
+    }
+
+    /// Add another geometry's vertices and triangles to this one. Attributes only one of them has
+    /// are filled in with defaults for the other.
+    pub fn append(&mut self, other: &Geometry) {
+        let (len, other_len) = (self.positions.len(), other.positions.len());
+        extend_attribute(
+            &mut self.normals,
+            &other.normals,
+            len,
+            other_len,
+            Vec3::ZERO,
+        );
+        extend_attribute(&mut self.uvs, &other.uvs, len, other_len, Vec2::ZERO);
+        extend_attribute(&mut self.colors, &other.colors, len, other_len, Vec4::ONE);
+
+        let mut other_indices = other.indices().into_owned();
+        if self.indices.is_empty() && len > 0 {
+            self.indices = (0..len as u32).collect();
+        }
+        other_indices.iter_mut().for_each(|i| *i += len as u32);
+        self.indices.extend(other_indices);
+        self.positions.extend_from_slice(&other.positions);
+    }
+
+    /// Move every vertex by `transform`. Mirroring transforms flip the triangles, so they still
+    /// face the right way.
+    pub fn transform(&mut self, transform: &Affine3A) {
+        for position in &mut self.positions {
+            *position = transform.transform_point3(*position);
+        }
+
+        let normal_matrix = Mat3::from(transform.matrix3).inverse().transpose();
+        for normal in &mut self.normals {
+            *normal = (normal_matrix * *normal).normalize_or_zero();
+        }
+
+        if transform.matrix3.determinant() < 0. {
+            self.indices = self.indices().into_owned();
+            for triangle in self.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    /// Replace the normals with smooth normals, worked out from the triangles
+    pub fn compute_normals(&mut self) {
+        self.normals = smooth_normals(&self.positions, &self.indices());
+    }
+
+    /// The bounding sphere of the vertices, as `[center, radius]`
+    pub fn bounding_sphere(&self) -> Vec4 {
+        calculate_bounding_sphere(&self.positions)
+    }
+
+    /// A box, centered on the origin
+    pub fn cuboid(half_extents: Vec3) -> Self {
+        let mut geometry = Geometry::default();
+        let faces = [
+            (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+        ];
+        for (normal, u, v) in faces {
+            geometry.append(&quad_grid(
+                normal * half_extents,
+                u * half_extents,
+                v * half_extents,
+                1,
+            ));
+        }
+        geometry
+    }
+
+    /// A flat, square grid on the XZ plane, facing up, with `subdivisions` quads along each side
+    pub fn plane(half_extents: Vec2, subdivisions: u32) -> Self {
+        quad_grid(
+            Vec3::ZERO,
+            Vec3::X * half_extents.x,
+            Vec3::NEG_Z * half_extents.y,
+            subdivisions.max(1),
+        )
+    }
+
+    /// A sphere, centered on the origin, with `segments` around its equator
+    pub fn sphere(radius: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let rings = (segments / 2).max(2);
+        let profile = (0..=rings)
+            .map(|ring| {
+                let (sin, cos) = (PI * ring as f32 / rings as f32).sin_cos();
+                (radius * sin, radius * cos, Vec2::new(sin, cos))
+            })
+            .collect::<Vec<_>>();
+        lathe(&profile, segments)
+    }
+
+    /// A capsule along the Y axis, centered on the origin. The whole capsule is
+    /// `2 * (half_height + radius)` tall.
+    pub fn capsule(radius: f32, half_height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let rings = (segments / 4).max(1);
+        let hemisphere = |start: f32, y: f32| {
+            (0..=rings).map(move |ring| {
+                let angle = start + FRAC_PI_2 * ring as f32 / rings as f32;
+                let (sin, cos) = angle.sin_cos();
+                (radius * sin, y + radius * cos, Vec2::new(sin, cos))
+            })
+        };
+        let profile = hemisphere(0., half_height)
+            .chain(hemisphere(FRAC_PI_2, -half_height))
+            .collect::<Vec<_>>();
+        lathe(&profile, segments)
+    }
+
+    /// A closed cylinder along the Y axis, centered on the origin
+    pub fn cylinder(radius: f32, half_height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let mut geometry = lathe(
+            &[
+                (radius, half_height, Vec2::X),
+                (radius, -half_height, Vec2::X),
+            ],
+            segments,
+        );
+        geometry.append(&disk(radius, half_height, true, segments));
+        geometry.append(&disk(radius, -half_height, false, segments));
+        geometry
+    }
+
+    /// Check there's one of each attribute for each position, and that the triangles only use
+    /// vertices that exist.
+    pub fn validate(&self) -> Result<()> {
+        let len = self.positions.len();
+        for (name, attribute_len) in [
+            ("normals", self.normals.len()),
+            ("uvs", self.uvs.len()),
+            ("colors", self.colors.len()),
+        ] {
+            ensure!(
+                attribute_len == 0 || attribute_len == len,
+                "Geometry has {len} positions but {attribute_len} {name}"
+            );
+        }
+
+        let indices = self.indices();
+        ensure!(
+            indices.len() % 3 == 0,
+            "Geometry has {} indices, which isn't a whole number of triangles",
+            indices.len()
+        );
+        if let Some(index) = indices.iter().find(|i| **i as usize >= len) {
+            bail!("Geometry has an index of {index}, but only {len} vertices");
+        }
+
+        Ok(())
+    }
+
+    /// The indices, or one for each position if there aren't any
+    fn indices(&self) -> Cow<[u32]> {
+        if self.indices.is_empty() {
+            Cow::Owned((0..self.positions.len() as u32).collect())
+        } else {
+            Cow::Borrowed(self.indices.as_slice())
+        }
+    }
+
+    fn vertices(&self) -> Vec<Vertex> {
+        let normals: Cow<[Vec3]> = if self.normals.is_empty() {
+            Cow::Owned(smooth_normals(&self.positions, &self.indices()))
+        } else {
+            Cow::Borrowed(self.normals.as_slice())
+        };
+
+        (0..self.positions.len())
+            .map(|i| Vertex {
+                normal: normals[i],
+                texture_coords: self.uvs.get(i).copied().unwrap_or_default(),
+                color: self
+                    .colors
+                    .get(i)
+                    .map(|c| pack_unorm4x8(&c.to_array()))
+                    .unwrap_or(u32::MAX),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// Builds a [`Mesh`] out of [`Geometry`], one primitive per geometry, and uploads it to the GPU.
+///
+/// The same builder can later update the mesh, eg. every frame for a mesh that's deformed in
+/// code. The GPU may still be drawing earlier frames from the mesh's old vertices, so each update
+/// is written to fresh space in the shared vertex and index buffers, and the old space is reused
+/// once those frames are finished. Bounding spheres are worked out again on every update.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::rendering::mesh_builder::{Geometry, MeshBuilder};
+/// let mut builder = MeshBuilder::new();
+/// builder.primitive(Geometry::sphere(0.5, 32), material_id);
+/// let mesh = builder.build(render_context)?;
+/// world.spawn((mesh, Visible {}, LocalTransform::default(), GlobalTransform::default()));
+///
+/// // Later on..
+/// let geometry = builder.geometry_mut(0).unwrap();
+/// for position in &mut geometry.positions {
+///     position.y += (time + position.x).sin() * 0.01;
+/// }
+/// geometry.compute_normals();
+/// builder.update(render_context, &mesh)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MeshBuilder {
+    primitives: Vec<(Geometry, u32)>,
+}
+
+impl MeshBuilder {
+    /// An empty mesh builder
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a primitive with the given geometry and material
+    pub fn primitive(&mut self, geometry: Geometry, material_id: u32) -> &mut Self {
+        self.primitives.push((geometry, material_id));
+        self
+    }
+
+    /// Change the material of the primitive at `index`
+    pub fn material(&mut self, index: usize, material_id: u32) -> &mut Self {
+        if let Some((_, material)) = self.primitives.get_mut(index) {
+            *material = material_id;
+        }
+        self
+    }
+
+    /// The geometry of the primitive at `index`, to change before an [`MeshBuilder::update`]
+    pub fn geometry_mut(&mut self, index: usize) -> Option<&mut Geometry> {
+        self.primitives.get_mut(index).map(|(geometry, _)| geometry)
+    }
+
+    /// Upload the primitives to the GPU, as a new mesh
+    pub fn build(&self, render_context: &mut RenderContext) -> Result<Mesh> {
+        let primitives = self.upload(&mut render_context.resources)?;
+        Ok(Mesh::new(MeshData::new(primitives), render_context))
+    }
+
+    /// Replace `mesh`'s primitives with these ones. Every entity using `mesh` is changed.
+    pub fn update(&self, render_context: &mut RenderContext, mesh: &Mesh) -> Result<()> {
+        let resources = &mut render_context.resources;
+        if resources.mesh_data.get(mesh.handle).is_none() {
+            bail!("Mesh {:?} doesn't exist", mesh.handle);
+        }
+        let primitives = self.upload(resources)?;
+        let existing = std::mem::replace(
+            &mut resources.mesh_data.get_mut(mesh.handle).unwrap().primitives,
+            primitives,
+        );
+        for primitive in &existing {
+            resources.free_primitive_space(primitive);
+        }
+        Ok(())
+    }
+
+    fn upload(&self, resources: &mut Resources) -> Result<Vec<Primitive>> {
+        for (geometry, _) in &self.primitives {
+            geometry.validate()?;
+        }
+
+        let mut primitives = Vec::with_capacity(self.primitives.len());
+        for (geometry, material_id) in &self.primitives {
+            match unsafe { upload_primitive(resources, geometry, *material_id) } {
+                Ok(primitive) => primitives.push(primitive),
+                Err(error) => {
+                    // Nothing has drawn the primitives we've uploaded so far, so their space
+                    // can be reused straight away.
+                    for primitive in &primitives {
+                        free_unused_primitive_space(resources, primitive);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(primitives)
+    }
+}
+
+/// Write `geometry` into fresh space in the shared buffers.
+unsafe fn upload_primitive(
+    resources: &mut Resources,
+    geometry: &Geometry,
+    material_id: u32,
+) -> Result<Primitive> {
+    let vertices = geometry.vertices();
+    let indices = geometry.indices();
+    let vertex_count = vertices.len() as u32;
+    let index_count = indices.len() as u32;
+
+    // The index buffer offset identifies the primitive when it's drawn, so even an empty
+    // primitive needs an index to itself.
+    let index_capacity = index_count.max(1);
+    let vertex_buffer_offset = match resources.free_vertices.allocate(vertex_count) {
+        Some(offset) => offset,
+        None => {
+            let offset = resources.vertex_buffer.len;
+            let vertex_end = offset + vertex_count as usize;
+            ensure!(
+                vertex_end <= resources.vertex_buffer.max_len,
+                "Not enough room for {vertex_count} more vertices"
+            );
+            resources.position_buffer.len = vertex_end;
+            resources.vertex_buffer.len = vertex_end;
+            offset as u32
+        }
+    };
+    let index_buffer_offset = match resources.free_indices.allocate(index_capacity) {
+        Some(offset) => offset,
+        None => {
+            let offset = resources.index_buffer.len;
+            let index_end = offset + index_capacity as usize;
+            if index_end > resources.index_buffer.max_len {
+                // Don't leak the vertices we've just taken.
+                resources
+                    .free_vertices
+                    .free(vertex_buffer_offset..vertex_buffer_offset + vertex_count);
+                bail!("Not enough room for {index_capacity} more indices");
+            }
+            resources.index_buffer.len = index_end;
+            offset as u32
+        }
+    };
+
+    let vertex_range = vertex_buffer_offset as usize..;
+    let index_range = index_buffer_offset as usize..;
+    resources.position_buffer.as_slice_mut()[vertex_range.clone()][..vertices.len()]
+        .copy_from_slice(&geometry.positions);
+    resources.vertex_buffer.as_slice_mut()[vertex_range][..vertices.len()]
+        .copy_from_slice(&vertices);
+    resources.index_buffer.as_slice_mut()[index_range][..indices.len()].copy_from_slice(&indices);
+
+    Ok(Primitive {
+        index_buffer_offset,
+        vertex_buffer_offset,
+        indices_count: index_count,
+        material_id,
+        bounding_sphere: geometry.bounding_sphere(),
+        vertex_capacity: vertex_count,
+        index_capacity,
+    })
+}
+
+/// Free the space of a primitive that has never been drawn.
+fn free_unused_primitive_space(resources: &mut Resources, primitive: &Primitive) {
+    let vertex_offset = primitive.vertex_buffer_offset;
+    let index_offset = primitive.index_buffer_offset;
+    resources
+        .free_vertices
+        .free(vertex_offset..vertex_offset + primitive.vertex_capacity);
+    resources
+        .free_indices
+        .free(index_offset..index_offset + primitive.index_capacity);
+}
+
+/// A grid of `subdivisions` by `subdivisions` quads, spanning from `center - u - v` to
+/// `center + u + v`, facing `u × v`.
+fn quad_grid(center: Vec3, u: Vec3, v: Vec3, subdivisions: u32) -> Geometry {
+    let mut geometry = Geometry::default();
+    let normal = u.cross(v).normalize_or_zero();
+    let columns = subdivisions + 1;
+    for row in 0..=subdivisions {
+        for column in 0..=subdivisions {
+            let s = column as f32 / subdivisions as f32;
+            let t = row as f32 / subdivisions as f32;
+            geometry.add_vertex(
+                center + u * (s * 2. - 1.) + v * (t * 2. - 1.),
+                normal,
+                Vec2::new(s, 1. - t),
+            );
+        }
+    }
+    for row in 0..subdivisions {
+        for column in 0..subdivisions {
+            let a = row * columns + column;
+            geometry.add_quad(a, a + 1, a + columns + 1, a + columns);
+        }
+    }
+    geometry
+}
+
+/// Spin a profile around the Y axis. Each point of the profile, from top to bottom, is
+/// `(radius, y, normal)`, where the normal's x points away from the axis.
+fn lathe(profile: &[(f32, f32, Vec2)], segments: u32) -> Geometry {
+    let mut geometry = Geometry::default();
+    let rows = profile.len() as u32;
+    let columns = segments + 1;
+    for (row, (radius, y, normal)) in profile.iter().enumerate() {
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin, cos) = (u * TAU).sin_cos();
+            geometry.add_vertex(
+                Vec3::new(radius * sin, *y, radius * cos),
+                Vec3::new(normal.x * sin, normal.y, normal.x * cos).normalize_or_zero(),
+                Vec2::new(u, row as f32 / (rows - 1) as f32),
+            );
+        }
+    }
+    for row in 0..rows - 1 {
+        for segment in 0..segments {
+            let a = row * columns + segment;
+            geometry.add_quad(a, a + columns, a + columns + 1, a + 1);
+        }
+    }
+    geometry
+}
+
+/// A flat disk at `y`, facing up or down
+fn disk(radius: f32, y: f32, up: bool, segments: u32) -> Geometry {
+    let mut geometry = Geometry::default();
+    let normal = if up { Vec3::Y } else { Vec3::NEG_Y };
+    let center = geometry.add_vertex(Vec3::new(0., y, 0.), normal, Vec2::splat(0.5));
+    for segment in 0..segments {
+        let (sin, cos) = (TAU * segment as f32 / segments as f32).sin_cos();
+        geometry.add_vertex(
+            Vec3::new(radius * sin, y, radius * cos),
+            normal,
+            Vec2::new(0.5 + sin * 0.5, 0.5 + cos * 0.5),
+        );
+    }
+    for segment in 0..segments {
+        let a = center + 1 + segment;
+        let b = center + 1 + (segment + 1) % segments;
+        if up {
+            geometry.add_triangle(center, a, b);
+        } else {
+            geometry.add_triangle(center, b, a);
+        }
+    }
+    geometry
+}
+
+/// Each vertex's normal is the average of the triangles around it, weighted by their area.
+fn smooth_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        normals[a] += normal;
+        normals[b] += normal;
+        normals[c] += normal;
+    }
+    normals.iter().map(|n| n.normalize_or_zero()).collect()
+}
+
+fn extend_attribute<T: Copy>(
+    attribute: &mut Vec<T>,
+    other: &[T],
+    len: usize,
+    other_len: usize,
+    default: T,
+) {
+    if attribute.is_empty() && other.is_empty() {
+        return;
+    }
+    attribute.resize(len, default);
+    if other.is_empty() {
+        attribute.resize(len + other_len, default);
+    } else {
+        attribute.extend_from_slice(other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_shapes() {
+        let shapes = [
+            ("cuboid", Geometry::cuboid(Vec3::new(1., 2., 3.))),
+            ("plane", Geometry::plane(Vec2::ONE, 4)),
+            ("sphere", Geometry::sphere(1., 16)),
+            ("capsule", Geometry::capsule(0.5, 1., 16)),
+            ("cylinder", Geometry::cylinder(0.5, 1., 16)),
+        ];
+
+        for (name, geometry) in shapes {
+            geometry.validate().unwrap();
+            assert!(!geometry.indices.is_empty(), "{name} has no triangles");
+            for normal in &geometry.normals {
+                assert_relative_eq!(normal.length(), 1., epsilon = 0.0001);
+            }
+
+            // Every triangle is counter-clockwise, seen from the side its normals face.
+            for triangle in geometry.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+                let p = &geometry.positions;
+                let face_normal = (p[b] - p[a]).cross(p[c] - p[a]);
+                if face_normal.length() < 0.00001 {
+                    continue;
+                }
+                assert!(
+                    face_normal.dot(geometry.normals[a]) > 0.,
+                    "{name} has a triangle facing the wrong way"
+                );
+            }
+
+            // Every shape is convex and centered on the origin, so it faces outwards.
+            for (position, normal) in geometry.positions.iter().zip(&geometry.normals) {
+                assert!(position.dot(*normal) >= -0.0001, "{name} faces inwards");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounding_sphere() {
+        let half_extents = Vec3::new(1., 2., 3.);
+        let bounding_sphere = Geometry::cuboid(half_extents).bounding_sphere();
+        assert_relative_eq!(bounding_sphere.truncate(), Vec3::ZERO, epsilon = 0.0001);
+        assert!(bounding_sphere.w >= half_extents.length());
+    }
+
+    #[test]
+    fn test_append_and_transform() {
+        // A triangle without indices or normals.
+        let mut triangle = Geometry {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            ..Default::default()
+        };
+        triangle.compute_normals();
+        assert_eq!(triangle.normals, vec![Vec3::Z; 3]);
+
+        let mut geometry = triangle.clone();
+        geometry.set_color(Vec4::new(1., 0., 0., 1.));
+        geometry.append(&triangle);
+        geometry.validate().unwrap();
+        assert_eq!(geometry.indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(geometry.colors[3], Vec4::ONE);
+
+        // Mirroring keeps the triangles facing their normals.
+        geometry.transform(&Affine3A::from_scale(Vec3::new(-1., 1., 1.)));
+        assert_eq!(geometry.indices, vec![0, 2, 1, 3, 5, 4]);
+        assert_relative_eq!(geometry.normals[0], Vec3::Z);
+        assert_eq!(geometry.positions[1], Vec3::NEG_X);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut geometry = Geometry::cuboid(Vec3::ONE);
+        geometry.uvs.pop();
+        assert!(geometry.validate().is_err());
+
+        let mut geometry = Geometry::cuboid(Vec3::ONE);
+        geometry.indices.push(1000);
+        assert!(geometry.validate().is_err());
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_build_and_update() {
+        use crate::contexts::render_context::PIPELINE_DEPTH;
+
+        let (mut render_context, _vulkan_context) = RenderContext::testing();
+        let mut builder = MeshBuilder::new();
+        builder.primitive(Geometry::cuboid(Vec3::ONE), 0);
+        let mesh = builder.build(&mut render_context).unwrap();
+        let primitive = |render_context: &RenderContext| {
+            render_context
+                .resources
+                .mesh_data
+                .get(mesh.handle)
+                .unwrap()
+                .primitives[0]
+                .clone()
+        };
+        let first = primitive(&render_context);
+        assert_eq!(first.indices_count, 36);
+
+        // Updates are written to fresh space, as the GPU may still be drawing the old vertices.
+        *builder.geometry_mut(0).unwrap() = Geometry::plane(Vec2::splat(2.), 1);
+        builder.update(&mut render_context, &mesh).unwrap();
+        let updated = primitive(&render_context);
+        assert_ne!(updated.vertex_buffer_offset, first.vertex_buffer_offset);
+        assert_ne!(updated.index_buffer_offset, first.index_buffer_offset);
+        assert_eq!(updated.indices_count, 6);
+        assert!(updated.bounding_sphere.w > first.bounding_sphere.w);
+
+        // Once every frame that could have drawn the old vertices is finished, their space is
+        // used again.
+        let resources = &mut render_context.resources;
+        for frame_index in 0..PIPELINE_DEPTH {
+            resources.frame_submitted(frame_index);
+        }
+        for frame_index in 0..PIPELINE_DEPTH {
+            resources.frame_finished(frame_index);
+        }
+        builder.update(&mut render_context, &mesh).unwrap();
+        let reused = primitive(&render_context);
+        assert_eq!(reused.vertex_buffer_offset, first.vertex_buffer_offset);
+        assert_eq!(reused.index_buffer_offset, first.index_buffer_offset);
+
+        // Primitives dropped by an update give their space back too.
+        builder.primitive(Geometry::sphere(1., 16), 0);
+        builder.update(&mut render_context, &mesh).unwrap();
+        let vertex_len = render_context.resources.vertex_buffer.len;
+        builder.primitives.pop();
+        builder.update(&mut render_context, &mesh).unwrap();
+        let resources = &mut render_context.resources;
+        for frame_index in 0..PIPELINE_DEPTH {
+            resources.frame_submitted(frame_index);
+            resources.frame_finished(frame_index);
+        }
+        assert!(resources.vertex_buffer.len < vertex_len);
+    }
+}
@@ -42,5 +42,45 @@ pub mod material;
 
 /// Lights and related functionality
 pub mod light;
+
+/// Real-time shadows
+pub mod shadows;
+
+/// Image based lighting environments and the skybox
+pub mod environment;
+
+/// Hi-Z occlusion culling
+pub mod occlusion;
+
+/// Tone mapping, color grading and other full screen effects
+pub mod post_processing;
+
+/// Fading the view to a color for scene transitions
+pub mod fade;
+
+/// MSAA, resolution scale, formats and blend mode
+pub mod render_settings;
+
+/// Scaling the render resolution to keep GPU frame time within budget
+pub mod dynamic_resolution;
+
+/// Drawing the scene into textures, for mirrors, portals and in-world screens
+pub mod render_target;
+
+/// Writing the spectator view to disk as an image sequence
+pub mod spectator_capture;
+
+/// Immediate mode debug lines, shapes and labels
+pub mod debug_draw;
+
+/// Billboarded particles, drawn after everything else that's transparent
+pub mod particles;
+
 /// Wrapper around geometry data.
 pub mod mesh_data;
+
+/// Building meshes in code, from shapes or vertices, and updating them
+pub mod mesh_builder;
+
+/// Reusing space in buffers shared by many primitives
+pub(crate) mod free_list;
@@ -0,0 +1,570 @@
+use std::{ffi::CStr, mem::size_of, slice::from_ref as slice_from_ref};
+
+use anyhow::Result;
+use ash::vk;
+use glam::Vec3;
+use vk_shader_macros::include_glsl;
+
+use crate::{
+    contexts::VulkanContext,
+    rendering::{descriptors::Descriptors, image::Image, primitive::Primitive, vertex::Vertex},
+    DEPTH_FORMAT, VIEW_COUNT,
+};
+
+static OCCLUDER_VERT: &[u32] = include_glsl!("src/shaders/occluder.vert", target: vulkan1_1);
+static HI_Z_COMP: &[u32] = include_glsl!("src/shaders/hi_z.comp", target: vulkan1_1);
+
+/// The width and height of the occluder depth buffer, and the first level of the Hi-Z pyramid.
+/// Occluders are only drawn at this resolution, so small gaps between them may be missed.
+pub const HI_Z_SIZE: u32 = 256;
+/// One 32 bit float per texel, holding the furthest depth in the texels it covers
+pub const HI_Z_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+/// The number of levels in the Hi-Z pyramid, down to a single texel
+pub const HI_Z_MIP_LEVELS: u32 = HI_Z_SIZE.trailing_zeros() + 1;
+
+const HI_Z_WORKGROUP_SIZE: u32 = 8;
+
+/// Hierarchical depth (Hi-Z) occlusion culling.
+///
+/// Before culling, every visible entity with an [`crate::components::Occluder`] component is drawn
+/// into a small depth buffer for both eyes. This is then reduced into a pyramid where each texel
+/// holds the furthest depth of the texels below it, and the culling compute shader skips any
+/// primitive whose bounding sphere is completely behind it in both eyes.
+///
+/// Occlusion culling is off by default. It pays off in indoor levels where big walls hide most of
+/// the scene, but it costs a little GPU time every frame:
+/// ```ignore
+/// engine.render_context.occlusion_culling.enabled = true;
+/// world.insert_one(wall, Occluder {});
+/// ```
+pub struct OcclusionCulling {
+    /// Draw occluders and test primitives against them?
+    pub enabled: bool,
+    /// Occluders are drawn into this depth image, with one layer per eye
+    pub depth_image: Image,
+    /// The Hi-Z pyramid, with one layer per eye
+    pub hi_z_image: Image,
+    /// One view for each level of the Hi-Z pyramid, used to build it
+    pub hi_z_mip_views: Vec<vk::ImageView>,
+    /// Nearest neighbour sampler used to read the Hi-Z pyramid
+    pub sampler: vk::Sampler,
+    /// Depth-only render pass used to draw the occluders
+    pub render_pass: vk::RenderPass,
+    /// Framebuffer covering both layers of `depth_image`
+    pub framebuffer: vk::Framebuffer,
+    /// Depth-only pipeline used to draw the occluders
+    pub pipeline: vk::Pipeline,
+    /// Compute pipeline that builds each level of the Hi-Z pyramid from the one before it
+    pub hi_z_pipeline: vk::Pipeline,
+    pub(crate) hi_z_pipeline_layout: vk::PipelineLayout,
+    /// One descriptor set per level of the Hi-Z pyramid
+    pub(crate) hi_z_sets: Vec<vk::DescriptorSet>,
+    /// The occluders to draw this frame, and the index of their draw data
+    pub(crate) occluders: Vec<(Primitive, u32)>,
+}
+
+impl OcclusionCulling {
+    pub(crate) fn new(
+        vulkan_context: &VulkanContext,
+        descriptors: &Descriptors,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<Self> {
+        let device = &vulkan_context.device;
+        let extent = vk::Extent2D {
+            width: HI_Z_SIZE,
+            height: HI_Z_SIZE,
+        };
+        let depth_image = vulkan_context.create_image(
+            DEPTH_FORMAT,
+            &extent,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            VIEW_COUNT,
+            1,
+        )?;
+        let hi_z_image = vulkan_context.create_image(
+            HI_Z_FORMAT,
+            &extent,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            VIEW_COUNT,
+            HI_Z_MIP_LEVELS,
+        )?;
+        let hi_z_mip_views = (0..HI_Z_MIP_LEVELS)
+            .map(|level| create_mip_view(device, &hi_z_image, level))
+            .collect::<Result<Vec<_>>>()?;
+
+        // The pyramid stays in the GENERAL layout, so it can be written to and read from freely.
+        vulkan_context.transition_image_layout(
+            hi_z_image.handle,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            VIEW_COUNT,
+            HI_Z_MIP_LEVELS,
+        );
+
+        let sampler = unsafe {
+            device.create_sampler(
+                &vk::SamplerCreateInfo::builder()
+                    .mag_filter(vk::Filter::NEAREST)
+                    .min_filter(vk::Filter::NEAREST)
+                    .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                    .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .max_lod(vk::LOD_CLAMP_NONE),
+                None,
+            )
+        }?;
+
+        let render_pass = create_render_pass(vulkan_context)?;
+        let attachments = [depth_image.view];
+        let framebuffer = unsafe {
+            device.create_framebuffer(
+                &vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(&attachments)
+                    .width(HI_Z_SIZE)
+                    .height(HI_Z_SIZE)
+                    .layers(1),
+                None,
+            )
+        }?;
+        let pipeline = create_pipeline(vulkan_context, pipeline_layout, render_pass)?;
+
+        let (hi_z_pipeline, hi_z_pipeline_layout, hi_z_sets) = unsafe {
+            create_hi_z_pipeline(vulkan_context, &depth_image, &hi_z_mip_views, sampler)
+        }?;
+
+        unsafe {
+            descriptors.write_hi_z_descriptor(vulkan_context, hi_z_image.view, sampler);
+        }
+
+        Ok(Self {
+            enabled: false,
+            depth_image,
+            hi_z_image,
+            hi_z_mip_views,
+            sampler,
+            render_pass,
+            framebuffer,
+            pipeline,
+            hi_z_pipeline,
+            hi_z_pipeline_layout,
+            hi_z_sets,
+            occluders: Vec::new(),
+        })
+    }
+
+    /// Should the culling shader test primitives against the Hi-Z pyramid this frame?
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.occluders.is_empty()
+    }
+
+    /// Draw the occluders and build the Hi-Z pyramid from them.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, and not inside a render pass. `graphics_set` must
+    /// contain the draw data for each occluder.
+    pub(crate) unsafe fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        graphics_set: vk::DescriptorSet,
+        vertex_buffers: &[vk::Buffer; 2],
+        index_buffer: vk::Buffer,
+    ) {
+        // Draw the occluders, clearing to infinitely far away.
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 0.0,
+                stencil: 0,
+            },
+        }];
+        device.cmd_begin_render_pass(
+            command_buffer,
+            &vk::RenderPassBeginInfo::builder()
+                .render_pass(self.render_pass)
+                .framebuffer(self.framebuffer)
+                .render_area(vk::Rect2D {
+                    offset: Default::default(),
+                    extent: self.depth_image.extent,
+                })
+                .clear_values(&clear_values),
+            vk::SubpassContents::INLINE,
+        );
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline_layout,
+            0,
+            slice_from_ref(&graphics_set),
+            &[],
+        );
+        device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, vk::IndexType::UINT32);
+        device.cmd_bind_vertex_buffers(command_buffer, 0, vertex_buffers, &[0, 0]);
+        for (primitive, instance) in &self.occluders {
+            device.cmd_draw_indexed(
+                command_buffer,
+                primitive.indices_count,
+                1,
+                primitive.index_buffer_offset,
+                primitive.vertex_buffer_offset as _,
+                *instance,
+            );
+        }
+        device.cmd_end_render_pass(command_buffer);
+
+        // Then build the pyramid, one level at a time.
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.hi_z_pipeline,
+        );
+        for (level, set) in (0..HI_Z_MIP_LEVELS).zip(&self.hi_z_sets) {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.hi_z_pipeline_layout,
+                0,
+                slice_from_ref(set),
+                &[],
+            );
+            let size = (HI_Z_SIZE >> level).max(1);
+            let group_count = (size + HI_Z_WORKGROUP_SIZE - 1) / HI_Z_WORKGROUP_SIZE;
+            device.cmd_dispatch(command_buffer, group_count, group_count, VIEW_COUNT);
+
+            // Each level must be finished before the next level, or the culling shader, reads it.
+            let barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                slice_from_ref(&barrier),
+                &[],
+                &[],
+            );
+        }
+    }
+}
+
+fn create_mip_view(device: &ash::Device, image: &Image, level: u32) -> Result<vk::ImageView> {
+    let create_info = vk::ImageViewCreateInfo::builder()
+        .image(image.handle)
+        .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+        .format(image.format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: level,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: image.layer_count,
+        });
+    unsafe { device.create_image_view(&create_info, None) }.map_err(Into::into)
+}
+
+fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass> {
+    let depth_attachment = vk::AttachmentDescription::builder()
+        .format(DEPTH_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .build();
+
+    let depth_attachment_reference = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_reference);
+
+    // Wait for the previous frame's pyramid to be built before drawing over the depth image, and
+    // finish drawing before the pyramid is built from it.
+    let dependencies = [
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
+            .src_access_mask(vk::AccessFlags::SHADER_READ)
+            .dst_stage_mask(
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build(),
+    ];
+
+    // One view per eye.
+    let view_masks = [!(!0 << VIEW_COUNT)];
+    let mut multiview = vk::RenderPassMultiviewCreateInfo::builder()
+        .view_masks(&view_masks)
+        .correlation_masks(&view_masks);
+
+    let attachments = [depth_attachment];
+    let create_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(std::slice::from_ref(&subpass))
+        .dependencies(&dependencies)
+        .push_next(&mut multiview);
+
+    let render_pass = unsafe { vulkan_context.device.create_render_pass(&create_info, None) }?;
+    Ok(render_pass)
+}
+
+fn create_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    let device = &vulkan_context.device;
+    let main = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    let vertex_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(OCCLUDER_VERT),
+            None,
+        )
+    }?;
+
+    // Depth only - there is no fragment shader.
+    let stages = [vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .name(main)
+        .module(vertex_shader)
+        .build()];
+
+    let vertex_binding_descriptions = [
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Vec3>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build(),
+        vk::VertexInputBindingDescription::builder()
+            .binding(1)
+            .stride(size_of::<Vertex>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build(),
+    ];
+    let vertex_attribute_descriptions = Vertex::attribute_descriptions();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions)
+        .vertex_binding_descriptions(&vertex_binding_descriptions);
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewports = [vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: HI_Z_SIZE as _,
+        height: HI_Z_SIZE as _,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    }];
+    let scissors = [vk::Rect2D {
+        offset: Default::default(),
+        extent: vk::Extent2D {
+            width: HI_Z_SIZE,
+            height: HI_Z_SIZE,
+        },
+    }];
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(&viewports)
+        .scissors(&scissors);
+
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::BACK)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    // Reverse Z, like the main pass.
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::GREATER)
+        .max_depth_bounds(1.0);
+
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder();
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0)
+        .build();
+
+    let pipelines = unsafe {
+        device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
+
+/// Create the compute pipeline that builds the Hi-Z pyramid, along with a descriptor set for each
+/// level. Level 0 reads from the occluder depth image, every other level reads from the level
+/// before it.
+unsafe fn create_hi_z_pipeline(
+    vulkan_context: &VulkanContext,
+    depth_image: &Image,
+    hi_z_mip_views: &[vk::ImageView],
+    sampler: vk::Sampler,
+) -> Result<(vk::Pipeline, vk::PipelineLayout, Vec<vk::DescriptorSet>)> {
+    let device = &vulkan_context.device;
+
+    let bindings = [
+        // The level being read
+        vk::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            descriptor_count: 1,
+            ..Default::default()
+        },
+        // The level being written
+        vk::DescriptorSetLayoutBinding {
+            binding: 1,
+            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            descriptor_count: 1,
+            ..Default::default()
+        },
+    ];
+    let set_layout = device.create_descriptor_set_layout(
+        &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+        None,
+    )?;
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: HI_Z_MIP_LEVELS,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: HI_Z_MIP_LEVELS,
+        },
+    ];
+    let pool = device.create_descriptor_pool(
+        &vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(HI_Z_MIP_LEVELS),
+        None,
+    )?;
+    let set_layouts = vec![set_layout; HI_Z_MIP_LEVELS as usize];
+    let sets = device.allocate_descriptor_sets(
+        &vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts),
+    )?;
+
+    for (level, set) in sets.iter().enumerate() {
+        let source = if level == 0 {
+            vk::DescriptorImageInfo {
+                sampler,
+                image_view: depth_image.view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        } else {
+            vk::DescriptorImageInfo {
+                sampler,
+                image_view: hi_z_mip_views[level - 1],
+                image_layout: vk::ImageLayout::GENERAL,
+            }
+        };
+        let destination = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: hi_z_mip_views[level],
+            image_layout: vk::ImageLayout::GENERAL,
+        };
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(*set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(slice_from_ref(&source))
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(*set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(slice_from_ref(&destination))
+                .build(),
+        ];
+        device.update_descriptor_sets(&writes, &[]);
+    }
+
+    let layout = device.create_pipeline_layout(
+        &vk::PipelineLayoutCreateInfo::builder().set_layouts(slice_from_ref(&set_layout)),
+        None,
+    )?;
+
+    let main = CStr::from_bytes_with_nul_unchecked(b"main\0");
+    let module = device
+        .create_shader_module(&vk::ShaderModuleCreateInfo::builder().code(HI_Z_COMP), None)?;
+    let create_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(module)
+                .name(main)
+                .build(),
+        )
+        .layout(layout);
+    let pipeline = device
+        .create_compute_pipelines(
+            vk::PipelineCache::null(),
+            slice_from_ref(&create_info),
+            None,
+        )
+        .map_err(|(_, r)| r)?[0];
+    device.destroy_shader_module(module, None);
+
+    Ok((pipeline, layout, sets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hi_z_mip_levels() {
+        assert_eq!(HI_Z_MIP_LEVELS, 9);
+        assert_eq!(HI_Z_SIZE >> (HI_Z_MIP_LEVELS - 1), 1);
+    }
+}
@@ -0,0 +1,222 @@
+use std::{ffi::CStr, mem::size_of};
+
+use anyhow::Result;
+use ash::vk;
+use glam::{Vec3, Vec4};
+use vk_shader_macros::include_glsl;
+
+use crate::contexts::{render_context::DYNAMIC_VIEWPORT_STATES, VulkanContext};
+
+static PARTICLE_VERT: &[u32] = include_glsl!("src/shaders/particle.vert", target: vulkan1_1);
+static PARTICLE_FRAG: &[u32] = include_glsl!("src/shaders/particle.frag", target: vulkan1_1);
+
+/// The most particles drawn each frame, across every emitter. Any more are dropped, furthest
+/// first.
+pub const MAX_PARTICLES: usize = 10_000;
+
+/// A particle as it's drawn: a single instance of a quad that faces each eye.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ParticleInstance {
+    /// Position, in globally oriented stage space
+    pub position: Vec3,
+    /// Width and height, in metres
+    pub size: f32,
+    /// Linear RGBA color
+    pub color: Vec4,
+    /// The index of the texture, or `u32::MAX` for a soft round dot
+    pub texture_id: u32,
+    /// 1 if the particle's color is added to what's behind it
+    pub additive: u32,
+    /// The emitter's [`crate::components::RenderLayers`]
+    pub layers: u32,
+}
+
+impl ParticleInstance {
+    pub(crate) fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 6] {
+        let attribute = |location, format, offset: usize| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(location)
+                .format(format)
+                .offset(offset as _)
+                .build()
+        };
+        [
+            attribute(
+                0,
+                vk::Format::R32G32B32_SFLOAT,
+                memoffset::offset_of!(ParticleInstance, position),
+            ),
+            attribute(
+                1,
+                vk::Format::R32_SFLOAT,
+                memoffset::offset_of!(ParticleInstance, size),
+            ),
+            attribute(
+                2,
+                vk::Format::R32G32B32A32_SFLOAT,
+                memoffset::offset_of!(ParticleInstance, color),
+            ),
+            attribute(
+                3,
+                vk::Format::R32_UINT,
+                memoffset::offset_of!(ParticleInstance, texture_id),
+            ),
+            attribute(
+                4,
+                vk::Format::R32_UINT,
+                memoffset::offset_of!(ParticleInstance, additive),
+            ),
+            attribute(
+                5,
+                vk::Format::R32_UINT,
+                memoffset::offset_of!(ParticleInstance, layers),
+            ),
+        ]
+    }
+}
+
+/// Sort particles so the furthest from `eye_position` is drawn first, as they're blended over
+/// each other.
+pub(crate) fn sort_back_to_front(particles: &mut [ParticleInstance], eye_position: Vec3) {
+    particles.sort_by(|a, b| {
+        let a = a.position.distance_squared(eye_position);
+        let b = b.position.distance_squared(eye_position);
+        b.total_cmp(&a)
+    });
+}
+
+pub(crate) fn create_particle_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    samples: vk::SampleCountFlags,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    let device = &vulkan_context.device;
+    let main = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    let vertex_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(PARTICLE_VERT),
+            None,
+        )
+    }?;
+    let fragment_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(PARTICLE_FRAG),
+            None,
+        )
+    }?;
+
+    let stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .name(main)
+            .module(vertex_shader)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .name(main)
+            .module(fragment_shader)
+            .build(),
+    ];
+
+    // One instance per particle. The quad's corners come from gl_VertexIndex.
+    let vertex_binding_descriptions = [vk::VertexInputBindingDescription::builder()
+        .binding(0)
+        .stride(size_of::<ParticleInstance>() as _)
+        .input_rate(vk::VertexInputRate::INSTANCE)
+        .build()];
+    let vertex_attribute_descriptions = ParticleInstance::attribute_descriptions();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions)
+        .vertex_binding_descriptions(&vertex_binding_descriptions);
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_VIEWPORT_STATES);
+
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .line_width(1.0);
+
+    let multisample_state =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    // Particles are hidden behind opaque meshes, but don't hide each other. Reverse Z.
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
+        .max_depth_bounds(1.0);
+
+    // Premultiplied alpha, so blended and additive particles can share a pipeline.
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .build()];
+    let color_blend_state =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0)
+        .build();
+
+    let pipelines = unsafe {
+        device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader, None);
+        device.destroy_shader_module(fragment_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_back_to_front() {
+        let particle = |z| ParticleInstance {
+            position: Vec3::new(0., 0., z),
+            ..Default::default()
+        };
+        let mut particles = [particle(-1.), particle(-5.), particle(2.), particle(-3.)];
+        sort_back_to_front(&mut particles, Vec3::ZERO);
+
+        let distances: Vec<f32> = particles.iter().map(|p| p.position.z.abs()).collect();
+        assert_eq!(distances, [5., 3., 2., 1.]);
+    }
+}
@@ -0,0 +1,282 @@
+use std::ffi::CStr;
+
+use anyhow::Result;
+use ash::vk;
+use glam::{Vec3, Vec4};
+use vk_shader_macros::include_glsl;
+
+use crate::{
+    contexts::{render_context::DYNAMIC_VIEWPORT_STATES, VulkanContext},
+    rendering::texture::Texture,
+};
+
+static POST_VERT: &[u32] = include_glsl!("src/shaders/post.vert", target: vulkan1_1);
+static POST_FRAG: &[u32] = include_glsl!("src/shaders/post.frag", target: vulkan1_1);
+static POST_FRAG_SINGLE_SAMPLE: &[u32] =
+    include_glsl!("src/shaders/post.frag", target: vulkan1_1, define: SINGLE_SAMPLE);
+
+/// The subpass of the main render pass that post-processing runs in
+pub const POST_PROCESSING_SUBPASS: u32 = 1;
+
+/// The curve used to map the scene's high dynamic range colors onto the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum Tonemapper {
+    /// Clamp colors above 1.0
+    None = 0,
+    /// Krzysztof Narkowicz's fast approximation of the ACES filmic curve
+    #[default]
+    Aces = 1,
+    /// The Khronos PBR Neutral tonemapper, which keeps base colors as authored where possible
+    KhronosNeutral = 2,
+    /// Simple Reinhard tonemapping, `color / (1 + color)`
+    Reinhard = 3,
+}
+
+/// Grade the final image with a lookup table (LUT).
+///
+/// The LUT is a horizontal strip of `size` squares, each `size` by `size` pixels, with red
+/// increasing to the right within each square, green increasing downwards and blue increasing from
+/// square to square. This is the layout most tools export, eg. a 256x16 image for a 16 point LUT.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGrading {
+    /// Index of the LUT in the shader's texture array
+    pub lut_texture_id: u32,
+    /// How much of the graded color to use, from 0 to 1
+    pub strength: f32,
+}
+
+impl ColorGrading {
+    /// Grade with a LUT loaded with [`crate::rendering::texture::TextureUsage::ColorGrading`].
+    pub fn new(lut: &Texture, strength: f32) -> Self {
+        let extent = lut.image.extent;
+        assert_eq!(
+            extent.width,
+            extent.height * extent.height,
+            "Color grading LUTs must be a strip of square slices"
+        );
+        Self {
+            lut_texture_id: lut.index,
+            strength,
+        }
+    }
+}
+
+/// Settings for the post-processing subpass, which runs after everything else in the main render
+/// pass. The defaults match how the scene looked before post-processing existed.
+///
+/// Each effect is optional, but the subpass itself always runs and can't be switched off. It's
+/// what resolves MSAA and turns the HDR scene into the swapchain's format, which a resolve
+/// attachment used to do, and it also draws [`crate::rendering::fade::ScreenFade`]s and teleport
+/// fades, and turns the scene's coverage into the alpha that lets underlay composition layers show
+/// through. The scene color never leaves tile memory, so with every effect off it only adds a full
+/// screen triangle that reads each pixel's samples once. Render target cameras don't use it, and
+/// tone map in their shaders instead.
+///
+/// Basic usage:
+/// ```ignore
+/// // Narrow the player's view while they're moving to reduce discomfort
+/// render_context.post_processing.vignette = if moving { 0.6 } else { 0. };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostProcessing {
+    /// The tone mapping curve
+    pub tonemapper: Tonemapper,
+    /// Colors are multiplied by this before tone mapping
+    pub exposure: f32,
+    /// Optional LUT based color grading, applied after tone mapping
+    pub color_grading: Option<ColorGrading>,
+    /// How much to darken the edges of the view, from 0 (off) to 1 (only a small window remains)
+    pub vignette: f32,
+    /// The color to fade the view to. Set each frame by the rendering system from
+    /// [`crate::rendering::fade::ScreenFade`] and teleport fades.
+    pub fade_color: Vec3,
+    /// How far the view has faded to `fade_color`, from 0 to 1. Set each frame by the rendering
+    /// system.
+    pub fade: f32,
+}
+
+impl Default for PostProcessing {
+    fn default() -> Self {
+        Self {
+            tonemapper: Default::default(),
+            exposure: 1.,
+            color_grading: None,
+            vignette: 0.,
+            fade_color: Vec3::ZERO,
+            fade: 0.,
+        }
+    }
+}
+
+impl PostProcessing {
+    /// The push constants read by `post.frag`.
+    pub(crate) fn push_constants(&self) -> PostProcessingConstants {
+        let (lut_texture_id, lut_strength) = self
+            .color_grading
+            .map(|c| (c.lut_texture_id, c.strength.clamp(0., 1.)))
+            .unwrap_or((0, 0.));
+        PostProcessingConstants {
+            fade: self.fade_color.extend(self.fade.clamp(0., 1.)).to_array(),
+            tonemapper: self.tonemapper as _,
+            exposure: self.exposure,
+            lut_texture_id,
+            lut_strength,
+            vignette: self.vignette.clamp(0., 1.),
+        }
+    }
+
+    /// [`crate::rendering::scene_data::SceneData::tone_mapping`] for views drawn without the
+    /// post-processing subpass, so they're tone mapped the same way as the eyes.
+    pub(crate) fn tone_mapping(&self) -> Vec4 {
+        Vec4::new(1., self.tonemapper as u32 as f32, self.exposure, 0.)
+    }
+}
+
+/// Must match the push constants in `post.frag`. Plain arrays are used so it stays smaller than
+/// [`crate::rendering::material::Material`], which sets the size of the push constant range.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PostProcessingConstants {
+    fade: [f32; 4],
+    tonemapper: u32,
+    exposure: f32,
+    lut_texture_id: u32,
+    lut_strength: f32,
+    vignette: f32,
+}
+
+/// Create the pipeline for the post-processing subpass: a single triangle covering the screen that
+/// reads the multisampled scene color and writes to the swapchain.
+pub(crate) fn create_post_processing_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    samples: vk::SampleCountFlags,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    let device = &vulkan_context.device;
+    let main = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    let vertex_shader = unsafe {
+        device.create_shader_module(&vk::ShaderModuleCreateInfo::builder().code(POST_VERT), None)
+    }?;
+    let fragment_code = if samples == vk::SampleCountFlags::TYPE_1 {
+        POST_FRAG_SINGLE_SAMPLE
+    } else {
+        POST_FRAG
+    };
+    let fragment_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(fragment_code),
+            None,
+        )
+    }?;
+
+    // The shader resolves the samples itself, so it needs to know how many there are.
+    let sample_count = samples.as_raw().to_ne_bytes();
+    let specialization_entries = [vk::SpecializationMapEntry {
+        constant_id: 0,
+        offset: 0,
+        size: std::mem::size_of::<u32>(),
+    }];
+    let specialization_info = vk::SpecializationInfo::builder()
+        .map_entries(&specialization_entries)
+        .data(&sample_count);
+
+    let stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .name(main)
+            .module(vertex_shader)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .name(main)
+            .module(fragment_shader)
+            .specialization_info(&specialization_info)
+            .build(),
+    ];
+
+    // The vertices are generated in the vertex shader.
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_VIEWPORT_STATES);
+
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    // The swapchain image isn't multisampled.
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .build()];
+    let color_blend_state =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(POST_PROCESSING_SUBPASS)
+        .build();
+
+    let pipelines = unsafe {
+        device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader, None);
+        device.destroy_shader_module(fragment_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::material::Material;
+
+    #[test]
+    fn test_push_constants_fit_in_range() {
+        assert!(std::mem::size_of::<PostProcessingConstants>() <= std::mem::size_of::<Material>());
+    }
+
+    #[test]
+    fn test_push_constants_are_clamped() {
+        let post_processing = PostProcessing {
+            vignette: 2.,
+            fade: -1.,
+            fade_color: Vec3::ONE,
+            ..Default::default()
+        };
+        let constants = post_processing.push_constants();
+        assert_eq!(constants.vignette, 1.);
+        assert_eq!(constants.fade, [1., 1., 1., 0.]);
+        assert_eq!(constants.tonemapper, Tonemapper::Aces as u32);
+    }
+}
@@ -1,7 +1,10 @@
 use crate::{
     asset_importer::ImportContext,
     contexts::render_context,
-    rendering::{material::NO_MATERIAL, vertex::Vertex},
+    rendering::{
+        material::{pack_unorm4x8, NO_MATERIAL},
+        vertex::Vertex,
+    },
 };
 use glam::{Affine3A, Vec3, Vec4};
 use itertools::izip;
@@ -21,6 +24,10 @@ pub struct Primitive {
     pub material_id: u32,
     /// Bounding sphere - used for culling
     pub bounding_sphere: Vec4,
+    /// How many vertices there's room for at `vertex_buffer_offset`
+    pub vertex_capacity: u32,
+    /// How many indices there's room for at `index_buffer_offset`
+    pub index_capacity: u32,
 }
 
 impl Primitive {
@@ -38,6 +45,8 @@ impl Primitive {
             index_buffer_offset: render_context.resources.index_buffer.len() as _,
             vertex_buffer_offset: render_context.resources.vertex_buffer.len() as _,
             bounding_sphere: calculate_bounding_sphere(positions),
+            vertex_capacity: positions.len() as _,
+            index_capacity: indices.len() as _,
         };
 
         unsafe {
@@ -57,6 +66,8 @@ impl Primitive {
         let mut indices = Vec::new();
         let mut positions = Vec::new();
         let mut tex_coords = Vec::new();
+        let mut tex_coords_1 = Vec::new();
+        let mut colors = Vec::new();
         let mut normals = Vec::new();
         let mut joint_indices = Vec::new();
         let mut joint_weights = Vec::new();
@@ -99,6 +110,27 @@ impl Primitive {
             }
         }
 
+        if let Some(iter) = reader.read_tex_coords(1) {
+            for v in iter.into_f32() {
+                tex_coords_1.push([v[0], v[1]].into());
+            }
+        } else {
+            for _ in 0..positions.len() {
+                tex_coords_1.push([0., 0.].into());
+            }
+        }
+
+        // Vertex colors. Missing colors are white, so they don't affect the base color.
+        if let Some(iter) = reader.read_colors(0) {
+            for c in iter.into_rgba_f32() {
+                colors.push(pack_unorm4x8(&c));
+            }
+        } else {
+            for _ in 0..positions.len() {
+                colors.push(u32::MAX);
+            }
+        }
+
         if let Some(iter) = reader.read_joints(0) {
             for t in iter.into_u16() {
                 joint_indices.push([t[0] as u8, t[1] as u8, t[2] as u8, t[3] as u8]);
@@ -119,9 +151,17 @@ impl Primitive {
             }
         }
 
-        let vertices: Vec<Vertex> = izip!(normals, tex_coords, joint_indices, joint_weights)
-            .map(Vertex::from_zip)
-            .collect();
+        let vertices: Vec<Vertex> = izip!(
+            izip!(normals, tex_coords, joint_indices, joint_weights),
+            tex_coords_1,
+            colors
+        )
+        .map(|(t, texture_coords_1, color)| Vertex {
+            texture_coords_1,
+            color,
+            ..Vertex::from_zip(t)
+        })
+        .collect();
 
         // All the materials in this glTF file will be imported into the material buffer, so all we need
         // to do is grab the index of this material and add it to the running offset. If we don't do this,
@@ -0,0 +1,158 @@
+use ash::vk;
+use openxr as xr;
+
+use crate::{
+    contexts::{render_context::SAMPLES, xr_context::Foveation},
+    rendering::dynamic_resolution::DynamicResolution,
+    BLEND_MODE, COLOR_FORMAT, DEPTH_FORMAT,
+};
+
+/// Settings that decide how the scene is rendered, set when the engine is built with
+/// [`crate::EngineBuilder`]. Anything the runtime or GPU doesn't support falls back to a default,
+/// with a warning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    /// How many samples to use for MSAA. Lower it to save GPU time and bandwidth at the cost of
+    /// jagged edges.
+    pub samples: vk::SampleCountFlags,
+    /// Multiplies the runtime's recommended render resolution. Values below 1 trade sharpness for
+    /// performance, values above 1 supersample.
+    pub resolution_scale: f32,
+    /// The format of the swapchain the final image is written to. sRGB formats are recommended,
+    /// as post-processing writes linear colors.
+    pub color_format: vk::Format,
+    /// The format of the depth buffer
+    pub depth_format: vk::Format,
+    /// How the rendered image is combined with the real world. Use `ADDITIVE` or `ALPHA_BLEND` on
+    /// passthrough and AR runtimes.
+    pub blend_mode: xr::EnvironmentBlendMode,
+    /// Adjust the resolution scale each frame to keep GPU frame time within budget
+    pub dynamic_resolution: Option<DynamicResolution>,
+    /// How much detail to drop towards the edges of the view. Can be changed later with
+    /// [`crate::contexts::XrContext::set_foveation`].
+    pub foveation: Foveation,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples: SAMPLES,
+            resolution_scale: 1.,
+            color_format: COLOR_FORMAT,
+            depth_format: DEPTH_FORMAT,
+            blend_mode: BLEND_MODE,
+            dynamic_resolution: None,
+            foveation: Default::default(),
+        }
+    }
+}
+
+impl RenderSettings {
+    /// The largest resolution scale that will be used. The swapchain is created at this scale.
+    pub(crate) fn max_resolution_scale(&self) -> f32 {
+        match self.dynamic_resolution {
+            Some(dynamic_resolution) => dynamic_resolution.max_scale.max(self.resolution_scale),
+            None => self.resolution_scale,
+        }
+    }
+
+    /// The resolution scale to start with.
+    pub(crate) fn initial_resolution_scale(&self) -> f32 {
+        match self.dynamic_resolution {
+            Some(dynamic_resolution) => dynamic_resolution.clamp(self.resolution_scale),
+            None => self.resolution_scale,
+        }
+    }
+}
+
+/// Scale an extent, keeping it at least one pixel in size.
+pub(crate) fn scale_extent(extent: vk::Extent2D, scale: f32) -> vk::Extent2D {
+    let scale = |n: u32| ((n as f32 * scale).round() as u32).max(1);
+    vk::Extent2D {
+        width: scale(extent.width),
+        height: scale(extent.height),
+    }
+}
+
+/// Pick the highest sample count the GPU supports for both color and depth, up to `requested`.
+pub(crate) fn supported_samples(
+    requested: vk::SampleCountFlags,
+    limits: &vk::PhysicalDeviceLimits,
+) -> vk::SampleCountFlags {
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    let mut samples = requested;
+    while samples != vk::SampleCountFlags::TYPE_1 && !supported.contains(samples) {
+        samples = vk::SampleCountFlags::from_raw(samples.as_raw() >> 1);
+    }
+    samples
+}
+
+/// Is `format` one that can only be used for depth?
+pub(crate) fn is_depth_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT
+    ) || has_stencil(format)
+}
+
+/// Does `format` have a stencil aspect as well as depth?
+pub(crate) fn has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_extent() {
+        let extent = vk::Extent2D {
+            width: 1832,
+            height: 1920,
+        };
+        let scaled = scale_extent(extent, 0.5);
+        assert_eq!((scaled.width, scaled.height), (916, 960));
+        assert_eq!(scale_extent(extent, 0.).width, 1);
+    }
+
+    #[test]
+    fn test_supported_samples() {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_2
+                | vk::SampleCountFlags::TYPE_4,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_4,
+            ..Default::default()
+        };
+        let samples = |requested| supported_samples(requested, &limits);
+        assert_eq!(
+            samples(vk::SampleCountFlags::TYPE_8),
+            vk::SampleCountFlags::TYPE_4
+        );
+        assert_eq!(
+            samples(vk::SampleCountFlags::TYPE_2),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+
+    #[test]
+    fn test_resolution_scales() {
+        let settings = RenderSettings {
+            resolution_scale: 1.2,
+            dynamic_resolution: Some(DynamicResolution {
+                min_scale: 0.5,
+                max_scale: 1.,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(settings.max_resolution_scale(), 1.2);
+        assert_eq!(settings.initial_resolution_scale(), 1.);
+    }
+}
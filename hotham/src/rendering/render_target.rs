@@ -0,0 +1,278 @@
+use std::slice::from_ref as slice_from_ref;
+
+use anyhow::Result;
+use ash::vk;
+use glam::{Mat4, UVec4, Vec4};
+
+use crate::{
+    components::RenderLayers,
+    contexts::{
+        render_context::{create_pipeline, BlendMode, Shaders, CLEAR_VALUES},
+        VulkanContext,
+    },
+    rendering::{
+        environment::create_skybox_pipeline, post_processing::PostProcessing, scene_data::SceneData,
+    },
+    COLOR_FORMAT,
+};
+
+/// The most [`crate::components::RenderTargetCamera`]s drawn each frame. Each one needs its own
+/// descriptor sets, so any more are skipped.
+pub const MAX_RENDER_TARGET_CAMERAS: usize = 4;
+
+/// The render pass and pipelines used to draw [`crate::components::RenderTargetCamera`]s.
+///
+/// Render targets are drawn with a single view, without MSAA or post-processing, straight into
+/// their texture. Instead, the PBR and skybox shaders tone map each pixel with the same
+/// [`PostProcessing`] tonemapper and exposure as the eyes. Color grading, the vignette and fades
+/// are only applied to the eyes.
+pub struct RenderTargets {
+    /// Single view render pass, which leaves the texture ready to be sampled
+    pub render_pass: vk::RenderPass,
+    /// Draws opaque and alpha masked primitives
+    pub pipeline: vk::Pipeline,
+    /// Draws `ALPHA_BLEND` primitives, after everything opaque
+    pub transparent_pipeline: vk::Pipeline,
+    /// Draws the skybox
+    pub skybox_pipeline: vk::Pipeline,
+    /// The format of each render target's depth buffer
+    pub depth_format: vk::Format,
+}
+
+impl RenderTargets {
+    pub(crate) fn new(
+        vulkan_context: &VulkanContext,
+        pipeline_layout: vk::PipelineLayout,
+        shaders: &Shaders,
+        depth_format: vk::Format,
+    ) -> Result<Self> {
+        let render_pass = create_render_pass(vulkan_context, depth_format)?;
+        let samples = vk::SampleCountFlags::TYPE_1;
+        let skybox_pipeline =
+            create_skybox_pipeline(vulkan_context, pipeline_layout, samples, render_pass)?;
+
+        let mut render_targets = Self {
+            render_pass,
+            pipeline: vk::Pipeline::null(),
+            transparent_pipeline: vk::Pipeline::null(),
+            skybox_pipeline,
+            depth_format,
+        };
+        render_targets.create_pipelines(vulkan_context, pipeline_layout, shaders)?;
+        Ok(render_targets)
+    }
+
+    /// (Re)create the PBR pipelines, eg. after the shaders have been hot reloaded.
+    pub(crate) fn create_pipelines(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        pipeline_layout: vk::PipelineLayout,
+        shaders: &Shaders,
+    ) -> Result<()> {
+        let samples = vk::SampleCountFlags::TYPE_1;
+        self.pipeline = create_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            samples,
+            self.render_pass,
+            shaders,
+            BlendMode::Opaque,
+        )?;
+        self.transparent_pipeline = create_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            samples,
+            self.render_pass,
+            shaders,
+            BlendMode::AlphaBlend,
+        )?;
+        Ok(())
+    }
+
+    /// Begin the render pass, clearing the render target, and set the viewport to cover it.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, and not inside a render pass
+    pub(crate) unsafe fn begin_render_pass(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+    ) {
+        let render_area = vk::Rect2D {
+            offset: Default::default(),
+            extent,
+        };
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as _,
+            height: extent.height as _,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        device.cmd_begin_render_pass(
+            command_buffer,
+            &vk::RenderPassBeginInfo::builder()
+                .render_pass(self.render_pass)
+                .framebuffer(framebuffer)
+                .render_area(render_area)
+                .clear_values(&CLEAR_VALUES),
+            vk::SubpassContents::INLINE,
+        );
+        device.cmd_set_viewport(command_buffer, 0, slice_from_ref(&viewport));
+        device.cmd_set_scissor(command_buffer, 0, slice_from_ref(&render_area));
+    }
+}
+
+/// The scene as seen by a render target camera, drawing only its render layers and tone mapped like
+/// the eyes. The PBR shaders index the views with `gl_ViewIndex`, which is always 0 in the render
+/// target pass, but both are set to be safe.
+pub(crate) fn render_target_scene_data(
+    scene_data: &SceneData,
+    post_processing: &PostProcessing,
+    view_projection: Mat4,
+    camera_position: Vec4,
+    layers: RenderLayers,
+) -> SceneData {
+    SceneData {
+        view_projection: [view_projection; 2],
+        camera_position: [camera_position; 2],
+        view_layers: UVec4::new(layers.0, layers.0, 0, 0),
+        tone_mapping: post_processing.tone_mapping(),
+        ..scene_data.clone()
+    }
+}
+
+fn create_render_pass(
+    vulkan_context: &VulkanContext,
+    depth_format: vk::Format,
+) -> Result<vk::RenderPass> {
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(COLOR_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .build();
+
+    let depth_attachment = vk::AttachmentDescription::builder()
+        .format(depth_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .build();
+
+    let color_attachment_reference = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .build();
+
+    let depth_stencil_reference = vk::AttachmentReference::builder()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(slice_from_ref(&color_attachment_reference))
+        .depth_stencil_attachment(&depth_stencil_reference);
+
+    // Wait for the previous frame to finish sampling the texture before drawing into it, and
+    // finish drawing before the main render pass samples it.
+    let dependencies = [
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::SHADER_READ)
+            .dst_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build(),
+    ];
+
+    // The PBR shaders use multiview, so draw a single view.
+    let view_masks = [1];
+    let mut multiview = vk::RenderPassMultiviewCreateInfo::builder()
+        .view_masks(&view_masks)
+        .correlation_masks(&view_masks);
+
+    let attachments = [color_attachment, depth_attachment];
+    let create_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(slice_from_ref(&subpass))
+        .dependencies(&dependencies)
+        .push_next(&mut multiview);
+
+    let render_pass = unsafe { vulkan_context.device.create_render_pass(&create_info, None) }?;
+    Ok(render_pass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::post_processing::Tonemapper;
+
+    #[test]
+    fn test_render_target_scene_data() {
+        let mut scene_data = SceneData::default();
+        scene_data.params.y = 3.;
+        let post_processing = PostProcessing {
+            tonemapper: Tonemapper::Reinhard,
+            exposure: 2.,
+            ..Default::default()
+        };
+
+        let view_projection = Mat4::from_translation([1., 2., 3.].into());
+        let camera_position = Vec4::new(1., 2., 3., 1.);
+        let layers = RenderLayers::layer(2);
+        let render_target_scene_data = render_target_scene_data(
+            &scene_data,
+            &post_processing,
+            view_projection,
+            camera_position,
+            layers,
+        );
+
+        // Both views see the scene from the camera, and everything else is kept.
+        assert_eq!(
+            render_target_scene_data.view_projection,
+            [view_projection; 2]
+        );
+        assert_eq!(
+            render_target_scene_data.camera_position,
+            [camera_position; 2]
+        );
+        assert_eq!(render_target_scene_data.view_layers, UVec4::new(4, 4, 0, 0));
+        assert_eq!(render_target_scene_data.params.y, 3.);
+
+        // Render targets are tone mapped like the eyes, which are tone mapped by post-processing.
+        assert_eq!(
+            render_target_scene_data.tone_mapping,
+            Vec4::new(1., Tonemapper::Reinhard as u32 as f32, 2., 0.)
+        );
+        assert_eq!(scene_data.tone_mapping.x, 0.);
+    }
+}
@@ -1,17 +1,24 @@
+use anyhow::{anyhow, ensure, Result};
 use ash::vk;
 use glam::{Mat4, Vec3, Vec4};
 use id_arena::Arena;
 use vulkan_context::VulkanContext;
 
-use crate::contexts::vulkan_context;
+use crate::{
+    components::{skin::NO_SKIN, RenderLayers},
+    contexts::{render_context::PIPELINE_DEPTH, vulkan_context},
+};
 
 use super::{
     buffer::Buffer,
     descriptors::{Descriptors, SKINS_BINDING},
+    environment::EnvironmentMap,
+    free_list::FreeList,
     image::Image,
-    material::Material,
+    material::{Material, MaterialDesc},
     memory::allocate_memory,
     mesh_data::MeshData,
+    primitive::Primitive,
     texture::{parse_ktx2, DEFAULT_COMPONENT_MAPPING},
     vertex::Vertex,
 };
@@ -52,14 +59,31 @@ pub struct Resources {
     /// Shared sampler
     pub cube_sampler: vk::Sampler,
 
+    /// The cube maps currently used for Image Based Lighting
+    pub environment_map: EnvironmentMap,
+
     /// Staging buffer for GPU data transfer
     pub staging_buffer: StagingBuffer,
 
     /// Texture descriptor information
     texture_count: u32,
     cube_texture_count: u32,
+
+    /// Space in the vertex and index buffers that primitives have stopped using, and that can be
+    /// used again by [`super::mesh_builder::MeshBuilder`]
+    pub(crate) free_vertices: FreeList,
+    pub(crate) free_indices: FreeList,
+
+    /// Space freed while recording the current frame, which earlier frames may still be drawing
+    pending_frees: Vec<PrimitiveSpace>,
+
+    /// Space that can be reused once the GPU has finished the frame with this index
+    in_flight_frees: [Vec<PrimitiveSpace>; PIPELINE_DEPTH],
 }
 
+/// The ranges of the vertex and index buffers a primitive was using
+type PrimitiveSpace = (std::ops::Range<u32>, std::ops::Range<u32>);
+
 impl Resources {
     /// Create all the buffers required and update the relevant descriptor sets.
     pub(crate) unsafe fn new(vulkan_context: &VulkanContext, descriptors: &Descriptors) -> Self {
@@ -93,7 +117,7 @@ impl Resources {
             SKINS_BUFFER_SIZE,
         );
 
-        for set in descriptors.sets {
+        for set in descriptors.graphics_sets() {
             skins_buffer.update_descriptor_set(&vulkan_context.device, set, SKINS_BINDING);
         }
 
@@ -105,7 +129,7 @@ impl Resources {
             .create_texture_sampler(vk::SamplerAddressMode::CLAMP_TO_EDGE)
             .unwrap();
 
-        load_ibl_textures(vulkan_context, descriptors, cube_sampler);
+        let environment_map = load_ibl_textures(vulkan_context, descriptors, cube_sampler);
 
         let staging_buffer = StagingBuffer::new(vulkan_context);
 
@@ -120,7 +144,12 @@ impl Resources {
             cube_texture_count: 2, // IMPORTANT! We stashed the IBL textures in here, so increment the count
             texture_sampler,
             cube_sampler,
+            environment_map,
             staging_buffer,
+            free_vertices: Default::default(),
+            free_indices: Default::default(),
+            pending_frees: Default::default(),
+            in_flight_frees: Default::default(),
         }
     }
 
@@ -153,6 +182,73 @@ impl Resources {
 
         index
     }
+
+    /// Add a material, returning its ID. Assign it to an entity with a
+    /// [`crate::components::MaterialOverride`], or to a primitive's `material_id`.
+    pub fn add_material(&mut self, desc: &MaterialDesc) -> Result<u32> {
+        let materials_buffer = &mut self.materials_buffer;
+        ensure!(
+            materials_buffer.len() < materials_buffer.max_len,
+            "Unable to add material, there are already {} materials",
+            materials_buffer.max_len
+        );
+        Ok(unsafe { materials_buffer.push(&desc.into()) })
+    }
+
+    /// The material with the given ID, unpacked so it can be changed or cloned.
+    pub fn material(&self, material_id: u32) -> Option<MaterialDesc> {
+        let materials = unsafe { self.materials_buffer.as_slice() };
+        materials.get(material_id as usize).map(MaterialDesc::from)
+    }
+
+    /// Replace the material with the given ID. Everything drawn with it changes from the next
+    /// frame.
+    ///
+    /// There's only one copy of the materials, which the frames still running on the GPU read
+    /// from, so those frames may draw some primitives with the old material and some with the new
+    /// one. That's fine for occasional changes, but don't animate a material by updating it every
+    /// frame. Use a [`crate::components::MaterialOverride`]'s tint and emissive instead, which are
+    /// sent with each frame's instances.
+    pub fn update_material(&mut self, material_id: u32, desc: &MaterialDesc) -> Result<()> {
+        let materials = unsafe { self.materials_buffer.as_slice_mut() };
+        let material = materials
+            .get_mut(material_id as usize)
+            .ok_or_else(|| anyhow!("Material {material_id} doesn't exist"))?;
+        *material = desc.into();
+        Ok(())
+    }
+
+    /// Free the space `primitive` uses in the vertex and index buffers. Frames that have already
+    /// been submitted may still be drawing from it, so it's only reused once they're finished.
+    pub(crate) fn free_primitive_space(&mut self, primitive: &Primitive) {
+        let vertices = primitive.vertex_buffer_offset
+            ..primitive.vertex_buffer_offset + primitive.vertex_capacity;
+        let indices =
+            primitive.index_buffer_offset..primitive.index_buffer_offset + primitive.index_capacity;
+        self.pending_frees.push((vertices, indices));
+    }
+
+    /// The frame with this index has been submitted, so anything freed while it was recorded
+    /// can be reused once it's finished.
+    pub(crate) fn frame_submitted(&mut self, frame_index: usize) {
+        let pending_frees = std::mem::take(&mut self.pending_frees);
+        self.in_flight_frees[frame_index].extend(pending_frees);
+    }
+
+    /// The GPU has finished the frame with this index. Every frame before it has been waited on
+    /// too, so the space freed before it was submitted can now be reused.
+    pub(crate) fn frame_finished(&mut self, frame_index: usize) {
+        for (vertices, indices) in self.in_flight_frees[frame_index].drain(..) {
+            self.free_vertices.free(vertices);
+            self.free_indices.free(indices);
+        }
+
+        // Give space at the end of the buffers back to them, so it can be pushed onto again.
+        let vertex_len = self.free_vertices.trim(self.vertex_buffer.len);
+        self.vertex_buffer.len = vertex_len;
+        self.position_buffer.len = vertex_len;
+        self.index_buffer.len = self.free_indices.trim(self.index_buffer.len);
+    }
 }
 
 // Upload the textures required for Image Based Lighting. A bit of silliness is required here.
@@ -162,7 +258,7 @@ fn load_ibl_textures(
     vulkan_context: &VulkanContext,
     descriptors: &Descriptors,
     cube_texture_sampler: vk::Sampler,
-) {
+) -> EnvironmentMap {
     // First, load in the LUT file.
     #[cfg(target_os = "android")]
     let brdf_lut_file = include_bytes!("../../data/brdf_lut_android.ktx2");
@@ -192,54 +288,16 @@ fn load_ibl_textures(
     }
 
     // OK. Next we've got to load in the cubemaps.
-    #[cfg(target_os = "android")]
-    let cubemaps = [
-        include_bytes!("../../data/environment_map_diffuse_android.ktx2").to_vec(),
-        include_bytes!("../../data/environment_map_specular_android.ktx2").to_vec(),
-    ];
-
-    #[cfg(not(target_os = "android"))]
-    let cubemaps = [
-        include_bytes!("../../data/environment_map_diffuse.ktx2").to_vec(),
-        include_bytes!("../../data/environment_map_specular.ktx2").to_vec(),
-    ];
-
-    for (index, image) in cubemaps.iter().enumerate() {
-        let ktx2_image = parse_ktx2(image);
-        let mip_levels = ktx2_image.mip_levels;
-
-        // Right. Now we've got to do the array/mip count dance.
-        let image = vulkan_context
-            .create_image_with_component_mapping(
-                ktx2_image.format,
-                &ktx2_image.extent,
-                vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
-                6,
-                mip_levels,
-                DEFAULT_COMPONENT_MAPPING,
-            )
-            .unwrap();
-
-        vulkan_context.upload_image(
-            &ktx2_image.image_buf,
-            mip_levels,
-            ktx2_image.offsets,
-            &image,
-        );
-
-        unsafe {
-            descriptors.write_cube_texture_descriptor(
-                vulkan_context,
-                image.view,
-                cube_texture_sampler,
-                index as _,
-            );
-        }
+    let environment_map = EnvironmentMap::built_in(vulkan_context).unwrap();
+    unsafe {
+        environment_map.write_descriptors(vulkan_context, descriptors, cube_texture_sampler);
     }
+
+    environment_map
 }
 
 /// Instructions on how to draw this primitive
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 #[repr(C, align(16))]
 pub struct DrawData {
     /// The transform of the parent mesh
@@ -249,6 +307,25 @@ pub struct DrawData {
     pub local_from_gos: Mat4,
     /// An optional skin to use.
     pub skin_id: u32,
+    /// The render layers the mesh is on. Views that draw none of them skip it.
+    pub layers: u32,
+    /// Linear RGBA color multiplied with the material's base color, packed as four unorm8
+    pub tint: u32,
+    /// Linear RGB color added to the material's emission, packed as three half floats
+    pub packed_emissive: [u32; 2],
+}
+
+impl Default for DrawData {
+    fn default() -> Self {
+        Self {
+            gos_from_local: Mat4::IDENTITY,
+            local_from_gos: Mat4::IDENTITY,
+            skin_id: NO_SKIN,
+            layers: RenderLayers::ALL.0,
+            tint: u32::MAX,
+            packed_emissive: [0, 0],
+        }
+    }
 }
 
 /// Information for the culling shader on how to cull this primitive.
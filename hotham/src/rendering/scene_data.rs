@@ -1,7 +1,10 @@
-use glam::{Mat4, Vec4};
+use glam::{Mat4, UVec4, Vec4};
 use serde::{Deserialize, Serialize};
 
-use super::light::{Light, MAX_LIGHTS};
+use super::{
+    light::{Light, MAX_LIGHTS},
+    shadows::{BlobShadowData, MAX_BLOB_SHADOWS},
+};
 
 /// The amount of Image Based Lighting (IBL) to show in the scene
 pub const DEFAULT_IBL_INTENSITY: f32 = 1.0;
@@ -14,10 +17,22 @@ pub struct SceneData {
     pub view_projection: [Mat4; 2],
     /// Position of the cameras (one per eye)
     pub camera_position: [Vec4; 2],
-    /// Scene Parameters - x = IBL intensity, y = unused, z = debug render inputs, w = debug render algorithm
+    /// Scene Parameters - x = IBL intensity, y = number of blob shadows, z = debug render inputs, w = debug render algorithm
     pub params: Vec4,
     /// Dynamic punctual lights
     pub lights: [Light; MAX_LIGHTS],
+    /// Transforms from globally oriented stage space into each light's shadow map
+    pub shadow_from_gos: [Mat4; MAX_LIGHTS],
+    /// Shadow parameters for each light
+    /// x = enabled, y = fraction of the shadow map used, z = depth bias, w = texel size
+    pub shadow_params: [Vec4; MAX_LIGHTS],
+    /// Blob shadows, the first `params.y` of which are drawn
+    pub blob_shadows: [BlobShadowData; MAX_BLOB_SHADOWS],
+    /// The render layers each eye draws - x = left, y = right, zw = unused
+    pub view_layers: UVec4,
+    /// Tone mapping done by the PBR and skybox shaders - x = enabled, y = tonemapper, z = exposure,
+    /// w = unused. Only enabled for render targets, as the eyes are tone mapped by post-processing.
+    pub tone_mapping: Vec4,
 }
 
 impl Default for SceneData {
@@ -27,6 +42,11 @@ impl Default for SceneData {
             camera_position: [Vec4::ZERO, Vec4::ZERO],
             params: [DEFAULT_IBL_INTENSITY, 0., 0., 0.].into(),
             lights: [Light::none(), Light::none(), Light::none(), Light::none()],
+            shadow_from_gos: [Mat4::IDENTITY; MAX_LIGHTS],
+            shadow_params: [Vec4::ZERO; MAX_LIGHTS],
+            blob_shadows: Default::default(),
+            view_layers: UVec4::new(u32::MAX, u32::MAX, 0, 0),
+            tone_mapping: Vec4::ZERO,
         }
     }
 }
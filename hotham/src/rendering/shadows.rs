@@ -0,0 +1,566 @@
+use std::{ffi::CStr, mem::size_of};
+
+use anyhow::Result;
+use ash::vk;
+use glam::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use vk_shader_macros::include_glsl;
+
+use crate::{
+    contexts::VulkanContext,
+    rendering::{
+        descriptors::Descriptors,
+        image::Image,
+        light::{Light, LIGHT_TYPE_DIRECTIONAL, LIGHT_TYPE_SPOT, MAX_LIGHTS},
+        vertex::Vertex,
+    },
+};
+
+static SHADOW_VERT: &[u32] = include_glsl!("src/shaders/shadow.vert", target: vulkan1_1);
+
+/// The size of each layer of the shadow map. Lights with a lower resolution use a corner of it.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+/// 16 bits of depth is plenty for shadows, and is kind to mobile GPUs.
+pub const SHADOW_MAP_FORMAT: vk::Format = vk::Format::D16_UNORM;
+/// Maximum number of [`crate::components::BlobShadow`]s drawn in a scene
+pub const MAX_BLOB_SHADOWS: usize = 8;
+
+const SPOT_SHADOW_NEAR: f32 = 0.05;
+
+/// Shadow settings for a single light.
+///
+/// Only directional and spot lights cast shadows. Directional lights use a single cascade fitted
+/// around the player, spot lights use a perspective shadow map covering their outer cone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// Does this light cast shadows?
+    pub enabled: bool,
+    /// The resolution of the shadow map, up to [`SHADOW_MAP_SIZE`]
+    pub resolution: u32,
+    /// Subtracted from the depth of each fragment before comparing it with the shadow map, to
+    /// avoid "shadow acne"
+    pub depth_bias: f32,
+    /// For directional lights, how far from the player shadows are drawn, in metres. For spot
+    /// lights without a range, how far the shadow map reaches.
+    pub distance: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            resolution: 1024,
+            depth_bias: 0.002,
+            distance: 10.,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// Default settings, with shadows enabled
+    pub fn enabled() -> Self {
+        Self {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// The fraction of the shadow map layer used by this light
+    fn scale(&self) -> f32 {
+        self.resolution.clamp(1, SHADOW_MAP_SIZE) as f32 / SHADOW_MAP_SIZE as f32
+    }
+}
+
+/// A blob shadow, as sent to the fragment shader
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct BlobShadowData {
+    /// xyz = position in globally oriented stage space, w = radius. A radius of 0 is ignored.
+    pub position_and_radius: Vec4,
+    /// x = opacity, y = maximum distance the shadow is cast, zw = unused
+    pub params: Vec4,
+}
+
+/// Shadow maps for every light in the scene, stored as layers of a single depth image and rendered
+/// in one multiview pass - one view per light.
+pub struct ShadowMaps {
+    /// Per-light settings, indexed the same as `SceneData::lights`
+    pub settings: [ShadowSettings; MAX_LIGHTS],
+    /// The depth image, with one layer per light
+    pub image: Image,
+    /// Comparison sampler used for percentage closer filtering
+    pub sampler: vk::Sampler,
+    /// Depth-only render pass
+    pub render_pass: vk::RenderPass,
+    /// Framebuffer covering every layer of `image`
+    pub framebuffer: vk::Framebuffer,
+    /// Depth-only pipeline
+    pub pipeline: vk::Pipeline,
+}
+
+impl ShadowMaps {
+    pub(crate) fn new(
+        vulkan_context: &VulkanContext,
+        descriptors: &Descriptors,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<Self> {
+        let device = &vulkan_context.device;
+        let extent = vk::Extent2D {
+            width: SHADOW_MAP_SIZE,
+            height: SHADOW_MAP_SIZE,
+        };
+        let image = vulkan_context.create_image(
+            SHADOW_MAP_FORMAT,
+            &extent,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            MAX_LIGHTS as _,
+            1,
+        )?;
+
+        let sampler = unsafe {
+            device.create_sampler(
+                &vk::SamplerCreateInfo::builder()
+                    .mag_filter(vk::Filter::LINEAR)
+                    .min_filter(vk::Filter::LINEAR)
+                    .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .compare_enable(true)
+                    .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                    .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                    .max_lod(0.),
+                None,
+            )
+        }?;
+
+        let render_pass = create_render_pass(vulkan_context)?;
+        let attachments = [image.view];
+        let framebuffer = unsafe {
+            device.create_framebuffer(
+                &vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(&attachments)
+                    .width(SHADOW_MAP_SIZE)
+                    .height(SHADOW_MAP_SIZE)
+                    .layers(1),
+                None,
+            )
+        }?;
+        let pipeline = create_pipeline(vulkan_context, pipeline_layout, render_pass)?;
+
+        let shadow_maps = Self {
+            settings: Default::default(),
+            image,
+            sampler,
+            render_pass,
+            framebuffer,
+            pipeline,
+        };
+
+        // Clear the shadow maps once so they're in the right layout to be sampled, even if no
+        // light ever casts a shadow.
+        let command_buffer = vulkan_context.begin_single_time_commands();
+        unsafe {
+            shadow_maps.begin_render_pass(device, command_buffer);
+            device.cmd_end_render_pass(command_buffer);
+        }
+        vulkan_context.end_single_time_commands(command_buffer);
+
+        unsafe {
+            descriptors.write_shadow_map_descriptor(
+                vulkan_context,
+                shadow_maps.image.view,
+                shadow_maps.sampler,
+            );
+        }
+
+        Ok(shadow_maps)
+    }
+
+    /// Does any light in `lights` cast shadows?
+    pub fn any_enabled(&self, lights: &[Light]) -> bool {
+        lights
+            .iter()
+            .zip(&self.settings)
+            .any(|(light, settings)| casts_shadows(light, settings))
+    }
+
+    /// Calculate the shadow transforms and parameters for each light. `lights` must already be in
+    /// globally oriented stage space.
+    pub fn shadow_data(
+        &self,
+        lights: &[Light; MAX_LIGHTS],
+        eyes_in_gos: Vec3,
+    ) -> ([Mat4; MAX_LIGHTS], [Vec4; MAX_LIGHTS]) {
+        let mut shadow_from_gos = [Mat4::IDENTITY; MAX_LIGHTS];
+        let mut shadow_params = [Vec4::ZERO; MAX_LIGHTS];
+
+        for (i, (light, settings)) in lights.iter().zip(&self.settings).enumerate() {
+            if !casts_shadows(light, settings) {
+                continue;
+            }
+
+            shadow_from_gos[i] = if light.light_type == LIGHT_TYPE_DIRECTIONAL {
+                directional_shadow_from_gos(light, eyes_in_gos, settings)
+            } else {
+                spot_shadow_from_gos(light, settings)
+            };
+            shadow_params[i] = [
+                1.,
+                settings.scale(),
+                settings.depth_bias,
+                1. / SHADOW_MAP_SIZE as f32,
+            ]
+            .into();
+        }
+
+        (shadow_from_gos, shadow_params)
+    }
+
+    /// Begin the shadow render pass, clearing every layer
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, and not inside a render pass
+    pub(crate) unsafe fn begin_render_pass(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }];
+        let render_area = vk::Rect2D {
+            offset: Default::default(),
+            extent: self.image.extent,
+        };
+        device.cmd_begin_render_pass(
+            command_buffer,
+            &vk::RenderPassBeginInfo::builder()
+                .render_pass(self.render_pass)
+                .framebuffer(self.framebuffer)
+                .render_area(render_area)
+                .clear_values(&clear_values),
+            vk::SubpassContents::INLINE,
+        );
+    }
+}
+
+fn casts_shadows(light: &Light, settings: &ShadowSettings) -> bool {
+    settings.enabled
+        && (light.light_type == LIGHT_TYPE_DIRECTIONAL || light.light_type == LIGHT_TYPE_SPOT)
+}
+
+/// An up vector that isn't parallel to `direction`
+fn up_vector(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    }
+}
+
+/// Fit a single orthographic cascade around `center`, snapped to the shadow map's texels so that
+/// shadows don't shimmer as the player moves.
+pub fn directional_shadow_from_gos(light: &Light, center: Vec3, settings: &ShadowSettings) -> Mat4 {
+    let direction = light.direction.normalize();
+    let distance = settings.distance;
+    let light_from_gos = Mat4::look_at_rh(Vec3::ZERO, direction, up_vector(direction));
+
+    let texel_size = 2. * distance / settings.resolution.clamp(1, SHADOW_MAP_SIZE) as f32;
+    let mut center = light_from_gos.transform_point3(center);
+    center.x = (center.x / texel_size).floor() * texel_size;
+    center.y = (center.y / texel_size).floor() * texel_size;
+
+    // Extend the box towards the light so that casters between it and the player are included.
+    let projection = Mat4::orthographic_rh(
+        center.x - distance,
+        center.x + distance,
+        center.y - distance,
+        center.y + distance,
+        -(center.z + 2. * distance),
+        distance - center.z,
+    );
+
+    projection * light_from_gos
+}
+
+/// A perspective shadow map covering the spot light's outer cone.
+pub fn spot_shadow_from_gos(light: &Light, settings: &ShadowSettings) -> Mat4 {
+    let direction = light.direction.normalize();
+
+    // Recover the outer cone angle from the pre-computed spot light values.
+    let cos_outer = (-light.light_angle_offset / light.light_angle_scale).clamp(-1., 1.);
+    let field_of_view = (2. * cos_outer.acos()).clamp(1_f32.to_radians(), 170_f32.to_radians());
+    let far = if light.falloff > 0. {
+        1. / light.falloff.sqrt()
+    } else {
+        settings.distance
+    };
+
+    let light_from_gos = Mat4::look_at_rh(
+        light.position,
+        light.position + direction,
+        up_vector(direction),
+    );
+    let projection = Mat4::perspective_rh(field_of_view, 1., SPOT_SHADOW_NEAR, far);
+
+    projection * light_from_gos
+}
+
+fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass> {
+    let depth_attachment = vk::AttachmentDescription::builder()
+        .format(SHADOW_MAP_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .build();
+
+    let depth_attachment_reference = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_reference);
+
+    // Wait for the previous frame to finish reading the shadow maps before writing to them, and
+    // finish writing before this frame reads from them.
+    let dependencies = [
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::SHADER_READ)
+            .dst_stage_mask(
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build(),
+    ];
+
+    // One view per light.
+    let view_masks = [!(!0 << MAX_LIGHTS)];
+    let mut multiview = vk::RenderPassMultiviewCreateInfo::builder()
+        .view_masks(&view_masks)
+        .correlation_masks(&view_masks);
+
+    let attachments = [depth_attachment];
+    let create_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(std::slice::from_ref(&subpass))
+        .dependencies(&dependencies)
+        .push_next(&mut multiview);
+
+    let render_pass = unsafe { vulkan_context.device.create_render_pass(&create_info, None) }?;
+    Ok(render_pass)
+}
+
+fn create_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    let device = &vulkan_context.device;
+    let main = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    let vertex_shader = unsafe {
+        device.create_shader_module(
+            &vk::ShaderModuleCreateInfo::builder().code(SHADOW_VERT),
+            None,
+        )
+    }?;
+
+    // Depth only - there is no fragment shader.
+    let stages = [vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .name(main)
+        .module(vertex_shader)
+        .build()];
+
+    let vertex_binding_descriptions = [
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Vec3>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build(),
+        vk::VertexInputBindingDescription::builder()
+            .binding(1)
+            .stride(size_of::<Vertex>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build(),
+    ];
+    let vertex_attribute_descriptions = Vertex::attribute_descriptions();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions)
+        .vertex_binding_descriptions(&vertex_binding_descriptions);
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewports = [vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: SHADOW_MAP_SIZE as _,
+        height: SHADOW_MAP_SIZE as _,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    }];
+    let scissors = [vk::Rect2D {
+        offset: Default::default(),
+        extent: vk::Extent2D {
+            width: SHADOW_MAP_SIZE,
+            height: SHADOW_MAP_SIZE,
+        },
+    }];
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(&viewports)
+        .scissors(&scissors);
+
+    // Don't cull, so single sided geometry still casts shadows, and use a slope scaled bias to
+    // keep acne under control.
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(true)
+        .depth_bias_constant_factor(1.25)
+        .depth_bias_slope_factor(1.75)
+        .line_width(1.0);
+
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS)
+        .max_depth_bounds(1.0);
+
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder();
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0)
+        .build();
+
+    let pipelines = unsafe {
+        device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    pub fn test_spot_shadow_from_gos() {
+        let light = Light::new_spotlight(
+            Vec3::NEG_Y,
+            10.,
+            5.,
+            Vec3::ONE,
+            [1., 3., 0.].into(),
+            0.,
+            45_f32.to_radians(),
+        );
+        let shadow_from_gos = spot_shadow_from_gos(&light, &ShadowSettings::enabled());
+
+        // Directly below the light is in the middle of the shadow map.
+        let below = shadow_from_gos.project_point3([1., 0., 0.].into());
+        assert_relative_eq!(below.x, 0., epsilon = 0.0001);
+        assert_relative_eq!(below.y, 0., epsilon = 0.0001);
+        assert!(below.z > 0. && below.z < 1.);
+
+        // The edge of the cone is at the edge of the shadow map.
+        let edge = shadow_from_gos.project_point3([3., 1., 0.].into());
+        assert_relative_eq!(edge.x.abs().max(edge.y.abs()), 1., epsilon = 0.0001);
+
+        // Further away is deeper.
+        let further = shadow_from_gos.project_point3([1., -1., 0.].into());
+        assert!(further.z > below.z);
+    }
+
+    #[test]
+    pub fn test_directional_shadow_from_gos() {
+        let light = Light::new_directional([0., -1., -1.].into(), 1., Vec3::ONE);
+        let settings = ShadowSettings::enabled();
+        let center = Vec3::new(5., 1.6, 3.);
+        let shadow_from_gos = directional_shadow_from_gos(&light, center, &settings);
+
+        // The player is (roughly - it's snapped to texels) in the middle of the shadow map.
+        let texel = 2. / settings.resolution as f32;
+        let player = shadow_from_gos.project_point3(center);
+        assert!(player.x.abs() <= texel && player.y.abs() <= texel);
+        assert!(player.z > 0. && player.z < 1.);
+
+        // Points towards the light are closer.
+        let towards_light = shadow_from_gos.project_point3(center - light.direction);
+        assert!(towards_light.z < player.z);
+
+        // Moving a tiny amount doesn't move the shadow map.
+        let nudged = directional_shadow_from_gos(&light, center + Vec3::X * 0.0001, &settings);
+        let nudged_player = nudged.project_point3(center);
+        assert!((nudged_player - player).length() <= texel * 1.5);
+    }
+
+    #[test]
+    pub fn test_casts_shadows() {
+        let lights = [
+            Light::new_directional(Vec3::NEG_Y, 1., Vec3::ONE),
+            Light::new_directional(Vec3::NEG_Y, 1., Vec3::ONE),
+            Light::new_point(Vec3::ZERO, 1., 1., Vec3::ONE),
+            Light::none(),
+        ];
+        let settings = [
+            ShadowSettings::default(),
+            ShadowSettings {
+                resolution: 512,
+                ..ShadowSettings::enabled()
+            },
+            ShadowSettings::enabled(),
+            ShadowSettings::enabled(),
+        ];
+
+        // Only enabled directional and spot lights cast shadows.
+        let casts: Vec<_> = lights
+            .iter()
+            .zip(&settings)
+            .map(|(light, settings)| casts_shadows(light, settings))
+            .collect();
+        assert_eq!(casts, [false, true, false, false]);
+        assert_relative_eq!(settings[1].scale(), 0.25);
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{sync_channel, SyncSender, TrySendError},
+    thread::JoinHandle,
+};
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::{
+    contexts::{render_context::PIPELINE_DEPTH, VulkanContext},
+    rendering::{buffer::Buffer, texture::Texture},
+};
+
+/// Writes what a [`crate::components::Spectator`] camera sees to disk, as a numbered sequence of
+/// PNGs (`00000.png`, `00001.png`, ..) that can be turned into a video with eg. `ffmpeg`.
+///
+/// Each frame's texture is copied into a staging buffer at the end of the frame, and written out
+/// on a background thread once the GPU has finished with it, so capturing doesn't stall the
+/// renderer. Enable it with [`crate::EngineBuilder::capture_spectator`].
+///
+/// Encoding a PNG is usually slower than a frame, so at most [`MAX_QUEUED_FRAMES`] frames wait to
+/// be written. Frames that arrive while the queue is full are dropped, and the sequence carries on
+/// without them.
+pub struct SpectatorCapture {
+    directory: PathBuf,
+    staging_buffers: Vec<Buffer<u8>>,
+    pending: [Option<vk::Extent2D>; PIPELINE_DEPTH],
+    next_frame_number: usize,
+    dropped_frame_count: usize,
+    sender: Option<SyncSender<CapturedFrame>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+/// The most captured frames waiting to be written to disk. Each one holds a copy of the texture.
+pub const MAX_QUEUED_FRAMES: usize = 4;
+
+struct CapturedFrame {
+    path: PathBuf,
+    extent: vk::Extent2D,
+    pixels: Vec<u8>,
+}
+
+impl SpectatorCapture {
+    /// Write the image sequence into `directory`, creating it if needed. Existing frames are
+    /// overwritten.
+    pub fn create<P: AsRef<Path>>(directory: P) -> Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        std::fs::create_dir_all(&directory)?;
+
+        let (sender, receiver) = sync_channel::<CapturedFrame>(MAX_QUEUED_FRAMES);
+        let writer = std::thread::spawn(move || {
+            for frame in receiver {
+                let image = image::RgbaImage::from_raw(
+                    frame.extent.width,
+                    frame.extent.height,
+                    frame.pixels,
+                )
+                .unwrap();
+                if let Err(e) = image.save(&frame.path) {
+                    println!("[HOTHAM_CAPTURE] Unable to write {:?}: {e:?}", frame.path);
+                }
+            }
+        });
+
+        Ok(Self {
+            directory,
+            staging_buffers: Vec::new(),
+            pending: Default::default(),
+            next_frame_number: 0,
+            dropped_frame_count: 0,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// The number of frames captured so far
+    pub fn frame_count(&self) -> usize {
+        self.next_frame_number
+    }
+
+    /// The number of frames dropped so far, because they couldn't be written out fast enough
+    pub fn dropped_frame_count(&self) -> usize {
+        self.dropped_frame_count
+    }
+
+    /// Record a copy of `texture` into this frame's staging buffer, and send the frame that was
+    /// last copied into it to be written to disk.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, outside a render pass, and the fence for `frame_index`
+    /// must have been waited on. `texture` must be a [`crate::components::RenderTargetCamera`]'s
+    /// texture, drawn earlier in the frame.
+    pub(crate) unsafe fn record(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        texture: &Texture,
+    ) {
+        let device = &vulkan_context.device;
+        let extent = texture.image.extent;
+        let size = (extent.width * extent.height * 4) as usize;
+
+        // The GPU has finished with this frame's staging buffer, so whatever was copied into it
+        // PIPELINE_DEPTH frames ago is ready.
+        self.send_pending(frame_index);
+
+        if self.staging_buffers.is_empty() {
+            self.staging_buffers = (0..PIPELINE_DEPTH)
+                .map(|_| Buffer::new(vulkan_context, vk::BufferUsageFlags::TRANSFER_DST, size))
+                .collect();
+        }
+        let staging_buffer = &mut self.staging_buffers[frame_index];
+        if staging_buffer.max_len < size {
+            staging_buffer.destroy(device);
+            *staging_buffer = Buffer::new(vulkan_context, vk::BufferUsageFlags::TRANSFER_DST, size);
+        }
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .image(texture.image.handle)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(subresource_range)
+                .build()
+        };
+
+        // The render target pass left the texture ready to be sampled.
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier(
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            )],
+        );
+
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            texture.image.handle,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            staging_buffer.buffer,
+            &[*region],
+        );
+
+        // Put the texture back the way it was, and make the copy visible to the CPU.
+        let buffer_barrier = vk::BufferMemoryBarrier::builder()
+            .buffer(staging_buffer.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .build();
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[buffer_barrier],
+            &[barrier(
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::AccessFlags::SHADER_READ,
+            )],
+        );
+
+        staging_buffer.len = size;
+        self.pending[frame_index] = Some(extent);
+    }
+
+    unsafe fn send_pending(&mut self, frame_index: usize) {
+        let extent = match self.pending[frame_index].take() {
+            Some(extent) => extent,
+            None => return,
+        };
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+
+        let size = (extent.width * extent.height * 4) as usize;
+        let pixels = self.staging_buffers[frame_index].as_slice()[..size].to_vec();
+        self.send(extent, pixels);
+    }
+
+    /// Queue a frame to be written, or drop it if the writer has fallen behind.
+    fn send(&mut self, extent: vk::Extent2D, pixels: Vec<u8>) {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+
+        let path = self.directory.join(frame_file_name(self.next_frame_number));
+        match sender.try_send(CapturedFrame {
+            path,
+            extent,
+            pixels,
+        }) {
+            Ok(()) => self.next_frame_number += 1,
+            Err(TrySendError::Full(_)) => {
+                if self.dropped_frame_count == 0 {
+                    println!("[HOTHAM_CAPTURE] Frames can't be written as fast as they're captured, dropping some");
+                }
+                self.dropped_frame_count += 1;
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                println!("[HOTHAM_CAPTURE] The image writer has stopped, capture stopped");
+                self.sender = None;
+            }
+        }
+    }
+
+    /// Stop capturing, and free the staging buffers. Frames still in flight are dropped, and the
+    /// ones already sent are written before this returns.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must have finished with the staging buffers, eg. after `device_wait_idle`.
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for staging_buffer in &mut self.staging_buffers {
+            staging_buffer.destroy(device);
+        }
+        self.staging_buffers.clear();
+        self.pending = Default::default();
+        self.finish_writing();
+    }
+
+    fn finish_writing(&mut self) {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+            if self.dropped_frame_count > 0 {
+                println!(
+                    "[HOTHAM_CAPTURE] Wrote {} frames, dropped {}",
+                    self.next_frame_number, self.dropped_frame_count
+                );
+            }
+        }
+    }
+}
+
+impl Drop for SpectatorCapture {
+    fn drop(&mut self) {
+        self.finish_writing();
+    }
+}
+
+fn frame_file_name(frame_number: usize) -> String {
+    format!("{frame_number:05}.png")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_file_name() {
+        // Frames sort in order, so tools like ffmpeg pick them up as a sequence.
+        assert_eq!(frame_file_name(0), "00000.png");
+        assert_eq!(frame_file_name(123), "00123.png");
+        assert!(frame_file_name(9) < frame_file_name(10));
+    }
+
+    #[test]
+    fn test_dropped_frames() {
+        let directory = std::env::temp_dir().join("hotham_spectator_capture_test");
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut capture = SpectatorCapture::create(&directory).unwrap();
+
+        // Send frames much faster than they can be encoded.
+        let extent = vk::Extent2D {
+            width: 512,
+            height: 512,
+        };
+        let frames = 100;
+        for _ in 0..frames {
+            capture.send(
+                extent,
+                vec![255; (extent.width * extent.height * 4) as usize],
+            );
+        }
+        assert!(capture.dropped_frame_count() > 0);
+        assert_eq!(
+            capture.frame_count() + capture.dropped_frame_count(),
+            frames
+        );
+
+        // The frames that were kept are numbered without gaps.
+        capture.finish_writing();
+        let written = std::fs::read_dir(&directory).unwrap().count();
+        assert_eq!(written, capture.frame_count());
+        assert!(directory.join(frame_file_name(written - 1)).exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
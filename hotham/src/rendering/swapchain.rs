@@ -3,9 +3,9 @@ use ash::vk::{self, Handle};
 use openxr::{Swapchain as SwapchainHandle, Vulkan};
 use vulkan_context::VulkanContext;
 
-use crate::{contexts::vulkan_context, COLOR_FORMAT, DEPTH_FORMAT};
+use crate::{contexts::vulkan_context, SCENE_COLOR_FORMAT};
 
-use super::texture::DEFAULT_COMPONENT_MAPPING;
+use super::{render_settings::RenderSettings, texture::DEFAULT_COMPONENT_MAPPING};
 
 /// A thin container for OpenXR to pass the details of its Swapchain to RenderContext.
 pub struct SwapchainInfo {
@@ -129,10 +129,15 @@ fn get_swapchain_images_with_ffr(
 
 /// A thin container for OpenXR to pass the details of its Swapchain to RenderContext.
 pub struct Swapchain {
-    /// The dimensions of the swapchain.
+    /// The area of the swapchain images that is rendered to. With dynamic resolution this can be
+    /// smaller than the images.
     pub render_area: vk::Rect2D,
+    /// The resolution of the swapchain images
+    pub resolution: vk::Extent2D,
     /// The framebuffers of the swapchain, one per swapchain image.
     pub framebuffers: Vec<vk::Framebuffer>,
+    /// The multisampled HDR image the scene is drawn into before post-processing.
+    pub scene_color_image: super::image::Image,
 }
 
 impl Swapchain {
@@ -141,6 +146,7 @@ impl Swapchain {
         swapchain_info: &SwapchainInfo,
         vulkan_context: &VulkanContext,
         render_pass: vk::RenderPass,
+        settings: &RenderSettings,
     ) -> Self {
         let render_area = vk::Rect2D {
             extent: swapchain_info.resolution,
@@ -149,24 +155,31 @@ impl Swapchain {
 
         // Depth image, shared between frames
         let depth_image = vulkan_context
-            .create_image(
-                DEPTH_FORMAT,
+            .create_image_with_samples(
+                settings.depth_format,
                 &swapchain_info.resolution,
                 vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
                     | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
                 2,
                 1,
+                DEFAULT_COMPONENT_MAPPING,
+                settings.samples,
             )
             .unwrap();
 
-        // Color image, used for MSAA.
-        let color_image = vulkan_context
-            .create_image(
-                COLOR_FORMAT,
+        // Color image, used for MSAA. It's read by the post-processing subpass, which writes the
+        // final image to the swapchain.
+        let scene_color_image = vulkan_context
+            .create_image_with_samples(
+                SCENE_COLOR_FORMAT,
                 &swapchain_info.resolution,
-                vk::ImageUsageFlags::TRANSIENT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+                    | vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::INPUT_ATTACHMENT,
                 2,
                 1,
+                DEFAULT_COMPONENT_MAPPING,
+                settings.samples,
             )
             .unwrap();
 
@@ -174,14 +187,17 @@ impl Swapchain {
         let framebuffers = create_framebuffers(
             swapchain_info,
             vulkan_context,
-            color_image,
-            depth_image,
+            &scene_color_image,
+            &depth_image,
             render_pass,
+            settings.color_format,
         );
 
         Self {
             render_area,
+            resolution: swapchain_info.resolution,
             framebuffers,
+            scene_color_image,
         }
     }
 }
@@ -190,9 +206,10 @@ impl Swapchain {
 fn create_framebuffers(
     swapchain_info: &SwapchainInfo,
     vulkan_context: &VulkanContext,
-    color_image: super::image::Image,
-    depth_image: super::image::Image,
+    color_image: &super::image::Image,
+    depth_image: &super::image::Image,
     render_pass: vk::RenderPass,
+    color_format: vk::Format,
 ) -> Vec<vk::Framebuffer> {
     let ffr_image_view = vulkan_context
         .create_image_view(
@@ -211,7 +228,7 @@ fn create_framebuffers(
             vulkan_context
                 .create_image_view(
                     swapchain_image,
-                    COLOR_FORMAT,
+                    color_format,
                     vk::ImageViewType::TYPE_2D_ARRAY,
                     2,
                     1,
@@ -249,9 +266,10 @@ fn create_framebuffers(
 fn create_framebuffers(
     swapchain_info: &SwapchainInfo,
     vulkan_context: &VulkanContext,
-    color_image: super::image::Image,
-    depth_image: super::image::Image,
+    color_image: &super::image::Image,
+    depth_image: &super::image::Image,
     render_pass: vk::RenderPass,
+    color_format: vk::Format,
 ) -> Vec<vk::Framebuffer> {
     let framebuffers = swapchain_info
         .images
@@ -259,7 +277,7 @@ fn create_framebuffers(
         .flat_map(|i| {
             vulkan_context.create_image_view(
                 i,
-                COLOR_FORMAT,
+                color_format,
                 vk::ImageViewType::TYPE_2D_ARRAY,
                 2,
                 1,
@@ -34,6 +34,10 @@ pub enum TextureUsage {
     MetallicRoughnessOcclusion,
     /// Indicates this texture is used for Image Based Lighting (IBL)
     IBL,
+    /// Baked diffuse lighting, sampled with the second set of texture coordinates
+    Lightmap,
+    /// A color grading lookup table, used by [`crate::rendering::post_processing::ColorGrading`]
+    ColorGrading,
     /// A non PBR texture
     Other,
 }
@@ -141,7 +145,9 @@ impl Texture {
             .create_image(
                 COLOR_FORMAT,
                 &resolution,
-                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
                 1,
                 1,
             )
@@ -238,7 +244,10 @@ impl Texture {
         };
 
         let format = match texture_usage {
-            TextureUsage::BaseColor | TextureUsage::Emission => vk::Format::R8G8B8A8_SRGB,
+            TextureUsage::BaseColor
+            | TextureUsage::Emission
+            | TextureUsage::Lightmap
+            | TextureUsage::ColorGrading => vk::Format::R8G8B8A8_SRGB,
             _ => vk::Format::R8G8B8A8_UNORM,
         };
 
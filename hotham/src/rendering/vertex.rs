@@ -5,7 +5,7 @@ const VERTEX_FORMAT: vk::Format = vk::Format::R32G32B32_SFLOAT;
 
 /// Representation of a single vertex, usually imported from a glTF file.
 #[repr(C)]
-#[derive(Clone, Debug, Copy, PartialEq, Default)]
+#[derive(Clone, Debug, Copy, PartialEq)]
 pub struct Vertex {
     // /// Position in model space
     // pub position: Vec3,
@@ -17,6 +17,23 @@ pub struct Vertex {
     pub joint_indices: u32,
     /// Joint weights (for skinning), one byte per weight.
     pub joint_weights: u32,
+    /// Second set of texture coordinates, eg. for ambient occlusion or lightmaps
+    pub texture_coords_1: Vec2,
+    /// Vertex color in linear space, one byte per channel. Multiplied with the base color.
+    pub color: u32,
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Self {
+            normal: Vec3::ZERO,
+            texture_coords: Vec2::ZERO,
+            joint_indices: 0,
+            joint_weights: 0,
+            texture_coords_1: Vec2::ZERO,
+            color: u32::MAX,
+        }
+    }
 }
 
 impl Vertex {
@@ -28,6 +45,7 @@ impl Vertex {
             texture_coords,
             joint_indices,
             joint_weights,
+            ..Default::default()
         }
     }
 
@@ -93,12 +111,28 @@ impl Vertex {
             .offset(memoffset::offset_of!(Vertex, joint_weights) as _)
             .build();
 
+        let texture_coords_1 = vk::VertexInputAttributeDescription::builder()
+            .binding(1)
+            .location(5)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(memoffset::offset_of!(Vertex, texture_coords_1) as _)
+            .build();
+
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(1)
+            .location(6)
+            .format(vk::Format::R8G8B8A8_UNORM)
+            .offset(memoffset::offset_of!(Vertex, color) as _)
+            .build();
+
         vec![
             position,
             normal,
             texture_coords,
             joint_indices,
             joint_weights,
+            texture_coords_1,
+            color,
         ]
     }
 }
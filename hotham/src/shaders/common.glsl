@@ -34,9 +34,22 @@ const uint LightType_Directional = 0;
 const uint LightType_Point = 1;
 const uint LightType_Spot = 2;
 
+#define MAX_BLOB_SHADOWS 8
+
+// A soft, round shadow cast straight down onto the surfaces below it.
+struct BlobShadow {
+    vec4 positionAndRadius;
+    vec4 params; // x = opacity, y = max distance
+};
+
 layout (set = 0, binding = 2) readonly uniform SceneData {
     mat4 viewProjection[2];
     vec4 cameraPosition[2];
     vec4 params;
     Light lights[4];
+    mat4 shadowFromGos[4];
+    vec4 shadowParams[4]; // x = enabled, y = fraction of the shadow map used, z = depth bias, w = texel size
+    BlobShadow blobShadows[MAX_BLOB_SHADOWS];
+    uvec4 viewLayers; // x = left eye, y = right eye
+    vec4 toneMapping; // x = enabled, y = tonemapper, z = exposure. Only set for render targets.
 } sceneData;
@@ -0,0 +1,19 @@
+// Draws debug lines, unlit and on top of everything else.
+#version 460
+#extension GL_EXT_multiview : enable
+
+#include "common.glsl"
+
+layout (location = 0) in vec3 inGosPos;
+layout (location = 1) in vec4 inColor;
+
+layout (location = 0) out vec4 outColor;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    outColor = inColor;
+    gl_Position = sceneData.viewProjection[gl_ViewIndex] * vec4(inGosPos, 1.0);
+}
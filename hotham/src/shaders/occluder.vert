@@ -0,0 +1,51 @@
+// Depth only shader used to draw occluders for occlusion culling. Each view is an eye.
+#version 460
+#extension GL_EXT_multiview : enable
+
+#include "common.glsl"
+
+layout (location = 0) in vec3 inPos;
+layout (location = 3) in uint inJoint;
+layout (location = 4) in uint inWeight;
+
+struct DrawData {
+    mat4 gosFromLocal;
+    mat4 localFromGos;
+    uint skinID;
+    uint layers;
+    uint tint;
+    uint packedEmissive[2];
+};
+
+layout (set = 0, binding = 0) readonly buffer DrawDataBuffer {
+    DrawData data[];
+} drawDataBuffer;
+
+layout (std430, set = 0, binding = 1) readonly buffer SkinsBuffer {
+    mat4 jointMatrices[100][64];
+} skinsBuffer;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    uint skinID = drawDataBuffer.data[gl_InstanceIndex].skinID;
+    mat4 gosFromLocal = drawDataBuffer.data[gl_InstanceIndex].gosFromLocal;
+    vec4 gosPos;
+
+    if (skinID == NOT_PRESENT) {
+        gosPos = gosFromLocal * vec4(inPos, 1.0);
+    } else {
+        // See pbr.vert for an explanation of how skinning works.
+        mat4 skinMatrix =
+            ((inWeight) & 255)       * skinsBuffer.jointMatrices[skinID][(inJoint) & 255] +
+            ((inWeight >> 8) & 255)  * skinsBuffer.jointMatrices[skinID][(inJoint >> 8) & 255] +
+            ((inWeight >> 16) & 255) * skinsBuffer.jointMatrices[skinID][(inJoint >> 16) & 255] +
+            ((inWeight >> 24) & 255) * skinsBuffer.jointMatrices[skinID][(inJoint >> 24) & 255];
+
+        gosPos = gosFromLocal * skinMatrix * vec4(inPos, 1.0);
+    }
+
+    gl_Position = sceneData.viewProjection[gl_ViewIndex] * gosPos;
+}
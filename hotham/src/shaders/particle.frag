@@ -0,0 +1,27 @@
+// Draws particles, either textured or as soft round dots.
+#version 460
+
+#define NOT_PRESENT 4294967295
+
+layout (set = 0, binding = 3) uniform sampler2D textures[10000];
+
+layout (location = 0) in vec2 inUV;
+layout (location = 1) in vec4 inColor;
+layout (location = 2) flat in uint inTextureID;
+layout (location = 3) flat in uint inAdditive;
+
+layout (location = 0) out vec4 outColor;
+
+void main() {
+    vec4 color = inColor;
+    if (inTextureID == NOT_PRESENT) {
+        float distanceFromCenter = length(inUV - 0.5) * 2.0;
+        color.a *= 1.0 - smoothstep(0.5, 1.0, distanceFromCenter);
+    } else {
+        color *= texture(textures[inTextureID], inUV);
+    }
+
+    // Colors are premultiplied, so additive particles are the ones that don't cover what's behind
+    // them at all.
+    outColor = vec4(color.rgb * color.a, inAdditive == 1 ? 0.0 : color.a);
+}
@@ -0,0 +1,51 @@
+// Draws particles as quads that face each eye.
+#version 460
+#extension GL_EXT_multiview : enable
+
+#include "common.glsl"
+
+layout (location = 0) in vec3 inGosPos;
+layout (location = 1) in float inSize;
+layout (location = 2) in vec4 inColor;
+layout (location = 3) in uint inTextureID;
+layout (location = 4) in uint inAdditive;
+layout (location = 5) in uint inLayers;
+
+layout (location = 0) out vec2 outUV;
+layout (location = 1) out vec4 outColor;
+layout (location = 2) flat out uint outTextureID;
+layout (location = 3) flat out uint outAdditive;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+// Two triangles, without an index or vertex buffer.
+const vec2 CORNERS[6] = vec2[](
+    vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+    vec2(-0.5, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5)
+);
+
+void main() {
+    // Skip particles on none of this view's render layers, like meshes.
+    if ((inLayers & sceneData.viewLayers[gl_ViewIndex]) == 0) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+
+    vec2 corner = CORNERS[gl_VertexIndex];
+
+    // Face this eye, keeping the quad upright where possible.
+    vec3 forward = normalize(sceneData.cameraPosition[gl_ViewIndex].xyz - inGosPos);
+    vec3 up = abs(forward.y) > 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(0.0, 1.0, 0.0);
+    vec3 right = normalize(cross(up, forward));
+    up = cross(forward, right);
+
+    vec3 position = inGosPos + (right * corner.x + up * corner.y) * inSize;
+    gl_Position = sceneData.viewProjection[gl_ViewIndex] * vec4(position, 1.0);
+
+    outUV = vec2(corner.x + 0.5, 0.5 - corner.y);
+    outColor = inColor;
+    outTextureID = inTextureID;
+    outAdditive = inAdditive;
+}
@@ -9,13 +9,19 @@
 
 #include "common.glsl"
 #include "lights.glsl"
+#include "shadows.glsl"
 #include "brdf.glsl"
 #include "pbr.glsl"
+#include "tonemapping.glsl"
 
 // Inputs
 layout (location = 0) in vec3 inGosPos;
 layout (location = 1) in vec2 inUV;
 layout (location = 2) in vec3 inNormal;
+layout (location = 3) in vec2 inUV1;
+layout (location = 4) in vec4 inColor;
+layout (location = 5) flat in vec4 inTint;
+layout (location = 6) flat in vec3 inEmissive;
 
 // Outputs
 layout (location = 0) out vec4 outColor;
@@ -30,7 +36,7 @@ vec3 getNormal() {
     }
 
     f16vec3 textureNormal;
-    textureNormal.xy = f16vec2(texture(textures[baseTextureID + 2], inUV).ga) * F16(2) - F16(1);
+    textureNormal.xy = f16vec2(texture(textures[baseTextureID + 2], uv).ga) * F16(2) - F16(1);
     textureNormal.z = sqrt(F16(1) - dot(textureNormal.xy, textureNormal.xy));
 
     // We compute the tangents on the fly because it is faster, presumably because it saves bandwidth.
@@ -40,8 +46,8 @@ vec3 getNormal() {
     // globally oriented stage space instead of view space and we rely on the UV map not being too distorted.
     vec3 dGosPosDx = dFdx(inGosPos);
     vec3 dGosPosDy = dFdy(inGosPos);
-    vec2 dUvDx = dFdx(inUV);
-    vec2 dUvDy = dFdy(inUV);
+    vec2 dUvDx = dFdx(uv);
+    vec2 dUvDy = dFdy(uv);
 
     vec3 T = normalize(dGosPosDx * dUvDy.t - dGosPosDy * dUvDx.t);
     vec3 B = normalize(cross(N, T));
@@ -55,31 +61,67 @@ void main() {
     materialFlags = material.flagsAndBaseTextureID & 0xFFFF;
     baseTextureID = material.flagsAndBaseTextureID >> 16;
 
+    // Underlay composition layers show through wherever this is visible. Opaque materials are
+    // drawn with blending off, so this clears the scene's coverage for the pixel.
+    if ((materialFlags & MATERIAL_FLAG_HOLE_PUNCH) != 0) {
+        outColor = vec4(0.0);
+        return;
+    }
+
+    uv = transformUV(inUV);
+    uv1 = inUV1;
+
     // Determine the base color
     f16vec3 baseColor;
+    vec4 baseColorFactor = unpackUnorm4x8(material.packedBaseColor);
+    float alpha = baseColorFactor.a;
 
     if ((materialFlags & MATERIAL_FLAG_HAS_BASE_COLOR_TEXTURE) != 0) {
         // This is *technically* against the spec, since material base color is meant to be treated as a "factor",
         // but as of writing no texture authoring tool actually changes these values, so we can skip unnecessary
-        // arithmetic.
-        baseColor = V16(texture(textures[baseTextureID], inUV));
+        // arithmetic. Alpha is the exception, as fading a textured material out is a common thing to do.
+        vec4 baseColorTexture = texture(textures[baseTextureID], uv);
+        baseColor = V16(baseColorTexture);
+        alpha *= baseColorTexture.a;
     } else {
         // If no base color texture is present, check to see if the material had the base color factors set. This
         // is usually only for very simple materials or prototyping.`
-        baseColor = V16(unpackUnorm4x8(material.packedBaseColor));
+        baseColor = V16(baseColorFactor);
     }
 
+    // Vertex colors are white if the mesh doesn't have any, as is the tint of meshes without a
+    // material override.
+    baseColor *= V16(inColor) * V16(inTint);
+    alpha *= inColor.a * inTint.a;
+
+    // Alpha masking: discard anything below the cutoff, which is packed in after metallic and roughness.
+    if ((materialFlags & MATERIAL_FLAG_ALPHA_MASK) != 0) {
+        if (alpha < unpackUnorm4x8(material.packedMetallicRoughnessFactor).z) {
+            discard;
+        }
+    }
+
+    // Only blended materials are transparent.
+    outColor.a = ((materialFlags & MATERIAL_FLAG_ALPHA_BLEND) != 0) ? alpha : 1.0;
+
     // Set globals that are read inside functions for lighting etc.
     pos = inGosPos;
     v = normalize(sceneData.cameraPosition[gl_ViewIndex].xyz - inGosPos);
     n = getNormal();
-    uv = inUV;
 
-    // Choose the correct workflow for this material
+    // Choose the correct workflow for this material. Tone mapping happens in the post-processing
+    // subpass, except for render targets which don't have one.
     if ((materialFlags & PBR_WORKFLOW_UNLIT) == 0) {
-        outColor.rgb = tonemap(getPBRMetallicRoughnessColor(baseColor));
+        outColor.rgb = getPBRMetallicRoughnessColor(baseColor);
     } else {
-        outColor.rgb = tonemap(baseColor);
+        outColor.rgb = baseColor;
+    }
+
+    // Add the emission from the material override, which glows even in the unlit workflow.
+    outColor.rgb += inEmissive;
+
+    if (sceneData.toneMapping.x > 0.0) {
+        outColor.rgb = toneMap(outColor.rgb, uint(sceneData.toneMapping.y), sceneData.toneMapping.z);
     }
 
     // Debugging
@@ -97,19 +139,19 @@ void main() {
                 break;
             // Occlusion
             case 3:
-                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_AO_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 1], inUV).rrr;
+                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_AO_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 1], getOcclusionUV()).rrr;
                 break;
             // Emission
             case 4:
-                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_EMISSION_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 3], inUV).rgb;
+                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_EMISSION_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 3], uv).rgb;
                 break;
             // Roughness
             case 5:
-                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_METALLIC_ROUGHNESS_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 1], inUV).ggg;
+                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_METALLIC_ROUGHNESS_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 1], uv).ggg;
                 break;
             // Metallic
             case 6:
-                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_METALLIC_ROUGHNESS_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 1], inUV).bbb;
+                outColor.rgb = ((materialFlags & MATERIAL_FLAG_HAS_METALLIC_ROUGHNESS_TEXTURE) != 0) ? ERROR_MAGENTA.rgb : texture(textures[baseTextureID + 1], uv).bbb;
                 break;
         }
         outColor = outColor;
@@ -12,6 +12,14 @@
 #define MATERIAL_FLAG_HAS_AO_TEXTURE 8
 #define MATERIAL_FLAG_HAS_EMISSION_TEXTURE 16
 #define PBR_WORKFLOW_UNLIT 32
+#define MATERIAL_FLAG_ALPHA_MASK 64
+#define MATERIAL_FLAG_ALPHA_BLEND 128
+#define MATERIAL_FLAG_HAS_CLEARCOAT 256
+#define MATERIAL_FLAG_OCCLUSION_TEXCOORD_1 512
+#define MATERIAL_FLAG_HAS_UV_TRANSFORM 1024
+#define MATERIAL_FLAG_HAS_LIGHTMAP 2048
+#define MATERIAL_FLAG_HOLE_PUNCH 4096
+#define NO_TEXTURE_16 0xFFFF
 
 // The default index of refraction of 1.5 yields a dielectric normal incidence reflectance (eg. f0) of 0.04
 #define DEFAULT_F0 V16(0.04)
@@ -26,6 +34,11 @@ layout( push_constant ) uniform constants
     uint flagsAndBaseTextureID;
     uint packedBaseColor;
     uint packedMetallicRoughnessFactor;
+    uint packedEmissiveFactor[2];
+    uint packedClearcoatFactor;
+    uint packedClearcoatTextureIDs;
+    uint packedUVTransform[3];
+    uint packedLightmap;
 } material;
 
 // Store the unpacked material in globals to avoid copying when calling functions.
@@ -36,7 +49,25 @@ uint baseTextureID;
 vec3 pos;   // pos
 vec3 n;     // normal
 vec3 v;     // view vector
-vec2 uv;    // inUV
+vec2 uv;    // inUV, transformed by the material's texture transform
+vec2 uv1;   // inUV1
+
+// Apply the material's texture transform (KHR_texture_transform), if it has one.
+vec2 transformUV(vec2 inUV) {
+    if ((materialFlags & MATERIAL_FLAG_HAS_UV_TRANSFORM) == 0) {
+        return inUV;
+    }
+    mat3x2 transform = mat3x2(
+        unpackHalf2x16(material.packedUVTransform[0]),
+        unpackHalf2x16(material.packedUVTransform[1]),
+        unpackHalf2x16(material.packedUVTransform[2]));
+    return transform * vec3(inUV, 1.0);
+}
+
+// The occlusion texture may use either set of texture coordinates.
+vec2 getOcclusionUV() {
+    return ((materialFlags & MATERIAL_FLAG_OCCLUSION_TEXCOORD_1) != 0) ? uv1 : uv;
+}
 
 // Calculation of the lighting contribution from an optional Image Based Light source.
 f16vec3 getIBLContribution(f16vec3 F0, float16_t perceptualRoughness, f16vec3 diffuseColor, f16vec3 reflection, float16_t NdotV) {
@@ -93,6 +124,76 @@ f16vec3 getLightContribution(f16vec3 f0, float16_t alphaRoughness, f16vec3 diffu
     return color;
 }
 
+f16vec3 getClearcoatLightContribution(float16_t alphaRoughness, float16_t NdotV, Light light) {
+    vec3 pointToLight;
+    if (light.type != LightType_Directional) {
+        pointToLight = light.position - pos;
+    } else {
+        pointToLight = -light.direction;
+    }
+
+    vec3 l = normalize(pointToLight);
+    vec3 h = normalize(l + v);
+
+    float16_t NdotL = F16(clamp(dot(n, l), 0, 1));
+    float16_t NdotH = F16(clamp(dot(n, h), 0, 1));
+    float16_t LdotH = F16(clamp(dot(l, h), 0, 1));
+
+    if (NdotL <= 0.) {
+        return V16(0);
+    }
+
+    float16_t attenuation = getLightAttenuation(light, pointToLight, l);
+    return BRDF_specular(DEFAULT_F0, alphaRoughness, V16(h), V16(n), NdotV, NdotL, NdotH, LdotH)
+        * (F16(light.intensity) * attenuation * NdotL);
+}
+
+// Layer a clear coat (KHR_materials_clearcoat) over the base material. The clear coat shares the
+// base material's normal, and is a dielectric with an index of refraction of 1.5.
+f16vec3 getClearcoatColor(f16vec3 baseColor, float16_t NdotV, float16_t ao) {
+    f16vec2 factors = f16vec2(unpackUnorm4x8(material.packedClearcoatFactor).xy);
+    float16_t clearcoat = factors.x;
+    float16_t perceptualRoughness = factors.y;
+
+    uint clearcoatTextureID = material.packedClearcoatTextureIDs & 0xFFFF;
+    uint roughnessTextureID = material.packedClearcoatTextureIDs >> 16;
+    if (clearcoatTextureID != NO_TEXTURE_16) {
+        clearcoat *= F16(texture(textures[clearcoatTextureID], uv).r);
+    }
+    if (roughnessTextureID != NO_TEXTURE_16) {
+        perceptualRoughness *= F16(texture(textures[roughnessTextureID], uv).g);
+    }
+    perceptualRoughness = clamp(perceptualRoughness, MEDIUMP_FLT_MIN, F16(1.0));
+    float16_t alphaRoughness = perceptualRoughness * perceptualRoughness;
+
+    f16vec3 coat = V16(0);
+    if (sceneData.params.x > 0.) {
+        f16vec3 reflection = normalize(reflect(V16(-v), V16(n)));
+        float16_t lod = perceptualRoughness * DEFAULT_CUBE_MIPMAP_LEVELS - F16(1);
+        f16vec2 f_ab = f16vec2(texture(textures[BRDF_LUT_TEXTURE_ID],
+            clamp(f16vec2(NdotV, perceptualRoughness), f16vec2(0), f16vec2(1.0)))).rg;
+        f16vec3 specularLight = V16(textureLod(cubeTextures[ENVIRONMENT_MAP_TEXTURE_ID], reflection, lod));
+        coat = specularLight * (DEFAULT_F0 * f_ab.x + f_ab.y) * ao * F16(sceneData.params.x);
+    }
+
+    if (sceneData.lights[0].type != NOT_PRESENT) {
+        coat += getClearcoatLightContribution(alphaRoughness, NdotV, sceneData.lights[0]) * getShadow(0, pos);
+    }
+    if (sceneData.lights[1].type != NOT_PRESENT) {
+        coat += getClearcoatLightContribution(alphaRoughness, NdotV, sceneData.lights[1]) * getShadow(1, pos);
+    }
+    if (sceneData.lights[2].type != NOT_PRESENT) {
+        coat += getClearcoatLightContribution(alphaRoughness, NdotV, sceneData.lights[2]) * getShadow(2, pos);
+    }
+    if (sceneData.lights[3].type != NOT_PRESENT) {
+        coat += getClearcoatLightContribution(alphaRoughness, NdotV, sceneData.lights[3]) * getShadow(3, pos);
+    }
+
+    // The clear coat reflects some of the light that would otherwise reach the base material.
+    f16vec3 fresnel = DEFAULT_F0 + (V16(1) - DEFAULT_F0) * pow(F16(1) - NdotV, F16(5));
+    return baseColor * (F16(1) - clearcoat * fresnel) + clearcoat * coat;
+}
+
 f16vec3 getPBRMetallicRoughnessColor(f16vec3 baseColor) {
     f16vec3 amrSample;
 
@@ -128,12 +229,23 @@ f16vec3 getPBRMetallicRoughnessColor(f16vec3 baseColor) {
 
     // Ambient Occlusion is stored in the 'r' channel as per the glTF spec
     float16_t ao;
-    if ((materialFlags & MATERIAL_FLAG_HAS_AO_TEXTURE) != 0) {
+    if ((materialFlags & MATERIAL_FLAG_OCCLUSION_TEXCOORD_1) != 0) {
+        ao = F16(texture(textures[baseTextureID + 1], uv1).r);
+    } else if ((materialFlags & MATERIAL_FLAG_HAS_AO_TEXTURE) != 0) {
         ao  = amrSample.r;
     } else {
         ao = F16(1);
     }
 
+    // Baked lighting replaces dynamic diffuse lighting, so lights and IBL only add specular.
+    // The lightmap is assumed to already include ambient occlusion.
+    f16vec3 bakedDiffuse = V16(0);
+    if ((materialFlags & MATERIAL_FLAG_HAS_LIGHTMAP) != 0) {
+        f16vec3 lightmap = V16(texture(textures[material.packedLightmap & 0xFFFF], uv1));
+        bakedDiffuse = diffuseColor * lightmap * F16(unpackHalf2x16(material.packedLightmap).y);
+        diffuseColor = V16(0);
+    }
+
     // Calculate lighting contribution from image based lighting source (IBL), scaled by a scene data parameter.
     f16vec3 color;
     if (sceneData.params.x > 0.) {
@@ -148,37 +260,38 @@ f16vec3 getPBRMetallicRoughnessColor(f16vec3 baseColor) {
     // Qualcomm's documentation suggests that loops are undesirable, so we do branches instead.
     // Since these values are uniform, they shouldn't have too high of a penalty.
     if (sceneData.lights[0].type != NOT_PRESENT) {
-        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[0], ao);
+        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[0], ao) * getShadow(0, pos);
     }
     if (sceneData.lights[1].type != NOT_PRESENT) {
-        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[1], ao);
+        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[1], ao) * getShadow(1, pos);
     }
     if (sceneData.lights[2].type != NOT_PRESENT) {
-        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[2], ao);
+        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[2], ao) * getShadow(2, pos);
     }
     if (sceneData.lights[3].type != NOT_PRESENT) {
-        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[3], ao);
+        color += getLightContribution(f0, alphaRoughness, diffuseColor, NdotV, sceneData.lights[3], ao) * getShadow(3, pos);
     }
 
-    // Add emission, if present
-    if ((materialFlags & MATERIAL_FLAG_HAS_EMISSION_TEXTURE) > 0) {
-        color += V16(texture(textures[baseTextureID + 3], uv)).rgb;
+    color += bakedDiffuse;
+
+    // Blob shadows darken everything but emission.
+    if (sceneData.params.y > 0.) {
+        color *= getBlobShadows(pos, n);
     }
 
-    return color;
-}
+    // Add the clear coat layer on top, if present
+    if ((materialFlags & MATERIAL_FLAG_HAS_CLEARCOAT) != 0) {
+        color = getClearcoatColor(color, NdotV, ao);
+    }
 
-// Fast approximation of ACES tonemap
-// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
-f16vec3 toneMapACES_Narkowicz(const f16vec3 color) {
-    const float16_t A = F16(2.51);
-    const float16_t B = F16(0.03);
-    const float16_t C = F16(2.43);
-    const float16_t D = F16(0.59);
-    const float16_t E = F16(0.14);
-    return clamp((color * (A * color + B)) / (color * (C * color + D) + E), F16(0), F16(1));
-}
+    // Add emission. The emissive factor has already been multiplied by the emissive strength.
+    f16vec3 emission = V16(vec3(
+        unpackHalf2x16(material.packedEmissiveFactor[0]),
+        unpackHalf2x16(material.packedEmissiveFactor[1]).x));
+    if ((materialFlags & MATERIAL_FLAG_HAS_EMISSION_TEXTURE) > 0) {
+        emission *= V16(texture(textures[baseTextureID + 3], uv)).rgb;
+    }
+    color += emission;
 
-f16vec3 tonemap(const f16vec3 color) {
-    return toneMapACES_Narkowicz(color);
+    return color;
 }
@@ -10,19 +10,28 @@ layout (location = 1) in vec3 inNormal;
 layout (location = 2) in vec2 inUV;
 layout (location = 3) in uint inJoint;
 layout (location = 4) in uint inWeight;
+layout (location = 5) in vec2 inUV1;
+layout (location = 6) in vec4 inColor;
 
 layout (location = 0) out vec4 outGosPos;
 layout (location = 1) out vec2 outUV;
 layout (location = 2) out vec3 outNormal;
+layout (location = 3) out vec2 outUV1;
+layout (location = 4) out vec4 outColor;
+layout (location = 5) flat out vec4 outTint;
+layout (location = 6) flat out vec3 outEmissive;
 
 struct DrawData {
     mat4 gosFromLocal;
     mat4 localFromGos;
     uint skinID;
+    uint layers;
+    uint tint;
+    uint packedEmissive[2];
 };
 
 layout (set = 0, binding = 0) readonly buffer DrawDataBuffer {
-    DrawData data[5000];
+    DrawData data[];
 } drawDataBuffer;
 
 layout (std430, set = 0, binding = 1) readonly buffer SkinsBuffer {
@@ -34,6 +43,13 @@ out gl_PerVertex {
 };
 
 void main() {
+    // Skip instances on none of this view's render layers. Every vertex is moved to the same point
+    // outside the view, so no triangles are drawn.
+    if ((drawDataBuffer.data[gl_InstanceIndex].layers & sceneData.viewLayers[gl_ViewIndex]) == 0) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+
     uint skinID = drawDataBuffer.data[gl_InstanceIndex].skinID;
     mat4 gosFromLocal = drawDataBuffer.data[gl_InstanceIndex].gosFromLocal;
     mat4 localFromGos = drawDataBuffer.data[gl_InstanceIndex].localFromGos;
@@ -57,5 +73,14 @@ void main() {
     }
 
     outUV = inUV;
+    outUV1 = inUV1;
+    outColor = inColor;
+
+    // Per instance overrides, from the entity's MaterialOverride.
+    DrawData drawData = drawDataBuffer.data[gl_InstanceIndex];
+    outTint = unpackUnorm4x8(drawData.tint);
+    outEmissive = vec3(
+        unpackHalf2x16(drawData.packedEmissive[0]),
+        unpackHalf2x16(drawData.packedEmissive[1]).x);
     gl_Position = sceneData.viewProjection[gl_ViewIndex] * outGosPos;
 }
@@ -0,0 +1,91 @@
+// Post-processing: resolves the multisampled HDR scene, then applies tone mapping, color grading,
+// a comfort vignette and a fade.
+#version 460
+#extension GL_EXT_multiview : enable
+#extension GL_GOOGLE_include_directive : require
+
+#include "tonemapping.glsl"
+
+layout (constant_id = 0) const int SAMPLE_COUNT = 4;
+
+layout (set = 0, binding = 2) readonly uniform SceneData {
+    mat4 viewProjection[2];
+    vec4 cameraPosition[2];
+    vec4 params;
+} sceneData;
+
+layout (set = 0, binding = 3) uniform sampler2D textures[10000];
+// Without MSAA the scene color isn't multisampled, so it needs a different input type.
+#ifdef SINGLE_SAMPLE
+layout (input_attachment_index = 0, set = 0, binding = 6) uniform subpassInput sceneColor;
+#define LOAD_SAMPLE(i) subpassLoad(sceneColor)
+#else
+layout (input_attachment_index = 0, set = 0, binding = 6) uniform subpassInputMS sceneColor;
+#define LOAD_SAMPLE(i) subpassLoad(sceneColor, i)
+#endif
+
+layout (push_constant) uniform constants {
+    vec4 fade; // rgb = color, a = amount
+    uint tonemapper;
+    float exposure;
+    uint lutTextureID;
+    float lutStrength;
+    float vignette;
+} post;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 linearToSRGB(vec3 color) {
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, greaterThan(color, vec3(0.0031308)));
+}
+
+// Look up a color in a strip of square slices, one per step of blue. The LUT is an sRGB texture,
+// so it's indexed with sRGB values and returns linear ones.
+vec3 applyLut(vec3 color) {
+    float size = float(textureSize(textures[post.lutTextureID], 0).y);
+    vec3 lutPos = linearToSRGB(color) * (size - 1.0);
+
+    float slice = floor(lutPos.b);
+    float nextSlice = min(slice + 1.0, size - 1.0);
+    vec2 sliceUV = (lutPos.rg + 0.5) / vec2(size * size, size);
+
+    vec3 a = texture(textures[post.lutTextureID], sliceUV + vec2(slice / size, 0.0)).rgb;
+    vec3 b = texture(textures[post.lutTextureID], sliceUV + vec2(nextSlice / size, 0.0)).rgb;
+    return mix(a, b, lutPos.b - slice);
+}
+
+void main() {
+    // Tone map each sample before averaging them, so that very bright samples don't bleed over
+    // their edges. The scene's alpha is its coverage, which is 0 where holes have been punched for
+    // underlay composition layers, and blending leaves the scene's color premultiplied by it.
+    bool debugView = sceneData.params.z > 0.0;
+    vec4 scene = vec4(0.0);
+    for (int i = 0; i < SAMPLE_COUNT; i++) {
+        vec4 sampleColor = LOAD_SAMPLE(i);
+        // Additive particles over a hole have no coverage, but still add light.
+        float weight = sampleColor.a > 0.0 ? sampleColor.a : 1.0;
+        vec3 color = sampleColor.rgb / weight;
+        color = debugView ? color : toneMap(color, post.tonemapper, post.exposure);
+        scene += vec4(color * weight, sampleColor.a);
+    }
+    scene /= float(SAMPLE_COUNT);
+    vec3 color = scene.rgb;
+    float alpha = scene.a;
+
+    if (post.lutStrength > 0.0 && alpha > 0.0) {
+        vec3 graded = applyLut(color / alpha) * alpha;
+        color = mix(color, graded, post.lutStrength);
+    }
+
+    // Darken everything outside a circle that shrinks as the vignette gets stronger.
+    if (post.vignette > 0.0) {
+        float distanceFromCenter = length(inUV * 2.0 - 1.0);
+        float outer = mix(1.5, 0.4, post.vignette);
+        color *= 1.0 - smoothstep(outer - 0.4, outer, distanceFromCenter);
+    }
+
+    // Output premultiplied alpha, and fade out the underlays along with everything else.
+    outColor = mix(vec4(color, alpha), vec4(post.fade.rgb, 1.0), post.fade.a);
+}
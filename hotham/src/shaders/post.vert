@@ -0,0 +1,14 @@
+// Draws a single triangle covering the screen for the post-processing subpass.
+#version 460
+#extension GL_EXT_multiview : enable
+
+layout (location = 0) out vec2 outUV;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    outUV = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(outUV * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,59 @@
+// Real-time shadows, from shadow maps and blob shadows.
+
+layout (set = 0, binding = 5) uniform sampler2DArrayShadow shadowMaps;
+
+// How much of the light at `lightIndex` reaches `gosPos`, from 0 (none) to 1 (all of it).
+float16_t getShadow(uint lightIndex, vec3 gosPos) {
+    vec4 params = sceneData.shadowParams[lightIndex];
+    if (params.x == 0.0) {
+        return F16(1);
+    }
+
+    vec4 shadowPos = sceneData.shadowFromGos[lightIndex] * vec4(gosPos, 1.0);
+    vec3 ndc = shadowPos.xyz / shadowPos.w;
+
+    // Anything outside the shadow map is lit.
+    if (abs(ndc.x) > 1.0 || abs(ndc.y) > 1.0 || ndc.z < 0.0 || ndc.z > 1.0) {
+        return F16(1);
+    }
+
+    vec2 uv = (ndc.xy * 0.5 + 0.5) * params.y;
+    float depth = ndc.z - params.z;
+
+    // Percentage closer filtering. Four bilinear comparisons give a 3x3 texel footprint, which is
+    // a good trade off between quality and cost on mobile GPUs.
+    float offset = params.w * 0.5;
+    float shadow =
+        texture(shadowMaps, vec4(uv + vec2(-offset, -offset), float(lightIndex), depth)) +
+        texture(shadowMaps, vec4(uv + vec2( offset, -offset), float(lightIndex), depth)) +
+        texture(shadowMaps, vec4(uv + vec2(-offset,  offset), float(lightIndex), depth)) +
+        texture(shadowMaps, vec4(uv + vec2( offset,  offset), float(lightIndex), depth));
+
+    return F16(shadow * 0.25);
+}
+
+// How much light reaches `gosPos` after blob shadows are applied, from 0 (none) to 1 (all of it).
+float16_t getBlobShadows(vec3 gosPos, vec3 normal) {
+    int count = int(sceneData.params.y);
+    float occlusion = 0.0;
+
+    for (int i = 0; i < count; i++) {
+        vec4 blob = sceneData.blobShadows[i].positionAndRadius;
+        vec4 params = sceneData.blobShadows[i].params;
+
+        // Blob shadows are only cast downwards.
+        float height = blob.y - gosPos.y;
+        if (height < 0.0 || height > params.y) {
+            continue;
+        }
+
+        float distance = length(gosPos.xz - blob.xz);
+        float blobOcclusion = params.x
+            * (1.0 - smoothstep(blob.w * 0.5, blob.w, distance))
+            * (1.0 - height / params.y);
+        occlusion = max(occlusion, blobOcclusion);
+    }
+
+    // Only darken surfaces that face upwards.
+    return F16(1.0 - occlusion * max(normal.y, 0.0));
+}
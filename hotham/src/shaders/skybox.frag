@@ -0,0 +1,28 @@
+// Draws a cube map behind everything else in the scene.
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+#include "tonemapping.glsl"
+
+layout (set = 0, binding = 4) uniform samplerCube cubeTextures[100];
+
+layout (push_constant) uniform constants {
+    uint cubeTextureID;
+    float lod;
+    float intensity;
+} skybox;
+
+layout (location = 0) in vec3 inDirection;
+
+layout (location = 0) out vec4 outColor;
+
+void main() {
+    vec3 color = textureLod(cubeTextures[skybox.cubeTextureID], inDirection, skybox.lod).rgb;
+    outColor = vec4(color * skybox.intensity, 1.0);
+
+    // Render targets have no post-processing subpass to tone map the scene.
+    if (sceneData.toneMapping.x > 0.0) {
+        outColor.rgb = toneMap(outColor.rgb, uint(sceneData.toneMapping.y), sceneData.toneMapping.z);
+    }
+}
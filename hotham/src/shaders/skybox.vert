@@ -0,0 +1,24 @@
+// Draws a cube map behind everything else in the scene.
+#version 460
+#extension GL_EXT_multiview : enable
+
+#include "common.glsl"
+
+layout (location = 0) out vec3 outDirection;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    // A single triangle that covers the whole screen.
+    vec2 clipPos = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+
+    // Find the direction through this pixel by unprojecting a point on the near plane, which is at
+    // a depth of 1 with reverse Z.
+    vec4 gosPos = inverse(sceneData.viewProjection[gl_ViewIndex]) * vec4(clipPos, 1.0, 1.0);
+    outDirection = gosPos.xyz / gosPos.w - sceneData.cameraPosition[gl_ViewIndex].xyz;
+
+    // Put the skybox at infinity, which is at a depth of 0.
+    gl_Position = vec4(clipPos, 0.0, 1.0);
+}
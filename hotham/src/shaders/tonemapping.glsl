@@ -0,0 +1,52 @@
+// Tone mapping curves, shared by the post-processing subpass and render target cameras, which draw
+// without it.
+
+#define TONEMAPPER_NONE 0u
+#define TONEMAPPER_ACES 1u
+#define TONEMAPPER_KHRONOS_NEUTRAL 2u
+#define TONEMAPPER_REINHARD 3u
+
+// Fast approximation of ACES tonemap
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+vec3 toneMapACES_Narkowicz(vec3 color) {
+    const float A = 2.51;
+    const float B = 0.03;
+    const float C = 2.43;
+    const float D = 0.59;
+    const float E = 0.14;
+    return clamp((color * (A * color + B)) / (color * (C * color + D) + E), 0.0, 1.0);
+}
+
+// https://github.com/KhronosGroup/ToneMapping/tree/main/PBR_Neutral
+vec3 toneMapKhronosPbrNeutral(vec3 color) {
+    const float startCompression = 0.8 - 0.04;
+    const float desaturation = 0.15;
+
+    float x = min(color.r, min(color.g, color.b));
+    float offset = x < 0.08 ? x - 6.25 * x * x : 0.04;
+    color -= offset;
+
+    float peak = max(color.r, max(color.g, color.b));
+    if (peak < startCompression) return color;
+
+    const float d = 1.0 - startCompression;
+    float newPeak = 1.0 - d * d / (peak + d - startCompression);
+    color *= newPeak / peak;
+
+    float g = 1.0 - 1.0 / (desaturation * (peak - newPeak) + 1.0);
+    return mix(color, vec3(newPeak), g);
+}
+
+vec3 toneMap(vec3 color, uint tonemapper, float exposure) {
+    color *= exposure;
+    switch (tonemapper) {
+        case TONEMAPPER_ACES:
+            return toneMapACES_Narkowicz(color);
+        case TONEMAPPER_KHRONOS_NEUTRAL:
+            return toneMapKhronosPbrNeutral(color);
+        case TONEMAPPER_REINHARD:
+            return color / (1.0 + color);
+        default:
+            return clamp(color, 0.0, 1.0);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    components::{animation_controller::AnimationController, LocalTransform},
+    components::{animation_controller::AnimationController, HandJoints, LocalTransform},
     Engine,
 };
 
@@ -10,7 +10,15 @@ pub fn animation_system(engine: &mut Engine) {
 }
 
 fn animation_system_inner(world: &mut hecs::World) {
-    for (_, controller) in world.query::<&AnimationController>().iter() {
+    for (_, (controller, hand_joints)) in world
+        .query::<(&AnimationController, Option<&HandJoints>)>()
+        .iter()
+    {
+        // Hands driven by hand tracking have already been posed by `hands_system`.
+        if hand_joints.map(|h| h.is_tracked).unwrap_or(false) {
+            continue;
+        }
+
         let blend_from = controller.blend_from;
         let blend_to = controller.blend_to;
         let blend_amount = controller.blend_amount;
@@ -0,0 +1,230 @@
+use ash::vk;
+use glam::{Affine3A, Vec3};
+use hecs::World;
+
+use crate::{
+    components::{
+        composition_layer::LayerShape, stage::get_global_from_stage, CompositionLayer,
+        GlobalTransform, Panel,
+    },
+    contexts::{xr_context::LayerSubmission, RenderContext, VulkanContext, XrContext},
+    util::posef_from_affine,
+    Engine,
+};
+
+/// Composition layers system
+/// Walks through each [`CompositionLayer`] in the World and
+/// - copies its panel's texture into the layer's swapchain
+/// - queues the layer to be submitted to the compositor in [`Engine::finish`]
+///
+/// Run this after [`crate::systems::draw_gui_system`], so the panels have been drawn this frame.
+pub fn composition_layers_system(engine: &mut Engine) {
+    let world = &engine.world;
+    let vulkan_context = &engine.vulkan_context;
+    let render_context = &engine.render_context;
+    let xr_context = &mut engine.xr_context;
+
+    composition_layers_system_inner(world, vulkan_context, render_context, xr_context);
+}
+
+fn composition_layers_system_inner(
+    world: &World,
+    vulkan_context: &VulkanContext,
+    render_context: &RenderContext,
+    xr_context: &mut XrContext,
+) {
+    if !xr_context.frame_state.should_render {
+        return;
+    }
+
+    let command_buffer = render_context.frames[render_context.frame_index].command_buffer;
+    let stage_from_global = get_global_from_stage(world).inverse();
+
+    for (_, (layer, panel, global_transform)) in world
+        .query::<(&CompositionLayer, &Panel, &GlobalTransform)>()
+        .iter()
+    {
+        let image_index = match xr_context.acquire_layer_image(layer.swapchain_index) {
+            Ok(index) => index,
+            Err(e) => {
+                println!("[HOTHAM_XR] Unable to acquire composition layer image: {e:?}");
+                continue;
+            }
+        };
+
+        unsafe {
+            copy_panel_to_layer(
+                vulkan_context,
+                command_buffer,
+                panel,
+                layer.swapchain_images[image_index],
+            );
+        }
+
+        let shape = if xr_context.supports_layer_shape(&layer.shape) {
+            layer.shape
+        } else {
+            LayerShape::Quad
+        };
+
+        xr_context.layer_submissions.push(LayerSubmission {
+            swapchain_index: layer.swapchain_index,
+            pose: layer_pose(&shape, stage_from_global * global_transform.0),
+            shape,
+            placement: layer.placement(),
+            sort_order: layer.sort_order,
+            world_size: panel.world_size,
+            resolution: panel.resolution,
+        });
+    }
+}
+
+/// The pose of a layer in stage space. Cylinders are positioned by their axis, so move it in front
+/// of the panel.
+fn layer_pose(shape: &LayerShape, stage_from_local: Affine3A) -> openxr::Posef {
+    let (_, rotation, translation) = stage_from_local.to_scale_rotation_translation();
+    let stage_from_layer = Affine3A::from_rotation_translation(rotation, translation);
+    match shape {
+        LayerShape::Cylinder { radius } => {
+            posef_from_affine(stage_from_layer * Affine3A::from_translation(Vec3::Z * *radius))
+        }
+        _ => posef_from_affine(stage_from_layer),
+    }
+}
+
+/// Record a copy of the panel's texture into a layer swapchain image, leaving the texture ready to
+/// be drawn or sampled again and the swapchain image ready to be released.
+unsafe fn copy_panel_to_layer(
+    vulkan_context: &VulkanContext,
+    command_buffer: vk::CommandBuffer,
+    panel: &Panel,
+    swapchain_image: vk::Image,
+) {
+    let device = &vulkan_context.device;
+    let panel_image = panel.texture.image.handle;
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let barrier = |image, old_layout, new_layout, src_access_mask, dst_access_mask| {
+        vk::ImageMemoryBarrier::builder()
+            .image(image)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(subresource_range)
+            .build()
+    };
+
+    // The panel was last drawn by the GUI render pass, or sampled by the scene. The previous
+    // contents of the swapchain image don't matter.
+    let before_copy = [
+        barrier(
+            panel_image,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        ),
+        barrier(
+            swapchain_image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+        ),
+    ];
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &before_copy,
+    );
+
+    let subresource = vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let region = vk::ImageCopy {
+        src_subresource: subresource,
+        src_offset: vk::Offset3D::default(),
+        dst_subresource: subresource,
+        dst_offset: vk::Offset3D::default(),
+        extent: vk::Extent3D {
+            width: panel.resolution.width,
+            height: panel.resolution.height,
+            depth: 1,
+        },
+    };
+    device.cmd_copy_image(
+        command_buffer,
+        panel_image,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        swapchain_image,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        &[region],
+    );
+
+    // OpenXR expects swapchain images to be released in COLOR_ATTACHMENT_OPTIMAL.
+    let after_copy = [
+        barrier(
+            panel_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::SHADER_READ,
+        ),
+        barrier(
+            swapchain_image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::empty(),
+        ),
+    ];
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &after_copy,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use glam::Quat;
+
+    #[test]
+    fn test_cylinder_pose_is_in_front_of_panel() {
+        // A panel 2m in front of the stage origin, facing the origin.
+        let stage_from_local = Affine3A::from_scale_rotation_translation(
+            Vec3::splat(2.),
+            Quat::IDENTITY,
+            Vec3::new(0., 1., -2.),
+        );
+
+        let pose = layer_pose(&LayerShape::Quad, stage_from_local);
+        assert_relative_eq!(pose.position.z, -2.);
+
+        // The cylinder's axis is where the player is, and scale doesn't move it.
+        let pose = layer_pose(&LayerShape::Cylinder { radius: 2. }, stage_from_local);
+        assert_relative_eq!(pose.position.y, 1.);
+        assert_relative_eq!(pose.position.z, 0.);
+    }
+}
@@ -290,7 +290,8 @@ mod tests {
         };
 
         let mut render_context =
-            RenderContext::new_from_swapchain_info(&vulkan_context, &swapchain).unwrap();
+            RenderContext::new_from_swapchain_info(&vulkan_context, &swapchain, Default::default())
+                .unwrap();
         let gui_context = GuiContext::new(&vulkan_context);
 
         let gltf_data: Vec<&[u8]> = vec![include_bytes!(
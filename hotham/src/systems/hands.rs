@@ -2,12 +2,13 @@ use crate::{
     asset_importer::add_model_to_world,
     components::{
         global_transform::GlobalTransform, hand::Handedness, local_transform::LocalTransform,
-        stage, AnimationController, Collider, Grabbed, Hand,
+        stage, AnimationController, Collider, Grabbed, Hand, HandJoints, Parent,
     },
     contexts::{physics_context::HAND_COLLISION_GROUP, InputContext},
-    Engine,
+    xr, Engine,
 };
-use hecs::World;
+use glam::Affine3A;
+use hecs::{Entity, World};
 use rapier3d::prelude::{ActiveCollisionTypes, SharedShape};
 
 /// Hands system
@@ -22,27 +23,51 @@ pub fn hands_system_inner(world: &mut World, input_context: &InputContext) {
     // Get the position
     let global_from_stage = stage::get_global_from_stage(world);
 
-    for (_, (hand, animation_controller, local_transform, global_transform)) in world
-        .query::<(
-            &mut Hand,
-            &mut AnimationController,
-            &mut LocalTransform,
-            &mut GlobalTransform,
-        )>()
-        .iter()
+    // Hands that are being driven by hand tracking, rather than by a controller.
+    let mut tracked_hands = Vec::new();
+
+    for (entity, (hand, animation_controller, local_transform, global_transform, hand_joints)) in
+        world
+            .query::<(
+                &mut Hand,
+                &mut AnimationController,
+                &mut LocalTransform,
+                &mut GlobalTransform,
+                Option<&mut HandJoints>,
+            )>()
+            .iter()
     {
         // Get the position of the hand in stage space.
-        let (stage_from_grip, grip_value) = match hand.handedness {
+        let (stage_from_grip, grip_value, hand_tracking) = match hand.handedness {
             Handedness::Left => (
                 input_context.left.stage_from_grip(),
                 input_context.left.grip_analog(),
+                input_context.left.hand_tracking(),
             ),
             Handedness::Right => (
                 input_context.right.stage_from_grip(),
                 input_context.right.grip_analog(),
+                input_context.right.hand_tracking(),
             ),
         };
 
+        // If the hand is tracked and we know where its joints are, follow the palm.
+        let is_tracked = hand_tracking.is_tracked() && hand_joints.is_some();
+        let stage_from_grip = if is_tracked {
+            hand_tracking.stage_from_joint(xr::HandJoint::PALM)
+        } else {
+            stage_from_grip
+        };
+        if let Some(hand_joints) = hand_joints {
+            hand_joints.is_tracked = is_tracked;
+        }
+        if is_tracked {
+            tracked_hands.push((entity, hand.handedness));
+        }
+
+        // A tracked hand "grips" by making a fist.
+        let grip_value = grip_value.max(hand_tracking.grab_strength());
+
         // Get global transform
         let global_from_local = global_from_stage * stage_from_grip;
 
@@ -72,6 +97,63 @@ pub fn hands_system_inner(world: &mut World, input_context: &InputContext) {
         // Apply to AnimationController
         animation_controller.blend_amount = grip_value;
     }
+
+    // Now pose the joints of any tracked hands.
+    for (hand_entity, handedness) in tracked_hands {
+        let hand_tracking = match handedness {
+            Handedness::Left => input_context.left.hand_tracking(),
+            Handedness::Right => input_context.right.hand_tracking(),
+        };
+        let joints = world.get::<&HandJoints>(hand_entity).unwrap().joints;
+
+        // The joints are in XR joint order, where eg. the palm comes before its parent, the
+        // wrist. Pose parents first, so their children are posed relative to where they are now.
+        let mut joints = joints
+            .iter()
+            .zip(hand_tracking.stage_from_joints().iter())
+            .filter_map(|(joint_entity, stage_from_joint)| {
+                Some(((*joint_entity)?, stage_from_joint))
+            })
+            .collect::<Vec<_>>();
+        joints.sort_by_cached_key(|(joint_entity, _)| hierarchy_depth(world, *joint_entity));
+
+        for (joint_entity, stage_from_joint) in joints {
+            // Joints are posed in global space, so we need to find out where their parents are.
+            let global_from_joint = global_from_stage * *stage_from_joint;
+            let global_from_parent = match world.get::<&Parent>(joint_entity) {
+                Ok(parent) => get_global_from_entity(world, parent.0),
+                Err(_) => Affine3A::IDENTITY,
+            };
+
+            let mut local_transform = world.get::<&mut LocalTransform>(joint_entity).unwrap();
+            local_transform.update_rotation_translation_from_affine(
+                &(global_from_parent.inverse() * global_from_joint),
+            );
+        }
+    }
+}
+
+/// Walk up the hierarchy to find the *current* transform of an entity in global space.
+/// We can't use `GlobalTransform` here as it is only updated by
+/// `update_global_transform_with_parent_system`.
+fn get_global_from_entity(world: &World, entity: Entity) -> Affine3A {
+    let local_from_entity = world
+        .get::<&LocalTransform>(entity)
+        .map(|l| l.to_affine())
+        .unwrap_or(Affine3A::IDENTITY);
+
+    match world.get::<&Parent>(entity) {
+        Ok(parent) => get_global_from_entity(world, parent.0) * local_from_entity,
+        Err(_) => local_from_entity,
+    }
+}
+
+/// How many ancestors an entity has.
+fn hierarchy_depth(world: &World, entity: Entity) -> usize {
+    match world.get::<&Parent>(entity) {
+        Ok(parent) => hierarchy_depth(world, parent.0) + 1,
+        Err(_) => 0,
+    }
 }
 
 /// Convenience function to add a Hand, Collider and corresponding Mesh to the world
@@ -107,6 +189,11 @@ pub fn add_hand(
     world
         .insert(hand_entity, (collider, hand_component))
         .unwrap();
+
+    // If the model has a skeleton we recognise, allow it to be driven by hand tracking.
+    if let Some(hand_joints) = HandJoints::from_model(world, hand_entity) {
+        world.insert_one(hand_entity, hand_joints).unwrap();
+    }
 }
 
 #[cfg(test)]
@@ -116,7 +203,10 @@ mod tests {
     use glam::Vec3;
     use hecs::Entity;
 
-    use crate::components::{LocalTransform, RigidBody};
+    use crate::{
+        components::{LocalTransform, RigidBody},
+        contexts::input_context::HAND_JOINT_COUNT,
+    };
 
     #[test]
     pub fn test_hands_system() {
@@ -178,6 +268,62 @@ mod tests {
         assert_relative_eq!(local_transform.rotation, Default::default());
     }
 
+    #[test]
+    pub fn test_hand_tracking() {
+        let (mut world, mut input_context) = setup();
+        let hand = add_hand_to_world(&mut world, None);
+
+        // Give the hand a wrist joint
+        let wrist = world.spawn((
+            Parent(hand),
+            LocalTransform::default(),
+            GlobalTransform::default(),
+        ));
+        // ..and a palm, which comes before its parent in XR joint order.
+        let palm = world.spawn((
+            Parent(wrist),
+            LocalTransform::default(),
+            GlobalTransform::default(),
+        ));
+        let mut hand_joints = HandJoints::default();
+        hand_joints.joints[xr::HandJoint::WRIST.into_raw() as usize] = Some(wrist);
+        hand_joints.joints[xr::HandJoint::PALM.into_raw() as usize] = Some(palm);
+        world.insert_one(hand, hand_joints).unwrap();
+
+        // Make a fist, with the whole hand at (1, 1, 1).
+        let mut stage_from_joints = [Affine3A::from_translation(Vec3::ONE); HAND_JOINT_COUNT];
+        stage_from_joints[xr::HandJoint::WRIST.into_raw() as usize] =
+            Affine3A::from_translation([1., 1., 1.1].into());
+        input_context
+            .left
+            .hand_tracking_mut()
+            .set_stage_from_joints(stage_from_joints);
+
+        tick(&mut world, &input_context);
+
+        let hand_component = world.get::<&Hand>(hand).unwrap();
+        assert_relative_eq!(hand_component.grip_value, 1.0);
+        assert!(world.get::<&HandJoints>(hand).unwrap().is_tracked);
+        assert_relative_eq!(
+            world.get::<&LocalTransform>(hand).unwrap().translation,
+            Vec3::ONE
+        );
+
+        // The wrist is posed relative to its parent, the hand.
+        assert_relative_eq!(
+            world.get::<&LocalTransform>(wrist).unwrap().translation,
+            [0., 0., 0.1].into(),
+            epsilon = 0.0001
+        );
+
+        // The palm is posed relative to where the wrist is this frame, not where it was.
+        assert_relative_eq!(
+            world.get::<&LocalTransform>(palm).unwrap().translation,
+            [0., 0., -0.1].into(),
+            epsilon = 0.0001
+        );
+    }
+
     // HELPER FUNCTIONS
     fn setup() -> (World, InputContext) {
         let world = World::new();
@@ -1,16 +1,34 @@
-use openxr::{Duration, HapticVibration};
+use openxr::{self as xr, Action, Duration, Haptic, HapticVibration};
 
 use crate::{
-    contexts::{HapticContext, XrContext},
+    components::hand::Handedness,
+    contexts::{haptic_context::HapticCommand, HapticContext, XrContext},
     Engine,
 };
-static HAPTIC_FREQUENCY: f32 = 400.;
-static HAPTIC_DURATION: i64 = 1e+8 as _; // 100ms
+
+/// The frame period used before OpenXR has told us the real one.
+const DEFAULT_FRAME_PERIOD: f32 = 1. / 72.;
+
+/// `XR_TYPE_HAPTIC_PCM_VIBRATION_FB`
+const TYPE_HAPTIC_PCM_VIBRATION_FB: i32 = 1000209001;
+
+/// `XrHapticPcmVibrationFB`, from `XR_FB_haptic_pcm`.
+#[repr(C)]
+struct HapticPcmVibrationFB {
+    ty: xr::sys::StructureType,
+    next: *const std::ffi::c_void,
+    buffer_size: u32,
+    buffer: *const f32,
+    sample_rate: f32,
+    append: xr::sys::Bool32,
+    samples_consumed: *mut u32,
+}
 
 /// Triggers the application of vibrations to the appropriate user input device at prescribed amplitude, frequency, and duration given a Hotham::resources::XrContent and Hotham::resources::HapticContext.
 ///
-/// During each tick of the Hotham engine, haptic feedback is applied to generate a HapticVibration
-/// event which propagates to the appropriate user input device.
+/// During each tick of the Hotham engine, the effects playing in the `HapticContext` are sampled
+/// and propagated to the appropriate user input device as `HapticVibration` events, or as PCM
+/// samples if the runtime supports `XR_FB_haptic_pcm`.
 ///
 /// Basic usage:
 /// ```ignore
@@ -23,36 +41,104 @@ pub fn haptics_system(engine: &mut Engine) {
 }
 
 fn haptics_system_inner(xr_context: &mut XrContext, haptic_context: &mut HapticContext) {
-    let input = &xr_context.input;
+    let frame_period = xr_context.frame_state.predicted_display_period.as_nanos() as f32 / 1e9;
+    let delta_time = if frame_period > 0. {
+        frame_period
+    } else {
+        DEFAULT_FRAME_PERIOD
+    };
 
-    let haptic_duration = Duration::from_nanos(HAPTIC_DURATION);
-    if haptic_context.left_hand_amplitude_this_frame != 0. {
-        let event = HapticVibration::new()
-            .amplitude(haptic_context.left_hand_amplitude_this_frame)
-            .frequency(HAPTIC_FREQUENCY)
-            .duration(haptic_duration);
+    let commands = haptic_context.tick(delta_time, xr_context.haptic_pcm_supported);
 
-        input
-            .haptic_feedback_action
-            .apply_feedback(&xr_context.session, input.left_hand_subaction_path, &event)
-            .expect("Unable to apply haptic feedback!");
+    let input = &xr_context.input;
+    let haptic_feedback_action = match input.haptic_action("haptic_feedback") {
+        Some(action) => action,
+        // The application's action map has no haptic action, so just drop the request.
+        None => return,
+    };
 
-        // Reset the value
-        haptic_context.left_hand_amplitude_this_frame = 0.;
-    }
+    for (handedness, commands) in [Handedness::Left, Handedness::Right]
+        .into_iter()
+        .zip(commands)
+    {
+        let subaction_path = input.subaction_path(handedness);
+        for command in commands {
+            match command {
+                HapticCommand::Vibrate {
+                    amplitude,
+                    frequency,
+                    duration,
+                } => {
+                    let event = HapticVibration::new()
+                        .amplitude(amplitude)
+                        .frequency(frequency)
+                        .duration(Duration::from_nanos((duration * 1e9) as _));
 
-    if haptic_context.right_hand_amplitude_this_frame != 0. {
-        let event = HapticVibration::new()
-            .amplitude(haptic_context.right_hand_amplitude_this_frame)
-            .frequency(HAPTIC_FREQUENCY)
-            .duration(haptic_duration);
+                    haptic_feedback_action
+                        .apply_feedback(&xr_context.session, subaction_path, &event)
+                        .expect("Unable to apply haptic feedback!");
+                }
+                HapticCommand::Pcm {
+                    handle,
+                    samples,
+                    sample_rate,
+                    append,
+                } => {
+                    let samples_consumed = apply_pcm_feedback(
+                        xr_context,
+                        haptic_feedback_action,
+                        subaction_path,
+                        &samples,
+                        sample_rate,
+                        append,
+                    );
+                    haptic_context.pcm_samples_consumed(handle, samples_consumed);
+                }
+                HapticCommand::Stop => {
+                    haptic_feedback_action
+                        .stop_feedback(&xr_context.session, subaction_path)
+                        .expect("Unable to stop haptic feedback!");
+                }
+            }
+        }
+    }
+}
 
-        input
-            .haptic_feedback_action
-            .apply_feedback(&xr_context.session, input.right_hand_subaction_path, &event)
-            .expect("Unable to apply haptic feedback!");
+fn apply_pcm_feedback(
+    xr_context: &XrContext,
+    action: &Action<Haptic>,
+    subaction_path: xr::Path,
+    samples: &[f32],
+    sample_rate: f32,
+    append: bool,
+) -> usize {
+    let mut samples_consumed = 0;
+    let pcm = HapticPcmVibrationFB {
+        ty: xr::sys::StructureType::from_raw(TYPE_HAPTIC_PCM_VIBRATION_FB),
+        next: std::ptr::null(),
+        buffer_size: samples.len() as _,
+        buffer: samples.as_ptr(),
+        sample_rate,
+        append: append.into(),
+        samples_consumed: &mut samples_consumed,
+    };
+    let info = xr::sys::HapticActionInfo {
+        ty: xr::sys::HapticActionInfo::TYPE,
+        next: std::ptr::null(),
+        action: action.as_raw(),
+        subaction_path,
+    };
 
-        // Reset the value
-        haptic_context.right_hand_amplitude_this_frame = 0.;
+    let result = unsafe {
+        (xr_context.instance.fp().apply_haptic_feedback)(
+            xr_context.session.as_raw(),
+            &info,
+            &pcm as *const _ as *const xr::sys::HapticBaseHeader,
+        )
+    };
+    if result.into_raw() < 0 {
+        println!("[HOTHAM_HAPTICS] Unable to apply PCM haptic feedback: {result:?}");
     }
+
+    samples_consumed as _
 }
@@ -0,0 +1,565 @@
+use glam::{Affine3A, Quat, Vec2, Vec3};
+use hecs::{Entity, World};
+use rapier3d::prelude::{InteractionGroups, QueryFilter, Ray};
+
+use crate::{
+    components::{hand::Handedness, LocalTransform},
+    contexts::{
+        locomotion_context::{MovementDirection, TeleportState, TurnMode},
+        physics_context::{DELTA_TIME, WALL_COLLISION_GROUP},
+        InputContext, LocomotionContext, PhysicsContext,
+    },
+    util::{glam_vec_from_na, na_vector_from_glam},
+    Engine,
+};
+
+/// The time between each point on the teleport arc, in seconds.
+const TELEPORT_ARC_TIME_STEP: f32 = 0.05;
+/// The maximum number of segments in the teleport arc.
+const TELEPORT_ARC_MAX_SEGMENTS: usize = 60;
+const GRAVITY: Vec3 = Vec3::new(0., -9.81, 0.);
+
+/// Locomotion system
+/// Moves the player around the world by moving the `Stage`, as configured by the
+/// `LocomotionContext`:
+///
+/// - smooth movement relative to the head or hand, which won't pass through wall colliders
+/// - snap or smooth turning, pivoting around the player's head
+/// - teleporting along a parabolic arc, with a fade out and in
+pub fn locomotion_system(engine: &mut Engine) {
+    let world = &mut engine.world;
+    let input_context = &engine.input_context;
+    let physics_context = &engine.physics_context;
+    let locomotion_context = &mut engine.locomotion_context;
+    let stage_entity = engine.stage_entity;
+
+    locomotion_system_inner(
+        world,
+        input_context,
+        physics_context,
+        locomotion_context,
+        stage_entity,
+        DELTA_TIME,
+    );
+}
+
+pub fn locomotion_system_inner(
+    world: &mut World,
+    input_context: &InputContext,
+    physics_context: &PhysicsContext,
+    locomotion_context: &mut LocomotionContext,
+    stage_entity: Entity,
+    delta_time: f32,
+) {
+    let mut stage_transform = match world.get::<&mut LocalTransform>(stage_entity) {
+        Ok(stage_transform) => stage_transform,
+        Err(_) => return,
+    };
+
+    // The stage is the root of the hierarchy, so its local transform is its global transform.
+    let mut global_from_stage = stage_transform.to_affine();
+    let hmd_in_stage = input_context.hmd.hmd_in_stage();
+
+    update_teleport(
+        input_context,
+        physics_context,
+        locomotion_context,
+        &mut global_from_stage,
+        hmd_in_stage,
+        delta_time,
+    );
+
+    // Don't let the player move while they're teleporting.
+    if !locomotion_context.is_teleporting() {
+        update_turn(
+            input_context,
+            locomotion_context,
+            &mut global_from_stage,
+            hmd_in_stage,
+            delta_time,
+        );
+        update_movement(
+            input_context,
+            physics_context,
+            locomotion_context,
+            &mut global_from_stage,
+            hmd_in_stage,
+            delta_time,
+        );
+    }
+
+    stage_transform.update_from_affine(&global_from_stage);
+}
+
+fn thumbstick(input_context: &InputContext, handedness: Handedness) -> Vec2 {
+    match handedness {
+        Handedness::Left => input_context.left.thumbstick_xy(),
+        Handedness::Right => input_context.right.thumbstick_xy(),
+    }
+}
+
+fn stage_from_aim(input_context: &InputContext, handedness: Handedness) -> Affine3A {
+    match handedness {
+        Handedness::Left => input_context.left.stage_from_aim(),
+        Handedness::Right => input_context.right.stage_from_aim(),
+    }
+}
+
+fn update_movement(
+    input_context: &InputContext,
+    physics_context: &PhysicsContext,
+    locomotion_context: &LocomotionContext,
+    global_from_stage: &mut Affine3A,
+    hmd_in_stage: Affine3A,
+    delta_time: f32,
+) {
+    let settings = &locomotion_context.settings;
+    if !settings.smooth_movement {
+        return;
+    }
+
+    let stick = thumbstick(input_context, settings.movement_hand);
+    if stick.length() < settings.thumbstick_deadzone {
+        return;
+    }
+
+    // Work out which way is forward, flattened onto the floor.
+    let global_from_reference = *global_from_stage
+        * match settings.movement_direction {
+            MovementDirection::Head => hmd_in_stage,
+            MovementDirection::Hand => stage_from_aim(input_context, settings.movement_hand),
+        };
+    let forward = flatten(global_from_reference.transform_vector3(Vec3::NEG_Z));
+    let right = flatten(global_from_reference.transform_vector3(Vec3::X));
+    let movement = (right * stick.x + forward * stick.y) * settings.movement_speed * delta_time;
+
+    let hmd_in_global = global_from_stage.transform_point3(hmd_in_stage.translation.into());
+    let movement = constrain_movement(
+        physics_context,
+        hmd_in_global,
+        movement,
+        settings.player_radius,
+    );
+    global_from_stage.translation += glam::Vec3A::from(movement);
+}
+
+/// Stop `movement` from taking the player within `radius` of a wall, sliding along the wall if
+/// possible.
+fn constrain_movement(
+    physics_context: &PhysicsContext,
+    origin: Vec3,
+    movement: Vec3,
+    radius: f32,
+) -> Vec3 {
+    let wall_filter = QueryFilter::new().groups(InteractionGroups::new(
+        WALL_COLLISION_GROUP,
+        WALL_COLLISION_GROUP,
+    ));
+    let cast = |origin: Vec3, movement: Vec3| {
+        let distance = movement.length();
+        if distance <= f32::EPSILON {
+            return None;
+        }
+        let direction = movement / distance;
+        let ray = Ray::new(
+            na_vector_from_glam(origin).into(),
+            na_vector_from_glam(direction),
+        );
+        physics_context
+            .query_pipeline
+            .cast_ray_and_get_normal(
+                &physics_context.rigid_bodies,
+                &physics_context.colliders,
+                &ray,
+                distance + radius,
+                true,
+                wall_filter,
+            )
+            .map(|(_, intersection)| {
+                let allowed = (intersection.toi - radius).max(0.);
+                let normal = flatten(glam_vec_from_na(&intersection.normal));
+                (direction * allowed, normal)
+            })
+    };
+
+    let (allowed, normal) = match cast(origin, movement) {
+        Some(hit) => hit,
+        None => return movement,
+    };
+
+    // Slide along the wall with whatever movement is left over.
+    let remaining = movement - allowed;
+    let slide = remaining - normal * remaining.dot(normal);
+    match cast(origin + allowed, slide) {
+        Some((slide_allowed, _)) => allowed + slide_allowed,
+        None => allowed + slide,
+    }
+}
+
+fn update_turn(
+    input_context: &InputContext,
+    locomotion_context: &mut LocomotionContext,
+    global_from_stage: &mut Affine3A,
+    hmd_in_stage: Affine3A,
+    delta_time: f32,
+) {
+    let settings = &locomotion_context.settings;
+
+    // The turn hand's thumbstick is busy aiming.
+    if locomotion_context.is_aiming_teleport() {
+        return;
+    }
+
+    let stick_x = thumbstick(input_context, settings.turn_hand).x;
+    let angle = match settings.turn_mode {
+        TurnMode::Off => return,
+        TurnMode::Snap { angle } => {
+            if stick_x.abs() < settings.thumbstick_deadzone {
+                locomotion_context.snap_turn_ready = true;
+                return;
+            }
+            if !locomotion_context.snap_turn_ready || stick_x.abs() < settings.thumbstick_threshold
+            {
+                return;
+            }
+            locomotion_context.snap_turn_ready = false;
+            -angle * stick_x.signum()
+        }
+        TurnMode::Smooth { speed } => {
+            if stick_x.abs() < settings.thumbstick_deadzone {
+                return;
+            }
+            -speed * stick_x * delta_time
+        }
+    };
+
+    *global_from_stage = turn_around_hmd(*global_from_stage, hmd_in_stage, angle);
+}
+
+/// Rotate the stage around the Y axis, keeping the player's head in the same place.
+fn turn_around_hmd(global_from_stage: Affine3A, hmd_in_stage: Affine3A, angle: f32) -> Affine3A {
+    let pivot = global_from_stage.transform_point3(hmd_in_stage.translation.into());
+    Affine3A::from_translation(pivot)
+        * Affine3A::from_quat(Quat::from_rotation_y(angle))
+        * Affine3A::from_translation(-pivot)
+        * global_from_stage
+}
+
+fn update_teleport(
+    input_context: &InputContext,
+    physics_context: &PhysicsContext,
+    locomotion_context: &mut LocomotionContext,
+    global_from_stage: &mut Affine3A,
+    hmd_in_stage: Affine3A,
+    delta_time: f32,
+) {
+    let settings = locomotion_context.settings.clone();
+    let stick = thumbstick(input_context, settings.turn_hand);
+
+    locomotion_context.teleport_state = match locomotion_context.teleport_state {
+        TeleportState::Idle if settings.teleport && stick.y > settings.thumbstick_threshold => {
+            TeleportState::Aiming { target: None }
+        }
+        TeleportState::Idle => TeleportState::Idle,
+        TeleportState::Aiming { target } if stick.length() < settings.thumbstick_deadzone => {
+            locomotion_context.teleport_arc.clear();
+            match target {
+                Some(target) => TeleportState::FadingOut {
+                    target,
+                    elapsed: 0.,
+                },
+                None => TeleportState::Idle,
+            }
+        }
+        TeleportState::Aiming { .. } => TeleportState::Aiming {
+            target: None, // updated below
+        },
+        TeleportState::FadingOut { target, elapsed } => {
+            let elapsed = elapsed + delta_time;
+            if elapsed >= settings.teleport_fade_duration {
+                *global_from_stage = teleport_to(*global_from_stage, hmd_in_stage, target);
+                TeleportState::FadingIn { elapsed: 0. }
+            } else {
+                TeleportState::FadingOut { target, elapsed }
+            }
+        }
+        TeleportState::FadingIn { elapsed } => {
+            let elapsed = elapsed + delta_time;
+            if elapsed >= settings.teleport_fade_duration {
+                TeleportState::Idle
+            } else {
+                TeleportState::FadingIn { elapsed }
+            }
+        }
+    };
+
+    if let TeleportState::Aiming { target } = &mut locomotion_context.teleport_state {
+        let global_from_aim =
+            *global_from_stage * stage_from_aim(input_context, settings.turn_hand);
+        let origin: Vec3 = global_from_aim.translation.into();
+        let velocity = global_from_aim
+            .transform_vector3(Vec3::NEG_Z)
+            .normalize_or_zero()
+            * settings.teleport_arc_speed;
+
+        let (arc, hit) = cast_teleport_arc(
+            physics_context,
+            origin,
+            velocity,
+            settings.teleport_collision_groups,
+        );
+        locomotion_context.teleport_arc = arc;
+        *target = hit
+            .filter(|(_, normal)| normal.y >= settings.teleport_max_slope.cos())
+            .map(|(point, _)| point);
+    }
+}
+
+/// Move the stage so that the player's head is directly above `target`, and the floor is at the
+/// same height as `target`.
+fn teleport_to(global_from_stage: Affine3A, hmd_in_stage: Affine3A, target: Vec3) -> Affine3A {
+    let hmd_on_floor = Vec3::new(hmd_in_stage.translation.x, 0., hmd_in_stage.translation.z);
+    let mut global_from_stage = global_from_stage;
+    global_from_stage.translation =
+        (target - global_from_stage.transform_vector3(hmd_on_floor)).into();
+    global_from_stage
+}
+
+/// Trace a parabola from `origin`, returning the points along it and where it hit, if anywhere.
+fn cast_teleport_arc(
+    physics_context: &PhysicsContext,
+    origin: Vec3,
+    velocity: Vec3,
+    collision_groups: u32,
+) -> (Vec<Vec3>, Option<(Vec3, Vec3)>) {
+    let filter =
+        QueryFilter::new().groups(InteractionGroups::new(collision_groups, collision_groups));
+    let mut points = vec![origin];
+
+    for segment in 1..=TELEPORT_ARC_MAX_SEGMENTS {
+        let t = segment as f32 * TELEPORT_ARC_TIME_STEP;
+        let start = *points.last().unwrap();
+        let end = origin + velocity * t + 0.5 * GRAVITY * t * t;
+        let distance = start.distance(end);
+        let ray = Ray::new(
+            na_vector_from_glam(start).into(),
+            na_vector_from_glam((end - start) / distance),
+        );
+
+        if let Some((_, intersection)) = physics_context.query_pipeline.cast_ray_and_get_normal(
+            &physics_context.rigid_bodies,
+            &physics_context.colliders,
+            &ray,
+            distance,
+            true,
+            filter,
+        ) {
+            let hit_point = start + (end - start) / distance * intersection.toi;
+            points.push(hit_point);
+            return (
+                points,
+                Some((hit_point, glam_vec_from_na(&intersection.normal))),
+            );
+        }
+
+        points.push(end);
+    }
+
+    (points, None)
+}
+
+fn flatten(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, 0., v.z).normalize_or_zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::{GlobalTransform, Stage},
+        contexts::{
+            input_recording::InputSnapshot, locomotion_context::LocomotionSettings,
+            physics_context::DEFAULT_COLLISION_GROUP,
+        },
+    };
+    use approx::assert_relative_eq;
+    use rapier3d::prelude::ColliderBuilder;
+
+    struct Setup {
+        world: World,
+        input_context: InputContext,
+        physics_context: PhysicsContext,
+        locomotion_context: LocomotionContext,
+        stage_entity: Entity,
+        snapshot: InputSnapshot,
+    }
+
+    impl Setup {
+        fn new(settings: LocomotionSettings) -> Self {
+            let mut world = World::new();
+            let stage_entity =
+                world.spawn((Stage, LocalTransform::default(), GlobalTransform::default()));
+
+            // A floor, and a wall one metre in front of the player.
+            let mut physics_context = PhysicsContext::default();
+            physics_context.colliders.insert(
+                ColliderBuilder::cuboid(10., 0.1, 10.)
+                    .translation([0., -0.1, 0.].into())
+                    .collision_groups(InteractionGroups::new(DEFAULT_COLLISION_GROUP, u32::MAX))
+                    .build(),
+            );
+            physics_context.colliders.insert(
+                ColliderBuilder::cuboid(10., 10., 0.1)
+                    .translation([0., 0., -1.1].into())
+                    .collision_groups(InteractionGroups::new(WALL_COLLISION_GROUP, u32::MAX))
+                    .build(),
+            );
+            physics_context.query_pipeline.update(
+                &physics_context.island_manager,
+                &physics_context.rigid_bodies,
+                &physics_context.colliders,
+            );
+
+            // The player is standing at the origin, looking down -Z.
+            let mut snapshot = InputSnapshot::default();
+            let hmd_in_stage = Affine3A::from_translation([0., 1.6, 0.].into());
+            snapshot.left_eye_in_stage = hmd_in_stage;
+            snapshot.right_eye_in_stage = hmd_in_stage;
+
+            Setup {
+                world,
+                input_context: InputContext::default(),
+                physics_context,
+                locomotion_context: LocomotionContext::new(settings),
+                stage_entity,
+                snapshot,
+            }
+        }
+
+        fn tick(&mut self) {
+            self.input_context.apply_snapshot(&self.snapshot);
+            locomotion_system_inner(
+                &mut self.world,
+                &self.input_context,
+                &self.physics_context,
+                &mut self.locomotion_context,
+                self.stage_entity,
+                DELTA_TIME,
+            );
+        }
+
+        fn global_from_stage(&self) -> Affine3A {
+            self.world
+                .get::<&LocalTransform>(self.stage_entity)
+                .unwrap()
+                .to_affine()
+        }
+    }
+
+    #[test]
+    pub fn test_smooth_movement_stops_at_walls() {
+        let mut setup = Setup::new(LocomotionSettings {
+            movement_speed: 72.,
+            ..Default::default()
+        });
+
+        // Move right - one metre per tick.
+        setup.snapshot.left.thumbstick_xy = Vec2::new(1., 0.);
+        setup.tick();
+        assert_relative_eq!(setup.global_from_stage().translation.x, 1., epsilon = 0.001);
+
+        // Move forward into the wall - we stop short of it.
+        setup.snapshot.left.thumbstick_xy = Vec2::new(0., 1.);
+        setup.tick();
+        let translation = setup.global_from_stage().translation;
+        assert_relative_eq!(translation.z, -0.8, epsilon = 0.001);
+        assert_relative_eq!(translation.x, 1., epsilon = 0.001);
+    }
+
+    #[test]
+    pub fn test_snap_turn() {
+        let mut setup = Setup::new(Default::default());
+        setup.snapshot.left_eye_in_stage = Affine3A::from_translation([1., 1.6, 0.].into());
+        setup.snapshot.right_eye_in_stage = setup.snapshot.left_eye_in_stage;
+
+        // Push the stick right: we turn once, around the HMD.
+        setup.snapshot.right.thumbstick_xy = Vec2::new(1., 0.);
+        setup.tick();
+        setup.tick();
+        let global_from_stage = setup.global_from_stage();
+        let (_, rotation, _) = global_from_stage.to_scale_rotation_translation();
+        assert_relative_eq!(rotation, Quat::from_rotation_y(-30_f32.to_radians()));
+        let hmd_in_global = global_from_stage.transform_point3([1., 1.6, 0.].into());
+        assert_relative_eq!(hmd_in_global, Vec3::new(1., 1.6, 0.), epsilon = 0.001);
+
+        // Let go and push again to turn again.
+        setup.snapshot.right.thumbstick_xy = Vec2::ZERO;
+        setup.tick();
+        setup.snapshot.right.thumbstick_xy = Vec2::new(1., 0.);
+        setup.tick();
+        let (_, rotation, _) = setup.global_from_stage().to_scale_rotation_translation();
+        assert_relative_eq!(rotation, Quat::from_rotation_y(-60_f32.to_radians()));
+    }
+
+    #[test]
+    pub fn test_teleport() {
+        let mut setup = Setup::new(Default::default());
+
+        // Aim down at the floor in front of us.
+        let aim_position = Vec3::new(0.5, 1., 0.);
+        setup.snapshot.right.stage_from_aim = Affine3A::from_rotation_translation(
+            Quat::from_rotation_x(-60_f32.to_radians()),
+            aim_position,
+        );
+        setup.snapshot.right.thumbstick_xy = Vec2::new(0., 1.);
+        setup.tick();
+        setup.tick();
+        assert!(setup.locomotion_context.is_aiming_teleport());
+        let target = setup.locomotion_context.teleport_target().unwrap();
+        assert_relative_eq!(target.y, 0., epsilon = 0.001);
+        assert!(target.z < 0. && target.z > -1.);
+        assert!(setup.locomotion_context.teleport_arc().len() > 2);
+
+        // Let go, and fade out.
+        setup.snapshot.right.thumbstick_xy = Vec2::ZERO;
+        setup.tick();
+        assert!(setup.locomotion_context.is_teleporting());
+        while setup.locomotion_context.fade() < 1. {
+            assert_eq!(setup.global_from_stage(), Affine3A::IDENTITY);
+            setup.tick();
+        }
+
+        // We've arrived. Fade back in.
+        let hmd_in_global = setup
+            .global_from_stage()
+            .transform_point3([0., 1.6, 0.].into());
+        assert_relative_eq!(
+            hmd_in_global,
+            target + Vec3::new(0., 1.6, 0.),
+            epsilon = 0.001
+        );
+        while setup.locomotion_context.is_teleporting() {
+            setup.tick();
+        }
+        assert_eq!(setup.locomotion_context.fade(), 0.);
+    }
+
+    #[test]
+    pub fn test_teleport_rejects_walls() {
+        let mut setup = Setup::new(Default::default());
+
+        // Aim straight at the wall.
+        setup.snapshot.right.stage_from_aim = Affine3A::from_translation([0., 1., 0.].into());
+        setup.snapshot.right.thumbstick_xy = Vec2::new(0., 1.);
+        setup.tick();
+        setup.tick();
+        assert!(setup.locomotion_context.is_aiming_teleport());
+        assert_eq!(setup.locomotion_context.teleport_target(), None);
+
+        // Letting go does nothing.
+        setup.snapshot.right.thumbstick_xy = Vec2::ZERO;
+        setup.tick();
+        assert!(!setup.locomotion_context.is_teleporting());
+        assert_eq!(setup.global_from_stage(), Affine3A::IDENTITY);
+    }
+}
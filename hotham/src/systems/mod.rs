@@ -1,27 +1,35 @@
 #![allow(missing_docs)]
 pub mod animation;
 pub mod audio;
+pub mod composition_layers;
 pub mod debug;
 pub mod draw_gui;
 pub mod grabbing;
 pub mod hands;
 pub mod haptics;
+pub mod locomotion;
+pub mod particles;
 pub mod physics;
 pub mod pointers;
 pub mod rendering;
 pub mod skinning;
+pub mod spectator;
 pub mod update_global_transform;
 pub mod update_global_transform_with_parent;
 
 pub use animation::animation_system;
 pub use audio::audio_system;
+pub use composition_layers::composition_layers_system;
 pub use draw_gui::draw_gui_system;
 pub use grabbing::grabbing_system;
 pub use hands::hands_system;
 pub use haptics::haptics_system;
+pub use locomotion::locomotion_system;
+pub use particles::particles_system;
 pub use physics::physics_system;
 pub use pointers::pointers_system;
 pub use rendering::rendering_system;
 pub use skinning::skinning_system;
+pub use spectator::spectator_system;
 pub use update_global_transform::update_global_transform_system;
 pub use update_global_transform_with_parent::update_global_transform_with_parent_system;
@@ -0,0 +1,136 @@
+use hecs::World;
+
+use crate::{
+    components::{GlobalTransform, ParticleEmitter},
+    Engine,
+};
+
+const DEFAULT_FRAME_PERIOD: f32 = 1. / 72.;
+
+/// Particles system
+/// Emits, ages and moves the particles of every [`ParticleEmitter`], from wherever its entity's
+/// [`GlobalTransform`] is. Particles are simulated on the CPU, which is plenty for the hundreds of
+/// particles in a spray of sparks, but not for hundreds of thousands.
+///
+/// Should run after the `update_global_transform_system`, so particles are emitted from where the
+/// emitter is this frame.
+///
+/// Basic usage:
+/// ```ignore
+/// fn tick (...) {
+///    update_global_transform_system(engine);
+///    particles_system(engine);
+///    rendering_system(engine, swapchain_image_index);
+/// }
+/// ```
+pub fn particles_system(engine: &mut Engine) {
+    let frame_period = engine
+        .xr_context
+        .frame_state
+        .predicted_display_period
+        .as_nanos() as f32
+        / 1e9;
+    let delta_time = if frame_period > 0. {
+        frame_period
+    } else {
+        DEFAULT_FRAME_PERIOD
+    };
+
+    particles_system_inner(&mut engine.world, delta_time);
+}
+
+pub fn particles_system_inner(world: &mut World, delta_time: f32) {
+    for (_, (emitter, global_transform)) in
+        world.query_mut::<(&mut ParticleEmitter, &GlobalTransform)>()
+    {
+        emitter.update(&global_transform.0, delta_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use glam::{Affine3A, Vec3};
+    use hecs::Entity;
+
+    #[test]
+    fn test_particles_burst_and_die() {
+        let mut world = World::new();
+        let position = Vec3::new(1., 2., 3.);
+        let emitter = world.spawn((
+            ParticleEmitter {
+                emitting: false,
+                lifetime: 0.5,
+                velocity: Vec3::ZERO,
+                velocity_spread: 0.,
+                gravity: Vec3::ZERO,
+                ..Default::default()
+            },
+            GlobalTransform(Affine3A::from_translation(position)),
+        ));
+
+        // Nothing is emitted until there's a burst.
+        particles_system_inner(&mut world, DEFAULT_FRAME_PERIOD);
+        assert_eq!(particle_count(&world, emitter), 0);
+
+        world
+            .get::<&mut ParticleEmitter>(emitter)
+            .unwrap()
+            .burst(10);
+        particles_system_inner(&mut world, DEFAULT_FRAME_PERIOD);
+        {
+            let emitter = world.get::<&ParticleEmitter>(emitter).unwrap();
+            assert_eq!(emitter.particles().len(), 10);
+            assert_eq!(emitter.particles()[0].position, position);
+        }
+
+        // They all die once they've outlived their lifetime.
+        for _ in 0..40 {
+            particles_system_inner(&mut world, DEFAULT_FRAME_PERIOD);
+        }
+        assert_eq!(particle_count(&world, emitter), 0);
+    }
+
+    #[test]
+    fn test_particles_rate_and_gravity() {
+        let mut world = World::new();
+        let emitter = world.spawn((
+            ParticleEmitter {
+                rate: 10.,
+                lifetime: 10.,
+                velocity: Vec3::X,
+                velocity_spread: 0.,
+                max_particles: 5,
+                ..Default::default()
+            },
+            GlobalTransform::default(),
+        ));
+
+        // One particle every tenth of a second..
+        particles_system_inner(&mut world, 0.25);
+        assert_eq!(particle_count(&world, emitter), 2);
+        particles_system_inner(&mut world, 0.25);
+        assert_eq!(particle_count(&world, emitter), 5);
+
+        // ..up to the maximum.
+        particles_system_inner(&mut world, 0.25);
+        let emitter = world.get::<&ParticleEmitter>(emitter).unwrap();
+        assert_eq!(emitter.particles().len(), 5);
+
+        // The oldest particles have fallen under gravity, and moved along their velocity.
+        let oldest = emitter.particles()[0];
+        assert_relative_eq!(oldest.age, 0.5);
+        assert_relative_eq!(oldest.velocity, Vec3::new(1., -9.81 * 0.5, 0.));
+        assert_relative_eq!(oldest.position.x, 0.5);
+        assert!(oldest.position.y < 0.);
+    }
+
+    fn particle_count(world: &World, emitter: Entity) -> usize {
+        world
+            .get::<&ParticleEmitter>(emitter)
+            .unwrap()
+            .particles()
+            .len()
+    }
+}
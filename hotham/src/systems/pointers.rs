@@ -3,18 +3,20 @@ use egui::Pos2;
 use glam::{Affine3A, Quat, Vec2, Vec3};
 use hecs::{With, World};
 use rapier3d::na::{Isometry3, Orthographic3, Point3};
-use rapier3d::prelude::{InteractionGroups, QueryFilter, Ray};
+use rapier3d::prelude::{ColliderHandle, InteractionGroups, QueryFilter, Ray};
 
 pub const POSITION_OFFSET: Vec3 = Vec3::new(4.656613e-10, 0.029968515, 0.0741747);
 pub const ROTATION_OFFSET: Quat = Quat::from_xyzw(0.8274912, 0.03413791, -0.050611533, -0.5581499);
+/// How far a pointer's ray reaches, in metres
+pub const POINTER_MAX_TOI: f32 = 40.0;
 
 use crate::util::na_vector_from_glam;
 use crate::{
     components::{
         hand::Handedness, panel::PanelInput, stage, Info, LocalTransform, Panel, Pointer, Visible,
     },
-    contexts::{InputContext, PhysicsContext},
-    Engine,
+    contexts::{input_context::HandTrackingInputContext, InputContext, PhysicsContext},
+    xr, Engine,
 };
 
 /// Pointers system
@@ -35,54 +37,43 @@ pub fn pointers_system_inner(
     // Get the isometry of the stage
     let global_from_stage = stage::get_global_from_stage(world);
 
-    // Create a transform from local space to grip space.
-    // NOTE: This is most likely *WRONG* as the order for these transforms was not recoreded correctly.
-    // TODO: Make these correct.
-
-    let grip_from_local = Affine3A::from_rotation_translation(ROTATION_OFFSET, POSITION_OFFSET);
-
     for (_, (pointer, local_transform)) in world
         .query::<With<(&mut Pointer, &mut LocalTransform), &Visible>>()
         .iter()
     {
         // Get the position of the pointer in stage space.
-        let (stage_from_grip, trigger_value) = match pointer.handedness {
+        // A tracked hand can "pull the trigger" by pinching.
+        let (stage_from_local, trigger_value) = match pointer.handedness {
             Handedness::Left => (
-                input_context.left.stage_from_grip(),
-                input_context.left.trigger_analog(),
+                stage_from_pointer(
+                    input_context.left.stage_from_grip(),
+                    input_context.left.hand_tracking(),
+                ),
+                input_context
+                    .left
+                    .trigger_analog()
+                    .max(input_context.left.hand_tracking().pinch_strength()),
             ),
             Handedness::Right => (
-                input_context.right.stage_from_grip(),
-                input_context.right.trigger_analog(),
+                stage_from_pointer(
+                    input_context.right.stage_from_grip(),
+                    input_context.right.hand_tracking(),
+                ),
+                input_context
+                    .right
+                    .trigger_analog()
+                    .max(input_context.right.hand_tracking().pinch_strength()),
             ),
         };
 
         // Compose transform
-        let global_from_local = global_from_stage * stage_from_grip * grip_from_local;
+        let global_from_local = global_from_stage * stage_from_local;
         local_transform.update_from_affine(&global_from_local);
 
         // Get trigger value
         pointer.trigger_value = trigger_value;
 
-        // Get the direction and position of the ray.
-        let ray_direction = na_vector_from_glam(local_transform.rotation * Vec3::Y);
-        let ray_origin = na_vector_from_glam(local_transform.translation);
-
-        // Sweet baby ray
-        let ray = Ray::new(ray_origin.into(), ray_direction);
-        let max_toi = 40.0;
-        let solid = true;
-        let groups = InteractionGroups::new(0b10, 0b10);
-        let filter = QueryFilter::new().groups(groups);
-
-        if let Some((handle, toi)) = physics_context.query_pipeline.cast_ray(
-            &physics_context.rigid_bodies,
-            &physics_context.colliders,
-            &ray,
-            max_toi,
-            solid,
-            filter,
-        ) {
+        if let (ray, Some((handle, toi))) = cast_pointer_ray(physics_context, local_transform) {
             // The first collider hit has the handle `handle` and it hit after
             // the ray traveled a distance equal to `ray.dir * toi`.
             let hit_point = ray.point_at(toi); // Same as: `ray.origin + ray.dir * toi`
@@ -111,6 +102,59 @@ pub fn pointers_system_inner(
     }
 }
 
+/// Where a pointer is in stage space. Its ray points along its +Y axis.
+///
+/// While a hand is tracked its controller has been put down, so the controller's pose is stale.
+/// Instead, the ray comes from the index finger's knuckle and points the way the fingers do.
+fn stage_from_pointer(
+    stage_from_grip: Affine3A,
+    hand_tracking: &HandTrackingInputContext,
+) -> Affine3A {
+    if !hand_tracking.is_tracked() {
+        // Create a transform from local space to grip space.
+        // NOTE: This is most likely *WRONG* as the order for these transforms was not recoreded correctly.
+        // TODO: Make these correct.
+        let grip_from_local = Affine3A::from_rotation_translation(ROTATION_OFFSET, POSITION_OFFSET);
+        return stage_from_grip * grip_from_local;
+    }
+
+    // The fingers point along the palm's -Z axis.
+    let (_, stage_from_palm, _) = hand_tracking
+        .stage_from_joint(xr::HandJoint::PALM)
+        .to_scale_rotation_translation();
+    let palm_from_local = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let knuckle = hand_tracking
+        .stage_from_joint(xr::HandJoint::INDEX_PROXIMAL)
+        .translation;
+    Affine3A::from_rotation_translation(stage_from_palm * palm_from_local, knuckle.into())
+}
+
+/// Cast a ray from a pointer in the direction it's pointing, at the panels in front of it.
+pub(crate) fn cast_pointer_ray(
+    physics_context: &PhysicsContext,
+    local_transform: &LocalTransform,
+) -> (Ray, Option<(ColliderHandle, f32)>) {
+    // Get the direction and position of the ray.
+    let ray_direction = na_vector_from_glam(local_transform.rotation * Vec3::Y);
+    let ray_origin = na_vector_from_glam(local_transform.translation);
+
+    // Sweet baby ray
+    let ray = Ray::new(ray_origin.into(), ray_direction);
+    let solid = true;
+    let groups = InteractionGroups::new(0b10, 0b10);
+    let filter = QueryFilter::new().groups(groups);
+
+    let hit = physics_context.query_pipeline.cast_ray(
+        &physics_context.rigid_bodies,
+        &physics_context.colliders,
+        &ray,
+        POINTER_MAX_TOI,
+        solid,
+        filter,
+    );
+    (ray, hit)
+}
+
 fn get_cursor_location_for_panel(
     hit_point: &Point3<f32>,
     panel_position: &Isometry3<f32>,
@@ -262,6 +306,42 @@ mod tests {
         pointers_system_inner(world, input_context, physics_context);
     }
 
+    #[test]
+    pub fn test_stage_from_pointer() {
+        use crate::contexts::input_context::HAND_JOINT_COUNT;
+
+        // Without hand tracking, the pointer follows the controller.
+        let mut input_context = InputContext::testing();
+        let stage_from_grip = Affine3A::from_translation([0.1, 1., -0.2].into());
+        let grip_from_local = Affine3A::from_rotation_translation(ROTATION_OFFSET, POSITION_OFFSET);
+        assert_relative_eq!(
+            stage_from_pointer(stage_from_grip, input_context.left.hand_tracking()),
+            stage_from_grip * grip_from_local
+        );
+
+        // A tracked hand points from its index knuckle the way its fingers do.
+        let mut joints = [Affine3A::from_translation([0., 1.2, -0.3].into()); HAND_JOINT_COUNT];
+        joints[xr::HandJoint::PALM.into_raw() as usize] = Affine3A::from_rotation_translation(
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            [0., 1.2, -0.3].into(),
+        );
+        joints[xr::HandJoint::INDEX_PROXIMAL.into_raw() as usize] =
+            Affine3A::from_translation([-0.04, 1.2, -0.32].into());
+        let hand_tracking = input_context.left.hand_tracking_mut();
+        hand_tracking.set_stage_from_joints(joints);
+
+        let stage_from_local = stage_from_pointer(stage_from_grip, hand_tracking);
+        assert_relative_eq!(
+            Vec3::from(stage_from_local.translation),
+            Vec3::new(-0.04, 1.2, -0.32)
+        );
+        assert_relative_eq!(
+            stage_from_local.transform_vector3(Vec3::Y),
+            -Vec3::X,
+            epsilon = 1e-6
+        );
+    }
+
     #[test]
     pub fn test_get_cursor_location_for_panel() {
         let panel_transform = Isometry3::default();
@@ -1,21 +1,40 @@
 use crate::{
-    components::{skin::NO_SKIN, stage, GlobalTransform, Mesh, Skin, Visible},
-    contexts::{render_context::create_push_constant, VulkanContext},
+    components::{
+        mesh_lods::screen_size, skin::NO_SKIN, stage, BlobShadow, GlobalTransform, Light,
+        MaterialOverride, Mesh, MeshLods, Occluder, ParticleEmitter, RenderLayers,
+        RenderTargetCamera, Skin, Visible,
+    },
+    contexts::{
+        render_context::{create_push_constant, NEAR_PLANE},
+        VulkanContext,
+    },
     contexts::{
         render_context::{Instance, InstancedPrimitive},
-        RenderContext,
+        PhysicsContext, RenderContext,
     },
     rendering::{
         buffer::Buffer,
+        camera::{extract_planes_from_frustum, Camera, Frustum},
+        debug_draw::{DebugDraw, DebugVertex, MAX_DEBUG_VERTICES},
+        environment::Skybox,
+        light::{self, select_lights, MAX_LIGHTS},
         material::Material,
+        particles::{sort_back_to_front, ParticleInstance, MAX_PARTICLES},
         primitive::Primitive,
+        render_target::{render_target_scene_data, MAX_RENDER_TARGET_CAMERAS},
         resources::{DrawData, PrimitiveCullData},
+        shadows::MAX_BLOB_SHADOWS,
     },
     Engine,
 };
-use glam::Affine3A;
-use hecs::{With, World};
+use glam::{Affine3A, Mat4, Vec3, Vec4};
+use hecs::{Entity, With, World};
 use openxr as xr;
+use std::collections::HashMap;
+
+/// Primitives drawn with a [`MaterialOverride`]'s material are given IDs from here up, as they
+/// share an index buffer offset with the primitive drawn with its own material.
+const OVERRIDDEN_PRIMITIVE_IDS_START: u32 = 1 << 31;
 
 /// Rendering system
 /// Walks through each Mesh that is Visible and renders it.
@@ -26,6 +45,18 @@ pub fn rendering_system(engine: &mut Engine, swapchain_image_index: usize) {
     let vulkan_context = &mut engine.vulkan_context;
     let render_context = &mut engine.render_context;
 
+    // Fade the view for scene transitions and teleports.
+    apply_fade(render_context, engine.locomotion_context.fade());
+
+    // Send this frame's debug lines to the GPU, to be drawn on top of the world.
+    upload_debug_draw(
+        world,
+        render_context,
+        &mut engine.debug_draw,
+        &engine.physics_context,
+        engine.hmd_entity,
+    );
+
     // Update views just before rendering.
     let views = engine.xr_context.update_views();
 
@@ -38,6 +69,60 @@ pub fn rendering_system(engine: &mut Engine, swapchain_image_index: usize) {
     );
 }
 
+/// Draw whichever of the screen fade and the teleport fade covers more of the view. Teleports
+/// always fade to black.
+fn apply_fade(render_context: &mut RenderContext, teleport_fade: f32) {
+    let screen_fade = &render_context.screen_fade;
+    let post_processing = &mut render_context.post_processing;
+    if screen_fade.amount() >= teleport_fade {
+        post_processing.fade_color = screen_fade.color();
+        post_processing.fade = screen_fade.amount();
+    } else {
+        post_processing.fade_color = Vec3::ZERO;
+        post_processing.fade = teleport_fade;
+    }
+}
+
+/// Add the physics visualizations that are switched on, lay out the labels facing the player and
+/// copy the lines into this frame's vertex buffer, in globally oriented stage space. The
+/// `DebugDraw` is cleared, ready for the next frame.
+fn upload_debug_draw(
+    world: &World,
+    render_context: &mut RenderContext,
+    debug_draw: &mut DebugDraw,
+    physics_context: &PhysicsContext,
+    hmd_entity: Entity,
+) {
+    if debug_draw.show_colliders {
+        debug_draw.colliders(physics_context);
+    }
+    if debug_draw.show_pointers {
+        debug_draw.pointers(world, physics_context);
+    }
+    let global_from_hmd = world
+        .get::<&GlobalTransform>(hmd_entity)
+        .map(|global_transform| global_transform.0)
+        .unwrap_or_default();
+    debug_draw.layout_labels(&global_from_hmd);
+
+    let global_from_stage = stage::get_global_from_stage(world);
+    let gos_from_global =
+        Affine3A::from_translation(global_from_stage.translation.into()).inverse();
+
+    let debug_vertex_buffer =
+        &mut render_context.frames[render_context.frame_index].debug_vertex_buffer;
+    let vertex_count = debug_draw.vertices().len().min(MAX_DEBUG_VERTICES) & !1;
+    for vertex in &debug_draw.vertices()[..vertex_count] {
+        unsafe {
+            debug_vertex_buffer.push(&DebugVertex {
+                position: gos_from_global.transform_point3(vertex.position),
+                color: vertex.color,
+            });
+        }
+    }
+    debug_draw.clear();
+}
+
 pub(crate) fn rendering_system_inner(
     world: &mut World,
     vulkan_context: &VulkanContext,
@@ -77,8 +162,10 @@ pub unsafe fn begin(
     // and create a list of instances, indexed by primitive ID.
     //
     // We use primitive.index_buffer_offset as our primitive ID as it is guaranteed to be unique between
-    // primitives.
+    // primitives. Primitives drawn with a different material are given an ID of their own, as
+    // they're drawn separately.
     let meshes = &render_context.resources.mesh_data;
+    let mut overridden_primitive_ids = HashMap::new();
 
     // Create transformations to globally oriented stage space
     let global_from_stage = stage::get_global_from_stage(world);
@@ -89,22 +176,71 @@ pub unsafe fn begin(
 
     let gos_from_stage: Affine3A = gos_from_global * global_from_stage;
 
-    for (_, (mesh, global_transform, skin)) in
-        world.query_mut::<With<(&Mesh, &GlobalTransform, Option<&Skin>), &Visible>>()
+    // Levels of detail are picked as seen from between the player's eyes.
+    let (eye_in_gos, lod_projection_scale) = get_lod_view(views, &gos_from_stage);
+
+    for (_, (mesh, global_transform, skin, mesh_lods, layers, material_override)) in world
+        .query_mut::<With<
+            (
+                &Mesh,
+                &GlobalTransform,
+                Option<&Skin>,
+                Option<&MeshLods>,
+                Option<&RenderLayers>,
+                Option<&MaterialOverride>,
+            ),
+            &Visible,
+        >>()
     {
+        // Create a transform from this mesh's local space into gos space.
+        let gos_from_local = gos_from_global * global_transform.0;
+
+        let mesh = match mesh_lods {
+            Some(mesh_lods) => {
+                let size = meshes
+                    .get(mesh.handle)
+                    .unwrap()
+                    .primitives
+                    .iter()
+                    .map(|p| {
+                        let bounding_sphere = p.get_bounding_sphere_in_gos(&gos_from_local);
+                        screen_size(bounding_sphere, eye_in_gos, lod_projection_scale)
+                    })
+                    .fold(0., f32::max);
+                match mesh_lods.select(mesh, size) {
+                    Some(mesh) => mesh,
+                    None => continue,
+                }
+            }
+            None => mesh,
+        };
+
         let mesh = meshes.get(mesh.handle).unwrap();
         let skin_id = skin.map(|s| s.id).unwrap_or(NO_SKIN);
+        let layers = layers.copied().unwrap_or_default();
+        let material_override = material_override.copied().unwrap_or_default();
         for primitive in &mesh.primitives {
-            let key = primitive.index_buffer_offset;
-
-            // Create a transform from this primitive's local space into gos space.
-            let gos_from_local = gos_from_global * global_transform.0;
+            let material_id = material_override
+                .material_id
+                .unwrap_or(primitive.material_id);
+            let key = if material_id == primitive.material_id {
+                primitive.index_buffer_offset
+            } else {
+                let next_id =
+                    OVERRIDDEN_PRIMITIVE_IDS_START + overridden_primitive_ids.len() as u32;
+                *overridden_primitive_ids
+                    .entry((primitive.index_buffer_offset, material_id))
+                    .or_insert(next_id)
+            };
 
             render_context
                 .primitive_map
                 .entry(key)
-                .or_insert(InstancedPrimitive {
-                    primitive: primitive.clone(),
+                .or_insert_with(|| InstancedPrimitive {
+                    primitive: Primitive {
+                        material_id,
+                        ..primitive.clone()
+                    },
                     instances: Default::default(),
                 })
                 .instances
@@ -112,6 +248,9 @@ pub unsafe fn begin(
                     gos_from_local,
                     bounding_sphere: primitive.get_bounding_sphere_in_gos(&gos_from_local),
                     skin_id,
+                    layers,
+                    tint: material_override.packed_tint(),
+                    packed_emissive: material_override.packed_emissive(),
                 });
         }
     }
@@ -132,31 +271,484 @@ pub unsafe fn begin(
     let cull_data = &mut frame.primitive_cull_data_buffer;
     cull_data.clear();
 
-    for instanced_primitive in render_context.primitive_map.values() {
-        let primitive = &instanced_primitive.primitive;
+    for (primitive_id, instanced_primitive) in &render_context.primitive_map {
         for (instance, i) in instanced_primitive.instances.iter().zip(0u32..) {
             cull_data.push(&PrimitiveCullData {
                 bounding_sphere: instance.bounding_sphere,
                 index_instance: i,
-                primitive_id: primitive.index_buffer_offset,
+                primitive_id: *primitive_id,
                 visible: false,
             });
         }
     }
 
+    // Collect the blob shadows. They're positioned in global space, like lights.
+    let scene_data = &mut render_context.scene_data;
+    let mut blob_shadow_count = 0;
+    for (_, (blob_shadow, global_transform)) in world
+        .query_mut::<(&BlobShadow, &GlobalTransform)>()
+        .into_iter()
+        .take(MAX_BLOB_SHADOWS)
+    {
+        scene_data.blob_shadows[blob_shadow_count] = blob_shadow.to_data(global_transform);
+        blob_shadow_count += 1;
+    }
+    for unused in &mut scene_data.blob_shadows[blob_shadow_count..] {
+        *unused = Default::default();
+    }
+    scene_data.params.y = blob_shadow_count as f32;
+
+    // Pick the lights to draw with. Like blob shadows, they follow their entities.
+    gather_lights(
+        world,
+        render_context,
+        views,
+        &gos_from_global,
+        &gos_from_stage,
+    );
+
+    // Collect the occluders, which are drawn into the Hi-Z buffer just before culling.
+    gather_occluders(world, render_context, &gos_from_global);
+
+    // Collect the particles, which are drawn after everything else that's transparent.
+    gather_particles(world, render_context, &gos_from_global, eye_in_gos);
+
     // This is the VERY LATEST we can possibly update our views, as the compute shader will need them.
     render_context.update_scene_data(views, &gos_from_global, &gos_from_stage);
 
     // Execute the culling shader on the GPU.
     render_context.cull_objects(vulkan_context);
 
+    // Draw the shadow maps, which must happen before the main render pass begins.
+    render_context.frames[render_context.frame_index]
+        .draw_data_buffer
+        .clear();
+    if render_context
+        .shadow_maps
+        .any_enabled(&render_context.scene_data.lights)
+    {
+        draw_shadows(vulkan_context, render_context);
+    }
+
+    // Draw the render target cameras, which must also happen before the main render pass begins.
+    draw_render_targets(world, vulkan_context, render_context, &gos_from_global);
+
     // Begin the render pass, bind descriptor sets.
     render_context.begin_pbr_render_pass(vulkan_context, swapchain_image_index);
 }
 
+/// The point between the player's eyes in globally oriented stage space, and how much the views
+/// scale things vertically: `1 / tan(vertical_fov / 2)`.
+fn get_lod_view(views: &[xr::View], gos_from_stage: &Affine3A) -> (Vec3, f32) {
+    let mut eyes_in_gos = Vec3::ZERO;
+    let mut projection_scale = 0_f32;
+    for view in views {
+        let mut camera = Camera::default();
+        camera.update(view, gos_from_stage);
+        eyes_in_gos += camera.position_in_gos().truncate() / views.len() as f32;
+
+        let half_fov = (view.fov.angle_up - view.fov.angle_down) * 0.5;
+        projection_scale = projection_scale.max(1. / half_fov.tan());
+    }
+    (eyes_in_gos, projection_scale)
+}
+
+/// Fill `scene_data.lights` with the most relevant [`Light`] components for the player's view.
+///
+/// If there are no `Light` components, `scene_data.lights` is left alone, so lights set on it
+/// directly keep working. The exception is the frame after the last `Light` is despawned, when the
+/// lights and shadows gathered from the world are removed.
+fn gather_lights(
+    world: &mut World,
+    render_context: &mut RenderContext,
+    views: &[xr::View],
+    gos_from_global: &Affine3A,
+    gos_from_stage: &Affine3A,
+) {
+    let (lights, shadows): (Vec<_>, Vec<_>) = world
+        .query_mut::<(&Light, &GlobalTransform)>()
+        .into_iter()
+        .map(|(_, (light, global_transform))| {
+            (light.to_scene_light(global_transform), light.shadows)
+        })
+        .unzip();
+    if lights.is_empty() {
+        if std::mem::take(&mut render_context.lights_from_world) {
+            for light in &mut render_context.scene_data.lights {
+                *light = light::Light::none();
+            }
+            render_context.shadow_maps.settings = Default::default();
+        }
+        return;
+    }
+    render_context.lights_from_world = true;
+
+    // The views are in globally oriented stage space, so cull and rank the lights there.
+    let lights_in_gos = lights
+        .iter()
+        .map(|light| light::Light {
+            position: gos_from_global.transform_point3(light.position),
+            ..light.clone()
+        })
+        .collect::<Vec<_>>();
+    let mut clip_planes = [Mat4::IDENTITY; 2];
+    let mut eyes_in_gos = Vec3::ZERO;
+    for (view, planes) in views.iter().zip(&mut clip_planes) {
+        let mut camera = Camera::default();
+        let view_from_gos = camera.update(view, gos_from_stage);
+        let projection = Frustum::from(view.fov).projection(NEAR_PLANE);
+        *planes = extract_planes_from_frustum(&(projection * view_from_gos));
+        eyes_in_gos += camera.position_in_gos().truncate() * 0.5;
+    }
+    let selected = select_lights(&lights_in_gos, &clip_planes, eyes_in_gos);
+
+    let scene_data = &mut render_context.scene_data;
+    let shadow_settings = &mut render_context.shadow_maps.settings;
+    for slot in 0..MAX_LIGHTS {
+        match selected.get(slot) {
+            Some(&index) => {
+                scene_data.lights[slot] = lights[index].clone();
+                shadow_settings[slot] = shadows[index];
+            }
+            None => {
+                scene_data.lights[slot] = light::Light::none();
+                shadow_settings[slot] = Default::default();
+            }
+        }
+    }
+}
+
+/// Collect the primitives of every visible [`Occluder`] and write their draw data. The draw data is
+/// only needed until culling is done, after which the buffer is cleared for the main pass.
+unsafe fn gather_occluders(
+    world: &mut World,
+    render_context: &mut RenderContext,
+    gos_from_global: &Affine3A,
+) {
+    let occlusion_culling = &mut render_context.occlusion_culling;
+    occlusion_culling.occluders.clear();
+    if !occlusion_culling.enabled {
+        return;
+    }
+
+    let meshes = &render_context.resources.mesh_data;
+    let draw_data_buffer = &mut render_context.frames[render_context.frame_index].draw_data_buffer;
+    draw_data_buffer.clear();
+
+    for (_, (mesh, global_transform, skin)) in
+        world.query_mut::<With<(&Mesh, &GlobalTransform, Option<&Skin>), (&Visible, &Occluder)>>()
+    {
+        let gos_from_local = gos_from_global * global_transform.0;
+        let skin_id = skin.map(|s| s.id).unwrap_or(NO_SKIN);
+        for primitive in &meshes.get(mesh.handle).unwrap().primitives {
+            let draw_data = DrawData {
+                gos_from_local: gos_from_local.into(),
+                local_from_gos: gos_from_local.inverse().into(),
+                skin_id,
+                ..Default::default()
+            };
+            let instance = match push_draw_data(draw_data_buffer, &draw_data, "occluder") {
+                Some(instance) => instance,
+                None => return,
+            };
+            occlusion_culling
+                .occluders
+                .push((primitive.clone(), instance));
+        }
+    }
+}
+
+/// Collect the particles of every visible [`ParticleEmitter`] into this frame's particle buffer,
+/// furthest from the player's eyes first. If there are too many, the furthest are dropped.
+fn gather_particles(
+    world: &mut World,
+    render_context: &mut RenderContext,
+    gos_from_global: &Affine3A,
+    eye_in_gos: Vec3,
+) {
+    let [left_layers, right_layers] = render_context.view_layers;
+    let view_layers = left_layers | right_layers;
+
+    let mut particles = Vec::new();
+    for (_, (emitter, layers)) in
+        world.query_mut::<With<(&ParticleEmitter, Option<&RenderLayers>), &Visible>>()
+    {
+        let layers = layers.copied().unwrap_or_default();
+        if !view_layers.intersects(layers) {
+            continue;
+        }
+
+        let texture_id = emitter.texture_id.unwrap_or(u32::MAX);
+        for particle in emitter.particles() {
+            let (color, size) = emitter.appearance(particle);
+            particles.push(ParticleInstance {
+                position: gos_from_global.transform_point3(particle.position),
+                size,
+                color,
+                texture_id,
+                additive: emitter.additive as u32,
+                layers: layers.0,
+            });
+        }
+    }
+    sort_back_to_front(&mut particles, eye_in_gos);
+
+    let particle_buffer = &mut render_context.frames[render_context.frame_index].particle_buffer;
+    particle_buffer.clear();
+    let first = particles.len().saturating_sub(MAX_PARTICLES);
+    for particle in &particles[first..] {
+        particle_buffer.push(particle);
+    }
+}
+
+/// Draw every opaque and alpha masked primitive into the shadow maps. Primitives aren't culled, as
+/// objects outside the player's view can still cast shadows into it. There's no fragment shader, so
+/// alpha masked primitives cast solid shadows.
+unsafe fn draw_shadows(vulkan_context: &VulkanContext, render_context: &mut RenderContext) {
+    let device = &vulkan_context.device;
+    let frame = &mut render_context.frames[render_context.frame_index];
+    let command_buffer = frame.command_buffer;
+    let draw_data_buffer = &mut frame.draw_data_buffer;
+    let materials = render_context.resources.materials_buffer.as_slice();
+    let shadow_maps = &render_context.shadow_maps;
+
+    shadow_maps.begin_render_pass(device, command_buffer);
+    device.cmd_bind_pipeline(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        shadow_maps.pipeline,
+    );
+    device.cmd_bind_descriptor_sets(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        render_context.pipeline_layout,
+        0,
+        std::slice::from_ref(&render_context.descriptors.sets[render_context.frame_index]),
+        &[],
+    );
+    device.cmd_bind_index_buffer(
+        command_buffer,
+        render_context.resources.index_buffer.buffer,
+        0,
+        ash::vk::IndexType::UINT32,
+    );
+    device.cmd_bind_vertex_buffers(
+        command_buffer,
+        0,
+        &[
+            render_context.resources.position_buffer.buffer,
+            render_context.resources.vertex_buffer.buffer,
+        ],
+        &[0, 0],
+    );
+
+    for instanced_primitive in render_context.primitive_map.values() {
+        let primitive = &instanced_primitive.primitive;
+        if materials[primitive.material_id as usize].is_alpha_blended() {
+            continue;
+        }
+
+        let instance_offset = draw_data_buffer.len() as u32;
+        let mut instance_count = 0;
+        let mut buffer_full = false;
+        for instance in &instanced_primitive.instances {
+            if push_draw_data(draw_data_buffer, &instance.draw_data(), "shadow").is_none() {
+                buffer_full = true;
+                break;
+            }
+            instance_count += 1;
+        }
+        if instance_count > 0 {
+            device.cmd_draw_indexed(
+                command_buffer,
+                primitive.indices_count,
+                instance_count,
+                primitive.index_buffer_offset,
+                primitive.vertex_buffer_offset as _,
+                instance_offset,
+            );
+        }
+        if buffer_full {
+            break;
+        }
+    }
+
+    device.cmd_end_render_pass(command_buffer);
+}
+
+/// Draw the world from each [`RenderTargetCamera`] into its texture. The culling shader only knows
+/// about the player's views, so instances are culled against each camera's frustum here instead.
+unsafe fn draw_render_targets(
+    world: &mut World,
+    vulkan_context: &VulkanContext,
+    render_context: &mut RenderContext,
+    gos_from_global: &Affine3A,
+) {
+    let device = &vulkan_context.device;
+    let frame_index = render_context.frame_index;
+    let frame = &mut render_context.frames[frame_index];
+    let command_buffer = frame.command_buffer;
+    let render_targets = &render_context.render_targets;
+    let materials_buffer = &render_context.resources.materials_buffer;
+    let materials = materials_buffer.as_slice();
+
+    for (slot, (_, (camera, global_transform))) in world
+        .query_mut::<(&RenderTargetCamera, &GlobalTransform)>()
+        .into_iter()
+        .take(MAX_RENDER_TARGET_CAMERAS)
+        .enumerate()
+    {
+        let gos_from_camera = *gos_from_global * global_transform.0;
+        let view_projection = camera.view_projection(&gos_from_camera);
+        let camera_position: Vec3 = gos_from_camera.translation.into();
+
+        // Everything but the views is shared with the player's view.
+        let scene_data = render_target_scene_data(
+            &frame.scene_data_buffer.as_slice()[0],
+            &render_context.post_processing,
+            view_projection,
+            camera_position.extend(1.),
+            camera.layers,
+        );
+        frame.render_target_scene_data_buffers[slot].overwrite(&[scene_data]);
+
+        render_targets.begin_render_pass(
+            device,
+            command_buffer,
+            camera.framebuffer(),
+            camera.resolution(),
+        );
+        device.cmd_bind_pipeline(
+            command_buffer,
+            ash::vk::PipelineBindPoint::GRAPHICS,
+            render_targets.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            ash::vk::PipelineBindPoint::GRAPHICS,
+            render_context.pipeline_layout,
+            0,
+            std::slice::from_ref(&render_context.descriptors.render_target_sets[slot][frame_index]),
+            &[],
+        );
+        device.cmd_bind_index_buffer(
+            command_buffer,
+            render_context.resources.index_buffer.buffer,
+            0,
+            ash::vk::IndexType::UINT32,
+        );
+        device.cmd_bind_vertex_buffers(
+            command_buffer,
+            0,
+            &[
+                render_context.resources.position_buffer.buffer,
+                render_context.resources.vertex_buffer.buffer,
+            ],
+            &[0, 0],
+        );
+
+        let clip_planes = extract_planes_from_frustum(&view_projection);
+        let mut transparent_draws = Vec::new();
+        let draw_data_buffer = &mut frame.draw_data_buffer;
+        for (primitive_id, instanced_primitive) in &render_context.primitive_map {
+            let primitive = &instanced_primitive.primitive;
+            let alpha_blended = materials[primitive.material_id as usize].is_alpha_blended();
+            let instance_offset = draw_data_buffer.len() as u32;
+            let mut instance_count = 0;
+            let mut buffer_full = false;
+            for instance in &instanced_primitive.instances {
+                if !camera.layers.intersects(instance.layers)
+                    || !is_sphere_in_frustum(&clip_planes, instance.bounding_sphere)
+                {
+                    continue;
+                }
+
+                let draw_data_index = match push_draw_data(
+                    draw_data_buffer,
+                    &instance.draw_data(),
+                    "render target",
+                ) {
+                    Some(draw_data_index) => draw_data_index,
+                    None => {
+                        buffer_full = true;
+                        break;
+                    }
+                };
+                if alpha_blended {
+                    transparent_draws.push(TransparentDraw {
+                        primitive_id: *primitive_id,
+                        instance_offset: draw_data_index,
+                        distance_squared: instance
+                            .bounding_sphere
+                            .truncate()
+                            .distance_squared(camera_position),
+                    });
+                } else {
+                    instance_count += 1;
+                }
+            }
+
+            if instance_count > 0 {
+                draw_primitive(
+                    materials_buffer,
+                    render_context.pipeline_layout,
+                    primitive,
+                    device,
+                    command_buffer,
+                    instance_count,
+                    instance_offset,
+                );
+            }
+            if buffer_full {
+                break;
+            }
+        }
+
+        if let Some(skybox) = &render_context.skybox {
+            draw_skybox(
+                device,
+                command_buffer,
+                render_targets.skybox_pipeline,
+                render_targets.pipeline,
+                render_context.pipeline_layout,
+                skybox,
+            );
+        }
+
+        transparent_draws.sort_by(|a, b| b.distance_squared.total_cmp(&a.distance_squared));
+        device.cmd_bind_pipeline(
+            command_buffer,
+            ash::vk::PipelineBindPoint::GRAPHICS,
+            render_targets.transparent_pipeline,
+        );
+        for transparent_draw in &transparent_draws {
+            let primitive = &render_context.primitive_map[&transparent_draw.primitive_id].primitive;
+            draw_primitive(
+                materials_buffer,
+                render_context.pipeline_layout,
+                primitive,
+                device,
+                command_buffer,
+                1,
+                transparent_draw.instance_offset,
+            );
+        }
+
+        device.cmd_end_render_pass(command_buffer);
+    }
+}
+
+/// Is any part of the bounding sphere inside the four planes of `clip_planes`?
+fn is_sphere_in_frustum(clip_planes: &Mat4, bounding_sphere: Vec4) -> bool {
+    // Each row of the clip planes matrix is a plane whose normal points into the frustum.
+    let center = bounding_sphere.truncate().extend(1.);
+    (*clip_planes * center).min_element() >= -bounding_sphere.w
+}
+
 /// Draw the world
 ///
-/// Records commands to draw all visible meshes
+/// Records commands to draw all visible meshes. Opaque and alpha masked primitives are drawn first,
+/// followed by alpha blended primitives, sorted back to front.
 ///
 /// # Safety
 ///
@@ -168,13 +760,23 @@ pub unsafe fn draw_world(vulkan_context: &VulkanContext, render_context: &mut Re
     let command_buffer = frame.command_buffer;
     let draw_data_buffer = &mut frame.draw_data_buffer;
     let material_buffer = &mut render_context.resources.materials_buffer;
-    draw_data_buffer.clear();
 
-    let mut instance_offset = 0;
+    // The shadow pass may have already used the start of the draw data buffer.
+    let mut instance_offset = draw_data_buffer.len() as u32;
     let mut current_primitive_id = u32::MAX;
     let mut instance_count = 0;
+    let mut transparent_draws = Vec::new();
     let cull_data = frame.primitive_cull_data_buffer.as_slice();
 
+    // Transparent primitives are sorted by their distance from the point between the two eyes.
+    let [left_eye, right_eye] = render_context.scene_data.camera_position;
+    let eyes_in_gos = ((left_eye + right_eye) * 0.5).truncate();
+
+    // Instances seen by only one eye are drawn into both, and the vertex shader hides them from the
+    // other.
+    let [left_layers, right_layers] = render_context.view_layers;
+    let view_layers = left_layers | right_layers;
+
     for cull_result in cull_data {
         // If we haven't yet set our primitive ID, set it now.
         if current_primitive_id == u32::MAX {
@@ -202,24 +804,41 @@ pub unsafe fn draw_world(vulkan_context: &VulkanContext, render_context: &mut Re
             }
 
             current_primitive_id = cull_result.primitive_id;
-            instance_offset += instance_count;
+            instance_offset = draw_data_buffer.len() as u32;
             instance_count = 0;
         }
 
-        // If this primitive is visible, increase the instance count and record its draw data.
+        // If this primitive is visible, record its draw data. Opaque instances are drawn together
+        // when we reach the next primitive, transparent instances are drawn one at a time later on.
         if cull_result.visible {
             let instanced_primitive = render_context
                 .primitive_map
                 .get(&cull_result.primitive_id)
                 .unwrap();
             let instance = &instanced_primitive.instances[cull_result.index_instance as usize];
-            let draw_data = DrawData {
-                gos_from_local: instance.gos_from_local.into(),
-                local_from_gos: instance.gos_from_local.inverse().into(),
-                skin_id: instance.skin_id,
-            };
-            draw_data_buffer.push(&draw_data);
-            instance_count += 1;
+            if !view_layers.intersects(instance.layers) {
+                continue;
+            }
+
+            let draw_data_index =
+                match push_draw_data(draw_data_buffer, &instance.draw_data(), "main") {
+                    Some(draw_data_index) => draw_data_index,
+                    None => break,
+                };
+
+            let primitive = &instanced_primitive.primitive;
+            if material_buffer.as_slice()[primitive.material_id as usize].is_alpha_blended() {
+                transparent_draws.push(TransparentDraw {
+                    primitive_id: cull_result.primitive_id,
+                    instance_offset: draw_data_index,
+                    distance_squared: instance
+                        .bounding_sphere
+                        .truncate()
+                        .distance_squared(eyes_in_gos),
+                });
+            } else {
+                instance_count += 1;
+            }
         }
     }
 
@@ -243,6 +862,105 @@ pub unsafe fn draw_world(vulkan_context: &VulkanContext, render_context: &mut Re
             instance_offset,
         );
     }
+
+    // The skybox goes behind everything opaque, but in front of nothing transparent.
+    if let Some(skybox) = &render_context.skybox {
+        draw_skybox(
+            device,
+            command_buffer,
+            render_context.skybox_pipeline,
+            render_context.pipeline,
+            render_context.pipeline_layout,
+            skybox,
+        );
+    }
+
+    if transparent_draws.is_empty() {
+        return;
+    }
+
+    // Now draw the transparent primitives, furthest away first, so they blend correctly.
+    transparent_draws.sort_by(|a, b| b.distance_squared.total_cmp(&a.distance_squared));
+    device.cmd_bind_pipeline(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        render_context.transparent_pipeline,
+    );
+    for transparent_draw in &transparent_draws {
+        let primitive = &render_context
+            .primitive_map
+            .get(&transparent_draw.primitive_id)
+            .unwrap()
+            .primitive;
+        draw_primitive(
+            material_buffer,
+            render_context.pipeline_layout,
+            primitive,
+            device,
+            command_buffer,
+            1,
+            transparent_draw.instance_offset,
+        );
+    }
+
+    // Leave the opaque pipeline bound for anyone drawing after us.
+    device.cmd_bind_pipeline(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        render_context.pipeline,
+    );
+}
+
+/// Draw the skybox behind everything opaque, then rebind the opaque pipeline.
+unsafe fn draw_skybox(
+    device: &ash::Device,
+    command_buffer: ash::vk::CommandBuffer,
+    skybox_pipeline: ash::vk::Pipeline,
+    pipeline: ash::vk::Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+    skybox: &Skybox,
+) {
+    device.cmd_bind_pipeline(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        skybox_pipeline,
+    );
+    device.cmd_push_constants(
+        command_buffer,
+        pipeline_layout,
+        ash::vk::ShaderStageFlags::FRAGMENT,
+        0,
+        create_push_constant(skybox),
+    );
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    device.cmd_bind_pipeline(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        pipeline,
+    );
+}
+
+/// A single instance of an alpha blended primitive, to be drawn after all opaque primitives.
+struct TransparentDraw {
+    primitive_id: u32,
+    instance_offset: u32,
+    distance_squared: f32,
+}
+
+/// Push the draw data of an instance, unless this frame's draw data buffer is full. If it is, a
+/// warning is logged and `None` is returned, and the pass should stop drawing instances.
+unsafe fn push_draw_data(
+    draw_data_buffer: &mut Buffer<DrawData>,
+    draw_data: &DrawData,
+    pass: &str,
+) -> Option<u32> {
+    if draw_data_buffer.len() >= draw_data_buffer.max_len {
+        println!(
+            "[HOTHAM_RENDERER] WARNING: Out of draw data, skipped instances in the {pass} pass!"
+        );
+        return None;
+    }
+    Some(draw_data_buffer.push(draw_data))
 }
 
 // TODO: Just push this into `RenderContext`
@@ -275,12 +993,61 @@ pub unsafe fn draw_primitive(
     );
 }
 
+/// Draw the particles gathered this frame, if there are any. Each particle is an instance of a
+/// quad, with its corners made up by the vertex shader.
+unsafe fn draw_particles(vulkan_context: &VulkanContext, render_context: &mut RenderContext) {
+    let frame = &mut render_context.frames[render_context.frame_index];
+    let particle_buffer = &mut frame.particle_buffer;
+    if particle_buffer.is_empty() {
+        return;
+    }
+
+    let device = &vulkan_context.device;
+    let command_buffer = frame.command_buffer;
+    device.cmd_bind_pipeline(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        render_context.particle_pipeline,
+    );
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[particle_buffer.buffer], &[0]);
+    device.cmd_draw(command_buffer, 6, particle_buffer.len() as _, 0, 0);
+    particle_buffer.clear();
+}
+
+/// Draw the lines uploaded from [`DebugDraw`] this frame, if there are any. Each upload is only
+/// drawn once.
+unsafe fn draw_debug_lines(vulkan_context: &VulkanContext, render_context: &mut RenderContext) {
+    let frame = &mut render_context.frames[render_context.frame_index];
+    let debug_vertex_buffer = &mut frame.debug_vertex_buffer;
+    if debug_vertex_buffer.is_empty() {
+        return;
+    }
+
+    let device = &vulkan_context.device;
+    let command_buffer = frame.command_buffer;
+    device.cmd_bind_pipeline(
+        command_buffer,
+        ash::vk::PipelineBindPoint::GRAPHICS,
+        render_context.debug_draw_pipeline,
+    );
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[debug_vertex_buffer.buffer], &[0]);
+    device.cmd_draw(command_buffer, debug_vertex_buffer.len() as _, 1, 0, 0);
+    debug_vertex_buffer.clear();
+}
+
 /// Finish drawing
 ///
 /// # Safety
 ///
 /// Must be called after `begin`
 pub fn end(vulkan_context: &VulkanContext, render_context: &mut RenderContext) {
+    // Particles go after every transparent mesh, and debug lines go on top of everything, before
+    // post-processing.
+    unsafe {
+        draw_particles(vulkan_context, render_context);
+        draw_debug_lines(vulkan_context, render_context);
+    }
+
     // OK. We're all done!
     render_context.primitive_map.clear();
     render_context.end_pbr_render_pass(vulkan_context);
@@ -290,13 +1057,14 @@ pub fn end(vulkan_context: &VulkanContext, render_context: &mut RenderContext) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ash::vk;
     use openxr::{Fovf, Quaternionf, Vector3f};
 
     use crate::{
         asset_importer,
         components::{stage::Stage, LocalTransform},
         contexts::RenderContext,
-        rendering::{image::Image, light::Light, scene_data},
+        rendering::{image::Image, light::Light, post_processing::Tonemapper, scene_data},
         systems::{
             update_global_transform::update_global_transform_system_inner,
             update_global_transform_with_parent::update_global_transform_with_parent_system_inner,
@@ -535,6 +1303,131 @@ mod tests {
         assert!(errors.is_empty(), "{errors:#?}");
     }
 
+    #[test]
+    pub fn test_render_target_tone_mapping() {
+        let (mut render_context, vulkan_context, image) = RenderContext::testing_with_image();
+
+        let gltf_data: Vec<&[u8]> = vec![include_bytes!("../../../test_assets/damaged_helmet.glb")];
+        let mut models =
+            asset_importer::load_models_from_glb(&gltf_data, &vulkan_context, &mut render_context)
+                .unwrap();
+        let (_, mut world) = models.drain().next().unwrap();
+
+        // Make the helmet glow much brighter than 1.0, with a tonemapper that never reaches it.
+        render_context.post_processing.tonemapper = Tonemapper::Reinhard;
+        let meshes: Vec<_> = world.query::<&Mesh>().iter().map(|(e, _)| e).collect();
+        for entity in meshes {
+            world
+                .insert_one(entity, MaterialOverride::emissive([4., 4., 4.].into()))
+                .unwrap();
+        }
+
+        // Look at it from the same place with the eyes and a render target camera.
+        let position = Vector3f {
+            x: 0.,
+            y: 0.,
+            z: 3.,
+        };
+        let view = openxr::View {
+            pose: openxr::Posef {
+                orientation: Quaternionf::IDENTITY,
+                position,
+            },
+            fov: Fovf {
+                angle_up: 45.0_f32.to_radians(),
+                angle_down: -45.0_f32.to_radians(),
+                angle_left: -45.0_f32.to_radians(),
+                angle_right: 45.0_f32.to_radians(),
+            },
+        };
+        let camera = RenderTargetCamera::new(
+            &vulkan_context,
+            &mut render_context,
+            image.extent,
+            90_f32.to_radians(),
+        )
+        .unwrap();
+        let render_target_image = camera.texture.image.clone();
+        world.spawn((
+            camera,
+            LocalTransform {
+                translation: [position.x, position.y, position.z].into(),
+                ..Default::default()
+            },
+            GlobalTransform::default(),
+        ));
+
+        render(
+            &mut render_context,
+            &vulkan_context,
+            0.,
+            scene_data::DEFAULT_IBL_INTENSITY,
+            &mut world,
+            &Light::none(),
+            &[view, view],
+        );
+
+        let eye = unsafe {
+            center_pixel(
+                &vulkan_context,
+                &image,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            )
+        };
+        let render_target = unsafe {
+            center_pixel(
+                &vulkan_context,
+                &render_target_image,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+        };
+
+        // Without tone mapping the render target would clip to white.
+        for channel in 0..3 {
+            assert!(render_target[channel] < 255, "{render_target:?}");
+            assert!(
+                (eye[channel] as i32 - render_target[channel] as i32).abs() <= 8,
+                "eye: {eye:?}, render target: {render_target:?}"
+            );
+        }
+    }
+
+    /// Read back the pixel in the middle of the first layer of `image`.
+    unsafe fn center_pixel(
+        vulkan_context: &VulkanContext,
+        image: &Image,
+        layout: vk::ImageLayout,
+    ) -> [u8; 4] {
+        let extent = image.extent;
+        let size = (extent.height * extent.width * 4) as usize;
+        let mut buffer = Buffer::new(vulkan_context, vk::BufferUsageFlags::TRANSFER_DST, size);
+
+        vulkan_context.device.device_wait_idle().unwrap();
+        vulkan_context.transition_image_layout(
+            image.handle,
+            layout,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            1,
+            1,
+        );
+        vulkan_context.copy_image_to_buffer(
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer.buffer,
+        );
+        buffer.len = size;
+        vulkan_context.device.device_wait_idle().unwrap();
+
+        let offset = ((extent.height / 2 * extent.width + extent.width / 2) * 4) as usize;
+        let pixels: &[u8] = buffer.as_slice();
+        [
+            pixels[offset],
+            pixels[offset + 1],
+            pixels[offset + 2],
+            pixels[offset + 3],
+        ]
+    }
+
     fn render_object_with_debug_data(
         vulkan_context: &VulkanContext,
         render_context: &mut RenderContext,
@@ -0,0 +1,141 @@
+use glam::{Affine3A, Quat, Vec3};
+use hecs::{Entity, World};
+
+use crate::{
+    components::{spectator::SpectatorMode, GlobalTransform, LocalTransform, Spectator},
+    Engine,
+};
+
+const DEFAULT_FRAME_PERIOD: f32 = 1. / 72.;
+
+/// Spectator system
+/// Moves each [`Spectator`] camera that follows the player's head. The camera is kept level and
+/// eased towards the head, so the view doesn't shake or roll with every small head movement.
+///
+/// Spectator cameras shouldn't have a parent, as their `LocalTransform` is set in global space.
+///
+/// Basic usage:
+/// ```ignore
+/// fn tick (...) {
+///    spectator_system(engine);
+///    rendering_system(engine, swapchain_image_index);
+/// }
+/// ```
+pub fn spectator_system(engine: &mut Engine) {
+    let frame_period = engine
+        .xr_context
+        .frame_state
+        .predicted_display_period
+        .as_nanos() as f32
+        / 1e9;
+    let delta_time = if frame_period > 0. {
+        frame_period
+    } else {
+        DEFAULT_FRAME_PERIOD
+    };
+
+    spectator_system_inner(&mut engine.world, engine.hmd_entity, delta_time);
+}
+
+pub fn spectator_system_inner(world: &mut World, hmd_entity: Entity, delta_time: f32) {
+    let global_from_hmd = match world.get::<&GlobalTransform>(hmd_entity) {
+        Ok(global_transform) => global_transform.0,
+        Err(_) => return,
+    };
+    let (head_rotation, head_position) = level_head(&global_from_hmd);
+
+    for (_, (spectator, local_transform)) in world.query_mut::<(&Spectator, &mut LocalTransform)>()
+    {
+        let offset = match spectator.mode {
+            SpectatorMode::Fixed => continue,
+            SpectatorMode::FollowHead { offset } => offset,
+        };
+        let target_position = head_position + head_rotation * offset;
+
+        // Ease towards the head at the same rate, whatever the frame rate.
+        let t = if spectator.smoothing > 0. {
+            1. - (-delta_time / spectator.smoothing).exp()
+        } else {
+            1.
+        };
+        local_transform.translation = local_transform.translation.lerp(target_position, t);
+        local_transform.rotation = local_transform.rotation.slerp(head_rotation, t);
+    }
+}
+
+/// The head's rotation without any roll, and its position
+fn level_head(global_from_hmd: &Affine3A) -> (Quat, Vec3) {
+    let (_, rotation, position) = global_from_hmd.to_scale_rotation_translation();
+    let forward = rotation * Vec3::NEG_Z;
+    let yaw = (-forward.x).atan2(-forward.z);
+    let pitch = forward.y.clamp(-1., 1.).asin();
+    (
+        Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch),
+        position,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_spectator_follows_head() {
+        let mut world = World::new();
+
+        // The player has turned left, and tilted their head to one side.
+        let turn = Quat::from_rotation_y(90_f32.to_radians());
+        let head_rotation = turn * Quat::from_rotation_z(30_f32.to_radians());
+        let head_position = Vec3::new(1., 1.7, 0.);
+        let hmd = world.spawn((GlobalTransform(Affine3A::from_rotation_translation(
+            head_rotation,
+            head_position,
+        )),));
+
+        let offset = Vec3::new(0., 0.5, 1.5);
+        let follower = world.spawn((
+            Spectator {
+                mode: SpectatorMode::FollowHead { offset },
+                smoothing: 0.,
+            },
+            LocalTransform::default(),
+        ));
+        let fixed = world.spawn((Spectator::fixed(), LocalTransform::default()));
+
+        spectator_system_inner(&mut world, hmd, DEFAULT_FRAME_PERIOD);
+
+        // The follower is behind the head, and doesn't roll with it.
+        let local_transform = *world.get::<&LocalTransform>(follower).unwrap();
+        assert_relative_eq!(local_transform.translation, head_position + turn * offset);
+        assert_relative_eq!(local_transform.rotation, turn, epsilon = 0.0001);
+
+        // The fixed camera stays put.
+        let local_transform = *world.get::<&LocalTransform>(fixed).unwrap();
+        assert_eq!(local_transform.translation, Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_spectator_smoothing() {
+        let mut world = World::new();
+        let head_position = Vec3::new(0., 1.7, 0.);
+        let hmd = world.spawn((GlobalTransform(Affine3A::from_translation(head_position)),));
+        let spectator = world.spawn((Spectator::first_person(), LocalTransform::default()));
+
+        // After one frame, the camera has only moved part of the way.
+        spectator_system_inner(&mut world, hmd, DEFAULT_FRAME_PERIOD);
+        let y = world
+            .get::<&LocalTransform>(spectator)
+            .unwrap()
+            .translation
+            .y;
+        assert!(y > 0. && y < head_position.y);
+
+        // But it gets there eventually.
+        for _ in 0..500 {
+            spectator_system_inner(&mut world, hmd, DEFAULT_FRAME_PERIOD);
+        }
+        let translation = world.get::<&LocalTransform>(spectator).unwrap().translation;
+        assert_relative_eq!(translation, head_position, epsilon = 0.0001);
+    }
+}